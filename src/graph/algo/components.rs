@@ -0,0 +1,114 @@
+use super::{Projection, Scores};
+
+
+// Numbers each component by the order its first node appears in the projection.
+fn renumber(roots: &[usize]) -> Vec<i64> {
+    let mut ids = vec![-1i64; roots.len()];
+    let mut by_root = vec![-1i64; roots.len()];
+    let mut count = 0;
+    for (i, root) in roots.iter().enumerate() {
+        if by_root[*root] < 0 {
+            by_root[*root] = count;
+            count += 1;
+        }
+        ids[i] = by_root[*root];
+    }
+    ids
+}
+
+
+fn find(parents: &mut [usize], mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+    i
+}
+
+// WeaklyConnectedComponents gives every node the id of the component it belongs to when
+// edge directions are ignored.
+pub fn weakly_connected_components(g: &Projection) -> Scores {
+    let mut parents: Vec<usize> = (0..g.len()).collect();
+
+    for i in 0..g.len() {
+        for o in g.out_edges(i) {
+            let a = find(&mut parents, i);
+            let b = find(&mut parents, *o);
+            if a != b {
+                parents[a.max(b)] = a.min(b);
+            }
+        }
+    }
+
+    let roots: Vec<usize> = (0..g.len()).map(|i| find(&mut parents, i)).collect();
+    g.scores(renumber(&roots))
+}
+
+
+// StronglyConnectedComponents gives every node the id of the set of nodes it can both
+// reach and be reached from. It is Tarjan's algorithm with an explicit stack so deep
+// graphs can not overflow the call stack.
+pub fn strongly_connected_components(g: &Projection) -> Scores {
+    let n = g.len();
+    let mut index = vec![usize::MAX; n];
+    let mut low = vec![0; n];
+    let mut on_stack = vec![false; n];
+    let mut stack = Vec::new();
+    let mut roots = vec![0; n];
+    let mut counter = 0;
+
+    for start in 0..n {
+        if index[start] != usize::MAX {
+            continue
+        }
+
+        // (node, position of the next out edge to visit)
+        let mut work = vec![(start, 0)];
+        index[start] = counter;
+        low[start] = counter;
+        counter += 1;
+        stack.push(start);
+        on_stack[start] = true;
+
+        while let Some((node, edge)) = work.pop() {
+            let out = g.out_edges(node);
+            if edge < out.len() {
+                work.push((node, edge + 1));
+                let next = out[edge];
+                if index[next] == usize::MAX {
+                    index[next] = counter;
+                    low[next] = counter;
+                    counter += 1;
+                    stack.push(next);
+                    on_stack[next] = true;
+                    work.push((next, 0));
+                } else if on_stack[next] {
+                    low[node] = low[node].min(index[next]);
+                }
+                continue
+            }
+
+            if low[node] == index[node] {
+                let mut root = node;
+                let mut members = Vec::new();
+                while let Some(m) = stack.pop() {
+                    on_stack[m] = false;
+                    root = root.min(m);
+                    members.push(m);
+                    if m == node {
+                        break
+                    }
+                }
+                for m in members {
+                    roots[m] = root;
+                }
+            }
+
+            if let Some((parent, _)) = work.last() {
+                low[*parent] = low[*parent].min(low[node]);
+            }
+        }
+    }
+
+    g.scores(renumber(&roots))
+}
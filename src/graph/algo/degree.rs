@@ -0,0 +1,17 @@
+use super::{Projection, Scores};
+
+
+// Each quad in scope counts once, so two nodes linked by several predicates are counted
+// once per predicate.
+
+pub fn in_degree(g: &Projection) -> Scores {
+    g.scores((0..g.len()).map(|i| g.in_edges(i).len()))
+}
+
+pub fn out_degree(g: &Projection) -> Scores {
+    g.scores((0..g.len()).map(|i| g.out_edges(i).len()))
+}
+
+pub fn degree(g: &Projection) -> Scores {
+    g.scores((0..g.len()).map(|i| g.in_edges(i).len() + g.out_edges(i).len()))
+}
@@ -0,0 +1,170 @@
+use super::value::Value;
+use super::quad::{Quad, QuadStore, Direction, Delta, Procedure, IgnoreOptions};
+use super::iterator::Shape;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::cell::RefCell;
+
+pub mod pagerank;
+pub mod components;
+pub mod degree;
+
+
+// Scope restricts the quads an algorithm sees. `None` predicates follow every predicate,
+// a `None` label reads quads from every label.
+#[derive(Clone)]
+pub struct Scope {
+    pub predicates: Option<Vec<Value>>,
+    pub label: Option<Value>
+}
+
+impl Scope {
+    pub fn all() -> Scope {
+        Scope {
+            predicates: None,
+            label: None
+        }
+    }
+
+    pub fn new(predicates: Option<Vec<Value>>, label: Option<Value>) -> Scope {
+        Scope {
+            predicates,
+            label
+        }
+    }
+}
+
+
+// Projection is the directed subject -> object graph of the quads in a Scope, with nodes
+// numbered in the order they are first seen so results are stable between runs.
+pub struct Projection {
+    nodes: Vec<Value>,
+    index: HashMap<Value, usize>,
+    out_edges: Vec<Vec<usize>>,
+    in_edges: Vec<Vec<usize>>
+}
+
+impl Projection {
+    pub fn load(qs: &dyn QuadStore, scope: &Scope) -> Result<Projection, String> {
+        let mut projection = Projection {
+            nodes: Vec::new(),
+            index: HashMap::new(),
+            out_edges: Vec::new(),
+            in_edges: Vec::new()
+        };
+
+        let iterators: Vec<Rc<RefCell<dyn Shape>>> = match &scope.predicates {
+            Some(predicates) => predicates.iter()
+                .filter_map(|p| qs.value_of(p))
+                .map(|r| qs.quad_iterator(&Direction::Predicate, &r))
+                .collect(),
+            None => vec![qs.quads_all_iterator()]
+        };
+
+        for shape in iterators {
+            let it = shape.borrow().iterate();
+            let mut batch = Vec::new();
+            while it.borrow_mut().next_batch(&mut batch) > 0 {
+                for quad in batch.drain(..).filter_map(|r| qs.quad(&r)) {
+                    if let Some(label) = &scope.label {
+                        if &quad.label != label {
+                            continue
+                        }
+                    }
+                    projection.add_edge(quad.subject, quad.object);
+                }
+            }
+            if let Some(e) = it.borrow().err() {
+                return Err(e)
+            }
+            it.borrow_mut().close()?;
+        }
+
+        Ok(projection)
+    }
+
+    fn node(&mut self, v: Value) -> usize {
+        if let Some(i) = self.index.get(&v) {
+            return *i
+        }
+        let i = self.nodes.len();
+        self.index.insert(v.clone(), i);
+        self.nodes.push(v);
+        self.out_edges.push(Vec::new());
+        self.in_edges.push(Vec::new());
+        i
+    }
+
+    fn add_edge(&mut self, subject: Value, object: Value) {
+        let s = self.node(subject);
+        let o = self.node(object);
+        self.out_edges[s].push(o);
+        self.in_edges[o].push(s);
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn nodes(&self) -> &[Value] {
+        &self.nodes
+    }
+
+    pub fn out_edges(&self, node: usize) -> &[usize] {
+        &self.out_edges[node]
+    }
+
+    pub fn in_edges(&self, node: usize) -> &[usize] {
+        &self.in_edges[node]
+    }
+
+    fn scores<V: Into<Value>, I: IntoIterator<Item = V>>(&self, values: I) -> Scores {
+        Scores {
+            scores: self.nodes.iter().cloned().zip(values.into_iter().map(|v| v.into())).collect()
+        }
+    }
+}
+
+
+// Scores holds one (node, score) pair per node of the Projection it was computed from.
+pub struct Scores {
+    pub scores: Vec<(Value, Value)>
+}
+
+impl Scores {
+    pub fn iter(&self) -> impl Iterator<Item = &(Value, Value)> {
+        self.scores.iter()
+    }
+
+    pub fn get(&self, node: &Value) -> Option<&Value> {
+        self.scores.iter().find(|(n, _)| n == node).map(|(_, s)| s)
+    }
+
+    pub fn quads(&self, predicate: &Value, label: &Value) -> Vec<Quad> {
+        self.scores.iter()
+            .map(|(n, s)| Quad::new(n.clone(), predicate.clone(), s.clone(), label.clone()))
+            .collect()
+    }
+
+    // Writes every score back into the store as `node -- predicate -> score` in `label`.
+    // Scores left over from an earlier run are not removed.
+    pub fn write(&self, qs: &mut dyn QuadStore, predicate: &Value, label: &Value) -> Result<(), String> {
+        let deltas = self.quads(predicate, label).into_iter()
+            .map(|quad| Delta { quad, action: Procedure::Add })
+            .collect();
+        qs.apply_deltas(deltas, &IgnoreOptions { ignore_dup: true, ignore_missing: true })
+    }
+}
+
+impl IntoIterator for Scores {
+    type Item = (Value, Value);
+    type IntoIter = std::vec::IntoIter<(Value, Value)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.scores.into_iter()
+    }
+}
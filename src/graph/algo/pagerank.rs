@@ -0,0 +1,57 @@
+use super::{Projection, Scores};
+
+
+pub struct PageRankOptions {
+    pub damping: f64,
+    pub max_iterations: usize,
+    pub tolerance: f64
+}
+
+impl Default for PageRankOptions {
+    fn default() -> PageRankOptions {
+        PageRankOptions {
+            damping: 0.85,
+            max_iterations: 100,
+            tolerance: 1e-6
+        }
+    }
+}
+
+
+// PageRank scores every node of the projection, iterating until the summed change of all
+// ranks falls below `tolerance`. The rank of nodes without out edges is spread over the
+// whole graph, so the scores always sum to 1.
+pub fn page_rank(g: &Projection, opts: &PageRankOptions) -> Scores {
+    let n = g.len();
+    if n == 0 {
+        return g.scores(Vec::<f64>::new())
+    }
+
+    let base = (1.0 - opts.damping) / n as f64;
+    let mut ranks = vec![1.0 / n as f64; n];
+
+    for _ in 0..opts.max_iterations {
+        let dangling: f64 = (0..n).filter(|i| g.out_edges(*i).is_empty()).map(|i| ranks[i]).sum();
+        let spread = base + opts.damping * dangling / n as f64;
+
+        let mut next = vec![spread; n];
+        for (i, rank) in ranks.iter().enumerate() {
+            let out = g.out_edges(i);
+            if out.is_empty() {
+                continue
+            }
+            let share = opts.damping * rank / out.len() as f64;
+            for o in out {
+                next[*o] += share;
+            }
+        }
+
+        let delta: f64 = ranks.iter().zip(next.iter()).map(|(a, b)| (a - b).abs()).sum();
+        ranks = next;
+        if delta < opts.tolerance {
+            break
+        }
+    }
+
+    g.scores(ranks)
+}
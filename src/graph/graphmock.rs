@@ -2,7 +2,7 @@ use super::quad::{Stats, Quad, QuadStore, Direction, Delta, IgnoreOptions, Proce
 use super::iterator::{Shape};
 use super::iterator::fixed::{Fixed};
 use super::value::{Value};
-use super::refs::{Size, Ref, Content, pre_fetched, Namer};
+use super::refs::{Size, Ref, Key, Content, pre_fetched, Namer};
 use std::rc::Rc;
 use std::cell::RefCell;
 use std::collections::HashSet;
@@ -21,7 +21,7 @@ impl Store {
 
 fn quad_value(q: Quad) -> Ref {
     Ref {
-        k: Value::String(q.to_string()),
+        k: Key::Value(Value::String(q.to_string())),
         content: Content::Quad(q)
     }
 }
@@ -40,7 +40,7 @@ impl Namer for Store {
     
     fn name_of(&self, key: &Ref) -> Option<Value> {
         return if let Some(k) = key.key() {
-            Some(k.clone())
+            Some(k.to_value())
         } else {
             None
         }
@@ -65,7 +65,7 @@ impl QuadStore for Store {
             println!("Quad Iterator {:?} == {:?}, Direction: {:?}", q.get(d), r.key(), d);
 
             if let Some(k) = r.key() {
-                if q.get(d) == k {
+                if matches!(k, Key::Value(v) if q.get(d) == v) {
                     fixed.borrow_mut().add(quad_value(q.clone()));
                 }
             }
@@ -82,7 +82,7 @@ impl QuadStore for Store {
         };
         for q in &self.data {
             if let Some(k) = r.key() {
-                if q.get(d) == k {
+                if matches!(k, Key::Value(v) if q.get(d) == v) {
                     sz.value += 1;
                 }
             }
@@ -167,7 +167,7 @@ impl QuadStore for Store {
         let fixed = Fixed::new(vec![]);
         for q in &self.data {
             fixed.borrow_mut().add(Ref {
-                k: Value::String(q.to_string()),
+                k: Key::Value(Value::String(q.to_string())),
                 content: Content::Quad(q.clone())
             });
         }
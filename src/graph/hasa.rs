@@ -143,17 +143,20 @@ impl Scanner for HasANext {
             return false
         }
         
-        self.result = match self.qs.borrow().quad_direction(self.primary.borrow().result().as_ref().unwrap(), &self.dir) {
-            Some(q) => {
-                Some(q)
-            },
-            None => {
-                Some(Ref::none())
-            }
-        };
+        let quad = self.primary.borrow().result();
+        self.result = Some(quad.and_then(|q| self.qs.borrow().quad_direction(&q, &self.dir)).unwrap_or_else(Ref::none));
 
         return true
     }
+
+    fn next_batch(&mut self, out: &mut Vec<Ref>) -> usize {
+        let mut quads = Vec::new();
+        let n = self.primary.borrow_mut().next_batch(&mut quads);
+        let qs = self.qs.borrow();
+        out.extend(quads.iter().map(|q| qs.quad_direction(q, &self.dir).unwrap_or_else(Ref::none)));
+        self.result = out.last().cloned().filter(|_| n > 0);
+        n
+    }
 }
 
 
@@ -0,0 +1,112 @@
+use super::super::value::Value;
+use super::super::refs::{Ref, Namer};
+use std::collections::HashMap;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Aggregate {
+    Count,
+    Sum,
+    Min,
+    Max,
+    Avg
+}
+
+
+// Accumulator folds the values of one group into a single result. Sums stay integers as
+// long as every value added is an integer, values that are not numbers are ignored by
+// Sum and Avg, while Min and Max compare any value with `Value::compare`.
+pub struct Accumulator {
+    aggregate: Aggregate,
+    count: i64,
+    numbers: i64,
+    int_sum: i64,
+    float_sum: f64,
+    all_ints: bool,
+    best: Option<Value>
+}
+
+impl Accumulator {
+    pub fn new(aggregate: Aggregate) -> Accumulator {
+        Accumulator {
+            aggregate,
+            count: 0,
+            numbers: 0,
+            int_sum: 0,
+            float_sum: 0.0,
+            all_ints: true,
+            best: None
+        }
+    }
+
+    pub fn add(&mut self, v: Option<&Value>) {
+        self.count += 1;
+        let v = match v { Some(v) => v, None => return };
+
+        match self.aggregate {
+            Aggregate::Count => {},
+            Aggregate::Sum | Aggregate::Avg => {
+                if let Some(f) = v.as_f64() {
+                    self.numbers += 1;
+                    self.float_sum += f;
+                    match v.as_i64().and_then(|i| self.int_sum.checked_add(i)) {
+                        Some(sum) => self.int_sum = sum,
+                        None => self.all_ints = false
+                    }
+                }
+            },
+            Aggregate::Min => {
+                if self.best.as_ref().is_none_or(|b| v.compare(b).is_lt()) {
+                    self.best = Some(v.clone());
+                }
+            },
+            Aggregate::Max => {
+                if self.best.as_ref().is_none_or(|b| v.compare(b).is_gt()) {
+                    self.best = Some(v.clone());
+                }
+            }
+        }
+    }
+
+    pub fn result(&self) -> Value {
+        match self.aggregate {
+            Aggregate::Count => Value::from(self.count),
+            Aggregate::Sum => if self.all_ints { Value::from(self.int_sum) } else { Value::from(self.float_sum) },
+            Aggregate::Avg => if self.numbers == 0 { Value::Null } else { Value::from(self.float_sum / self.numbers as f64) },
+            Aggregate::Min | Aggregate::Max => self.best.clone().unwrap_or(Value::Null)
+        }
+    }
+}
+
+
+// Aggregates tagged result rows, such as the ones produced by TagEachIterator, grouped by
+// the value of `group_tag`. Rows without the group tag are skipped. With no `group_tag`
+// every row falls in a single group keyed by `Value::None`. Groups are returned in the
+// order they are first seen.
+pub fn aggregate<I: Iterator<Item = HashMap<String, Ref>>>(rows: I, namer: &dyn Namer, group_tag: Option<&str>, value_tag: Option<&str>, aggregate: Aggregate) -> Vec<(Value, Value)> {
+    let mut keys: Vec<Value> = Vec::new();
+    let mut groups: HashMap<Value, Accumulator> = HashMap::new();
+
+    for row in rows {
+        let key = match group_tag {
+            Some(t) => match row.get(t).and_then(|r| namer.name_of(r)) {
+                Some(k) => k,
+                None => continue
+            },
+            None => Value::None
+        };
+
+        let value = value_tag.and_then(|t| row.get(t)).and_then(|r| namer.name_of(r));
+
+        if !groups.contains_key(&key) {
+            keys.push(key.clone());
+            groups.insert(key.clone(), Accumulator::new(aggregate));
+        }
+        groups.get_mut(&key).unwrap().add(value.as_ref());
+    }
+
+    keys.into_iter().map(|k| {
+        let v = groups[&k].result();
+        (k, v)
+    }).collect()
+}
@@ -0,0 +1,453 @@
+use super::{Shape, Base, Index, Scanner, Costs, ShapeType, neighbors};
+use super::super::refs;
+use super::super::value::Value;
+use super::super::quad::QuadStore;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::fmt;
+
+pub const DEFAULT_MAX_ALL_PATHS_DEPTH:i32 = 10;
+
+// A step of a route: the node reached, and the predicate and direction of the quad
+// followed to reach it, None for the node the route starts from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PathStep {
+    pub node: Value,
+    pub predicate: Option<Value>,
+    pub forward: bool
+}
+
+// the tag of the node of step i of the route saved under `tag`
+pub fn step_tag(tag: &str, i: usize) -> String {
+    format!("{}.{}", tag, i)
+}
+
+// the tag of the predicate followed to reach step i, by the direction it was followed in
+pub fn predicate_tag(tag: &str, i: usize, forward: bool) -> String {
+    format!("{}.{}.{}", tag, i, if forward { "out" } else { "in" })
+}
+
+// Reads back the route an AllPaths saves under `tag` from the named tags of a result. A
+// step whose node or predicate is missing, as when it has no name, is an error rather than
+// a shorter route.
+pub fn route_steps(tags: &HashMap<String, Value>, tag: &str) -> Result<Vec<PathStep>, String> {
+    let len = match tags.get(tag).and_then(|n| n.as_i64()).and_then(|n| usize::try_from(n).ok()) {
+        Some(len) => len,
+        None => return Err(format!("no route is saved under {:?}", tag))
+    };
+    (0..len).map(|i| {
+        let node = tags.get(&step_tag(tag, i)).cloned().ok_or_else(|| format!("step {} of the route {:?} has no node", i, tag))?;
+        if i == 0 {
+            return Ok(PathStep { node, predicate: None, forward: true })
+        }
+        let (predicate, forward) = match (tags.get(&predicate_tag(tag, i, true)), tags.get(&predicate_tag(tag, i, false))) {
+            (Some(p), _) => (p.clone(), true),
+            (None, Some(p)) => (p.clone(), false),
+            (None, None) => return Err(format!("step {} of the route {:?} has no predicate", i, tag))
+        };
+        Ok(PathStep { node, predicate: Some(predicate), forward })
+    }).collect()
+}
+
+// AllPaths enumerates every simple path of one to `max_depth` quads from a node of `from`
+// to a node of `to`, a start node is not a route to itself. Each path is a separate result
+// whose value is the end node. The route is saved as tags of the store's own refs: the
+// number of steps under `tag`, the node of each step under `step_tag`, and the predicate
+// followed to reach it under `predicate_tag`, read back together with `route_steps`. A
+// reversed AllPaths follows quads from object to subject, or both ways round when `both`
+// is set.
+pub struct AllPaths {
+    qs: Rc<RefCell<dyn QuadStore>>,
+    from: Rc<RefCell<dyn Shape>>,
+    to: Rc<RefCell<dyn Shape>>,
+    via: Option<Rc<RefCell<dyn Shape>>>,
+    labels: Option<Rc<RefCell<dyn Shape>>>,
+    max_depth: i32,
+    both: bool,
+    reversed: bool,
+    tag: String
+}
+
+impl AllPaths {
+    pub fn new(qs: Rc<RefCell<dyn QuadStore>>, from: Rc<RefCell<dyn Shape>>, to: Rc<RefCell<dyn Shape>>, via: Option<Rc<RefCell<dyn Shape>>>, max_depth: i32, both: bool, tag: String) -> Rc<RefCell<AllPaths>> {
+        Rc::new(RefCell::new(AllPaths {
+            qs,
+            from,
+            to,
+            via,
+            labels: None,
+            max_depth: if max_depth <= 0 { DEFAULT_MAX_ALL_PATHS_DEPTH } else { max_depth },
+            both,
+            reversed: false,
+            tag
+        }))
+    }
+
+    pub fn set_labels(&mut self, labels: Rc<RefCell<dyn Shape>>) {
+        self.labels = Some(labels);
+    }
+
+    pub fn set_reversed(&mut self, reversed: bool) {
+        self.reversed = reversed;
+    }
+
+    fn new_next(&self) -> Rc<RefCell<AllPathsNext>> {
+        AllPathsNext::new(
+            self.qs.clone(),
+            self.from.borrow().iterate(),
+            self.to.borrow().lookup(),
+            self.via.as_ref().map(|v| v.borrow().lookup()),
+            self.labels.as_ref().map(|l| l.borrow().lookup()),
+            self.max_depth,
+            self.both,
+            self.reversed,
+            self.tag.clone()
+        )
+    }
+}
+
+
+impl fmt::Display for AllPaths {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "AllPaths({})", self.max_depth)
+    }
+}
+
+
+impl Shape for AllPaths {
+    fn iterate(&self) -> Rc<RefCell<dyn Scanner>> {
+        self.new_next()
+    }
+
+    fn lookup(&self) -> Rc<RefCell<dyn Index>> {
+        AllPathsContains::new(self.new_next())
+    }
+
+    fn stats(&mut self) -> Result<Costs, String> {
+        let from_stats = self.from.borrow_mut().stats()?;
+        let to_stats = self.to.borrow_mut().stats()?;
+        let fanout = 2i64.pow(self.max_depth.min(16) as u32);
+        Ok(Costs {
+            next_cost: from_stats.next_cost + to_stats.contains_cost,
+            contains_cost: (from_stats.next_cost + to_stats.contains_cost) * fanout,
+            size: refs::Size {
+                value: from_stats.size.value * fanout,
+                exact: false
+            }
+        })
+    }
+
+    fn optimize(&mut self) -> Option<Rc<RefCell<dyn Shape>>> {
+        let new_from = self.from.borrow_mut().optimize();
+        if let Some(f) = new_from {
+            self.from = f;
+        }
+        let new_to = self.to.borrow_mut().optimize();
+        if let Some(t) = new_to {
+            self.to = t;
+        }
+        None
+    }
+
+    fn sub_iterators(&self) -> Option<Vec<Rc<RefCell<dyn Shape>>>> {
+        Some(vec![self.from.clone(), self.to.clone()])
+    }
+
+    fn shape_type(&mut self) -> ShapeType<'_> {
+        ShapeType::AllPaths
+    }
+}
+
+
+#[derive(Clone)]
+struct Step {
+    node: refs::Ref,
+    predicate: Option<refs::Ref>,
+    forward: bool
+}
+
+// a route found, with the tags of the node it starts from
+#[derive(Clone)]
+struct Route {
+    steps: Vec<Step>,
+    start_tags: HashMap<String, refs::Ref>
+}
+
+impl Route {
+    fn end(&self) -> Option<refs::Ref> {
+        self.steps.last().map(|s| s.node.clone())
+    }
+
+    // the tags of the start node, and the steps of the route under `tag`
+    fn tag_results(&self, tag: &str, tags: &mut HashMap<String, refs::Ref>) {
+        for (k, v) in &self.start_tags {
+            tags.insert(k.clone(), v.clone());
+        }
+        tags.insert(tag.to_string(), refs::pre_fetched(Value::from(self.steps.len() as i64)));
+        for (i, s) in self.steps.iter().enumerate() {
+            tags.insert(step_tag(tag, i), s.node.clone());
+            if let Some(p) = &s.predicate {
+                tags.insert(predicate_tag(tag, i, s.forward), p.clone());
+            }
+        }
+    }
+}
+
+// a node on the current route along with the hops from it that are still to be explored
+struct Frame {
+    hops: Vec<Step>
+}
+
+struct AllPathsNext {
+    qs: Rc<RefCell<dyn QuadStore>>,
+    from: Rc<RefCell<dyn Scanner>>,
+    to: Rc<RefCell<dyn Index>>,
+    via: Option<Rc<RefCell<dyn Index>>>,
+    labels: Option<Rc<RefCell<dyn Index>>>,
+    max_depth: i32,
+    both: bool,
+    reversed: bool,
+    tag: String,
+
+    start_tags: HashMap<String, refs::Ref>,
+    route: Vec<Step>,
+    on_route: HashSet<refs::Key>,
+    stack: Vec<Frame>,
+    result: Option<Route>,
+    err: Option<String>
+}
+
+impl AllPathsNext {
+    #[allow(clippy::too_many_arguments)]
+    fn new(qs: Rc<RefCell<dyn QuadStore>>, from: Rc<RefCell<dyn Scanner>>, to: Rc<RefCell<dyn Index>>, via: Option<Rc<RefCell<dyn Index>>>, labels: Option<Rc<RefCell<dyn Index>>>, max_depth: i32, both: bool, reversed: bool, tag: String) -> Rc<RefCell<AllPathsNext>> {
+        Rc::new(RefCell::new(AllPathsNext {
+            qs,
+            from,
+            to,
+            via,
+            labels,
+            max_depth,
+            both,
+            reversed,
+            tag,
+            start_tags: HashMap::new(),
+            route: Vec::new(),
+            on_route: HashSet::new(),
+            stack: Vec::new(),
+            result: None,
+            err: None
+        }))
+    }
+
+    fn hops(&self, node: &refs::Ref) -> Result<Vec<Step>, String> {
+        let forward = !self.reversed;
+        let mut hops: Vec<Step> = neighbors(&self.qs, node, forward, self.via.as_ref(), self.labels.as_ref())?
+            .into_iter()
+            .map(|(p, n)| Step { node: n, predicate: Some(p), forward })
+            .collect();
+        if self.both {
+            hops.extend(neighbors(&self.qs, node, !forward, self.via.as_ref(), self.labels.as_ref())?
+                .into_iter()
+                .map(|(p, n)| Step { node: n, predicate: Some(p), forward: !forward }));
+        }
+        // hops are popped from the back, reverse so they are explored in index order
+        hops.reverse();
+        Ok(hops)
+    }
+
+    // an error reading the hops of the step ends the search, it is kept as the error
+    fn push(&mut self, step: Step) -> bool {
+        let hops = if (self.route.len() as i32) < self.max_depth {
+            match self.hops(&step.node) {
+                Ok(hops) => hops,
+                Err(e) => {
+                    self.err = Some(e);
+                    return false
+                }
+            }
+        } else {
+            Vec::new()
+        };
+        self.on_route.insert(step.node.key().unwrap().clone());
+        self.route.push(step);
+        self.stack.push(Frame { hops });
+        true
+    }
+
+    fn pop(&mut self) {
+        self.stack.pop();
+        if let Some(step) = self.route.pop() {
+            self.on_route.remove(step.node.key().unwrap());
+        }
+    }
+
+    // moves to the next start node, returning false once `from` is exhausted
+    fn next_start(&mut self) -> bool {
+        while self.from.borrow_mut().next() {
+            let node = self.from.borrow().result().unwrap();
+            if node.key().is_none() {
+                continue
+            }
+            self.start_tags = HashMap::new();
+            self.from.borrow().tag_results(&mut self.start_tags);
+            return self.push(Step { node, predicate: None, forward: true })
+        }
+        self.err = self.from.borrow().err();
+        false
+    }
+}
+
+impl fmt::Display for AllPathsNext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "AllPathsNext")
+    }
+}
+
+impl Base for AllPathsNext {
+
+    fn tag_results(&self, tags: &mut HashMap<String, refs::Ref>) {
+        if let Some(route) = &self.result {
+            route.tag_results(&self.tag, tags);
+        }
+    }
+
+    fn result(&self) -> Option<refs::Ref> {
+        self.result.as_ref().and_then(|r| r.end())
+    }
+
+    fn next_path(&mut self) -> bool {
+        false
+    }
+
+    fn err(&self) -> Option<String> {
+        self.err.clone()
+    }
+
+    fn close(&mut self) -> Result<(), String> {
+        self.stack = Vec::new();
+        self.route = Vec::new();
+        self.on_route = HashSet::new();
+        let res = self.from.borrow_mut().close();
+        let res2 = self.to.borrow_mut().close();
+        res.and(res2)
+    }
+}
+
+impl Scanner for AllPathsNext {
+    fn next(&mut self) -> bool {
+        if self.err.is_some() {
+            return false
+        }
+        loop {
+            if self.stack.is_empty() && !self.next_start() {
+                self.result = None;
+                return false
+            }
+
+            let hop = self.stack.last_mut().unwrap().hops.pop();
+            match hop {
+                Some(step) => {
+                    if self.on_route.contains(step.node.key().unwrap()) {
+                        continue
+                    }
+                    let is_end = self.to.borrow_mut().contains(&step.node);
+                    if !self.push(step) {
+                        self.result = None;
+                        return false
+                    }
+                    if is_end {
+                        self.result = Some(Route { steps: self.route.clone(), start_tags: self.start_tags.clone() });
+                        return true
+                    }
+                },
+                None => self.pop()
+            }
+        }
+    }
+}
+
+
+
+// AllPathsContains runs the search once, on the first contains, and keeps the routes it
+// finds by the node they end at. Every route to a node is a path of its result.
+struct AllPathsContains {
+    next: Rc<RefCell<AllPathsNext>>,
+    routes: Option<HashMap<refs::Key, Vec<Route>>>,
+    // the routes to the node checked last, and the one that is the result
+    current: Vec<Route>,
+    index: usize
+}
+
+impl AllPathsContains {
+    fn new(next: Rc<RefCell<AllPathsNext>>) -> Rc<RefCell<AllPathsContains>> {
+        Rc::new(RefCell::new(AllPathsContains {
+            next,
+            routes: None,
+            current: Vec::new(),
+            index: 0
+        }))
+    }
+
+    fn routes(&mut self) -> &HashMap<refs::Key, Vec<Route>> {
+        if self.routes.is_none() {
+            let mut routes: HashMap<refs::Key, Vec<Route>> = HashMap::new();
+            let mut next = self.next.borrow_mut();
+            while next.next() {
+                let route = next.result.clone().unwrap();
+                if let Some(key) = route.end().as_ref().and_then(|r| r.key()) {
+                    routes.entry(key.clone()).or_default().push(route);
+                }
+            }
+            self.routes = Some(routes);
+        }
+        self.routes.as_ref().unwrap()
+    }
+}
+
+impl fmt::Display for AllPathsContains {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "AllPathsContains({})", self.next.borrow())
+    }
+}
+
+impl Base for AllPathsContains {
+
+    fn tag_results(&self, tags: &mut HashMap<String, refs::Ref>) {
+        if let Some(route) = self.current.get(self.index) {
+            route.tag_results(&self.next.borrow().tag, tags);
+        }
+    }
+
+    fn result(&self) -> Option<refs::Ref> {
+        self.current.get(self.index).and_then(|r| r.end())
+    }
+
+    fn next_path(&mut self) -> bool {
+        if self.index + 1 < self.current.len() {
+            self.index += 1;
+            return true
+        }
+        false
+    }
+
+    fn err(&self) -> Option<String> {
+        self.next.borrow().err()
+    }
+
+    fn close(&mut self) -> Result<(), String> {
+        self.current = Vec::new();
+        self.next.borrow_mut().close()
+    }
+}
+
+impl Index for AllPathsContains {
+    fn contains(&mut self, val:&refs::Ref) -> bool {
+        let key = match val.key() { Some(k) => k.clone(), None => return false };
+        let current = self.routes().get(&key).cloned().unwrap_or_default();
+        self.current = current;
+        self.index = 0;
+        !self.current.is_empty()
+    }
+}
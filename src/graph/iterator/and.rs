@@ -1,4 +1,4 @@
-use super::{Base, Shape, Scanner, Costs, Index, Null, height, is_null, ShapeType};
+use super::{Base, Shape, Scanner, Costs, Index, Null, height, is_null, ShapeType, SortOrder, result_id};
 use super::materialize::Materialize;
 use super::super::refs;
 use std::collections::HashMap;
@@ -9,7 +9,10 @@ use std::fmt;
 pub struct And {
     sub: Vec<Rc<RefCell<dyn Shape>>>,
     check_list: Option<Vec<Rc<RefCell<dyn Shape>>>>,
-    opt: Option<Vec<Rc<RefCell<dyn Shape>>>>
+    opt: Option<Vec<Rc<RefCell<dyn Shape>>>>,
+    // set by optimize when every sub iterator is sorted the same way, the scanner then
+    // steps through all of them together instead of checking each result with contains
+    merge: Option<SortOrder>
 }
 
 
@@ -19,10 +22,20 @@ impl And  {
         Rc::new(RefCell::new(And {
             sub,
             check_list: None,
-            opt: None
+            opt: None,
+            merge: None
         }))
     }
 
+    // An And that merge-joins its sub iterators when they all report the same sort order,
+    // like optimize chooses, for callers that build iterators without optimizing them.
+    pub fn new_merged(sub: Vec<Rc<RefCell<dyn Shape>>>) -> Rc<RefCell<And>> {
+        let merge = merge_order(&sub);
+        let and = And::new(sub);
+        and.borrow_mut().merge = merge;
+        and
+    }
+
     pub fn add_sub_iterator(&mut self, sub: Rc<RefCell<dyn Shape>>) {
         self.sub.push(sub);
     }
@@ -44,6 +57,9 @@ impl And  {
 
 impl fmt::Display for And {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.merge.is_some() {
+            return write!(f, "And(merge)")
+        }
         write!(f, "And")
     }
 }
@@ -54,6 +70,10 @@ impl Shape for And {
             return Null::new()
         }
 
+        if self.merge.is_some() {
+            return AndMergeNext::new(self.sub.iter().map(|s| s.borrow().iterate()).collect())
+        }
+
         let mut sub = Vec::new();
 
         for s in self.sub.iter().skip(1) {
@@ -117,6 +137,8 @@ impl Shape for And {
 
         let its = materialize_its(&its).unwrap(); // TODO: why is there even an error?
 
+        let merge = merge_order(&its);
+
         let new_and = And::new(its);
 
         if self.opt.is_some() {
@@ -126,6 +148,10 @@ impl Shape for And {
             }
         }
 
+        if new_and.borrow().opt.as_ref().is_none_or(|o| o.is_empty()) {
+            new_and.borrow_mut().merge = merge;
+        }
+
         let _ = new_and.borrow_mut().optimize_contains();
 
         // TODO: Logging
@@ -148,6 +174,23 @@ impl Shape for And {
     fn shape_type(&mut self) -> ShapeType {
         ShapeType::And
     }
+
+    // results come in the order of the first sub iterator
+    fn sort_order(&self) -> Option<SortOrder> {
+        self.sub.first().and_then(|s| s.borrow().sort_order())
+    }
+}
+
+// the order shared by all the iterators, when there are at least two of them
+fn merge_order(its: &[Rc<RefCell<dyn Shape>>]) -> Option<SortOrder> {
+    if its.len() < 2 {
+        return None
+    }
+    let order = its[0].borrow().sort_order()?;
+    if its.iter().all(|it| it.borrow().sort_order() == Some(order)) {
+        return Some(order)
+    }
+    None
 }
 
 fn optimize_replacement(its: &Vec<Rc<RefCell<dyn Shape>>>) -> Option<Rc<RefCell<dyn Shape>>> {
@@ -257,7 +300,7 @@ fn materialize_its(its:&Vec<Rc<RefCell<dyn Shape>>>) -> Result<Vec<Rc<RefCell<dy
             if height(&mut *it.borrow_mut(), |it| {
                 //it.borrow().string() != "Materialize"
                 match it.shape_type() {                                                                 
-                    ShapeType::Materialize => false,                                     
+                    ShapeType::Materialize(_) => false,                                     
                     _ => true,                                                            
                 }
             }) > 10 {
@@ -402,16 +445,188 @@ impl Scanner for AndNext {
         let mut primary = self.primary.borrow_mut();
         let mut secondary = self.secondary.borrow_mut();
         while primary.next() {
+            if let Some(cur) = primary.result() {
+                if secondary.contains(&cur) {
+                    self.result = Some(cur);
+                    return true
+                }
+                if secondary.err().is_some() {
+                    return false
+                }
+            }
+        }
+
+        false
+    }
+
+    fn next_batch(&mut self, out: &mut Vec<refs::Ref>) -> usize {
+        let mut primary = self.primary.borrow_mut();
+        let mut secondary = self.secondary.borrow_mut();
+        if secondary.err().is_some() {
+            return 0
+        }
+        let mut candidates = Vec::new();
+        loop {
+            candidates.clear();
+            if primary.next_batch(&mut candidates) == 0 {
+                return 0
+            }
+            let before = out.len();
+            for r in candidates.drain(..) {
+                if secondary.contains(&r) {
+                    out.push(r);
+                } else if secondary.err().is_some() {
+                    // the results before the failure are kept, the next call returns 0
+                    break
+                }
+            }
+            // a batch where nothing matched must not read as the end of the scan
+            if out.len() > before || secondary.err().is_some() {
+                self.result = out.last().cloned().filter(|_| out.len() > before);
+                return out.len() - before
+            }
+        }
+    }
+
+    fn seek(&mut self, id: u64) -> bool {
+        let found = {
+            let mut primary = self.primary.borrow_mut();
+            if !primary.seek(id) {
+                return false
+            }
             let cur = primary.result();
+            cur.filter(|c| self.secondary.borrow_mut().contains(c))
+        };
+        match found {
+            Some(r) => {
+                self.result = Some(r);
+                true
+            },
+            None => self.next()
+        }
+    }
+}
 
-            if cur.is_some() && secondary.contains(&cur.as_ref().unwrap()) {
-                self.result = Some(cur.as_ref().unwrap().clone());
-                return true
+
+
+// AndMergeNext intersects scanners that return ascending ids (leapfrog join): each one
+// seeks to the largest id seen so far until they all agree, so runs of ids missing from
+// any of them are skipped rather than checked one at a time.
+struct AndMergeNext {
+    sub: Vec<Rc<RefCell<dyn Scanner>>>,
+    result: Option<refs::Ref>,
+    done: bool
+}
+
+impl AndMergeNext {
+    fn new(sub: Vec<Rc<RefCell<dyn Scanner>>>) -> Rc<RefCell<AndMergeNext>> {
+        Rc::new(RefCell::new(AndMergeNext {
+            sub,
+            result: None,
+            done: false
+        }))
+    }
+
+    // moves every scanner to the first id at least `target` that they all share
+    fn search(&mut self, mut target: u64) -> bool {
+        'round: loop {
+            for s in &self.sub {
+                let mut s = s.borrow_mut();
+                if !s.seek(target) {
+                    break 'round
+                }
+                match result_id(s.result().as_ref()) {
+                    Some(id) if id > target => {
+                        target = id;
+                        continue 'round
+                    },
+                    Some(_) => {},
+                    None => break 'round
+                }
             }
+            self.result = self.sub[0].borrow().result();
+            return true
+        }
+        self.result = None;
+        self.done = true;
+        false
+    }
+}
+
+impl fmt::Display for AndMergeNext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "AndMergeNext")
+    }
+}
+
+impl Base for AndMergeNext {
+    fn tag_results(&self, tags: &mut HashMap<String, refs::Ref>) {
+        for s in &self.sub {
+            s.borrow().tag_results(tags);
         }
+    }
+
+    fn result(&self) -> Option<refs::Ref> {
+        self.result.clone()
+    }
 
+    fn next_path(&mut self) -> bool {
+        for s in &self.sub {
+            if s.borrow_mut().next_path() {
+                return true
+            }
+        }
         false
     }
+
+    fn err(&self) -> Option<String> {
+        self.sub.iter().find_map(|s| s.borrow().err())
+    }
+
+    fn close(&mut self) -> Result<(), String> {
+        let mut res = Ok(());
+        for s in &self.sub {
+            let r = s.borrow_mut().close();
+            if res.is_ok() {
+                res = r;
+            }
+        }
+        res
+    }
+}
+
+impl Scanner for AndMergeNext {
+    fn next(&mut self) -> bool {
+        if self.done {
+            return false
+        }
+        let target = {
+            let mut first = self.sub[0].borrow_mut();
+            if !first.next() {
+                None
+            } else {
+                result_id(first.result().as_ref())
+            }
+        };
+        match target {
+            Some(t) => self.search(t),
+            None => {
+                self.result = None;
+                self.done = true;
+                false
+            }
+        }
+    }
+
+    fn seek(&mut self, id: u64) -> bool {
+        if self.done {
+            return false
+        }
+        if result_id(self.result.as_ref()).is_some_and(|cur| cur >= id) {
+            return true
+        }
+        self.search(id)
+    }
 }
 
 
@@ -419,6 +634,8 @@ struct AndContains {
     base: Option<Rc<RefCell<dyn Shape>>>,
     sub: Vec<Rc<RefCell<dyn Index>>>,
     opt: Vec<Rc<RefCell<dyn Index>>>,
+    // whether each optional iterator holds the current result, only those that do add
+    // their tags and paths to it
     opt_check: HashMap<usize, bool>,
     
     result: Option<refs::Ref>,
@@ -437,6 +654,10 @@ impl AndContains {
             err: None
         }))
     }
+
+    fn opt_matched(&self, i: usize) -> bool {
+        self.opt_check.get(&i).copied().unwrap_or(false)
+    }
 }
 
 
@@ -453,7 +674,7 @@ impl Base for AndContains {
             sub.borrow().tag_results(dst);
         }
         for (i, sub) in self.opt.iter().enumerate() {
-            if !self.opt_check.contains_key(&i) {
+            if !self.opt_matched(i) {
                 continue
             } 
             sub.borrow().tag_results(dst);
@@ -482,7 +703,7 @@ impl Base for AndContains {
         }
         for (i, _sub) in self.opt.iter().enumerate() {
             let mut sub = _sub.borrow_mut();
-            if !self.opt_check.contains_key(&i) {
+            if !self.opt_matched(i) {
                 continue
             }
             if sub.next_path() {
@@ -1,18 +1,28 @@
-use super::{Shape, Base, Index, Scanner, Costs, Null, ShapeType};
+use super::{Shape, Base, Index, Scanner, Costs, Null, ShapeType, SortOrder, BATCH_SIZE};
 use super::super::refs;
-use super::super::value::Value;
 use std::collections::HashMap;
 use std::rc::Rc;
 use std::cell::RefCell;
 use std::fmt;
 
 pub struct Fixed {
-    pub values: Rc<RefCell<Vec<refs::Ref>>>}
+    pub values: Rc<RefCell<Vec<refs::Ref>>>,
+    order: Option<SortOrder>
+}
 
 impl Fixed {
     pub fn new(vals: Vec<refs::Ref>) -> Rc<RefCell<Fixed>> {
         Rc::new(RefCell::new(Fixed{
-            values: Rc::new(RefCell::new(vals))
+            values: Rc::new(RefCell::new(vals)),
+            order: None
+        }))
+    }
+
+    // values the caller knows are already in `order`, added values must keep to it
+    pub fn new_sorted(vals: Vec<refs::Ref>, order: SortOrder) -> Rc<RefCell<Fixed>> {
+        Rc::new(RefCell::new(Fixed{
+            values: Rc::new(RefCell::new(vals)),
+            order: Some(order)
         }))
     }
 
@@ -69,6 +79,10 @@ impl Shape for Fixed {
     fn shape_type(&mut self) -> ShapeType {
         ShapeType::Fixed(self)
     }
+
+    fn sort_order(&self) -> Option<SortOrder> {
+        self.order
+    }
 }
 
 
@@ -129,13 +143,26 @@ impl Scanner for FixedNext {
         self.ind += 1;
         true
     }
+
+    fn next_batch(&mut self, out: &mut Vec<refs::Ref>) -> usize {
+        let values = self.values.borrow();
+        let end = values.len().min(self.ind + BATCH_SIZE);
+        if self.ind >= end {
+            return 0
+        }
+        out.extend_from_slice(&values[self.ind..end]);
+        self.result = values.get(end - 1).cloned();
+        let n = end - self.ind;
+        self.ind = end;
+        n
+    }
 }
 
 
 
 struct FixedContains {
     values: Rc<RefCell<Vec<refs::Ref>>>,
-    keys: Vec<Value>,
+    keys: Vec<refs::Key>,
     result: Option<refs::Ref>
 }
 
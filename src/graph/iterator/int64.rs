@@ -0,0 +1,224 @@
+use super::{Shape, Base, Index, Scanner, Costs, ShapeType, SortOrder};
+use super::super::refs;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::convert::TryFrom;
+use std::fmt;
+
+// Int64 iterates every id from `min` to `max` inclusive, as node refs holding the number
+// or, when `node` is false, as quad refs holding just the id.
+pub struct Int64 {
+    node: bool,
+    min: i64,
+    max: i64
+}
+
+impl Int64 {
+    pub fn new(min: i64, max: i64, node: bool) -> Rc<RefCell<Int64>> {
+        Rc::new(RefCell::new(Int64 {
+            node,
+            min,
+            max
+        }))
+    }
+
+    pub fn is_node(&self) -> bool {
+        self.node
+    }
+
+    pub fn size(&self) -> refs::Size {
+        refs::Size {
+            value: if self.max < self.min { 0 } else { self.max - self.min + 1 },
+            exact: true
+        }
+    }
+}
+
+
+fn id_ref(id: i64, node: bool) -> refs::Ref {
+    if node { refs::Ref::new_i64_node(id) } else { refs::Ref::new_i64_quad(id) }
+}
+
+
+impl fmt::Display for Int64 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Int64({}-{}, {})", self.min, self.max, if self.node { "node" } else { "quad" })
+    }
+}
+
+
+impl Shape for Int64 {
+    fn iterate(&self) -> Rc<RefCell<dyn Scanner>> {
+        Int64Next::new(self.min, self.max, self.node)
+    }
+
+    fn lookup(&self) -> Rc<RefCell<dyn Index>> {
+        Int64Contains::new(self.min, self.max, self.node)
+    }
+
+    fn stats(&mut self) -> Result<Costs, String> {
+        Ok(Costs {
+            contains_cost: 1,
+            next_cost: 1,
+            size: self.size()
+        })
+    }
+
+    fn optimize(&mut self) -> Option<Rc<RefCell<dyn Shape>>> {
+        None
+    }
+
+    fn sub_iterators(&self) -> Option<Vec<Rc<RefCell<dyn Shape>>>> {
+        None
+    }
+
+    fn shape_type(&mut self) -> ShapeType<'_> {
+        ShapeType::Int64
+    }
+
+    fn sort_order(&self) -> Option<SortOrder> {
+        // negative ids don't sort as store ids
+        if self.min < 0 {
+            return None
+        }
+        Some(if self.node { SortOrder::NodeIds } else { SortOrder::QuadIds })
+    }
+}
+
+
+
+struct Int64Next {
+    node: bool,
+    max: i64,
+    at: i64,
+    result: Option<i64>
+}
+
+impl Int64Next {
+    fn new(min: i64, max: i64, node: bool) -> Rc<RefCell<Int64Next>> {
+        Rc::new(RefCell::new(Int64Next {
+            node,
+            max,
+            at: min,
+            result: None
+        }))
+    }
+}
+
+impl fmt::Display for Int64Next {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Int64Next")
+    }
+}
+
+impl Base for Int64Next {
+    fn tag_results(&self, _tags: &mut HashMap<String, refs::Ref>) {}
+
+    fn result(&self) -> Option<refs::Ref> {
+        self.result.map(|i| id_ref(i, self.node))
+    }
+
+    fn next_path(&mut self) -> bool {
+        false
+    }
+
+    fn err(&self) -> Option<String> {
+        None
+    }
+
+    fn close(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl Scanner for Int64Next {
+    fn next(&mut self) -> bool {
+        if self.at > self.max {
+            self.result = None;
+            return false
+        }
+        self.result = Some(self.at);
+        // stepping past i64::MAX ends the range instead of overflowing
+        match self.at.checked_add(1) {
+            Some(n) => self.at = n,
+            None => self.max = self.at - 1
+        }
+        true
+    }
+
+    fn seek(&mut self, id: u64) -> bool {
+        if self.result.is_some_and(|cur| cur >= 0 && cur as u64 >= id) {
+            return true
+        }
+        let id = i64::try_from(id).unwrap_or(i64::MAX);
+        if id > self.max {
+            self.at = self.max.saturating_add(1);
+            self.result = None;
+            return false
+        }
+        self.at = self.at.max(id);
+        self.next()
+    }
+}
+
+
+
+struct Int64Contains {
+    node: bool,
+    min: i64,
+    max: i64,
+    result: Option<i64>
+}
+
+impl Int64Contains {
+    fn new(min: i64, max: i64, node: bool) -> Rc<RefCell<Int64Contains>> {
+        Rc::new(RefCell::new(Int64Contains {
+            node,
+            min,
+            max,
+            result: None
+        }))
+    }
+}
+
+impl fmt::Display for Int64Contains {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Int64Contains")
+    }
+}
+
+impl Base for Int64Contains {
+    fn tag_results(&self, _tags: &mut HashMap<String, refs::Ref>) {}
+
+    fn result(&self) -> Option<refs::Ref> {
+        self.result.map(|i| id_ref(i, self.node))
+    }
+
+    fn next_path(&mut self) -> bool {
+        false
+    }
+
+    fn err(&self) -> Option<String> {
+        None
+    }
+
+    fn close(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl Index for Int64Contains {
+    fn contains(&mut self, v:&refs::Ref) -> bool {
+        match v.key().and_then(|k| k.as_i64()) {
+            Some(i) if self.min <= i && i <= self.max => {
+                self.result = Some(i);
+                true
+            },
+            _ => {
+                self.result = None;
+                false
+            }
+        }
+    }
+}
@@ -10,7 +10,8 @@ pub struct BaseIterator {
     it: Option<Rc<RefCell<dyn Scanner>>>,
     paths: bool,
     optimize: bool,
-    n: i64
+    n: i64,
+    err: Option<String>
 }
 
 impl BaseIterator {
@@ -27,7 +28,11 @@ impl BaseIterator {
 
     pub fn end(&mut self) {
         let i = &mut*self.it.as_ref().unwrap().borrow_mut();
-        i.close().unwrap();
+        self.err = i.err();
+        // an iterator that failed may fail to close with the same error
+        if let Err(err) = i.close() {
+            self.err.get_or_insert(err);
+        }
     }
 
 
@@ -61,7 +66,8 @@ impl TagEachIterator {
                 it: None,
                 paths,
                 optimize,
-                n: 0
+                n: 0,
+                err: None
             }
         }
     }
@@ -90,6 +96,11 @@ impl TagEachIterator {
             return self.do_val()
         }
     }
+
+    // the error that ended the iteration, if any
+    pub fn err(&self) -> Option<String> {
+        self.base.err.clone()
+    }
 }
 
 
@@ -124,7 +135,8 @@ impl EachIterator {
                 it: None,
                 paths,
                 optimize,
-                n: 0
+                n: 0,
+                err: None
             }
         }
     }
@@ -149,6 +161,11 @@ impl EachIterator {
             return self.do_val()
         }
     }
+
+    // the error that ended the iteration, if any
+    pub fn err(&self) -> Option<String> {
+        self.base.err.clone()
+    }
 }
 
 impl Iterator for EachIterator {
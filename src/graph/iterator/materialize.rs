@@ -1,6 +1,6 @@
 use super::{Shape, Scanner, Index, Costs, Base, is_null, ShapeType};
+use super::spill::{SpillFiles, MemoryLimit, MemoryLimitError, LimitFailures, OnLimit, ref_size};
 use super::super::refs;
-use super::super::value::Value;
 use std::rc::Rc;
 use std::cell::RefCell;
 use std::collections::HashMap;
@@ -10,23 +10,38 @@ pub const MATERIALIZE_LIMIT: i32 = 1000;
 
 pub struct Materialize {
     sub: Rc<RefCell<dyn Shape>>,
-    expected_size: i64
+    expected_size: i64,
+    limit: Option<MemoryLimit>,
+    failures: LimitFailures
 }
 
 impl Materialize {
     pub fn new(sub: Rc<RefCell<dyn Shape>>) -> Rc<RefCell<Materialize>> {
         Rc::new(RefCell::new(Materialize {
             sub,
-            expected_size: 0
+            expected_size: 0,
+            limit: None,
+            failures: LimitFailures::new()
         }))
     }
 
     pub fn new_with_size(sub: Rc<RefCell<dyn Shape>>, size: i64) -> Rc<RefCell<Materialize>> {
         Rc::new(RefCell::new(Materialize {
             sub,
-            expected_size: size
+            expected_size: size,
+            limit: None,
+            failures: LimitFailures::new()
         }))
     }
+
+    // Bounds the memory used to hold the materialized results. Results that spilled to disk
+    // are read back one hash bucket at a time, so next returns them grouped by bucket
+    // rather than in the order the sub iterator found them.
+    // A failure at the limit is recorded in `failures`.
+    pub fn set_memory_limit(&mut self, limit: Option<MemoryLimit>, failures: LimitFailures) {
+        self.limit = limit;
+        self.failures = failures;
+    }
 }
 
 
@@ -39,11 +54,11 @@ impl fmt::Display for Materialize {
 
 impl Shape for Materialize {
     fn iterate(&self) -> Rc<RefCell<dyn Scanner>> {
-        return MaterializeNext::new(self.sub.clone())
+        MaterializeNext::new(self.sub.clone(), self.limit, self.failures.clone())
     }
 
     fn lookup(&self) -> Rc<RefCell<dyn Index>> {
-        return MaterializeContains::new(self.sub.clone())
+        MaterializeContains::new(self.sub.clone(), self.limit, self.failures.clone())
     }
 
     fn stats(&mut self) -> Result<Costs, String> {
@@ -78,11 +93,11 @@ impl Shape for Materialize {
     }
 
     fn shape_type(&mut self) -> ShapeType {
-        ShapeType::Materialize
+        ShapeType::Materialize(self)
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MaterializeResult {
     pub id: refs::Ref,
     pub tags: HashMap<String, refs::Ref>
@@ -91,17 +106,24 @@ pub struct MaterializeResult {
 struct MaterializeNext {
     sub: Rc<RefCell<dyn Shape>>,
     next: Rc<RefCell<dyn Scanner>>,
-    contains_map: HashMap<Value, usize>,
+    contains_map: HashMap<refs::Key, usize>,
     values: Vec<Vec<MaterializeResult>>,
     index: Option<usize>,
     sub_index: Option<usize>,
     has_run: bool,
     aborted: bool,
-    err: Option<String>
+    err: Option<String>,
+    limit: Option<MemoryLimit>,
+    failures: LimitFailures,
+    bytes: usize,
+    // once the results outgrow the limit they all go to disk, and values holds the
+    // results of one bucket at a time
+    spilled: Option<SpillFiles>,
+    bucket: Option<usize>
 }
 
 impl MaterializeNext {
-    fn new(sub: Rc<RefCell<dyn Shape>>) -> Rc<RefCell<MaterializeNext>> {
+    fn new(sub: Rc<RefCell<dyn Shape>>, limit: Option<MemoryLimit>, failures: LimitFailures) -> Rc<RefCell<MaterializeNext>> {
         // TODO: fix indexes being Options is not a good pattern
         // TODO: a vector of vectors is not a good pattern
         Rc::new(RefCell::new(MaterializeNext {
@@ -113,10 +135,91 @@ impl MaterializeNext {
             sub_index: None,
             has_run: false,
             aborted: false,
-            err: None
+            err: None,
+            limit,
+            failures,
+            bytes: 0,
+            spilled: None,
+            bucket: None
         }))
     }
 
+    fn add(&mut self, k: refs::Key, r: MaterializeResult) {
+        let i = match self.contains_map.get(&k) {
+            Some(i) => *i,
+            None => {
+                self.contains_map.insert(k, self.values.len());
+                self.values.push(Vec::new());
+                self.values.len() - 1
+            }
+        };
+        self.values[i].push(r);
+    }
+
+    fn push(&mut self, k: &refs::Key, id: refs::Ref) {
+        let mut tags: HashMap<String, refs::Ref> = HashMap::new();
+        self.next.as_ref().borrow().tag_results(&mut tags);
+        self.bytes += ref_size(&id) + tags.iter().map(|(t, v)| t.len() + ref_size(v)).sum::<usize>();
+        self.add(k.clone(), MaterializeResult{id, tags});
+
+        if let Some(limit) = self.limit {
+            if self.bytes > limit.bytes {
+                match limit.on_limit {
+                    OnLimit::Fail => {
+                        let e = MemoryLimitError::new("Materialize", limit.bytes);
+                        self.failures.record(e.clone());
+                        self.err = Some(e.to_string())
+                    },
+                    OnLimit::Spill => if let Err(e) = self.spill() {
+                        self.err = Some(e)
+                    }
+                }
+            }
+        }
+    }
+
+    fn spill(&mut self) -> Result<(), String> {
+        if self.spilled.is_none() {
+            self.spilled = Some(SpillFiles::new()?);
+        }
+        let files = self.spilled.as_mut().unwrap();
+        for (k, i) in self.contains_map.drain() {
+            let b = files.bucket_of(&k);
+            for r in &self.values[i] {
+                files.push(b, &(&k, r))?;
+            }
+        }
+        self.values = Vec::new();
+        self.bytes = 0;
+        self.bucket = None;
+        Ok(())
+    }
+
+    fn load_bucket(&mut self, b: usize) -> Result<(), String> {
+        let records: Vec<(refs::Key, MaterializeResult)> = self.spilled.as_mut().unwrap().read(b)?;
+        self.values = Vec::new();
+        self.contains_map = HashMap::new();
+        for (k, r) in records {
+            self.add(k, r);
+        }
+        self.bucket = Some(b);
+        Ok(())
+    }
+
+    // the index of the results for k, reading its bucket back when the results were spilled
+    fn find(&mut self, k: &refs::Key) -> Option<usize> {
+        let b = self.spilled.as_ref().map(|files| files.bucket_of(k));
+        if let Some(b) = b {
+            if self.bucket != Some(b) {
+                if let Err(e) = self.load_bucket(b) {
+                    self.err = Some(e);
+                    return None
+                }
+            }
+        }
+        self.contains_map.get(k).cloned()
+    }
+
     fn materialize_set(&mut self) {
         let mut i = 0;
         while self.next.borrow_mut().next() {
@@ -126,37 +229,36 @@ impl MaterializeNext {
                 break
             }
             let id = self.next.borrow().result().unwrap();
-            let val = id.key();
-
-            if let Some(v) = val {
-                if !self.contains_map.contains_key(v) {
-                    self.contains_map.insert(v.clone(), self.values.len());
-                    self.values.push(Vec::new());
-                }
-                let index = self.contains_map.get(&v);
 
-                let mut tags: HashMap<String, refs::Ref> = HashMap::new();
-                self.next.as_ref().borrow().tag_results(&mut tags);
-                self.values[*index.unwrap()].push(MaterializeResult{id: id.clone(), tags});
-                while self.next.borrow_mut().next_path() {
+            if let Some(k) = id.key() {
+                self.push(k, id.clone());
+                while self.err.is_none() && self.next.borrow_mut().next_path() {
                     i += 1;
                     if i > MATERIALIZE_LIMIT {
                         self.aborted = true;
                         break
                     }
-                    let mut tags: HashMap<String, refs::Ref> = HashMap::new();
-                    self.next.as_ref().borrow().tag_results(&mut tags);
-                    self.values[*index.unwrap()].push(MaterializeResult{id: id.clone(), tags});
+                    self.push(k, id.clone());
                 }
             }
+            if self.err.is_some() || self.aborted {
+                break
+            }
+        }
+        if self.err.is_none() {
+            self.err = self.next.borrow().err();
         }
-        self.err = self.next.borrow().err();
         if self.err.is_none() && self.aborted {
             // TODO: logging
             self.values = Vec::new();
             self.contains_map = HashMap::new();
+            self.spilled = None;
             let _ = self.next.borrow_mut().close();
             self.next = self.sub.borrow().iterate();
+        } else if self.err.is_none() && self.spilled.is_some() {
+            if let Err(e) = self.spill() {
+                self.err = Some(e);
+            }
         }
         self.has_run = true;
     }
@@ -171,11 +273,12 @@ impl fmt::Display for MaterializeNext {
 impl Base for MaterializeNext {
 
     fn tag_results(&self, dst: &mut HashMap<String, refs::Ref>) {
-        if self.has_run {
+        if !self.has_run {
             return
         }
         if self.aborted {
             self.next.borrow().tag_results(dst);
+            return
         }
         if self.result().is_none() {
             return
@@ -226,13 +329,17 @@ impl Base for MaterializeNext {
             return self.next.borrow_mut().next_path()
         }
 
-        self.sub_index = Some(self.sub_index.unwrap() + 1);
-        let v = self.values.get(self.index.unwrap()).unwrap();
-        if self.sub_index.unwrap() >= v.len() {
-            self.sub_index = Some(self.sub_index.unwrap() - 1);
-            return false
+        let (x, y) = match (self.index, self.sub_index) {
+            (Some(x), Some(y)) => (x, y),
+            _ => return false
+        };
+        match self.values.get(x) {
+            Some(v) if y + 1 < v.len() => {
+                self.sub_index = Some(y + 1);
+                true
+            },
+            _ => false
         }
-        return true
     }
 
     fn err(&self) -> Option<String> {
@@ -242,6 +349,11 @@ impl Base for MaterializeNext {
     fn close(&mut self) -> Result<(), String> {
         self.values = Vec::new();
         self.contains_map = HashMap::new();
+        self.index = None;
+        self.sub_index = None;
+        self.bytes = 0;
+        self.spilled = None;
+        self.bucket = None;
         self.has_run = false;
         self.next.borrow_mut().close()
     }
@@ -260,12 +372,30 @@ impl Scanner for MaterializeNext {
             self.err = self.next.borrow().err();
             return n
         }
-        self.index = Some(self.index.unwrap() + 1);
+        let i = self.index.map_or(0, |i| i + 1);
+        self.index = Some(i);
         self.sub_index = Some(0);
-        if self.index.unwrap() >= self.values.len() {
-            return false
+        if i < self.values.len() {
+            return true
         }
-        return true
+        let buckets = match self.spilled.as_ref() {
+            Some(files) => files.buckets(),
+            None => return false
+        };
+        // move on to the next bucket with results
+        let mut b = self.bucket.map_or(0, |b| b + 1);
+        while b < buckets {
+            if let Err(e) = self.load_bucket(b) {
+                self.err = Some(e);
+                return false
+            }
+            if !self.values.is_empty() {
+                self.index = Some(0);
+                return true
+            }
+            b += 1;
+        }
+        false
     }
 }
 
@@ -275,9 +405,9 @@ struct MaterializeContains {
 }
 
 impl MaterializeContains {
-    fn new(sub: Rc<RefCell<dyn Shape>>) -> Rc<RefCell<MaterializeContains>>  {
+    fn new(sub: Rc<RefCell<dyn Shape>>, limit: Option<MemoryLimit>, failures: LimitFailures) -> Rc<RefCell<MaterializeContains>>  {
         Rc::new(RefCell::new(MaterializeContains {
-            next: MaterializeNext::new(sub),
+            next: MaterializeNext::new(sub, limit, failures),
             sub: None
         }))
     }
@@ -364,7 +494,7 @@ impl Index for MaterializeContains {
         }
 
         let i = if let Some(k) = v.key() {
-            self.next.borrow_mut().contains_map.get(k).map(|x| x.clone())
+            self.next.borrow_mut().find(k)
         } else {
             None
         };
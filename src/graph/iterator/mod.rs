@@ -2,6 +2,7 @@ pub mod and;
 pub mod materialize;
 pub mod count;
 pub mod fixed;
+pub mod int64;
 pub mod limit;
 pub mod not;
 pub mod or;
@@ -13,15 +14,23 @@ pub mod sort;
 pub mod unique;
 pub mod value_filter;
 pub mod iterate;
+pub mod aggregate;
+pub mod shortest_path;
+pub mod all_paths;
+pub mod spill;
 
 use std::collections::HashMap;
 use super::refs;
+use super::quad::{QuadStore, Direction};
 use std::rc::Rc;
 use std::cell::RefCell;
 use std::fmt;
 
 use super::iterator::fixed::Fixed;
 use super::iterator::save::Save;
+use super::iterator::materialize::Materialize;
+use super::iterator::unique::Unique;
+use super::iterator::spill::{MemoryLimit, LimitFailures};
 
 #[derive(Clone)]
 pub struct Tags {
@@ -59,8 +68,46 @@ pub trait Base : fmt::Display {
 }
 
 
+// the most results a single next_batch call adds
+pub const BATCH_SIZE: usize = 256;
+
 pub trait Scanner : Base {
     fn next(&mut self) -> bool;
+
+    // Appends up to BATCH_SIZE further results to `out` and returns how many were added,
+    // 0 once the scanner is exhausted or has failed. Only results are batched: tags and
+    // alternate paths are not reported, scans that need them should call next instead.
+    fn next_batch(&mut self, out: &mut Vec<refs::Ref>) -> usize {
+        let mut n = 0;
+        while n < BATCH_SIZE && self.next() {
+            if let Some(r) = self.result() {
+                out.push(r);
+                n += 1;
+            }
+        }
+        n
+    }
+
+    // Moves to the first result whose id is at least `id`, staying on the current result
+    // when it already is, and returns whether there is one. Meant for scanners of shapes
+    // with a sort_order, the default steps through the results with next.
+    fn seek(&mut self, id: u64) -> bool {
+        if result_id(self.result().as_ref()).is_some_and(|cur| cur >= id) {
+            return true
+        }
+        while self.next() {
+            if result_id(self.result().as_ref()).is_some_and(|cur| cur >= id) {
+                return true
+            }
+        }
+        false
+    }
+}
+
+
+// the store id of a result, used to merge scanners with a sort_order
+pub fn result_id(r: Option<&refs::Ref>) -> Option<u64> {
+    r.and_then(|r| r.key()).and_then(|k| k.as_id())
 }
 
 
@@ -88,6 +135,7 @@ impl Costs {
 
 
 pub enum ShapeType<'a> {
+    AllPaths,
     And,
     Count,
     Error,
@@ -96,17 +144,18 @@ pub enum ShapeType<'a> {
     Int64,
     Limit,
     LinksTo,
-    Materialize,
+    Materialize(&'a mut Materialize),
     Not,
     Null,
     Or,
     Recursive,
     Resolver,
     Save(&'a mut Save),
+    ShortestPath,
     Skip,
     Sort,
     Test,
-    Unique,
+    Unique(&'a mut Unique),
     ValueFilter,
     MemStoreIterator
 }
@@ -129,6 +178,19 @@ pub trait Shape : fmt::Display {
     fn sub_iterators(&self) -> Option<Vec<Rc<RefCell<dyn Shape>>>>;
 
     fn shape_type(&mut self) -> ShapeType;
+
+    // The order the scanner returns results in, if any. Scanners of shapes that report an
+    // order must return ascending ids of that kind and support seek efficiently.
+    fn sort_order(&self) -> Option<SortOrder> {
+        None
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortOrder {
+    NodeIds,
+    QuadIds
 }
 
 
@@ -166,6 +228,66 @@ pub fn height(it: &mut dyn Shape, filter: fn(&mut dyn Shape) -> bool) -> i32 {
     return max_depth + 1;
 }
 
+// Sets the memory limit of every Unique and Materialize in the tree, the iterators that
+// hold the results they have seen. The first of them to fail at the limit records its
+// error in `failures`.
+pub fn set_memory_limit(it: &Rc<RefCell<dyn Shape>>, limit: Option<MemoryLimit>, failures: &LimitFailures) {
+    match it.borrow_mut().shape_type() {
+        ShapeType::Unique(u) => u.set_memory_limit(limit, failures.clone()),
+        ShapeType::Materialize(m) => m.set_memory_limit(limit, failures.clone()),
+        _ => {}
+    }
+    let subs = it.borrow().sub_iterators();
+    for sub in subs.unwrap_or_default() {
+        set_memory_limit(&sub, limit, failures);
+    }
+}
+
+// Returns the (predicate, node) pairs one quad away from `node`, following quads from
+// subject to object when `forward` is true and from object to subject otherwise.
+// `via` and `labels`, when given, restrict the predicates and labels of the quads followed.
+// An error of the quad iterator is returned rather than the hops read before it.
+pub fn neighbors(qs: &Rc<RefCell<dyn QuadStore>>, node: &refs::Ref, forward: bool, via: Option<&Rc<RefCell<dyn Index>>>, labels: Option<&Rc<RefCell<dyn Index>>>) -> Result<Vec<(refs::Ref, refs::Ref)>, String> {
+    let (dir, goal) = if forward { (Direction::Subject, Direction::Object) } else { (Direction::Object, Direction::Subject) };
+
+    let mut hops = Vec::new();
+
+    let quads = qs.borrow().quad_iterator(&dir, node).borrow().iterate();
+    let mut batch = Vec::new();
+    while quads.borrow_mut().next_batch(&mut batch) > 0 {}
+    let err = quads.borrow().err();
+    if let Some(e) = err {
+        let _ = quads.borrow_mut().close();
+        return Err(e)
+    }
+
+    for quad in batch {
+        let predicate = match qs.borrow().quad_direction(&quad, &Direction::Predicate) {
+            Some(p) => p,
+            None => continue
+        };
+        if let Some(via) = via {
+            if !via.borrow_mut().contains(&predicate) {
+                continue
+            }
+        }
+        if let Some(labels) = labels {
+            match qs.borrow().quad_direction(&quad, &Direction::Label) {
+                Some(l) => if !labels.borrow_mut().contains(&l) { continue },
+                None => continue
+            }
+        }
+
+        match qs.borrow().quad_direction(&quad, &goal) {
+            Some(r) if r.key().is_some() => hops.push((predicate, r)),
+            _ => continue
+        }
+    }
+    let _ = quads.borrow_mut().close();
+
+    Ok(hops)
+}
+
 #[derive(Debug, Clone)]
 pub struct Null {}
 
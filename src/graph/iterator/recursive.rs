@@ -99,11 +99,11 @@ struct RecursiveNext {
     err: Option<String>,
 
     morphism: Rc<dyn Morphism>,
-    seen: HashMap<Value, SeenAt>,
+    seen: HashMap<refs::Key, SeenAt>,
     next_it: Rc<RefCell<dyn Scanner>>,
     depth: i32,
     max_depth: i32,
-    path_map: HashMap<Value, Vec<HashMap<String, refs::Ref>>>,
+    path_map: HashMap<refs::Key, Vec<HashMap<String, refs::Ref>>>,
     path_index: usize,
     contains_value: Option<refs::Ref>,
     depth_tags: Vec<String>,
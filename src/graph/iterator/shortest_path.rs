@@ -0,0 +1,423 @@
+use super::{Shape, Base, Index, Scanner, Costs, ShapeType, neighbors};
+use super::super::refs;
+use super::super::quad::QuadStore;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::fmt;
+
+pub const DEFAULT_MAX_SHORTEST_PATH_DEPTH:i32 = 50;
+
+// ShortestPath finds the shortest route from any node of `from` to any node of `to`,
+// following quads whose predicate is in `via` (or any predicate if `via` is None).
+// It yields the nodes of that route in order, tagging each one with the predicate
+// that was followed to reach it. A reversed ShortestPath follows quads from object to
+// subject instead.
+pub struct ShortestPath {
+    qs: Rc<RefCell<dyn QuadStore>>,
+    from: Rc<RefCell<dyn Shape>>,
+    to: Rc<RefCell<dyn Shape>>,
+    via: Option<Rc<RefCell<dyn Shape>>>,
+    labels: Option<Rc<RefCell<dyn Shape>>>,
+    max_depth: i32,
+    reversed: bool,
+    tags: Vec<String>
+}
+
+impl ShortestPath {
+    pub fn new(qs: Rc<RefCell<dyn QuadStore>>, from: Rc<RefCell<dyn Shape>>, to: Rc<RefCell<dyn Shape>>, via: Option<Rc<RefCell<dyn Shape>>>, max_depth: i32) -> Rc<RefCell<ShortestPath>> {
+        Rc::new(RefCell::new(ShortestPath {
+            qs,
+            from,
+            to,
+            via,
+            labels: None,
+            max_depth: if max_depth <= 0 { DEFAULT_MAX_SHORTEST_PATH_DEPTH } else { max_depth },
+            reversed: false,
+            tags: Vec::new()
+        }))
+    }
+
+    pub fn set_labels(&mut self, labels: Rc<RefCell<dyn Shape>>) {
+        self.labels = Some(labels);
+    }
+
+    pub fn set_reversed(&mut self, reversed: bool) {
+        self.reversed = reversed;
+    }
+
+    pub fn add_tag(&mut self, s: String) {
+        self.tags.push(s);
+    }
+
+    fn new_search(&self) -> Search {
+        Search {
+            qs: self.qs.clone(),
+            from: self.from.borrow().iterate(),
+            to: self.to.borrow().iterate(),
+            via: self.via.as_ref().map(|v| v.borrow().lookup()),
+            labels: self.labels.as_ref().map(|l| l.borrow().lookup()),
+            max_depth: self.max_depth,
+            reversed: self.reversed
+        }
+    }
+}
+
+
+impl fmt::Display for ShortestPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ShortestPath({})", self.max_depth)
+    }
+}
+
+
+impl Shape for ShortestPath {
+    fn iterate(&self) -> Rc<RefCell<dyn Scanner>> {
+        ShortestPathNext::new(self.new_search(), self.tags.clone())
+    }
+
+    fn lookup(&self) -> Rc<RefCell<dyn Index>> {
+        ShortestPathContains::new(ShortestPathNext::new(self.new_search(), self.tags.clone()))
+    }
+
+    fn stats(&mut self) -> Result<Costs, String> {
+        let from_stats = self.from.borrow_mut().stats()?;
+        let to_stats = self.to.borrow_mut().stats()?;
+        let search_cost = (from_stats.size.value + to_stats.size.value) * self.max_depth as i64;
+        Ok(Costs {
+            next_cost: from_stats.next_cost + to_stats.next_cost + search_cost,
+            contains_cost: from_stats.next_cost + to_stats.next_cost + search_cost,
+            size: refs::Size {
+                value: self.max_depth as i64 + 1,
+                exact: false
+            }
+        })
+    }
+
+    fn optimize(&mut self) -> Option<Rc<RefCell<dyn Shape>>> {
+        let new_from = self.from.borrow_mut().optimize();
+        if let Some(f) = new_from {
+            self.from = f;
+        }
+        let new_to = self.to.borrow_mut().optimize();
+        if let Some(t) = new_to {
+            self.to = t;
+        }
+        None
+    }
+
+    fn sub_iterators(&self) -> Option<Vec<Rc<RefCell<dyn Shape>>>> {
+        Some(vec![self.from.clone(), self.to.clone()])
+    }
+
+    fn shape_type(&mut self) -> ShapeType<'_> {
+        ShapeType::ShortestPath
+    }
+}
+
+
+// a single hop of a found path: the node reached and the predicate followed to reach it
+#[derive(Clone)]
+struct Step {
+    node: refs::Ref,
+    predicate: Option<refs::Ref>
+}
+
+// a node discovered by one side of the search, linked to the node it was reached from
+struct Visit {
+    node: refs::Ref,
+    depth: i32,
+    link: Option<(refs::Key, refs::Ref)>
+}
+
+// the steps of the found route and the tags of the node it starts from
+type Route = (Vec<Step>, HashMap<String, refs::Ref>);
+
+struct Search {
+    qs: Rc<RefCell<dyn QuadStore>>,
+    from: Rc<RefCell<dyn Scanner>>,
+    to: Rc<RefCell<dyn Scanner>>,
+    via: Option<Rc<RefCell<dyn Index>>>,
+    labels: Option<Rc<RefCell<dyn Index>>>,
+    max_depth: i32,
+    reversed: bool
+}
+
+impl Search {
+
+    // bidirectional breadth first search, expanding the smaller frontier at each step
+    fn run(&mut self) -> Result<Option<Route>, String> {
+        let mut forward: HashMap<refs::Key, Visit> = HashMap::new();
+        let mut backward: HashMap<refs::Key, Visit> = HashMap::new();
+        let mut start_tags: HashMap<refs::Key, HashMap<String, refs::Ref>> = HashMap::new();
+
+        let mut forward_frontier = Vec::new();
+        while self.from.borrow_mut().next() {
+            let node = self.from.borrow().result().unwrap();
+            let key = match node.key() { Some(k) => k.clone(), None => continue };
+            let mut tags = HashMap::new();
+            self.from.borrow().tag_results(&mut tags);
+            start_tags.entry(key.clone()).or_insert(tags);
+            if !forward.contains_key(&key) {
+                forward.insert(key.clone(), Visit { node: node.clone(), depth: 0, link: None });
+                forward_frontier.push(key);
+            }
+        }
+        if let Some(e) = self.from.borrow().err() {
+            return Err(e)
+        }
+
+        let mut backward_frontier = Vec::new();
+        while self.to.borrow_mut().next() {
+            let node = self.to.borrow().result().unwrap();
+            let key = match node.key() { Some(k) => k.clone(), None => continue };
+            if !backward.contains_key(&key) {
+                backward.insert(key.clone(), Visit { node: node.clone(), depth: 0, link: None });
+                backward_frontier.push(key);
+            }
+        }
+        if let Some(e) = self.to.borrow().err() {
+            return Err(e)
+        }
+
+        let mut meet = forward_frontier.iter().find(|k| backward.contains_key(k)).cloned();
+
+        let mut depth = 0;
+        while meet.is_none() && depth < self.max_depth && !forward_frontier.is_empty() && !backward_frontier.is_empty() {
+            if forward_frontier.len() <= backward_frontier.len() {
+                let (frontier, found) = self.expand(&forward_frontier, &mut forward, &backward, true)?;
+                forward_frontier = frontier;
+                meet = found;
+            } else {
+                let (frontier, found) = self.expand(&backward_frontier, &mut backward, &forward, false)?;
+                backward_frontier = frontier;
+                meet = found;
+            }
+            depth += 1;
+        }
+
+        let meet = match meet { Some(m) => m, None => return Ok(None) };
+
+        // walk back to the start node, then forward to the end node
+        let mut steps = Vec::new();
+        let mut cur = meet.clone();
+        loop {
+            let visit = &forward[&cur];
+            steps.push(Step {
+                node: visit.node.clone(),
+                predicate: visit.link.as_ref().map(|(_, p)| p.clone())
+            });
+            match &visit.link {
+                Some((prev, _)) => cur = prev.clone(),
+                None => break
+            }
+        }
+        steps.reverse();
+
+        let mut cur = meet;
+        while let Some((next, predicate)) = &backward[&cur].link {
+            steps.push(Step {
+                node: backward[next].node.clone(),
+                predicate: Some(predicate.clone())
+            });
+            cur = next.clone();
+        }
+
+        let tags = start_tags.remove(steps[0].node.key().unwrap()).unwrap_or_default();
+
+        Ok(Some((steps, tags)))
+    }
+
+    // expands every node of one frontier by a single hop, returning the next frontier
+    // and the meeting node of the shortest complete route found at this depth, if any
+    fn expand(&self, frontier: &[refs::Key], visited: &mut HashMap<refs::Key, Visit>, other: &HashMap<refs::Key, Visit>, forward: bool) -> Result<(Vec<refs::Key>, Option<refs::Key>), String> {
+        let mut next_frontier = Vec::new();
+        let mut best: Option<(i32, refs::Key)> = None;
+
+        for key in frontier {
+            let node = visited[key].node.clone();
+            let depth = visited[key].depth;
+
+            for (predicate, reached) in neighbors(&self.qs, &node, forward != self.reversed, self.via.as_ref(), self.labels.as_ref())? {
+                let reached_key = reached.key().unwrap().clone();
+                if visited.contains_key(&reached_key) {
+                    continue
+                }
+
+                if let Some(o) = other.get(&reached_key) {
+                    let length = depth + 1 + o.depth;
+                    if best.as_ref().is_none_or(|(l, _)| length < *l) {
+                        best = Some((length, reached_key.clone()));
+                    }
+                }
+
+                visited.insert(reached_key.clone(), Visit {
+                    node: reached,
+                    depth: depth + 1,
+                    link: Some((key.clone(), predicate))
+                });
+                next_frontier.push(reached_key);
+            }
+        }
+
+        Ok((next_frontier, best.map(|(_, k)| k)))
+    }
+
+    fn close(&mut self) -> Result<(), String> {
+        let res = self.from.borrow_mut().close();
+        let res2 = self.to.borrow_mut().close();
+        res.and(res2)
+    }
+}
+
+
+
+struct ShortestPathNext {
+    search: Search,
+    tags: Vec<String>,
+    steps: Option<Vec<Step>>,
+    start_tags: HashMap<String, refs::Ref>,
+    index: usize,
+    result: Option<Step>,
+    err: Option<String>
+}
+
+impl ShortestPathNext {
+    fn new(search: Search, tags: Vec<String>) -> Rc<RefCell<ShortestPathNext>> {
+        Rc::new(RefCell::new(ShortestPathNext {
+            search,
+            tags,
+            steps: None,
+            start_tags: HashMap::new(),
+            index: 0,
+            result: None,
+            err: None
+        }))
+    }
+
+    fn steps(&mut self) -> &Vec<Step> {
+        if self.steps.is_none() {
+            match self.search.run() {
+                Ok(Some((steps, tags))) => {
+                    self.steps = Some(steps);
+                    self.start_tags = tags;
+                },
+                Ok(None) => self.steps = Some(Vec::new()),
+                Err(e) => {
+                    self.err = Some(e);
+                    self.steps = Some(Vec::new());
+                }
+            }
+        }
+        self.steps.as_ref().unwrap()
+    }
+}
+
+impl fmt::Display for ShortestPathNext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ShortestPathNext")
+    }
+}
+
+impl Base for ShortestPathNext {
+
+    fn tag_results(&self, tags: &mut HashMap<String, refs::Ref>) {
+        for (k, v) in &self.start_tags {
+            tags.insert(k.clone(), v.clone());
+        }
+        if let Some(Step { predicate: Some(p), .. }) = &self.result {
+            for tag in &self.tags {
+                tags.insert(tag.clone(), p.clone());
+            }
+        }
+    }
+
+    fn result(&self) -> Option<refs::Ref> {
+        self.result.as_ref().map(|s| s.node.clone())
+    }
+
+    fn next_path(&mut self) -> bool {
+        false
+    }
+
+    fn err(&self) -> Option<String> {
+        self.err.clone()
+    }
+
+    fn close(&mut self) -> Result<(), String> {
+        self.steps = None;
+        self.search.close()
+    }
+}
+
+impl Scanner for ShortestPathNext {
+    fn next(&mut self) -> bool {
+        let index = self.index;
+        let step = self.steps().get(index).cloned();
+        if step.is_none() {
+            self.result = None;
+            return false
+        }
+        self.result = step;
+        self.index += 1;
+        true
+    }
+}
+
+
+
+struct ShortestPathContains {
+    next: Rc<RefCell<ShortestPathNext>>,
+}
+
+impl ShortestPathContains {
+    fn new(next: Rc<RefCell<ShortestPathNext>>) -> Rc<RefCell<ShortestPathContains>> {
+        Rc::new(RefCell::new(ShortestPathContains {
+            next
+        }))
+    }
+}
+
+impl fmt::Display for ShortestPathContains {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ShortestPathContains({})", self.next.borrow())
+    }
+}
+
+impl Base for ShortestPathContains {
+
+    fn tag_results(&self, tags: &mut HashMap<String, refs::Ref>) {
+        self.next.borrow().tag_results(tags)
+    }
+
+    fn result(&self) -> Option<refs::Ref> {
+        self.next.borrow().result()
+    }
+
+    fn next_path(&mut self) -> bool {
+        false
+    }
+
+    fn err(&self) -> Option<String> {
+        self.next.borrow().err()
+    }
+
+    fn close(&mut self) -> Result<(), String> {
+        self.next.borrow_mut().close()
+    }
+}
+
+impl Index for ShortestPathContains {
+    fn contains(&mut self, val:&refs::Ref) -> bool {
+        let key = match val.key() { Some(k) => k.clone(), None => return false };
+
+        let mut next = self.next.borrow_mut();
+        let step = next.steps().iter().find(|s| s.node.key() == Some(&key)).cloned();
+        if step.is_some() {
+            next.result = step;
+            return true
+        }
+        next.result = None;
+        false
+    }
+}
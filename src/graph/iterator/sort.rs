@@ -1,23 +1,65 @@
 use super::{Shape, ShapeType, Base, Index, Scanner, Costs};
 use super::materialize::MaterializeResult;
 use super::super::refs;
+use super::super::value::Value;
 use super::super::quad::QuadStore;
-use std::collections::HashMap;
+use std::collections::{HashMap, BinaryHeap};
 use std::rc::Rc;
 use std::cell::RefCell;
 use std::cmp::Ordering;
 use std::fmt;
 
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Order {
+    Asc,
+    Desc
+}
+
+// SortKey orders results by the value saved under `tag`, or by the result itself when
+// `tag` is None. Results missing the tag sort as `Value::None`, so first when ascending.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SortKey {
+    pub tag: Option<String>,
+    pub order: Order
+}
+
+impl SortKey {
+    pub fn node(order: Order) -> SortKey {
+        SortKey {
+            tag: None,
+            order
+        }
+    }
+
+    pub fn tag<S: Into<String>>(tag: S, order: Order) -> SortKey {
+        SortKey {
+            tag: Some(tag.into()),
+            order
+        }
+    }
+}
+
 pub struct Sort {
     qs: Rc<RefCell<dyn QuadStore>>,
     sub_it: Rc<RefCell<dyn Shape>>,
+    keys: Rc<Vec<SortKey>>,
+    top: Option<usize>
 }
 
 impl Sort {
     pub fn new(qs: Rc<RefCell<dyn QuadStore>>, sub_it: Rc<RefCell<dyn Shape>>) -> Rc<RefCell<Sort>> {
+        Sort::new_by(qs, sub_it, vec![SortKey::node(Order::Asc)], None)
+    }
+
+    // Sorts by each key in turn, results that compare equal keep the order of `sub_it`.
+    // With `top` only that many of the first results are kept while sorting, so paging
+    // through a large result costs a bounded heap instead of the whole set.
+    pub fn new_by(qs: Rc<RefCell<dyn QuadStore>>, sub_it: Rc<RefCell<dyn Shape>>, keys: Vec<SortKey>, top: Option<usize>) -> Rc<RefCell<Sort>> {
         Rc::new(RefCell::new(Sort {
             qs,
-            sub_it
+            sub_it,
+            keys: Rc::new(keys),
+            top
         }))
     }
 }
@@ -32,7 +74,7 @@ impl fmt::Display for Sort {
 
 impl Shape for Sort {
     fn iterate(&self) -> Rc<RefCell<dyn Scanner>> {
-        SortNext::new(self.qs.clone(), self.sub_it.borrow().iterate())
+        SortNext::new(self.qs.clone(), self.sub_it.borrow().iterate(), self.keys.clone(), self.top)
     }
 
     fn lookup(&self) -> Rc<RefCell<dyn Index>> {
@@ -71,13 +113,22 @@ impl Shape for Sort {
 
 struct SortValue  {
     result: MaterializeResult,
-    string: String,
+    key: Vec<Value>,
+    keys: Rc<Vec<SortKey>>,
+    seq: usize,
     paths: Vec<MaterializeResult>
 }
 
 impl Ord for SortValue {
     fn cmp(&self, other: &Self) -> Ordering {
-        self.string.cmp(&other.string)
+        for (i, k) in self.keys.iter().enumerate() {
+            let o = self.key[i].compare(&other.key[i]);
+            let o = if k.order == Order::Desc { o.reverse() } else { o };
+            if o != Ordering::Equal {
+                return o
+            }
+        }
+        self.seq.cmp(&other.seq)
     }
 }
 
@@ -89,7 +140,7 @@ impl PartialOrd for SortValue {
 
 impl PartialEq for SortValue {
     fn eq(&self, other: &Self) -> bool {
-        self.string == other.string
+        self.cmp(other) == Ordering::Equal
     }
 }
 
@@ -100,6 +151,8 @@ impl Eq for SortValue {}
 struct SortNext {
     qs: Rc<RefCell<dyn QuadStore>>,
     sub_it: Rc<RefCell<dyn Scanner>>,
+    keys: Rc<Vec<SortKey>>,
+    top: Option<usize>,
     ordered: Option<Vec<SortValue>>,
     result: Option<MaterializeResult>,
     err: Option<String>,
@@ -108,10 +161,12 @@ struct SortNext {
 }
 
 impl SortNext {
-    fn new(qs: Rc<RefCell<dyn QuadStore>>, sub_it: Rc<RefCell<dyn Scanner>>) -> Rc<RefCell<SortNext>> {
+    fn new(qs: Rc<RefCell<dyn QuadStore>>, sub_it: Rc<RefCell<dyn Scanner>>, keys: Rc<Vec<SortKey>>, top: Option<usize>) -> Rc<RefCell<SortNext>> {
        Rc::new(RefCell::new(SortNext {
            qs,
            sub_it,
+           keys,
+           top,
            ordered: None,
            result: None,
            err: None,
@@ -139,12 +194,12 @@ impl Base for SortNext {
         return if let Some(r) = &self.result { Some(r.id.clone()) } else { None }
     }
 
-    #[allow(unused)]
     fn next_path(&mut self) -> bool {
-        if self.index >= self.ordered.as_ref().unwrap().len() {
-            return false
-        }
-        let r = self.ordered.as_ref().unwrap().get(self.index).unwrap();
+        // `index` has already moved past the current result
+        let r = match self.ordered.as_ref().and_then(|o| o.get(self.index.wrapping_sub(1))) {
+            Some(r) => r,
+            None => return false
+        };
         if (self.path_index+1) >= r.paths.len() as i32 {
             return false
         }
@@ -170,7 +225,7 @@ impl Scanner for SortNext {
         }
 
         if self.ordered.is_none() {
-            let v = get_sorted_values(&self.qs, &self.sub_it);
+            let v = get_sorted_values(&self.qs, &self.sub_it, &self.keys, self.top);
             if let Err(e) = v {
                 self.err = Some(e);
                 return false
@@ -192,38 +247,62 @@ impl Scanner for SortNext {
     }
 }
 
-fn get_sorted_values(qs: &Rc<RefCell<dyn QuadStore>>, it: &Rc<RefCell<dyn Scanner>>) -> Result<Vec<SortValue>, String> {
+fn get_sorted_values(qs: &Rc<RefCell<dyn QuadStore>>, it: &Rc<RefCell<dyn Scanner>>, keys: &Rc<Vec<SortKey>>, top: Option<usize>) -> Result<Vec<SortValue>, String> {
     let mut v:Vec<SortValue> = Vec::new();
+    let mut heap:BinaryHeap<SortValue> = BinaryHeap::new();
+    let mut seq = 0;
+
+    if top == Some(0) {
+        return Ok(v)
+    }
 
     while it.borrow_mut().next() {
         let id = it.borrow().result().unwrap();
-        let name = qs.borrow().name_of(&id).unwrap();
-        let string = name.to_string();
         let mut tags = HashMap::new();
         it.borrow().tag_results(&mut tags);
+        let key = keys.iter().map(|k| {
+            let r = match &k.tag { Some(t) => tags.get(t), None => Some(&id) };
+            r.and_then(|r| qs.borrow().name_of(r)).unwrap_or(Value::None)
+        }).collect();
         let mut val = SortValue {
             result: MaterializeResult {
                 id: id.clone(),
                 tags
             },
-            string,
+            key,
+            keys: keys.clone(),
+            seq,
             paths: Vec::new()
         };
+        seq += 1;
         while it.borrow_mut().next_path() {
-            tags = HashMap::new();
+            let mut tags = HashMap::new();
             it.borrow().tag_results(&mut tags);
             val.paths.push(MaterializeResult {
                 id: id.clone(),
                 tags
             });
         }
-        v.push(val);
+        match top {
+            Some(k) => {
+                // the heap keeps the k smallest values seen so far, largest on top
+                heap.push(val);
+                if heap.len() > k {
+                    heap.pop();
+                }
+            },
+            None => v.push(val)
+        }
     }
 
     if it.borrow().err().is_some() {
         return Err(it.borrow().err().unwrap());
     }
 
+    if top.is_some() {
+        return Ok(heap.into_sorted_vec())
+    }
+
     v.sort();
-    return Ok(v)
-} 
\ No newline at end of file
+    Ok(v)
+}
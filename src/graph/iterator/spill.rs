@@ -0,0 +1,332 @@
+use super::super::refs::{Ref, Key, Content};
+use super::super::value::Value;
+use super::super::quad::Quad;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::collections::HashSet;
+use std::collections::hash_map::DefaultHasher;
+use std::fs::{self, File, OpenOptions};
+use std::hash::{Hash, Hasher};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::mem::size_of;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::fmt;
+
+// What an iterator does once the values it holds reach its memory limit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OnLimit {
+    // move the values to temporary files on local disk and keep going
+    Spill,
+    // stop with a MemoryLimitError
+    Fail
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoryLimit {
+    pub bytes: usize,
+    pub on_limit: OnLimit
+}
+
+impl MemoryLimit {
+    pub fn spill(bytes: usize) -> MemoryLimit {
+        MemoryLimit {
+            bytes,
+            on_limit: OnLimit::Spill
+        }
+    }
+
+    pub fn fail(bytes: usize) -> MemoryLimit {
+        MemoryLimit {
+            bytes,
+            on_limit: OnLimit::Fail
+        }
+    }
+}
+
+
+// The error of an iterator that reached a memory limit set to OnLimit::Fail. The iterator
+// reports it through `err` as text, and records it in its LimitFailures as it is.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryLimitError {
+    pub iterator: String,
+    pub limit: usize
+}
+
+const MEMORY_LIMIT_ERROR: &str = "memory limit exceeded:";
+
+impl MemoryLimitError {
+    pub fn new<S: Into<String>>(iterator: S, limit: usize) -> MemoryLimitError {
+        MemoryLimitError {
+            iterator: iterator.into(),
+            limit
+        }
+    }
+}
+
+impl fmt::Display for MemoryLimitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} held more than {} bytes", MEMORY_LIMIT_ERROR, self.iterator, self.limit)
+    }
+}
+
+impl std::error::Error for MemoryLimitError {}
+
+
+// LimitFailures is shared by the iterators of a query, the first MemoryLimitError one of
+// them fails with is kept here for the query to report.
+#[derive(Debug, Clone, Default)]
+pub struct LimitFailures(Rc<RefCell<Option<MemoryLimitError>>>);
+
+impl LimitFailures {
+    pub fn new() -> LimitFailures {
+        LimitFailures::default()
+    }
+
+    pub fn record(&self, e: MemoryLimitError) {
+        let mut first = self.0.borrow_mut();
+        if first.is_none() {
+            *first = Some(e);
+        }
+    }
+
+    // the error recorded, which is cleared for the next query
+    pub fn take(&self) -> Option<MemoryLimitError> {
+        self.0.borrow_mut().take()
+    }
+}
+
+
+// rough heap and inline size of values, used to account for what an iterator holds
+pub fn value_size(v: &Value) -> usize {
+    size_of::<Value>() + match v {
+        Value::String(s) | Value::IRI(s) => s.len(),
+        _ => 0
+    }
+}
+
+pub fn key_size(k: &Key) -> usize {
+    match k {
+        Key::Value(v) => value_size(v),
+        _ => size_of::<Key>()
+    }
+}
+
+pub fn ref_size(r: &Ref) -> usize {
+    key_size(&r.k) + match &r.content {
+        Content::None => size_of::<Content>(),
+        Content::Value(v) => value_size(v),
+        Content::Quad(q) => quad_size(q)
+    }
+}
+
+fn quad_size(q: &Quad) -> usize {
+    value_size(&q.subject) + value_size(&q.predicate) + value_size(&q.object) + value_size(&q.label)
+}
+
+
+pub fn hash_key(k: &Key) -> u64 {
+    let mut h = DefaultHasher::new();
+    k.hash(&mut h);
+    h.finish()
+}
+
+
+const SPILL_BUCKETS: usize = 64;
+
+static SPILL_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+// SpillFiles appends records to one of SPILL_BUCKETS files in a temporary directory, chosen
+// by the hash of their key, so the records for a key are read back without reading the
+// others. The directory is removed when it is dropped.
+pub struct SpillFiles {
+    dir: PathBuf,
+    writers: Vec<Option<BufWriter<File>>>
+}
+
+impl SpillFiles {
+    pub fn new() -> Result<SpillFiles, String> {
+        let n = SPILL_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let dir = std::env::temp_dir().join(format!("gizmo-spill-{}-{}", std::process::id(), n));
+        fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        Ok(SpillFiles {
+            dir,
+            writers: (0..SPILL_BUCKETS).map(|_| None).collect()
+        })
+    }
+
+    pub fn buckets(&self) -> usize {
+        SPILL_BUCKETS
+    }
+
+    pub fn bucket_of(&self, k: &Key) -> usize {
+        (hash_key(k) % SPILL_BUCKETS as u64) as usize
+    }
+
+    fn path(&self, bucket: usize) -> PathBuf {
+        self.dir.join(format!("{}.jsonl", bucket))
+    }
+
+    pub fn push<T: Serialize>(&mut self, bucket: usize, record: &T) -> Result<(), String> {
+        if self.writers[bucket].is_none() {
+            let f = OpenOptions::new().create(true).append(true).open(self.path(bucket)).map_err(|e| e.to_string())?;
+            self.writers[bucket] = Some(BufWriter::new(f));
+        }
+        let w = self.writers[bucket].as_mut().unwrap();
+        serde_json::to_writer(&mut *w, record).map_err(|e| e.to_string())?;
+        w.write_all(b"\n").map_err(|e| e.to_string())
+    }
+
+    // every record written to the bucket so far, in the order they were pushed
+    pub fn read<T: DeserializeOwned>(&mut self, bucket: usize) -> Result<Vec<T>, String> {
+        match self.writers[bucket].as_mut() {
+            Some(w) => w.flush().map_err(|e| e.to_string())?,
+            None => return Ok(Vec::new())
+        }
+        let f = File::open(self.path(bucket)).map_err(|e| e.to_string())?;
+        BufReader::new(f).lines().map(|line| {
+            let line = line.map_err(|e| e.to_string())?;
+            serde_json::from_str(&line).map_err(|e| e.to_string())
+        }).collect()
+    }
+}
+
+impl Drop for SpillFiles {
+    fn drop(&mut self) {
+        self.writers.clear();
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+
+// a bloom filter over key hashes, so keys never spilled are not looked for on disk
+struct Bloom {
+    bits: Vec<u64>
+}
+
+impl Bloom {
+    fn new(bytes: usize) -> Bloom {
+        Bloom {
+            bits: vec![0; (bytes / 8).max(1024)]
+        }
+    }
+
+    fn positions(&self, h: u64) -> [usize; 3] {
+        let n = (self.bits.len() * 64) as u64;
+        let h2 = h.rotate_left(32) | 1;
+        [
+            (h % n) as usize,
+            (h.wrapping_add(h2) % n) as usize,
+            (h.wrapping_add(h2.wrapping_mul(2)) % n) as usize
+        ]
+    }
+
+    fn insert(&mut self, h: u64) {
+        for p in self.positions(h) {
+            self.bits[p / 64] |= 1 << (p % 64);
+        }
+    }
+
+    fn may_contain(&self, h: u64) -> bool {
+        self.positions(h).iter().all(|p| self.bits[p / 64] & (1 << (p % 64)) != 0)
+    }
+}
+
+
+struct SpilledKeys {
+    files: SpillFiles,
+    bloom: Bloom,
+    // the last bucket read back from disk
+    bucket: Option<(usize, HashSet<Key>)>
+}
+
+// KeySet remembers the keys it is given within a memory limit. Past the limit it either
+// fails or moves the keys it holds to disk, reading a bucket back only when the bloom
+// filter can't rule a key out.
+pub struct KeySet {
+    name: &'static str,
+    limit: Option<MemoryLimit>,
+    failures: LimitFailures,
+    keys: HashSet<Key>,
+    bytes: usize,
+    spilled: Option<SpilledKeys>
+}
+
+impl KeySet {
+    pub fn new(name: &'static str, limit: Option<MemoryLimit>, failures: LimitFailures) -> KeySet {
+        KeySet {
+            name,
+            limit,
+            failures,
+            keys: HashSet::new(),
+            bytes: 0,
+            spilled: None
+        }
+    }
+
+    pub fn has_spilled(&self) -> bool {
+        self.spilled.is_some()
+    }
+
+    pub fn contains(&mut self, k: &Key) -> Result<bool, String> {
+        if self.keys.contains(k) {
+            return Ok(true)
+        }
+        let spilled = match self.spilled.as_mut() {
+            Some(s) => s,
+            None => return Ok(false)
+        };
+        if !spilled.bloom.may_contain(hash_key(k)) {
+            return Ok(false)
+        }
+        let b = spilled.files.bucket_of(k);
+        if spilled.bucket.as_ref().is_none_or(|(cur, _)| *cur != b) {
+            let keys: Vec<Key> = spilled.files.read(b)?;
+            spilled.bucket = Some((b, keys.into_iter().collect()));
+        }
+        Ok(spilled.bucket.as_ref().unwrap().1.contains(k))
+    }
+
+    // adds the key, returning false when it was already in the set
+    pub fn insert(&mut self, k: &Key) -> Result<bool, String> {
+        if self.contains(k)? {
+            return Ok(false)
+        }
+        self.bytes += key_size(k);
+        self.keys.insert(k.clone());
+
+        if let Some(limit) = self.limit {
+            if self.bytes > limit.bytes {
+                match limit.on_limit {
+                    OnLimit::Fail => {
+                        let e = MemoryLimitError::new(self.name, limit.bytes);
+                        self.failures.record(e.clone());
+                        return Err(e.to_string())
+                    },
+                    OnLimit::Spill => self.spill(limit.bytes)?
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    fn spill(&mut self, limit: usize) -> Result<(), String> {
+        if self.spilled.is_none() {
+            self.spilled = Some(SpilledKeys {
+                files: SpillFiles::new()?,
+                bloom: Bloom::new(limit / 4),
+                bucket: None
+            });
+        }
+        let spilled = self.spilled.as_mut().unwrap();
+        for k in self.keys.drain() {
+            spilled.files.push(spilled.files.bucket_of(&k), &k)?;
+            spilled.bloom.insert(hash_key(&k));
+        }
+        spilled.bucket = None;
+        self.bytes = 0;
+        Ok(())
+    }
+}
@@ -1,7 +1,6 @@
 use super::{Shape, ShapeType, Base, Index, Scanner, Costs};
+use super::spill::{KeySet, MemoryLimit, LimitFailures};
 use super::super::refs;
-use super::super::value::{Value};
-use std::collections::HashSet;
 use std::collections::HashMap;
 use std::rc::Rc;
 use std::cell::RefCell;
@@ -9,14 +8,25 @@ use std::fmt;
 
 pub struct Unique {
     sub_it: Rc<RefCell<dyn Shape>>,
+    limit: Option<MemoryLimit>,
+    failures: LimitFailures
 }
 
 impl Unique {
     pub fn new(sub_it: Rc<RefCell<dyn Shape>>,) -> Rc<RefCell<Unique>> {
         Rc::new(RefCell::new( Unique {
-            sub_it
+            sub_it,
+            limit: None,
+            failures: LimitFailures::new()
         }))
     }
+
+    // bounds the memory used to remember the results already returned, a failure at the
+    // limit is recorded in `failures`
+    pub fn set_memory_limit(&mut self, limit: Option<MemoryLimit>, failures: LimitFailures) {
+        self.limit = limit;
+        self.failures = failures;
+    }
 }
 
 impl fmt::Display for Unique {
@@ -30,7 +40,7 @@ const UNIQUENESS_FACTOR:i64 = 2;
 impl Shape for Unique {
 
     fn iterate(&self) -> Rc<RefCell<dyn Scanner>> {
-        UniqueNext::new(self.sub_it.borrow().iterate())
+        UniqueNext::new(self.sub_it.borrow().iterate(), self.limit, self.failures.clone())
     }
 
     fn lookup(&self) -> Rc<RefCell<dyn Index>> {
@@ -62,7 +72,7 @@ impl Shape for Unique {
     }
 
     fn shape_type(&mut self) -> ShapeType {
-        ShapeType::Unique
+        ShapeType::Unique(self)
     }
 }
 
@@ -72,16 +82,20 @@ struct UniqueNext {
     sub_it: Rc<RefCell<dyn Scanner>>,
     result: Option<refs::Ref>,
     err: Option<String>,
-    seen: HashSet<Value>
+    limit: Option<MemoryLimit>,
+    failures: LimitFailures,
+    seen: KeySet
 }
 
 impl UniqueNext {
-    fn new(sub_it: Rc<RefCell<dyn Scanner>>) -> Rc<RefCell<UniqueNext>> {
+    fn new(sub_it: Rc<RefCell<dyn Scanner>>, limit: Option<MemoryLimit>, failures: LimitFailures) -> Rc<RefCell<UniqueNext>> {
        Rc::new(RefCell::new(UniqueNext {
            sub_it,
            result: None,
            err: None,
-           seen: HashSet::new()
+           limit,
+           seen: KeySet::new("Unique", limit, failures.clone()),
+           failures
        }))
     }
 }
@@ -112,20 +126,32 @@ impl Base for UniqueNext {
     }
 
     fn close(&mut self) -> Result<(), String> {
-        self.seen = HashSet::new();
+        self.seen = KeySet::new("Unique", self.limit, self.failures.clone());
         self.sub_it.borrow_mut().close()
     }
 }
 
 impl Scanner for UniqueNext {
     fn next(&mut self) -> bool {
+        if self.err.is_some() {
+            return false
+        }
         while self.sub_it.borrow_mut().next() {
             let curr = self.sub_it.borrow().result();
-            let key = curr.as_ref().unwrap().key().clone();
-            if key.is_some() && !self.seen.contains(key.unwrap()) {
-                self.result = curr.clone();
-                self.seen.insert(key.unwrap().clone());
-                return true
+            let key = curr.as_ref().unwrap().key();
+            if let Some(k) = key {
+                match self.seen.insert(k) {
+                    Ok(true) => {
+                        self.result = curr.clone();
+                        return true
+                    },
+                    Ok(false) => {},
+                    Err(e) => {
+                        self.result = None;
+                        self.err = Some(e);
+                        return false
+                    }
+                }
             }
         }
         self.err = self.sub_it.borrow().err();
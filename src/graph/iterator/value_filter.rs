@@ -52,7 +52,7 @@ impl ValueFilterFunction for RegexValueFilter {
 
 
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Operator {
     LT,
     LTE,
@@ -1,10 +1,10 @@
 
-use super::refs::{Ref, Size};
+use super::refs::{Ref, Content, Size};
 use super::quad::{Direction, QuadStore};
-use super::iterator::{Shape, Scanner, Costs, Index, Base, ShapeType, Null, is_null};
+use super::iterator::{Shape, Scanner, Costs, Index, Base, ShapeType, Null, is_null, BATCH_SIZE};
 use std::rc::Rc;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::fmt;
 
 pub struct LinksTo {
@@ -125,6 +125,8 @@ struct LinksToNext {
     primary: Rc<RefCell<dyn Scanner>>,
     dir: Direction,
     next_it: Rc<RefCell<dyn Scanner>>,
+    // results of next_it read by next_batch that didn't fit in the batch
+    pending: VecDeque<Ref>,
     result: Option<Ref>,
     err: Option<String>
 }
@@ -137,6 +139,7 @@ impl LinksToNext {
             primary,
             dir,
             next_it: Null::new().borrow().iterate(),
+            pending: VecDeque::new(),
             result: None,
             err: None
         }))
@@ -145,6 +148,14 @@ impl LinksToNext {
     fn direction(&self) -> Direction {
         return self.dir.clone()
     }
+
+    // the quads linking to the current node of the primary, looked up by its key alone
+    fn links_to_primary(&self) -> Rc<RefCell<dyn Scanner>> {
+        match self.primary.borrow().result().and_then(|r| r.key().cloned()) {
+            Some(k) => self.qs.borrow().quad_iterator(&self.dir, &Ref { k, content: Content::None }).borrow().iterate(),
+            None => Null::new().borrow().iterate()
+        }
+    }
 }
 
 impl fmt::Display for LinksToNext {
@@ -188,6 +199,10 @@ impl Base for LinksToNext {
 
 impl Scanner for LinksToNext {
     fn next(&mut self) -> bool {
+        if let Some(r) = self.pending.pop_front() {
+            self.result = Some(r);
+            return true
+        }
         loop {
             if self.next_it.borrow_mut().next() {
                 self.result = self.next_it.borrow().result();
@@ -205,8 +220,43 @@ impl Scanner for LinksToNext {
             }
 
             let _ = self.next_it.borrow_mut().close();
-            self.next_it = self.qs.borrow().quad_iterator(&self.dir, self.primary.borrow().result().as_ref().unwrap()).borrow().iterate();
+            self.next_it = self.links_to_primary();
+        }
+    }
+
+    fn next_batch(&mut self, out: &mut Vec<Ref>) -> usize {
+        let mut n = 0;
+        let mut batch = Vec::new();
+        while n < BATCH_SIZE {
+            if !self.pending.is_empty() {
+                let take = self.pending.len().min(BATCH_SIZE - n);
+                out.extend(self.pending.drain(..take));
+                n += take;
+                continue
+            }
+
+            if self.next_it.borrow_mut().next_batch(&mut batch) > 0 {
+                self.pending.extend(batch.drain(..));
+                continue
+            }
+
+            self.err = self.next_it.borrow().err();
+            if self.err.is_some() {
+                break
+            }
+
+            if !self.primary.borrow_mut().next() {
+                self.err = self.primary.borrow().err();
+                break
+            }
+
+            let _ = self.next_it.borrow_mut().close();
+            self.next_it = self.links_to_primary();
+        }
+        if n > 0 {
+            self.result = out.last().cloned();
         }
+        n
     }
 
 }
@@ -1,31 +1,39 @@
-use crate::graph::value::Value;
-use crate::graph::refs::{Size, Ref, Content};
-use crate::graph::iterator::{Base, Scanner, Index, Shape, Costs, ShapeType};
+use crate::graph::refs::{Size, Ref};
+use crate::graph::iterator::{Base, Scanner, Index, Shape, Costs, ShapeType, SortOrder, BATCH_SIZE};
+use crate::graph::iterator::int64::Int64;
 
 use std::rc::Rc;
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::fmt;
+use std::ops::Bound::{Excluded, Included, Unbounded};
 
-use std::collections::BTreeMap;
-use super::quadstore::{PrimStore, Primitive};
-use std::ops::Bound::{Excluded, Unbounded};
+use super::quadstore::PrimStore;
 
 use std::sync::{Arc, RwLock};
 
+// MemStoreAllIterator holds the nodes (or the quads when `nodes` is false) among the ids
+// handed out so far, the Int64 range up to `maxid`. Scanners read the store's set of ids of
+// that kind BATCH_SIZE at a time under one lock, skipping the ids of the other kind, and
+// like MemStoreIterator only see the ids that existed when they were created.
 pub struct MemStoreAllIterator {
     all: Arc<RwLock<dyn PrimStore>>,
+    range: Rc<RefCell<Int64>>,
     maxid: i64,
-    nodes: bool
+    nodes: bool,
+    // the number of ids of the kind when the iterator was created
+    size: i64
 }
 
 impl MemStoreAllIterator {
-    pub fn new(all: Arc<RwLock<dyn PrimStore>>, maxid: i64, nodes: bool) -> Rc<RefCell<MemStoreAllIterator>> {
-  
+    pub fn new(all: Arc<RwLock<dyn PrimStore>>, maxid: i64, nodes: bool, size: i64) -> Rc<RefCell<MemStoreAllIterator>> {
         Rc::new(RefCell::new(MemStoreAllIterator {
             all,
+            range: Int64::new(1, maxid, nodes),
             maxid,
-            nodes
+            nodes,
+            size
         }))
     }
 }
@@ -37,17 +45,16 @@ impl Shape for MemStoreAllIterator {
     }
 
     fn lookup(&self) -> Rc<RefCell<dyn Index>> {
-        MemStoreAllIteratorContains::new(self.all.clone(), self.maxid, self.nodes)
+        MemStoreAllIteratorContains::new(self.all.clone(), self.range.borrow().lookup(), self.nodes)
     }
 
     fn stats(&mut self) -> Result<Costs, String> {
-        let all = self.all.read().unwrap();
-
+        let range = self.range.borrow_mut().stats()?;
         Ok(Costs {
-            contains_cost: 1,
-            next_cost: 1,
+            contains_cost: range.contains_cost,
+            next_cost: range.next_cost,
             size: Size {
-                value: all.len() as i64,
+                value: self.size,
                 exact: true
             }
         })
@@ -61,10 +68,14 @@ impl Shape for MemStoreAllIterator {
         None
     }
 
-    fn shape_type(&mut self) -> ShapeType {
+    fn shape_type(&mut self) -> ShapeType<'_> {
         ShapeType::MemStoreIterator
     }
 
+    fn sort_order(&self) -> Option<SortOrder> {
+        Some(if self.nodes { SortOrder::NodeIds } else { SortOrder::QuadIds })
+    }
+
 }
 
 
@@ -75,41 +86,57 @@ impl fmt::Display for MemStoreAllIterator {
 }
 
 
+fn id_ref(id: i64) -> Ref {
+    Ref::new_id(id as u64)
+}
+
 
 
 pub struct MemStoreAllIteratorNext {
     all: Arc<RwLock<dyn PrimStore>>,
     maxid: i64,
     nodes: bool,
-    done: bool,
+    // the batch of ids read last, and the position of the next one to return
+    batch: Vec<i64>,
+    pos: usize,
+    exhausted: bool,
     cur: Option<i64>
 }
 
-
-
 impl MemStoreAllIteratorNext {
     pub fn new(all: Arc<RwLock<dyn PrimStore>>, maxid: i64, nodes: bool) -> Rc<RefCell<MemStoreAllIteratorNext>> {
         Rc::new(RefCell::new(MemStoreAllIteratorNext {
             all,
             maxid,
             nodes,
-            done: false,
+            batch: Vec::new(),
+            pos: 0,
+            exhausted: false,
             cur: None
         }))
     }
+
+    // reads the next batch of ids after `after`, returning false when there are none
+    fn fill(&mut self, after: Option<i64>) -> bool {
+        if self.exhausted {
+            return false
+        }
+        let from = match after {
+            Some(a) => Excluded(a),
+            None => Unbounded
+        };
+        self.batch = self.all.read().unwrap().ids(self.nodes).range((from, Included(self.maxid))).take(BATCH_SIZE).copied().collect();
+        self.exhausted = self.batch.len() < BATCH_SIZE;
+        self.pos = 0;
+        !self.batch.is_empty()
+    }
 }
 
 impl Base for MemStoreAllIteratorNext {
-    fn tag_results(&self, tags: &mut HashMap<String, Ref>) {}
+    fn tag_results(&self, _tags: &mut HashMap<String, Ref>) {}
 
     fn result(&self) -> Option<Ref> {
-        match self.cur {
-            Some(c) => Some(Ref {
-                k: Value::from(c),
-                content: Content::None
-            }),
-            None => None
-        }
+        self.cur.map(id_ref)
     }
 
     fn next_path(&mut self) -> bool {
@@ -121,53 +148,50 @@ impl Base for MemStoreAllIteratorNext {
     }
 
     fn close(&mut self) -> Result<(), String> {
-        self.done = true;
+        self.batch = Vec::new();
         Ok(())
     }
 }
 
 impl Scanner for MemStoreAllIteratorNext {
     fn next(&mut self) -> bool {
-        
-        if self.done {
-            println!("MemStoreAllIteratorNext called after done");
+        if self.pos >= self.batch.len() && !self.fill(self.cur) {
+            self.cur = None;
             return false
         }
+        self.cur = Some(self.batch[self.pos]);
+        self.pos += 1;
+        true
+    }
 
-        // TODO: This is ridiculous, there has to be a way to just use a single iterator.
-
-        let lam = |(k, v):&(&i64, &Primitive)| {
-
-            let is_node = v.is_node();
-
-            if **k > self.maxid {
-                return false
-            } else if self.nodes && is_node {
-                return true
-            } else if !self.nodes && !is_node {
-                return true
-            } 
-
-            return false
-            
-        };
-
-        let all = self.all.read().unwrap();
-
-        self.cur = if !self.done && self.cur.is_none() {
-            all.iter().filter(lam).map(|(k, _)| *k).next()
-        } else {
-            all.range((Excluded(self.cur.unwrap()), Unbounded)).filter(lam).map(|(k, _)| *k).next()
-        };
-
-        println!("MemStoreAllIteratorNext {:?}", self.cur);
-
-        if !self.cur.is_some() {
-            self.done = true;
+    fn seek(&mut self, id: u64) -> bool {
+        if self.cur.is_some_and(|cur| cur as u64 >= id) {
+            return true
+        }
+        let id = i64::try_from(id).unwrap_or(i64::MAX);
+        while self.pos < self.batch.len() && self.batch[self.pos] < id {
+            self.pos += 1;
+        }
+        if self.pos >= self.batch.len() && !self.fill(self.cur.max(Some(id - 1))) {
+            self.cur = None;
             return false
         }
+        self.cur = Some(self.batch[self.pos]);
+        self.pos += 1;
+        true
+    }
 
-        return true
+    fn next_batch(&mut self, out: &mut Vec<Ref>) -> usize {
+        if self.pos >= self.batch.len() && !self.fill(self.cur) {
+            self.cur = None;
+            return 0
+        }
+        let ids = &self.batch[self.pos..];
+        out.extend(ids.iter().map(|id| id_ref(*id)));
+        self.cur = ids.last().copied();
+        let n = ids.len();
+        self.pos = self.batch.len();
+        n
     }
 }
 
@@ -182,35 +206,27 @@ impl fmt::Display for MemStoreAllIteratorNext {
 
 pub struct MemStoreAllIteratorContains {
     all: Arc<RwLock<dyn PrimStore>>,
-    maxid: i64,
+    range: Rc<RefCell<dyn Index>>,
     nodes: bool,
-    cur: Option<i64>,
-    done: bool
+    cur: Option<i64>
 }
 
 impl MemStoreAllIteratorContains {
-    pub fn new(all: Arc<RwLock<dyn PrimStore>>, maxid: i64, nodes: bool) -> Rc<RefCell<MemStoreAllIteratorContains>> {
+    pub fn new(all: Arc<RwLock<dyn PrimStore>>, range: Rc<RefCell<dyn Index>>, nodes: bool) -> Rc<RefCell<MemStoreAllIteratorContains>> {
         Rc::new(RefCell::new(MemStoreAllIteratorContains {
             all,
-            maxid,
+            range,
             nodes,
-            cur: None,
-            done: false
+            cur: None
         }))
     }
 }
 
 impl Base for MemStoreAllIteratorContains {
-    fn tag_results(&self, tags: &mut HashMap<String, Ref>) {}
+    fn tag_results(&self, _tags: &mut HashMap<String, Ref>) {}
 
     fn result(&self) -> Option<Ref> {
-        match self.cur {
-            Some(c) => Some(Ref {
-                k: Value::from(c),
-                content: Content::None
-            }),
-            None => None
-        }
+        self.cur.map(id_ref)
     }
 
     fn next_path(&mut self) -> bool {
@@ -222,36 +238,23 @@ impl Base for MemStoreAllIteratorContains {
     }
 
     fn close(&mut self) -> Result<(), String> {
-        self.done = true;
-        Ok(())
+        self.range.borrow_mut().close()
     }
 }
 
 impl Index for MemStoreAllIteratorContains {
     fn contains(&mut self, v:&Ref) -> bool {
-        if self.done {
+        self.cur = None;
+        if !self.range.borrow_mut().contains(v) {
             return false
         }
-
-        let all = self.all.read().unwrap();
-
-        let id = if let Some(k) = v.key() { k.as_i64() } else { None };
-
-        // TODO: if id > maxid
+        let id = v.key().and_then(|k| k.as_i64());
         match id {
-            Some(i) => {
-                match all.get(&i) {
-                    Some(_) => {
-                        self.cur = Some(i);
-                        return true
-                    },
-                    None => {
-                        self.cur = None;
-                        return false
-                    }
-                }
+            Some(i) if self.all.read().unwrap().ids(self.nodes).contains(&i) => {
+                self.cur = Some(i);
+                true
             },
-            None => false
+            _ => false
         }
     }
 }
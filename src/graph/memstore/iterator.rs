@@ -1,25 +1,82 @@
-use crate::graph::value::Value;
-use crate::graph::refs::{Size, Ref, Content};
-use crate::graph::iterator::{Base, Scanner, Index, Shape, Costs, ShapeType};
+use crate::graph::refs::{Size, Ref};
+use crate::graph::iterator::{Base, Scanner, Index, Shape, Costs, ShapeType, SortOrder, BATCH_SIZE};
 use crate::graph::quad::{Direction};
 
 use std::rc::Rc;
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::fmt;
 
 use std::collections::BTreeSet;
 use std::ops::Bound::{Excluded, Unbounded};
+use std::sync::{Arc, RwLock};
 
+use super::quadstore::IndexStore;
+
+// QuadIds is where a MemStoreIterator reads its quad ids from, either a set built up front
+// or the store's direction index for one value, which is read in place a batch at a time.
+#[derive(Clone)]
+enum QuadIds {
+    Set(Rc<BTreeSet<i64>>),
+    Index(Arc<RwLock<dyn IndexStore>>, i64)
+}
+
+impl QuadIds {
+    fn len(&self, d: &Direction) -> i64 {
+        match self {
+            QuadIds::Set(s) => s.len() as i64,
+            QuadIds::Index(store, value_id) => store.read().unwrap().quad_count(d, *value_id)
+        }
+    }
+
+    fn last_id(&self) -> i64 {
+        match self {
+            QuadIds::Set(_) => i64::MAX,
+            QuadIds::Index(store, _) => store.read().unwrap().last_id()
+        }
+    }
+
+    fn next_n(&self, d: &Direction, after: Option<i64>, limit: usize) -> Vec<i64> {
+        match self {
+            QuadIds::Set(s) => match after {
+                Some(a) => s.range((Excluded(a), Unbounded)).take(limit).copied().collect(),
+                None => s.iter().take(limit).copied().collect()
+            },
+            QuadIds::Index(store, value_id) => store.read().unwrap().next_quads(d, *value_id, after, limit)
+        }
+    }
+
+    fn contains(&self, d: &Direction, quad_id: i64) -> bool {
+        match self {
+            QuadIds::Set(s) => s.contains(&quad_id),
+            QuadIds::Index(store, value_id) => store.read().unwrap().has_quad(d, *value_id, quad_id)
+        }
+    }
+}
+
+
+// MemStoreIterator iterates the quads that have a value in direction `d`. When it reads
+// the store's index, a scanner copies the ids out BATCH_SIZE at a time under one lock. It
+// is a bounded scan rather than a snapshot: quads added after the scanner was created are
+// skipped, a quad deleted before the batch holding it is read is not returned, and one
+// deleted after is, its id then resolving to no quad.
 pub struct MemStoreIterator {
-    quad_ids: Rc<BTreeSet<i64>>,
+    quad_ids: QuadIds,
     d: Direction
 }
 
 impl MemStoreIterator {
     pub fn new(quad_ids: Rc<BTreeSet<i64>>, d: Direction) -> Rc<RefCell<MemStoreIterator>> {
         Rc::new(RefCell::new(MemStoreIterator {
-            quad_ids,
+            quad_ids: QuadIds::Set(quad_ids),
+            d
+        }))
+    }
+
+    pub fn new_index(store: Arc<RwLock<dyn IndexStore>>, d: Direction, value_id: i64) -> Rc<RefCell<MemStoreIterator>> {
+        Rc::new(RefCell::new(MemStoreIterator {
+            quad_ids: QuadIds::Index(store, value_id),
             d
         }))
     }
@@ -36,11 +93,12 @@ impl Shape for MemStoreIterator {
     }
 
     fn stats(&mut self) -> Result<Costs, String> {
+        let size = self.quad_ids.len(&self.d);
         Ok(Costs {
-            contains_cost: ((self.quad_ids.len() as f64).ln() as i64) + 1,
+            contains_cost: ((size as f64).ln() as i64) + 1,
             next_cost: 1,
             size: Size {
-                value: self.quad_ids.len() as i64,
+                value: size,
                 exact: true
             }
         })
@@ -58,6 +116,10 @@ impl Shape for MemStoreIterator {
         ShapeType::MemStoreIterator
     }
 
+    fn sort_order(&self) -> Option<SortOrder> {
+        Some(SortOrder::QuadIds)
+    }
+
 }
 
 
@@ -69,22 +131,54 @@ impl fmt::Display for MemStoreIterator {
 
 
 pub struct MemStoreIteratorNext {
-    quad_ids: Rc<BTreeSet<i64>>,
+    quad_ids: QuadIds,
     d: Direction,
+    last_id: i64,
+    // the batch of ids read last, and the position of the next one to return
+    batch: Vec<i64>,
+    pos: usize,
+    // set once a batch came back short, the index has no ids past it
+    exhausted: bool,
     cur: Option<i64>,
     done: bool
 }
 
 impl MemStoreIteratorNext {
-    pub fn new(quad_ids: Rc<BTreeSet<i64>>, d: Direction) -> Rc<RefCell<MemStoreIteratorNext>> {
-        
+    fn new(quad_ids: QuadIds, d: Direction) -> Rc<RefCell<MemStoreIteratorNext>> {
+        let last_id = quad_ids.last_id();
         Rc::new(RefCell::new(MemStoreIteratorNext {
             quad_ids,
             d,
+            last_id,
+            batch: Vec::new(),
+            pos: 0,
+            exhausted: false,
             cur: None,
             done: false
         }))
-        
+    }
+
+    // reads the next batch of ids after `after`, returning false when there are none
+    fn fill(&mut self, after: Option<i64>) -> bool {
+        if self.exhausted {
+            return false
+        }
+        let mut ids = self.quad_ids.next_n(&self.d, after, BATCH_SIZE);
+        self.exhausted = ids.len() < BATCH_SIZE;
+        // ids only grow, so the first id past the last one at creation ends the scan
+        if let Some(end) = ids.iter().position(|id| *id > self.last_id) {
+            ids.truncate(end);
+            self.exhausted = true;
+        }
+        self.batch = ids;
+        self.pos = 0;
+        !self.batch.is_empty()
+    }
+
+    fn finish(&mut self) -> bool {
+        self.cur = None;
+        self.done = true;
+        false
     }
 }
 
@@ -93,10 +187,7 @@ impl Base for MemStoreIteratorNext {
 
     fn result(&self) -> Option<Ref> {
         match self.cur {
-            Some(quad_id) => Some(Ref {
-                k: Value::from(quad_id),
-                content: Content::None
-            }),
+            Some(quad_id) => Some(Ref::new_id(quad_id as u64)),
             None => None
         }
     }
@@ -116,28 +207,54 @@ impl Base for MemStoreIteratorNext {
 
 impl Scanner for MemStoreIteratorNext {
     fn next(&mut self) -> bool {
+        if self.done {
+            return false
+        }
+        if self.pos >= self.batch.len() && !self.fill(self.cur) {
+            return self.finish()
+        }
+        self.cur = Some(self.batch[self.pos]);
+        self.pos += 1;
+        return true
+    }
 
+    fn seek(&mut self, id: u64) -> bool {
         if self.done {
-            println!("MemStoreAllIteratorNext called after done");
             return false
         }
-        
-        // TODO: This is ridiculous, there has to be a way to just use a single iterator.
+        let id = i64::try_from(id).unwrap_or(i64::MAX);
+        if self.cur.is_some_and(|cur| cur >= id) {
+            return true
+        }
 
-        self.cur = if !self.done && self.cur.is_none() {
-            self.quad_ids.iter().map(|quad_id| *quad_id).next()
-        } else {
-            self.quad_ids.range((Excluded(self.cur.unwrap()), Unbounded)).map(|quad_id| *quad_id).next()
-        };
-        
-        println!("MemStoreIteratorNext {:?}", self.cur);
+        // the first id at or after `id`, never moving backwards, from the batch when it
+        // holds one and from the index past it otherwise
+        while self.pos < self.batch.len() && self.batch[self.pos] < id {
+            self.pos += 1;
+        }
+        if self.pos >= self.batch.len() && !self.fill(self.cur.max(Some(id - 1))) {
+            return self.finish()
+        }
+        self.cur = Some(self.batch[self.pos]);
+        self.pos += 1;
+        true
+    }
 
-        if !self.cur.is_some() {
-            self.done = true;
-            return false
+    fn next_batch(&mut self, out: &mut Vec<Ref>) -> usize {
+        if self.done {
+            return 0
         }
 
-        return true
+        if self.pos >= self.batch.len() && !self.fill(self.cur) {
+            self.finish();
+            return 0
+        }
+        let ids = &self.batch[self.pos..];
+        out.extend(ids.iter().map(|id| Ref::new_id(*id as u64)));
+        self.cur = ids.last().copied();
+        let n = ids.len();
+        self.pos = self.batch.len();
+        n
     }
 }
 
@@ -151,16 +268,19 @@ impl fmt::Display for MemStoreIteratorNext {
 
 
 pub struct MemStoreIteratorContains {
-    quad_ids: Rc<BTreeSet<i64>>,
+    quad_ids: QuadIds,
     d: Direction,
+    last_id: i64,
     cur: Option<i64>
 }
 
 impl MemStoreIteratorContains {
-    pub fn new(quad_ids: Rc<BTreeSet<i64>>, d: Direction) -> Rc<RefCell<MemStoreIteratorContains>> {
+    fn new(quad_ids: QuadIds, d: Direction) -> Rc<RefCell<MemStoreIteratorContains>> {
+        let last_id = quad_ids.last_id();
         Rc::new(RefCell::new(MemStoreIteratorContains {
             quad_ids,
             d,
+            last_id,
             cur: None
         }))
     }
@@ -171,10 +291,7 @@ impl Base for MemStoreIteratorContains {
 
     fn result(&self) -> Option<Ref> {
         match self.cur {
-            Some(c) => Some(Ref {
-                k: Value::from(c),
-                content: Content::None
-            }),
+            Some(c) => Some(Ref::new_id(c as u64)),
             None => None
         }
     }
@@ -197,7 +314,7 @@ impl Index for MemStoreIteratorContains {
         let id = if let Some(k) = v.key() { k.as_i64() } else { None };
         match id {
             Some(i) => {
-                let c = self.quad_ids.contains(&i);
+                let c = i <= self.last_id && self.quad_ids.contains(&self.d, i);
                 if c {
                     self.cur = Some(i);
                     return true
@@ -14,26 +14,35 @@ use super::all_iterator::MemStoreAllIterator;
 
 use std::sync::{Arc, RwLock};
 use std::ops::Bound;
+use std::ops::Bound::{Included, Excluded};
 
 
 pub struct InternalMemStore {
     vals: HashMap<Value, i64>, // value to value_id
     quads: HashMap<InternalQuad, i64>, // quad to quad_id
     prim: BTreeMap<i64, Primitive>, // value_id or quad_id to value or quad
+    node_ids: BTreeSet<i64>, // the ids in prim that hold a value
+    quad_ids: BTreeSet<i64>, // the ids in prim that hold a quad
     index: QuadDirectionIndex, // value_id and direction to quad id
+    pair_index: Option<QuadPairIndex>, // two value_ids in a pair of directions to quad id
     last: i64, // keeps track of ids for values and quads
+    metadata: BTreeMap<String, String>, // entries kept apart from the quads
     horizon: i64 // keeps track of ids for transactions
 }
 
 impl InternalMemStore {
 
-    fn new() -> InternalMemStore {
+    fn new(composite: bool) -> InternalMemStore {
         InternalMemStore {
             vals: HashMap::new(),
             quads: HashMap::new(),
             prim: BTreeMap::new(),
+            node_ids: BTreeSet::new(),
+            quad_ids: BTreeSet::new(),
             index: QuadDirectionIndex::new(),
+            pair_index: if composite { Some(QuadPairIndex::new()) } else { None },
             last: 0,
+            metadata: BTreeMap::new(),
             horizon: 0
         }
     }
@@ -44,6 +53,11 @@ impl InternalMemStore {
         let id = self.last;
         p.id = id;
         p.refs = 1;
+        if p.is_node() {
+            self.node_ids.insert(id);
+        } else {
+            self.quad_ids.insert(id);
+        }
         self.prim.insert(id, p);
         return id
     }
@@ -152,11 +166,16 @@ impl InternalMemStore {
         }
         
         self.prim.remove(&id);
+        self.node_ids.remove(&id);
+        self.quad_ids.remove(&id);
         
         if let Some(q) = quad {
             for d in Direction::iterator() {
                 self.index.remove(&q.dir(d), d, &id);
             }
+            if let Some(pi) = self.pair_index.as_mut() {
+                pi.remove(&q, id);
+            }
 
             self.quads.remove(&q);
 
@@ -193,11 +212,36 @@ impl InternalMemStore {
         for d in Direction::iterator() {
             self.index.insert(p.dir(d), d, id);
         }
+        if let Some(pi) = self.pair_index.as_mut() {
+            pi.insert(&p, id);
+        }
 
         return id;
     }
 
 
+    // get all quad_ids that have every given value_id in its direction. Candidates come from
+    // the pair index when it covers two of the directions, otherwise from the smallest
+    // direction index, and are then checked against every lookup.
+    fn quad_ids_with(&self, lookups: &[(Direction, i64)]) -> BTreeSet<i64> {
+        let paired = self.pair_index.as_ref().and_then(|pi| pi.get(lookups));
+        let candidates = match paired {
+            Some(ids) => ids,
+            None => match lookups.iter().min_by_key(|(d, v)| self.index.count(d, v)) {
+                Some((d, v)) => self.index.iter(d, v).collect(),
+                None => return BTreeSet::new()
+            }
+        };
+
+        candidates.into_iter().filter(|id| {
+            match self.prim.get(id).map(|p| &p.content) {
+                Some(PrimitiveContent::Quad(q)) => lookups.iter().all(|(d, v)| q.dir(d) == *v),
+                _ => false
+            }
+        }).collect()
+    }
+
+
     fn lookup_val(&self, id: &i64) -> Option<Value> {
         match self.prim.get(id) {
             Some(p) => {
@@ -263,6 +307,8 @@ pub trait PrimStore {
     fn get(&self, key: &i64) -> Option<&Primitive>;
     fn iter(&self) -> std::collections::btree_map::Iter<'_, i64, Primitive>;
     fn range(&self, bounds: (Bound<i64>, Bound<i64>)) -> std::collections::btree_map::Range<'_, i64, Primitive>;
+    // the ids of the nodes, or of the quads when `nodes` is false
+    fn ids(&self, nodes: bool) -> &BTreeSet<i64>;
 }
 
 impl PrimStore for InternalMemStore {
@@ -281,6 +327,38 @@ impl PrimStore for InternalMemStore {
     fn range(&self, range: (Bound<i64>, Bound<i64>)) -> std::collections::btree_map::Range<'_, i64, Primitive> {
         self.prim.range(range)
     }
+
+    fn ids(&self, nodes: bool) -> &BTreeSet<i64> {
+        if nodes { &self.node_ids } else { &self.quad_ids }
+    }
+}
+
+// IndexStore gives iterators access to the direction index without copying it. Iterators
+// take the lock for each batch of ids they read, so they can be held across writes to the
+// store.
+pub trait IndexStore {
+    fn last_id(&self) -> i64;
+    fn quad_count(&self, d: &Direction, value_id: i64) -> i64;
+    fn next_quads(&self, d: &Direction, value_id: i64, after: Option<i64>, limit: usize) -> Vec<i64>;
+    fn has_quad(&self, d: &Direction, value_id: i64, quad_id: i64) -> bool;
+}
+
+impl IndexStore for InternalMemStore {
+    fn last_id(&self) -> i64 {
+        self.last
+    }
+
+    fn quad_count(&self, d: &Direction, value_id: i64) -> i64 {
+        self.index.count(d, &value_id)
+    }
+
+    fn next_quads(&self, d: &Direction, value_id: i64, after: Option<i64>, limit: usize) -> Vec<i64> {
+        self.index.iter_after(d, &value_id, after).take(limit).collect()
+    }
+
+    fn has_quad(&self, d: &Direction, value_id: i64, quad_id: i64) -> bool {
+        self.index.contains(d, &value_id, &quad_id)
+    }
 }
 
 pub struct MemStore {
@@ -290,7 +368,16 @@ pub struct MemStore {
 impl MemStore {
     pub fn new() -> MemStore {
         MemStore {
-            store: Arc::new(RwLock::new(InternalMemStore::new()))
+            store: Arc::new(RwLock::new(InternalMemStore::new(false)))
+        }
+    }
+
+    // Also keeps composite indexes over the predicate-object, subject-predicate,
+    // object-subject and label-predicate pairs, so a lookup fixing two directions reads
+    // the matching quads directly at the cost of more memory per quad.
+    pub fn new_with_composite_indexes() -> MemStore {
+        MemStore {
+            store: Arc::new(RwLock::new(InternalMemStore::new(true)))
         }
     }
 }
@@ -304,10 +391,7 @@ impl Namer for MemStore {
         }
         let id = datastore.vals.get(v);
         match id {
-            Some(i) => Some(Ref {
-                k: Value::from(*i),
-                content: Content::None
-            }),
+            Some(i) => Some(Ref::new_id(*i as u64)),
             None => None
         } 
     }
@@ -327,6 +411,14 @@ impl Namer for MemStore {
             return None
         }
     }
+    fn names_of(&self, refs: &[Ref]) -> Vec<Option<Value>> {
+        let datastore = self.store.read().unwrap();
+
+        refs.iter().map(|r| match &r.content {
+            Content::Value(v) => Some(v.clone()),
+            _ => r.key().and_then(|k| k.as_i64()).and_then(|i| datastore.lookup_val(&i))
+        }).collect()
+    }
 }
 
 impl QuadStore for MemStore {
@@ -347,25 +439,44 @@ impl QuadStore for MemStore {
         let id = if let Some(k) = r.key() { k.as_i64() } else { None };
         
         if let Some(i) = id {
-
-            let quad_ids = datastore.index.get(d, &i);
-
-            if !quad_ids.is_empty() {
-                return MemStoreIterator::new(Rc::new(quad_ids), d.clone())
+            if datastore.index.count(d, &i) > 0 {
+                return MemStoreIterator::new_index(self.store.clone(), d.clone(), i)
             }
         } 
             
         Null::new()
     }
 
+    // without the composite indexes this would only intersect the single direction
+    // indexes, which the shapes already do
+    fn quad_iterator_multi(&self, lookups: &[(Direction, Ref)]) -> Option<Rc<RefCell<dyn Shape>>> {
+        let datastore = self.store.read().unwrap();
+        datastore.pair_index.as_ref()?;
+
+        let mut ids = Vec::new();
+        for (d, r) in lookups {
+            match r.key().and_then(|k| k.as_i64()) {
+                Some(i) => ids.push((d.clone(), i)),
+                None => return Some(Null::new())
+            }
+        }
+
+        let quad_ids = datastore.quad_ids_with(&ids);
+        if quad_ids.is_empty() {
+            return Some(Null::new())
+        }
+
+        let d = ids.first().map(|(d, _)| d.clone()).unwrap_or(Direction::Subject);
+        Some(MemStoreIterator::new(Rc::new(quad_ids), d))
+    }
+
     fn quad_iterator_size(&self, d: &Direction, r: &Ref) -> Result<Size, String> {
         let datastore = self.store.read().unwrap();
 
         let id = if let Some(k) = r.key() { k.as_i64() } else { None };
 
         if let Some(i) = id {
-            let quad_ids = datastore.index.get(d, &i);
-            return Ok(Size{value: quad_ids.len() as i64, exact: true})
+            return Ok(Size{value: datastore.index.count(d, &i), exact: true})
         }
 
         return Ok(Size{value: 0, exact: true})
@@ -383,10 +494,7 @@ impl QuadStore for MemStore {
                     // The quad exsists, but the value is none
                     return Some(Ref::none())
                 }
-                return Some(Ref {
-                    k: Value::from(id),
-                    content: Content::None
-                })
+                Some(Ref::new_id(id as u64))
             }
             // the quad does not exsist
             None => None
@@ -453,12 +561,30 @@ impl QuadStore for MemStore {
     
     fn nodes_all_iterator(&self) -> Rc<RefCell<dyn Shape>> {
         let datastore = self.store.read().unwrap();
-        MemStoreAllIterator::new(self.store.clone(), datastore.last, true)
+        MemStoreAllIterator::new(self.store.clone(), datastore.last, true, datastore.node_ids.len() as i64)
     }
     
     fn quads_all_iterator(&self) -> Rc<RefCell<dyn Shape>> {
         let datastore = self.store.read().unwrap();
-        MemStoreAllIterator::new(self.store.clone(), datastore.last, false)
+        MemStoreAllIterator::new(self.store.clone(), datastore.last, false, datastore.quad_ids.len() as i64)
+    }
+
+    fn metadata(&self, key: &str) -> Option<String> {
+        self.store.read().unwrap().metadata.get(key).cloned()
+    }
+
+    fn metadata_keys(&self, prefix: &str) -> Vec<String> {
+        let datastore = self.store.read().unwrap();
+        datastore.metadata.range(prefix.to_string()..).map(|(k, _)| k).take_while(|k| k.starts_with(prefix)).cloned().collect()
+    }
+
+    fn set_metadata(&mut self, key: &str, value: Option<String>) -> Result<(), String> {
+        let mut datastore = self.store.write().unwrap();
+        match value {
+            Some(v) => datastore.metadata.insert(key.to_string(), v),
+            None => datastore.metadata.remove(key)
+        };
+        Ok(())
     }
     
     fn close(&self) -> Option<String> {
@@ -486,30 +612,127 @@ impl QuadDirectionKey {
 
 struct QuadDirectionIndex {
     index: BTreeSet<QuadDirectionKey>,
+    counts: HashMap<(i8, i64), i64>
 }
 
 impl QuadDirectionIndex {
 
     fn new() -> QuadDirectionIndex {
         QuadDirectionIndex {
-            index: BTreeSet::new()
+            index: BTreeSet::new(),
+            counts: HashMap::new()
         }
     }
 
-    // get all quad_ids that have the given value_id at the given location
-    fn get(&self, d: &Direction, value_id: &i64) -> BTreeSet<i64> {
-        let lower_bound = QuadDirectionKey::new(value_id.clone(), d, 0);
-        self.index.range(lower_bound..).take_while(|k| {
-            k.value_id == *value_id
-        }).map(|k| k.quad_id).collect()
+    // iterate all quad_ids that have the given value_id at the given location, in order
+    fn iter<'a>(&'a self, d: &Direction, value_id: &i64) -> impl Iterator<Item = i64> + 'a {
+        let lower_bound = QuadDirectionKey::new(*value_id, d, i64::MIN);
+        let upper_bound = QuadDirectionKey::new(*value_id, d, i64::MAX);
+        self.index.range(lower_bound..=upper_bound).map(|k| k.quad_id)
+    }
+
+    // the quad_ids after `after` that have the given value_id at the given location, in order
+    fn iter_after<'a>(&'a self, d: &Direction, value_id: &i64, after: Option<i64>) -> impl Iterator<Item = i64> + 'a {
+        let lower_bound = match after {
+            Some(q) => Excluded(QuadDirectionKey::new(*value_id, d, q)),
+            None => Included(QuadDirectionKey::new(*value_id, d, i64::MIN))
+        };
+        let upper_bound = Included(QuadDirectionKey::new(*value_id, d, i64::MAX));
+        self.index.range((lower_bound, upper_bound)).map(|k| k.quad_id)
+    }
+
+    fn contains(&self, d: &Direction, value_id: &i64, quad_id: &i64) -> bool {
+        self.index.contains(&QuadDirectionKey::new(*value_id, d, *quad_id))
+    }
+
+    fn count(&self, d: &Direction, value_id: &i64) -> i64 {
+        self.counts.get(&(d.to_byte(), *value_id)).copied().unwrap_or(0)
     }
 
     fn insert(&mut self, value_id: i64, d: &Direction, quad_id: i64) {
-        self.index.insert(QuadDirectionKey::new(value_id, d, quad_id));
+        if self.index.insert(QuadDirectionKey::new(value_id, d, quad_id)) {
+            *self.counts.entry((d.to_byte(), value_id)).or_insert(0) += 1;
+        }
     }
 
     fn remove(&mut self, value_id: &i64, d: &Direction, quad_id: &i64) {
-        self.index.remove(&QuadDirectionKey::new(value_id.clone(), d, quad_id.clone()));
+        if self.index.remove(&QuadDirectionKey::new(*value_id, d, *quad_id)) {
+            let key = (d.to_byte(), *value_id);
+            let n = self.counts.get(&key).copied().unwrap_or(0) - 1;
+            if n > 0 {
+                self.counts.insert(key, n);
+            } else {
+                self.counts.remove(&key);
+            }
+        }
+    }
+}
+
+
+// the direction pairs kept by QuadPairIndex, a key's `pair` is the position in this list
+static DIRECTION_PAIRS: [(Direction, Direction); 4] = [
+    (Direction::Predicate, Direction::Object),
+    (Direction::Subject, Direction::Predicate),
+    (Direction::Object, Direction::Subject),
+    (Direction::Label, Direction::Predicate)
+];
+
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct QuadPairKey {
+    pair: u8,
+    first: i64,
+    second: i64,
+    quad_id: i64
+}
+
+impl QuadPairKey {
+    pub fn new(pair: u8, first: i64, second: i64, quad_id: i64) -> QuadPairKey {
+        QuadPairKey {
+            pair,
+            first,
+            second,
+            quad_id
+        }
+    }
+}
+
+struct QuadPairIndex {
+    index: BTreeSet<QuadPairKey>,
+}
+
+impl QuadPairIndex {
+
+    fn new() -> QuadPairIndex {
+        QuadPairIndex {
+            index: BTreeSet::new()
+        }
+    }
+
+    // get all quad_ids matching the first pair of directions covered by the lookups,
+    // or None when no pair is covered
+    fn get(&self, lookups: &[(Direction, i64)]) -> Option<BTreeSet<i64>> {
+        let find = |d: &Direction| lookups.iter().find(|(ld, _)| ld == d).map(|(_, v)| *v);
+
+        for (i, (a, b)) in DIRECTION_PAIRS.iter().enumerate() {
+            if let (Some(first), Some(second)) = (find(a), find(b)) {
+                let lower = QuadPairKey::new(i as u8, first, second, i64::MIN);
+                let upper = QuadPairKey::new(i as u8, first, second, i64::MAX);
+                return Some(self.index.range(lower..=upper).map(|k| k.quad_id).collect())
+            }
+        }
+        None
+    }
+
+    fn insert(&mut self, q: &InternalQuad, quad_id: i64) {
+        for (i, (a, b)) in DIRECTION_PAIRS.iter().enumerate() {
+            self.index.insert(QuadPairKey::new(i as u8, q.dir(a), q.dir(b), quad_id));
+        }
+    }
+
+    fn remove(&mut self, q: &InternalQuad, quad_id: i64) {
+        for (i, (a, b)) in DIRECTION_PAIRS.iter().enumerate() {
+            self.index.remove(&QuadPairKey::new(i as u8, q.dir(a), q.dir(b), quad_id));
+        }
     }
 }
 
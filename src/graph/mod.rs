@@ -6,4 +6,5 @@ pub mod graphmock;
 pub mod linksto;
 pub mod hasa;
 pub mod transaction;
-pub mod memstore;
\ No newline at end of file
+pub mod memstore;
+pub mod algo;
\ No newline at end of file
@@ -7,7 +7,7 @@ use std::cell::RefCell;
 use std::fmt;
 use std::slice::Iter;
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
 pub struct Quad {
     pub subject: Value,
     pub predicate: Value,
@@ -106,6 +106,11 @@ pub enum Procedure {
 pub trait QuadStore : Namer {
     fn quad(&self, r: &Ref) -> Option<Quad>;
     fn quad_iterator(&self, d: &Direction, r: &Ref) -> Rc<RefCell<dyn Shape>>;
+    // Iterates the quads holding every given ref in its direction at once. Returns None when
+    // the store has no better way to do this than intersecting single direction iterators.
+    fn quad_iterator_multi(&self, _lookups: &[(Direction, Ref)]) -> Option<Rc<RefCell<dyn Shape>>> {
+        None
+    }
     fn quad_iterator_size(&self, d: &Direction, r: &Ref) -> Result<Size, String>;
     fn quad_direction(&self, r: &Ref, d: &Direction) -> Option<Ref>;
     fn stats(&self, exact: bool) -> Result<Stats, String>;
@@ -114,6 +119,20 @@ pub trait QuadStore : Namer {
     // fn new_quad_writer(&self) -> Result<QuadWriter, String>;
     fn nodes_all_iterator(&self) -> Rc<RefCell<dyn Shape>>;
     fn quads_all_iterator(&self) -> Rc<RefCell<dyn Shape>>;
+    // Entries the store keeps by key apart from its quads, like the definitions of named
+    // morphisms, which no iterator, count or value of the graph holds. A store with nowhere
+    // to keep them has none and fails to set one.
+    fn metadata(&self, _key: &str) -> Option<String> {
+        None
+    }
+    // the keys of the entries starting with `prefix`, in order
+    fn metadata_keys(&self, _prefix: &str) -> Vec<String> {
+        Vec::new()
+    }
+    // sets the entry of the key, or removes it for None
+    fn set_metadata(&mut self, key: &str, _value: Option<String>) -> Result<(), String> {
+        Err(format!("the store keeps no metadata, {} can't be set", key))
+    }
     fn close(&self) -> Option<String>;
 }
 
@@ -140,7 +159,7 @@ impl QuadWriter {
     }
 
     pub fn remove_quad(&self, quad: Quad) -> Result<(), String> {
-        Ok(())
+        self.qs.borrow_mut().apply_deltas(vec![Delta{action: Procedure::Delete, quad}], &self.ignore_opts)
     }
 
     pub fn apply_transaction(&self, transaction: Transaction) -> Result<(), String> {
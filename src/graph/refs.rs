@@ -1,6 +1,9 @@
 use serde_json::value::Number;
 use super::value::Value;
 use super::quad::Quad;
+use std::collections::{HashMap, BTreeMap};
+use std::convert::TryFrom;
+use std::fmt;
 
 
 
@@ -24,6 +27,12 @@ pub trait Namer {
     fn value_of(&self, v: &Value) -> Option<Ref>;
     fn name_of(&self, key: &Ref) -> Option<Value>;
     
+    // resolves many refs at once, stores should override this to answer the whole batch
+    // under one lock instead of one lookup per ref
+    fn names_of(&self, refs: &[Ref]) -> Vec<Option<Value>> {
+        refs.iter().map(|r| self.name_of(r)).collect()
+    }
+
     #[allow(unused)]
     fn values_of(&self, values: &Vec<Ref>) -> Result<Vec<Value>, String> {
         Ok(values.iter().map(|v| self.name_of(v).unwrap()).collect())
@@ -38,51 +47,213 @@ pub trait Namer {
 }
 
 
+// NameCache keeps the names of up to `capacity` keys, dropping the least recently used
+// when full. Keys must not be reused for a different value while the cache is alive.
+pub struct NameCache {
+    capacity: usize,
+    tick: u64,
+    names: HashMap<Key, (Value, u64)>,
+    order: BTreeMap<u64, Key>
+}
+
+impl NameCache {
+    pub fn new(capacity: usize) -> NameCache {
+        NameCache {
+            capacity,
+            tick: 0,
+            names: HashMap::new(),
+            order: BTreeMap::new()
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.names.len() > capacity {
+            self.evict();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.names.clear();
+        self.order.clear();
+    }
+
+    pub fn get(&mut self, k: &Key) -> Option<Value> {
+        let tick = self.next_tick();
+        let (v, used) = self.names.get_mut(k)?;
+        self.order.remove(used);
+        self.order.insert(tick, k.clone());
+        *used = tick;
+        Some(v.clone())
+    }
+
+    pub fn insert(&mut self, k: Key, v: Value) {
+        if self.capacity == 0 {
+            return
+        }
+        let tick = self.next_tick();
+        if let Some((_, used)) = self.names.get(&k) {
+            self.order.remove(used);
+        } else if self.names.len() >= self.capacity {
+            self.evict();
+        }
+        self.order.insert(tick, k.clone());
+        self.names.insert(k, (v, tick));
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    fn evict(&mut self) {
+        let oldest = self.order.keys().next().copied();
+        if let Some(t) = oldest {
+            if let Some(k) = self.order.remove(&t) {
+                self.names.remove(&k);
+            }
+        }
+    }
+}
+
+
 pub fn pre_fetched(v: Value) -> Ref {
     Ref {
-        k: v.clone(),
+        k: Key::Value(v.clone()),
         content: Content::Value(v),
     }
 }
 
 
+// Key is what a Ref points at. Stores that number their nodes and quads hand out Id keys,
+// which copy, hash and compare without touching a Value; stores without ids, like
+// graphmock::Store, key refs by the Value itself.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
+pub enum Key {
+    None,
+    Id(u64),
+    Value(Value)
+}
+
+impl Key {
+    // an Id key, or a Number value key for a negative number that no id can hold
+    pub fn from_i64(v: i64) -> Key {
+        match u64::try_from(v) {
+            Ok(id) => Key::Id(id),
+            Err(_) => Key::Value(Value::from(v))
+        }
+    }
+
+    pub fn as_id(&self) -> Option<u64> {
+        match self {
+            Key::Id(i) => Some(*i),
+            _ => None
+        }
+    }
+
+    // the key as a signed id, a Number value key is read as one too
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Key::Id(i) => i64::try_from(*i).ok(),
+            Key::Value(v) => v.as_i64(),
+            Key::None => None
+        }
+    }
+
+    pub fn to_value(&self) -> Value {
+        match self {
+            Key::Id(i) => Value::from(*i),
+            Key::Value(v) => v.clone(),
+            Key::None => Value::None
+        }
+    }
+}
+
+impl From<Value> for Key {
+    fn from(v: Value) -> Key {
+        match v {
+            Value::None => Key::None,
+            v => Key::Value(v)
+        }
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Key::Id(i) => write!(f, "{}", i),
+            Key::Value(v) => write!(f, "{}", v),
+            Key::None => write!(f, "undefined")
+        }
+    }
+}
+
+
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum Content {
     None,
     Value(Value),
     Quad(Quad)
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Ref {
-    pub k: Value,
+    pub k: Key,
     pub content: Content
 }
 
 impl Ref {
     pub fn none() -> Ref {
         Ref {
-            k: Value::None,
+            k: Key::None,
             content: Content::None
         }
     }
 
-    // a Ref with key Value::None is used to refer to an exsisting quad but the direction is unassigned
+    // a Ref with key Key::None is used to refer to an exsisting quad but the direction is unassigned
     // this is often the case with the label direction
     // using this method helps to ensure we are checking and handling this scenerio properly
-    pub fn key(&self) -> Option<&Value> {
-        if let Value::None = self.k {
+    pub fn key(&self) -> Option<&Key> {
+        if let Key::None = self.k {
             return None
         }
         return Some(&self.k)
     }
 
+    // a store-native id with no prefetched content
+    pub fn new_id(id: u64) -> Ref {
+        Ref {
+            k: Key::Id(id),
+            content: Content::None,
+        }
+    }
+
+    // An id node that also holds its number. Unlike pre_fetched(Value::from(v)) it is keyed
+    // by the id, so it only equals refs with the same id key.
     pub fn new_i64_node(v: i64) -> Ref {
-        let value = Value::Number(Number::from(v));
         Ref {
-            k: value.clone(),
-            content: Content::Value(value),
+            k: Key::from_i64(v),
+            content: Content::Value(Value::Number(Number::from(v))),
+        }
+    }
+
+    pub fn new_i64_quad(v: i64) -> Ref {
+        Ref {
+            k: Key::from_i64(v),
+            content: Content::None,
         }
     }
 
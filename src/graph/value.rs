@@ -3,6 +3,7 @@ use ordered_float::OrderedFloat;
 use serde_json::value::Number;
 
 use std::borrow::Cow;
+use std::cmp::Ordering;
 use std::fmt;
 
 // use wasm_bindgen::JsValue;
@@ -28,6 +29,39 @@ impl Value {
         None
     }
 
+    pub fn as_f64(&self) -> Option<f64> {
+        if let Value::Number(n) = self {
+            return n.as_f64()
+        }
+        None
+    }
+
+    // Orders values of different types by None, Null, Bool, Number, IRI, String. Numbers
+    // compare by magnitude whether they are integers or floats, everything else by content.
+    pub fn compare(&self, other: &Value) -> Ordering {
+        fn rank(v: &Value) -> u8 {
+            match v {
+                Value::None => 0,
+                Value::Null => 1,
+                Value::Bool(_) => 2,
+                Value::Number(_) => 3,
+                Value::IRI(_) => 4,
+                Value::String(_) => 5
+            }
+        }
+
+        match (self, other) {
+            (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+            (Value::Number(a), Value::Number(b)) => match (a.as_i64(), b.as_i64()) {
+                (Some(x), Some(y)) => x.cmp(&y),
+                _ => OrderedFloat::from(a.as_f64().unwrap_or(f64::NAN)).cmp(&OrderedFloat::from(b.as_f64().unwrap_or(f64::NAN)))
+            },
+            (Value::IRI(a), Value::IRI(b)) => a.cmp(b),
+            (Value::String(a), Value::String(b)) => a.cmp(b),
+            _ => rank(self).cmp(&rank(other))
+        }
+    }
+
     fn from_string<S: Into<String>>(s: S) -> Value {
         let s = s.into();
         if s.is_empty() {
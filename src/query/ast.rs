@@ -0,0 +1,836 @@
+use crate::graph::value::Value;
+use crate::graph::iterator::sort::{SortKey, Order};
+use crate::graph::iterator::value_filter::Operator;
+use crate::graph::quad::QuadStore;
+use crate::query::shape::{ValueFilter, Regexp, Wildcard, Comparison};
+use crate::query::path::{Path, Via};
+use crate::query::lexer::{Token, Tokens};
+use serde_json::Number;
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::convert::TryFrom;
+use std::str::FromStr;
+use std::fmt;
+
+
+// the version written by Path::to_ast, paths of a later version are refused when read
+pub const PATH_AST_VERSION: u32 = 1;
+
+// PathAst is the JSON form of a Path, used to store paths or send them between the wasm
+// client and the backend. Nested paths are written as the list of their morphisms.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PathAst {
+    pub version: u32,
+    pub morphisms: Vec<MorphismAst>
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MorphismAst {
+    Is {
+        nodes: Vec<Value>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        param: Option<String>
+    },
+    In { tags: Option<Vec<String>>, via: ViaAst },
+    Out { tags: Option<Vec<String>>, via: ViaAst },
+    Both { tags: Option<Vec<String>>, via: ViaAst },
+    Follow { path: Vec<MorphismAst> },
+    FollowRecursive { path: Vec<MorphismAst>, max_depth: i32, depth_tags: Vec<String> },
+    ShortestPath {
+        to: Vec<MorphismAst>,
+        via: ViaAst,
+        max_depth: i32,
+        // searched against the direction of the quads, the reversal of a ShortestPath
+        #[serde(default, skip_serializing_if = "is_false")]
+        rev: bool,
+        tags: Vec<String>
+    },
+    AllPaths {
+        to: Vec<MorphismAst>,
+        via: ViaAst,
+        max_depth: i32,
+        both: bool,
+        // searched against the direction of the quads, the reversal of an AllPaths
+        #[serde(default, skip_serializing_if = "is_false")]
+        rev: bool,
+        tag: String
+    },
+    And { path: Vec<MorphismAst> },
+    Or { path: Vec<MorphismAst> },
+    Filter { filters: Vec<ValueFilterAst> },
+    Tag { tags: Vec<String> },
+    Except { path: Vec<MorphismAst> },
+    Unique,
+    Has { via: ViaAst, rev: bool, nodes: HasNodesAst },
+    Limit { limit: i64 },
+    Skip { offset: i64 },
+    Order { keys: Vec<SortKey> },
+    Save { via: ViaAst, tag: String, rev: bool, opt: bool },
+    Predicates { rev: bool },
+    SavePredicates { tag: String, rev: bool },
+    Labels,
+    LabelContext { via: ViaAst, tags: Vec<String> }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ViaAst {
+    Any,
+    Values { values: Vec<Value> },
+    Path { morphisms: Vec<MorphismAst> },
+    Param { name: String }
+}
+
+// what the far end of a Has morphism must be
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HasNodesAst {
+    Any,
+    Values { values: Vec<Value> },
+    Filters { filters: Vec<ValueFilterAst> },
+    Param { name: String }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ValueFilterAst {
+    Regexp { pattern: String, iri: bool },
+    Wildcard { pattern: String },
+    Comparison { op: Operator, value: Value }
+}
+
+fn is_false(b: &bool) -> bool {
+    !*b
+}
+
+
+impl PathAst {
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string(self).map_err(|e| e.to_string())
+    }
+
+    pub fn from_json(json: &str) -> Result<PathAst, String> {
+        serde_json::from_str(json).map_err(|e| e.to_string())
+    }
+}
+
+
+impl ValueFilterAst {
+    pub fn filter(&self) -> Result<Rc<dyn ValueFilter>, String> {
+        Ok(match self {
+            ValueFilterAst::Regexp { pattern, iri } => {
+                regex::Regex::new(pattern).map_err(|e| e.to_string())?;
+                Rc::new(Regexp::new(pattern.clone(), *iri))
+            },
+            ValueFilterAst::Wildcard { pattern } => Rc::new(Wildcard::new(pattern.clone())),
+            ValueFilterAst::Comparison { op, value } => Rc::new(Comparison::new(op.clone(), value.clone()))
+        })
+    }
+}
+
+fn filters(filters: &[ValueFilterAst]) -> Result<Vec<Rc<dyn ValueFilter>>, String> {
+    filters.iter().map(|f| f.filter()).collect()
+}
+
+
+impl ViaAst {
+    pub fn via(&self, qs: &Option<Rc<RefCell<dyn QuadStore>>>) -> Result<Via, String> {
+        Ok(match self {
+            ViaAst::Any => Via::None,
+            ViaAst::Values { values } => Via::Values(values.clone()),
+            ViaAst::Path { morphisms } => Via::Path(build_path(qs, morphisms)?),
+            ViaAst::Param { name } => Via::Param(name.clone())
+        })
+    }
+}
+
+
+// Rebuilds a path from its morphisms with the same Path methods the query languages use.
+pub fn build_path(qs: &Option<Rc<RefCell<dyn QuadStore>>>, morphisms: &[MorphismAst]) -> Result<Path, String> {
+    let mut p = Path::new(qs.clone(), Vec::new());
+
+    for m in morphisms {
+        match m {
+            MorphismAst::Is { nodes: _, param: Some(name) } => p.is_param(name.clone()),
+            MorphismAst::Is { nodes, param: None } => p.is(nodes.clone()),
+            MorphismAst::In { tags: None, via } => p.r#in(via.via(qs)?),
+            MorphismAst::In { tags: Some(tags), via } => p.in_with_tags(tags.clone(), via.via(qs)?),
+            MorphismAst::Out { tags: None, via } => p.out(via.via(qs)?),
+            MorphismAst::Out { tags: Some(tags), via } => p.out_with_tags(tags.clone(), via.via(qs)?),
+            MorphismAst::Both { tags: None, via } => p.both(via.via(qs)?),
+            MorphismAst::Both { tags: Some(tags), via } => p.both_with_tags(tags.clone(), via.via(qs)?),
+            MorphismAst::Follow { path } => p.follow(build_path(qs, path)?),
+            MorphismAst::FollowRecursive { path, max_depth, depth_tags } => {
+                p.follow_recursive(Via::Path(build_path(qs, path)?), *max_depth, depth_tags.clone())
+            },
+            MorphismAst::ShortestPath { to, via, max_depth, rev: false, tags } => {
+                p.shortest_path(build_path(qs, to)?, via.via(qs)?, *max_depth, tags.clone())
+            },
+            MorphismAst::ShortestPath { to, via, max_depth, rev: true, tags } => {
+                p.shortest_path_reverse(build_path(qs, to)?, via.via(qs)?, *max_depth, tags.clone())
+            },
+            MorphismAst::AllPaths { to, via, max_depth, both, rev: false, tag } => {
+                p.all_paths(build_path(qs, to)?, via.via(qs)?, *max_depth, *both, tag.clone())
+            },
+            MorphismAst::AllPaths { to, via, max_depth, both, rev: true, tag } => {
+                p.all_paths_reverse(build_path(qs, to)?, via.via(qs)?, *max_depth, *both, tag.clone())
+            },
+            MorphismAst::And { path } => p.and(build_path(qs, path)?),
+            MorphismAst::Or { path } => p.or(build_path(qs, path)?),
+            MorphismAst::Filter { filters: f } => p.filters(filters(f)?),
+            MorphismAst::Tag { tags } => p.tag(tags.clone()),
+            MorphismAst::Except { path } => p.except(build_path(qs, path)?),
+            MorphismAst::Unique => p.unique(),
+            MorphismAst::Has { via, rev, nodes } => {
+                let via = via.via(qs)?;
+                match nodes {
+                    HasNodesAst::Any => p.has(via, *rev, Vec::new()),
+                    HasNodesAst::Values { values } => p.has(via, *rev, values.clone()),
+                    HasNodesAst::Filters { filters: f } => p.has_filter(via, *rev, filters(f)?),
+                    HasNodesAst::Param { name } => p.has_param(via, *rev, name.clone())
+                }
+            },
+            MorphismAst::Limit { limit } => p.limit(*limit),
+            MorphismAst::Skip { offset } => p.skip(*offset),
+            MorphismAst::Order { keys } => p.order_by(keys.clone()),
+            MorphismAst::Save { via, tag, rev, opt } => p.save(via.via(qs)?, tag.clone(), *rev, *opt),
+            MorphismAst::Predicates { rev } => p.predicates(*rev),
+            MorphismAst::SavePredicates { tag, rev } => p.save_predicates(tag.clone(), *rev),
+            MorphismAst::Labels => p.labels(),
+            MorphismAst::LabelContext { via, tags } => p.label_context_with_tags(via.via(qs)?, tags.clone())
+        }
+    }
+
+    Ok(p)
+}
+
+
+// Renders the path as Gizmo text, the morphisms of nested paths after g.V() or, for the
+// paths given to Follow and FollowRecursive, after g.M(). The text parses back into the
+// same PathAst, see from_str below, for paths that start with an Is as built paths do.
+impl fmt::Display for PathAst {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", gizmo_text(&self.morphisms, "g.V"))
+    }
+}
+
+// `start` is written before the morphisms, unless the path starts from values
+pub fn gizmo_text(morphisms: &[MorphismAst], start: &str) -> String {
+    let (mut text, rest) = match morphisms.split_first() {
+        Some((MorphismAst::Is { nodes, param: None }, rest)) if nodes.is_empty() => (format!("{}()", start), rest),
+        Some((MorphismAst::Is { nodes, param: None }, rest)) => (format!("g.V({})", list(nodes.iter().map(value_text))), rest),
+        Some((MorphismAst::Is { param: Some(name), .. }, rest)) => (format!("g.V({})", param_text(name)), rest),
+        _ => (format!("{}()", start), morphisms)
+    };
+    for m in rest {
+        text.push_str(&morphism_text(m));
+    }
+    text
+}
+
+fn morphism_text(m: &MorphismAst) -> String {
+    match m {
+        MorphismAst::Is { param: Some(name), .. } => call("Is", vec![param_text(name)]),
+        MorphismAst::Is { nodes, param: None } => call("Is", nodes.iter().map(value_text).collect()),
+        MorphismAst::In { tags, via } => call("In", traversal_args(via, tags)),
+        MorphismAst::Out { tags, via } => call("Out", traversal_args(via, tags)),
+        MorphismAst::Both { tags, via } => call("Both", traversal_args(via, tags)),
+        MorphismAst::Follow { path } => call("Follow", vec![gizmo_text(path, "g.M")]),
+        MorphismAst::FollowRecursive { path, max_depth, depth_tags } => {
+            // a predicate given as a value is kept as the path g.V(value)
+            let path = match path.as_slice() {
+                [MorphismAst::Is { nodes, param: None }] if !nodes.is_empty() => values_text(nodes),
+                _ => gizmo_text(path, "g.M")
+            };
+            call("FollowRecursive", with_strings(vec![path, max_depth.to_string()], depth_tags))
+        },
+        MorphismAst::ShortestPath { to, via, max_depth, rev, tags } => {
+            let text = call("ShortestPath", with_strings(vec![gizmo_text(to, "g.V"), via_text(via), max_depth.to_string()], tags));
+            // Gizmo has no reversed ShortestPath or AllPaths, they are the reversal of the morphism
+            if *rev { call("FollowR", vec![format!("g.M(){}", text)]) } else { text }
+        },
+        MorphismAst::AllPaths { to, via, max_depth, both, rev, tag } => {
+            let name = if *both { "AllPathsBoth" } else { "AllPaths" };
+            let text = call(name, vec![gizmo_text(to, "g.V"), via_text(via), max_depth.to_string(), string_text(tag)]);
+            if *rev { call("FollowR", vec![format!("g.M(){}", text)]) } else { text }
+        },
+        MorphismAst::And { path } => call("And", vec![gizmo_text(path, "g.V")]),
+        MorphismAst::Or { path } => call("Or", vec![gizmo_text(path, "g.V")]),
+        MorphismAst::Filter { filters } => call("Filter", filters.iter().map(filter_text).collect()),
+        MorphismAst::Tag { tags } => call("Tag", with_strings(Vec::new(), tags)),
+        MorphismAst::Except { path } => call("Except", vec![gizmo_text(path, "g.V")]),
+        MorphismAst::Unique => call("Unique", Vec::new()),
+        MorphismAst::Has { via, rev, nodes } => {
+            let mut args = vec![via_text(via)];
+            match nodes {
+                HasNodesAst::Any => {},
+                HasNodesAst::Values { values } => args.push(values_text(values)),
+                HasNodesAst::Filters { filters: f } if f.len() == 1 => args.push(filter_text(&f[0])),
+                HasNodesAst::Filters { filters: f } => args.push(format!("[{}]", list(f.iter().map(filter_text)))),
+                HasNodesAst::Param { name } => args.push(param_text(name))
+            }
+            call(if *rev { "HasR" } else { "Has" }, args)
+        },
+        MorphismAst::Limit { limit } => call("Limit", vec![limit.to_string()]),
+        MorphismAst::Skip { offset } => call("Skip", vec![offset.to_string()]),
+        MorphismAst::Order { keys } if keys.as_slice() == [SortKey::node(Order::Asc)] => call("Order", Vec::new()),
+        MorphismAst::Order { keys } => call("OrderBy", vec![format!("[{}]", list(keys.iter().map(sort_key_text)))]),
+        MorphismAst::Save { via, tag, rev, opt } => {
+            let name = match (rev, opt) {
+                (false, false) => "Save",
+                (true, false) => "SaveR",
+                (false, true) => "SaveOpt",
+                (true, true) => "SaveOptR"
+            };
+            call(name, vec![via_text(via), string_text(tag)])
+        },
+        MorphismAst::Predicates { rev } => call(if *rev { "InPredicates" } else { "OutPredicates" }, Vec::new()),
+        MorphismAst::SavePredicates { tag, rev } => {
+            call(if *rev { "SaveInPredicates" } else { "SaveOutPredicates" }, vec![string_text(tag)])
+        },
+        MorphismAst::Labels => call("Labels", Vec::new()),
+        MorphismAst::LabelContext { via, tags } => call("LabelContext", via_args(via, tags))
+    }
+}
+
+fn call(name: &str, args: Vec<String>) -> String {
+    format!(".{}({})", name, args.join(", "))
+}
+
+fn list<I: Iterator<Item = String>>(items: I) -> String {
+    items.collect::<Vec<String>>().join(", ")
+}
+
+// the tags left out are the empty tags of a built path, a traversal without them has null
+fn traversal_args(via: &ViaAst, tags: &Option<Vec<String>>) -> Vec<String> {
+    match tags {
+        Some(tags) => via_args(via, tags),
+        None => vec![via_text(via), "null".to_string()]
+    }
+}
+
+// the via is left out when it matches anything and nothing follows it
+fn via_args(via: &ViaAst, tags: &[String]) -> Vec<String> {
+    if *via == ViaAst::Any && tags.is_empty() {
+        return Vec::new()
+    }
+    with_strings(vec![via_text(via)], tags)
+}
+
+// adds a string argument, or an array of them, unless there are none
+fn with_strings(mut args: Vec<String>, strings: &[String]) -> Vec<String> {
+    match strings {
+        [] => {},
+        [s] => args.push(string_text(s)),
+        _ => args.push(format!("[{}]", list(strings.iter().map(|s| string_text(s)))))
+    }
+    args
+}
+
+fn string_text(s: &str) -> String {
+    serde_json::to_string(s).unwrap_or_default()
+}
+
+fn param_text(name: &str) -> String {
+    format!("param({})", string_text(name))
+}
+
+fn value_text(v: &Value) -> String {
+    match v {
+        Value::IRI(_) | Value::String(_) => string_text(&v.to_string()),
+        Value::None | Value::Null => "null".to_string(),
+        _ => v.to_string()
+    }
+}
+
+fn values_text(values: &[Value]) -> String {
+    match values {
+        [v] => value_text(v),
+        _ => format!("[{}]", list(values.iter().map(value_text)))
+    }
+}
+
+fn via_text(via: &ViaAst) -> String {
+    match via {
+        ViaAst::Any => "null".to_string(),
+        ViaAst::Values { values } => values_text(values),
+        ViaAst::Path { morphisms } => gizmo_text(morphisms, "g.V"),
+        ViaAst::Param { name } => param_text(name)
+    }
+}
+
+fn filter_text(f: &ValueFilterAst) -> String {
+    match f {
+        ValueFilterAst::Regexp { pattern, iri: false } => format!("regex({})", string_text(pattern)),
+        ValueFilterAst::Regexp { pattern, iri: true } => format!("regex({}, true)", string_text(pattern)),
+        ValueFilterAst::Wildcard { pattern } => format!("like({})", string_text(pattern)),
+        ValueFilterAst::Comparison { op, value } => {
+            let name = match op {
+                Operator::LT => "lt",
+                Operator::LTE => "lte",
+                Operator::GT => "gt",
+                Operator::GTE => "gte"
+            };
+            format!("{}({})", name, value_text(value))
+        }
+    }
+}
+
+fn sort_key_text(k: &SortKey) -> String {
+    let order = match k.order {
+        Order::Asc => "asc",
+        Order::Desc => "desc"
+    };
+    format!("[{}, {}]", string_text(k.tag.as_deref().unwrap_or("id")), string_text(order))
+}
+
+
+// Reads Gizmo text back into a PathAst, the text gizmo_text writes or the like written by
+// hand. g.V() and g.M() both start with an Is that matches every node, and FollowR() of a
+// path is a Follow of its reversal.
+impl FromStr for PathAst {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<PathAst, String> {
+        let mut parser = TextParser { tokens: text_tokens(text)?, pos: 0 };
+        let morphisms = parser.path()?;
+        if let Some(t) = parser.peek() {
+            return Err(format!("unexpected {:?} after the path", t))
+        }
+        Ok(PathAst { version: PATH_AST_VERSION, morphisms })
+    }
+}
+
+const PUNCTS: [&str; 6] = [".", ",", "(", ")", "[", "]"];
+
+fn text_tokens(text: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '"' {
+            let (s, end) = json_string(&chars, i)?;
+            out.push(Token::Str(s));
+            i = end;
+        } else if c.is_ascii_digit() || (c == '-' && chars.get(i + 1).is_some_and(|d| d.is_ascii_digit())) {
+            let (n, end) = json_number(&chars, i)?;
+            out.push(Token::Number(n));
+            i = end;
+        } else if c == '_' || c.is_alphabetic() {
+            let start = i;
+            while i < chars.len() && (chars[i] == '_' || chars[i].is_alphanumeric()) {
+                i += 1;
+            }
+            out.push(Token::Word(chars[start..i].iter().collect()));
+        } else if let Some(p) = PUNCTS.iter().find(|p| p.starts_with(c)) {
+            out.push(Token::Punct(p));
+            i += 1;
+        } else {
+            return Err(format!("unexpected character {:?} at {}", c, i))
+        }
+    }
+
+    Ok(out)
+}
+
+// the string at chars[start] with the escapes of JSON, as string_text writes it
+fn json_string(chars: &[char], start: usize) -> Result<(String, usize), String> {
+    let mut i = start + 1;
+    loop {
+        match chars.get(i) {
+            None => return Err("unterminated string".to_string()),
+            Some('"') => break,
+            Some('\\') => i += 2,
+            Some(_) => i += 1
+        }
+    }
+    let s: String = chars[start..=i].iter().collect();
+    let s = serde_json::from_str(&s).map_err(|e| format!("bad string {}: {}", s, e))?;
+    Ok((s, i + 1))
+}
+
+// the number at chars[start], its exponent included as a float is written
+fn json_number(chars: &[char], start: usize) -> Result<(Number, usize), String> {
+    let mut i = start + 1;
+    while let Some(c) = chars.get(i) {
+        let sign = (*c == '-' || *c == '+') && (chars[i - 1] == 'e' || chars[i - 1] == 'E');
+        if !(c.is_ascii_digit() || *c == '.' || *c == 'e' || *c == 'E' || sign) {
+            break
+        }
+        i += 1;
+    }
+    let s: String = chars[start..i].iter().collect();
+    let n = serde_json::from_str::<Number>(&s).map_err(|_| format!("bad number {}", s))?;
+    Ok((n, i))
+}
+
+// an argument of g.V() or of a morphism
+enum Arg {
+    Str(String),
+    Value(Value),
+    List(Vec<Arg>),
+    Path(Vec<MorphismAst>),
+    Param(String),
+    // a filter such as regex() or lt()
+    Call(String, Vec<Arg>)
+}
+
+struct TextParser {
+    tokens: Vec<Token>,
+    pos: usize
+}
+
+impl Tokens for TextParser {
+    fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    fn pos(&self) -> usize {
+        self.pos
+    }
+
+    fn set_pos(&mut self, pos: usize) {
+        self.pos = pos;
+    }
+}
+
+impl TextParser {
+    fn name(&mut self) -> Result<String, String> {
+        match self.next() {
+            Some(Token::Word(n)) => Ok(n),
+            t => Err(format!("expected a name, found {:?}", t))
+        }
+    }
+
+    // g.V(...) or g.M() and the morphisms called on it
+    fn path(&mut self) -> Result<Vec<MorphismAst>, String> {
+        if self.name()? != "g" {
+            return Err("a path starts with g.V() or g.M()".to_string())
+        }
+        self.expect(".")?;
+        let start = self.name()?;
+        if start != "V" && start != "M" {
+            return Err(format!("a path starts with g.V() or g.M(), not g.{}()", start))
+        }
+        let mut morphisms = vec![is(self.args()?)?];
+        while self.is(".") {
+            self.next();
+            let name = self.name()?;
+            let args = self.args()?;
+            morphism(&name, args, &mut morphisms)?;
+        }
+        Ok(morphisms)
+    }
+
+    fn args(&mut self) -> Result<Vec<Arg>, String> {
+        self.expect("(")?;
+        self.items(")")
+    }
+
+    // the arguments separated by commas up to the close
+    fn items(&mut self, close: &str) -> Result<Vec<Arg>, String> {
+        let mut items = Vec::new();
+        if self.is(close) {
+            self.next();
+            return Ok(items)
+        }
+        loop {
+            items.push(self.arg()?);
+            if !self.is(",") {
+                self.expect(close)?;
+                return Ok(items)
+            }
+            self.next();
+        }
+    }
+
+    fn arg(&mut self) -> Result<Arg, String> {
+        if matches!(self.peek(), Some(Token::Word(w)) if w == "g") {
+            return Ok(Arg::Path(self.path()?))
+        }
+        Ok(match self.next() {
+            Some(Token::Str(s)) => Arg::Str(s),
+            Some(Token::Number(n)) => Arg::Value(Value::Number(n)),
+            Some(Token::Punct("[")) => Arg::List(self.items("]")?),
+            Some(Token::Word(w)) if w == "null" => Arg::Value(Value::Null),
+            Some(Token::Word(w)) if w == "true" => Arg::Value(Value::Bool(true)),
+            Some(Token::Word(w)) if w == "false" => Arg::Value(Value::Bool(false)),
+            Some(Token::Word(w)) if w == "param" => match self.args()?.as_slice() {
+                [Arg::Str(name)] => Arg::Param(name.clone()),
+                _ => return Err("param() takes the name of the parameter".to_string())
+            },
+            Some(Token::Word(w)) => Arg::Call(w, self.args()?),
+            t => return Err(format!("expected an argument, found {:?}", t))
+        })
+    }
+}
+
+// the arguments of a morphism, taken in order
+struct Args {
+    name: String,
+    args: std::vec::IntoIter<Arg>
+}
+
+impl Args {
+    fn next(&mut self) -> Option<Arg> {
+        self.args.next()
+    }
+
+    fn required(&mut self) -> Result<Arg, String> {
+        self.args.next().ok_or_else(|| format!("{}() is missing an argument", self.name))
+    }
+
+    fn rest(&mut self) -> Vec<Arg> {
+        self.args.by_ref().collect()
+    }
+
+    fn end(&mut self) -> Result<(), String> {
+        if self.args.len() > 0 {
+            return Err(format!("{}() has too many arguments", self.name))
+        }
+        Ok(())
+    }
+}
+
+fn morphism(name: &str, args: Vec<Arg>, morphisms: &mut Vec<MorphismAst>) -> Result<(), String> {
+    let mut a = Args { name: name.to_string(), args: args.into_iter() };
+    let m = match name {
+        "Is" => is(a.rest())?,
+        "In" | "Out" | "Both" => {
+            let via = a.next().map(via).transpose()?.unwrap_or(ViaAst::Any);
+            let tags = match a.next() {
+                Some(Arg::Value(Value::Null)) => None,
+                Some(arg) => Some(strings(arg)?),
+                None => Some(Vec::new())
+            };
+            match name {
+                "In" => MorphismAst::In { tags, via },
+                "Out" => MorphismAst::Out { tags, via },
+                _ => MorphismAst::Both { tags, via }
+            }
+        },
+        "Follow" => MorphismAst::Follow { path: path(a.required()?)? },
+        "FollowR" => {
+            let path = path(a.required()?)?;
+            a.end()?;
+            morphisms.push(follow_reverse(path)?);
+            return Ok(())
+        },
+        "FollowRecursive" => {
+            let path = match a.required()? {
+                Arg::Path(path) => path,
+                Arg::Param(name) => vec![MorphismAst::Is { nodes: Vec::new(), param: Some(name) }],
+                arg => vec![MorphismAst::Is { nodes: values(arg)?, param: None }]
+            };
+            let max_depth = depth(a.required()?)?;
+            let depth_tags = a.next().map(strings).transpose()?.unwrap_or_default();
+            MorphismAst::FollowRecursive { path, max_depth, depth_tags }
+        },
+        "ShortestPath" => {
+            let to = path(a.required()?)?;
+            let via = via(a.required()?)?;
+            let max_depth = depth(a.required()?)?;
+            let tags = a.next().map(strings).transpose()?.unwrap_or_default();
+            MorphismAst::ShortestPath { to, via, max_depth, rev: false, tags }
+        },
+        "AllPaths" | "AllPathsBoth" => {
+            let to = path(a.required()?)?;
+            let via = via(a.required()?)?;
+            let max_depth = depth(a.required()?)?;
+            let tag = string(a.required()?)?;
+            MorphismAst::AllPaths { to, via, max_depth, both: name == "AllPathsBoth", rev: false, tag }
+        },
+        "And" | "Intersect" => MorphismAst::And { path: path(a.required()?)? },
+        "Or" | "Union" => MorphismAst::Or { path: path(a.required()?)? },
+        "Except" | "Difference" => MorphismAst::Except { path: path(a.required()?)? },
+        "Filter" => MorphismAst::Filter { filters: a.rest().into_iter().map(filter).collect::<Result<_, _>>()? },
+        "Tag" | "As" => {
+            let mut tags = Vec::new();
+            for arg in a.rest() {
+                tags.extend(strings(arg)?);
+            }
+            MorphismAst::Tag { tags }
+        },
+        "Unique" => MorphismAst::Unique,
+        "Has" | "HasR" => {
+            let via = via(a.required()?)?;
+            MorphismAst::Has { via, rev: name == "HasR", nodes: has_nodes(a.next())? }
+        },
+        "Limit" => MorphismAst::Limit { limit: int(a.required()?)? },
+        "Skip" => MorphismAst::Skip { offset: int(a.required()?)? },
+        "Order" => MorphismAst::Order { keys: vec![SortKey::node(Order::Asc)] },
+        "OrderBy" => MorphismAst::Order { keys: sort_keys(a.required()?)? },
+        "Save" | "SaveR" | "SaveOpt" | "SaveOptR" => {
+            let via = via(a.required()?)?;
+            let tag = string(a.required()?)?;
+            MorphismAst::Save { via, tag, rev: name.ends_with('R'), opt: name.starts_with("SaveOpt") }
+        },
+        "InPredicates" => MorphismAst::Predicates { rev: true },
+        "OutPredicates" => MorphismAst::Predicates { rev: false },
+        "SaveInPredicates" => MorphismAst::SavePredicates { tag: string(a.required()?)?, rev: true },
+        "SaveOutPredicates" => MorphismAst::SavePredicates { tag: string(a.required()?)?, rev: false },
+        "Labels" => MorphismAst::Labels,
+        "LabelContext" => {
+            let via = a.next().map(via).transpose()?.unwrap_or(ViaAst::Any);
+            let tags = a.next().map(strings).transpose()?.unwrap_or_default();
+            MorphismAst::LabelContext { via, tags }
+        },
+        _ => return Err(format!("unknown morphism {}()", name))
+    };
+    a.end()?;
+    morphisms.push(m);
+    Ok(())
+}
+
+// an Is of the values, or of a param
+fn is(args: Vec<Arg>) -> Result<MorphismAst, String> {
+    if let [Arg::Param(name)] = args.as_slice() {
+        return Ok(MorphismAst::Is { nodes: Vec::new(), param: Some(name.clone()) })
+    }
+    let mut nodes = Vec::new();
+    for arg in args {
+        nodes.extend(values(arg)?);
+    }
+    Ok(MorphismAst::Is { nodes, param: None })
+}
+
+// The text of a reversed ShortestPath or AllPaths is FollowR() of the path with only that
+// morphism, which reads back as the morphism reversed. Any other path is followed reversed.
+fn follow_reverse(path: Vec<MorphismAst>) -> Result<MorphismAst, String> {
+    if let [MorphismAst::Is { nodes, param: None }, m] = path.as_slice() {
+        if nodes.is_empty() {
+            match m.clone() {
+                MorphismAst::ShortestPath { to, via, max_depth, rev, tags } => {
+                    return Ok(MorphismAst::ShortestPath { to, via, max_depth, rev: !rev, tags })
+                },
+                MorphismAst::AllPaths { to, via, max_depth, both, rev, tag } => {
+                    return Ok(MorphismAst::AllPaths { to, via, max_depth, both, rev: !rev, tag })
+                },
+                _ => {}
+            }
+        }
+    }
+    Ok(MorphismAst::Follow { path: build_path(&None, &path)?.reverse().morphism_asts()? })
+}
+
+fn path(arg: Arg) -> Result<Vec<MorphismAst>, String> {
+    match arg {
+        Arg::Path(path) => Ok(path),
+        _ => Err("expected a path, g.V() or g.M()".to_string())
+    }
+}
+
+fn value(arg: Arg) -> Result<Value, String> {
+    match arg {
+        Arg::Str(s) => Ok(Value::from(s)),
+        Arg::Value(v) => Ok(v),
+        _ => Err("expected a value".to_string())
+    }
+}
+
+// a value or a list of them
+fn values(arg: Arg) -> Result<Vec<Value>, String> {
+    match arg {
+        Arg::List(items) => items.into_iter().map(value).collect(),
+        arg => Ok(vec![value(arg)?])
+    }
+}
+
+fn string(arg: Arg) -> Result<String, String> {
+    match arg {
+        Arg::Str(s) => Ok(s),
+        _ => Err("expected a string".to_string())
+    }
+}
+
+// a string or a list of them
+fn strings(arg: Arg) -> Result<Vec<String>, String> {
+    match arg {
+        Arg::List(items) => items.into_iter().map(string).collect(),
+        arg => Ok(vec![string(arg)?])
+    }
+}
+
+fn int(arg: Arg) -> Result<i64, String> {
+    match arg {
+        Arg::Value(Value::Number(n)) if n.is_i64() => Ok(n.as_i64().unwrap()),
+        _ => Err("expected an integer".to_string())
+    }
+}
+
+fn depth(arg: Arg) -> Result<i32, String> {
+    i32::try_from(int(arg)?).map_err(|_| "the max depth is out of range".to_string())
+}
+
+fn via(arg: Arg) -> Result<ViaAst, String> {
+    Ok(match arg {
+        Arg::Value(Value::Null) => ViaAst::Any,
+        Arg::Path(morphisms) => ViaAst::Path { morphisms },
+        Arg::Param(name) => ViaAst::Param { name },
+        arg => ViaAst::Values { values: values(arg)? }
+    })
+}
+
+fn has_nodes(arg: Option<Arg>) -> Result<HasNodesAst, String> {
+    Ok(match arg {
+        None => HasNodesAst::Any,
+        Some(Arg::Param(name)) => HasNodesAst::Param { name },
+        Some(f @ Arg::Call(..)) => HasNodesAst::Filters { filters: vec![filter(f)?] },
+        Some(Arg::List(items)) if !items.is_empty() && items.iter().all(|i| matches!(i, Arg::Call(..))) => {
+            HasNodesAst::Filters { filters: items.into_iter().map(filter).collect::<Result<_, _>>()? }
+        },
+        Some(arg) => HasNodesAst::Values { values: values(arg)? }
+    })
+}
+
+fn filter(arg: Arg) -> Result<ValueFilterAst, String> {
+    let (name, args) = match arg {
+        Arg::Call(name, args) => (name, args),
+        _ => return Err("expected a filter such as regex() or lt()".to_string())
+    };
+    let op = match name.as_str() {
+        "lt" => Some(Operator::LT),
+        "lte" => Some(Operator::LTE),
+        "gt" => Some(Operator::GT),
+        "gte" => Some(Operator::GTE),
+        _ => None
+    };
+    let mut args = args.into_iter();
+    Ok(match (name.as_str(), op, args.next(), args.next(), args.next()) {
+        ("regex", _, Some(Arg::Str(pattern)), None, None) => ValueFilterAst::Regexp { pattern, iri: false },
+        ("regex", _, Some(Arg::Str(pattern)), Some(Arg::Value(Value::Bool(iri))), None) => ValueFilterAst::Regexp { pattern, iri },
+        ("like", _, Some(Arg::Str(pattern)), None, None) => ValueFilterAst::Wildcard { pattern },
+        (_, Some(op), Some(v), None, None) => ValueFilterAst::Comparison { op, value: value(v)? },
+        _ => return Err(format!("{}() is not a filter with those arguments", name))
+    })
+}
+
+// [[tag, order], ...] with the tag id for the nodes
+fn sort_keys(arg: Arg) -> Result<Vec<SortKey>, String> {
+    let items = match arg {
+        Arg::List(items) => items,
+        _ => return Err("OrderBy() takes a list of [tag, order]".to_string())
+    };
+    items.into_iter().map(|item| {
+        let pair = match item {
+            Arg::List(pair) => pair,
+            _ => return Err("OrderBy() takes a list of [tag, order]".to_string())
+        };
+        let mut pair = pair.into_iter();
+        let (tag, order) = match (pair.next(), pair.next(), pair.next()) {
+            (Some(Arg::Str(tag)), Some(Arg::Str(order)), None) => (tag, order),
+            _ => return Err("OrderBy() takes a list of [tag, order]".to_string())
+        };
+        let order = match order.as_str() {
+            "asc" => Order::Asc,
+            "desc" => Order::Desc,
+            _ => return Err(format!("unknown order {}, asc or desc", order))
+        };
+        Ok(if tag == "id" { SortKey::node(order) } else { SortKey::tag(tag, order) })
+    }).collect()
+}
@@ -0,0 +1,504 @@
+use crate::graph::value::Value;
+use crate::graph::quad::QuadStore;
+use crate::graph::iterator::sort::Order;
+use crate::graph::iterator::value_filter::Operator;
+use super::lexer::{self, Token, Tokens};
+use super::sparql::{self, Term, Triple, Test, Condition, Group};
+use serde_json::Number;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use std::cell::RefCell;
+
+
+// A subset of Cypher, read into a SPARQL query and compiled like one:
+//
+//   MATCH (a {status: "cool_person"})-[:follows]->(b)<-[r]-(c)
+//   OPTIONAL MATCH (b)-[:follows]->(d)
+//   WHERE a.age > 20 AND d.name =~ "D.*"
+//   RETURN DISTINCT a, b.status AS status, r, d.name
+//   ORDER BY status DESC SKIP 1 LIMIT 10
+//
+// Relationship types and property keys name predicates, read as the IRI of the name, so
+// `:follows` and `a.status` are <follows> and <status> (quote other IRIs with backticks).
+// A relationship follows its predicate one way, and one without a type matches any
+// predicate, bound to its variable when it has one. `{key: value}` and WHERE conditions
+// keep the nodes with that property, while properties only returned or sorted on are
+// optional. WHERE joins comparisons (=, <>, <, <=, >, >=, =~ and IS NOT NULL) with AND,
+// and applies to the MATCH before it. Node labels, variable length relationships, OR and
+// NOT aren't supported.
+
+
+// anonymous nodes and relationships get variables no name can hold
+const ANONYMOUS: &str = "\u{0}";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Return {
+    // the key of the value in the results, the alias or the item as written
+    pub name: String,
+    pub var: String
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    pub query: sparql::Query,
+    pub returns: Vec<Return>
+}
+
+
+// Runs the query against the store, returning a map from each returned name to its value
+// for every match, like gizmo::Path::iter. Optional values that aren't found are left out.
+pub fn query(qs: Rc<RefCell<dyn QuadStore>>, text: &str) -> Result<Vec<HashMap<String, Value>>, String> {
+    let q = parse(text)?;
+    let solutions = sparql::run(qs, &q.query)?;
+    Ok(solutions.into_iter().map(|s| {
+        q.returns.iter().filter_map(|r| s.get(&r.var).map(|v| (r.name.clone(), v.clone()))).collect()
+    }).collect())
+}
+
+
+///////////////////////////////////////////////
+// parsing
+///////////////////////////////////////////////
+
+const PUNCTS: [&str; 20] = ["<>", "!=", "<=", ">=", "=~", "(", ")", "[", "]", "{", "}", ":", ",", ".", "-", "<", ">", "=", "|", "*"];
+
+fn tokens(text: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+        if c.is_whitespace() {
+            i += 1;
+        } else if rest == "//" {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '`' {
+            let end = (i + 1..chars.len()).find(|&j| chars[j] == '`').ok_or("unterminated `")?;
+            out.push(Token::Quoted(chars[i + 1..end].iter().collect()));
+            i = end + 1;
+        } else if c == '"' || c == '\'' {
+            let (s, end) = lexer::string(&chars, i)?;
+            out.push(Token::Str(s));
+            i = end;
+        } else if c.is_ascii_digit() {
+            let (n, end) = lexer::number(&chars, i)?;
+            out.push(Token::Number(n));
+            i = end;
+        } else if c == '_' || c.is_alphabetic() {
+            let start = i;
+            while i < chars.len() && (chars[i] == '_' || chars[i].is_alphanumeric()) {
+                i += 1;
+            }
+            out.push(Token::Word(chars[start..i].iter().collect()));
+        } else if let Some(p) = PUNCTS.iter().find(|p| rest.starts_with(**p)) {
+            out.push(Token::Punct(p));
+            i += p.len();
+        } else {
+            return Err(format!("unexpected character {:?} at {}", c, i))
+        }
+    }
+
+    Ok(out)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    pattern: Group,
+    // the OPTIONAL MATCH a WHERE applies to, None for the patterns of MATCH
+    current: Option<usize>,
+    // the named nodes and relationships, for RETURN *
+    names: Vec<String>,
+    // the variables "node.key" of the properties read so far
+    properties: HashSet<String>,
+    anonymous: usize
+}
+
+pub fn parse(text: &str) -> Result<Query, String> {
+    let mut p = Parser {
+        tokens: tokens(text)?,
+        pos: 0,
+        pattern: Group::default(),
+        current: None,
+        names: Vec::new(),
+        properties: HashSet::new(),
+        anonymous: 0
+    };
+    let q = p.query()?;
+    if let Some(t) = p.peek() {
+        return Err(format!("unexpected {:?} after the query", t))
+    }
+    Ok(q)
+}
+
+impl Tokens for Parser {
+    fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    fn pos(&self) -> usize {
+        self.pos
+    }
+
+    fn set_pos(&mut self, pos: usize) {
+        self.pos = pos;
+    }
+}
+
+impl Parser {
+    fn name(&mut self) -> Result<String, String> {
+        match self.next() {
+            Some(Token::Word(n)) | Some(Token::Quoted(n)) => Ok(n),
+            t => Err(format!("expected a name, found {:?}", t))
+        }
+    }
+
+    fn anonymous(&mut self) -> String {
+        self.anonymous += 1;
+        format!("{}{}", ANONYMOUS, self.anonymous)
+    }
+
+    fn name_var(&mut self, name: String) -> String {
+        if !self.names.contains(&name) {
+            self.names.push(name.clone());
+        }
+        name
+    }
+
+    fn group(&mut self) -> &mut Group {
+        match self.current {
+            Some(i) => &mut self.pattern.optionals[i],
+            None => &mut self.pattern
+        }
+    }
+
+    fn query(&mut self) -> Result<Query, String> {
+        if !self.keyword("MATCH") {
+            return Err(format!("expected MATCH, found {:?}", self.peek()))
+        }
+        loop {
+            if self.keyword("MATCH") {
+                self.pos += 1;
+                self.current = None;
+                self.patterns()?;
+            } else if self.keyword("OPTIONAL") {
+                self.pos += 1;
+                self.expect_keyword("MATCH")?;
+                self.pattern.optionals.push(Group::default());
+                self.current = Some(self.pattern.optionals.len() - 1);
+                self.patterns()?;
+            } else if self.keyword("WHERE") {
+                self.pos += 1;
+                self.conditions()?;
+            } else {
+                break
+            }
+        }
+        self.expect_keyword("RETURN")?;
+        self.current = None;
+        self.returns()
+    }
+
+    // path (, path)*
+    fn patterns(&mut self) -> Result<(), String> {
+        loop {
+            self.path()?;
+            if !self.is(",") {
+                return Ok(())
+            }
+            self.pos += 1;
+        }
+    }
+
+    // node (relationship node)*
+    fn path(&mut self) -> Result<(), String> {
+        let mut from = self.node()?;
+        while self.is("-") || self.is("<") {
+            let rev = self.is("<");
+            if rev {
+                self.pos += 1;
+            }
+            self.expect("-")?;
+
+            let mut predicate = None;
+            if self.is("[") {
+                self.pos += 1;
+                if let Some(Token::Word(_)) | Some(Token::Quoted(_)) = self.peek() {
+                    let name = self.name()?;
+                    predicate = Some(Term::Var(self.name_var(name)));
+                }
+                if self.is(":") {
+                    self.pos += 1;
+                    let via = Value::IRI(self.name()?);
+                    predicate = Some(match predicate {
+                        // the variable of a typed relationship holds its type
+                        Some(Term::Var(var)) => {
+                            self.group().filters.push(Condition { var: var.clone(), test: Test::Eq(via) });
+                            Term::Var(var)
+                        },
+                        _ => Term::Value(via)
+                    });
+                    if self.is("|") {
+                        return Err("relationships with more than one type are not supported".to_string())
+                    }
+                }
+                if self.is("*") {
+                    return Err("variable length relationships are not supported".to_string())
+                }
+                self.expect("]")?;
+            }
+            let predicate = match predicate {
+                Some(p) => p,
+                None => Term::Var(self.anonymous())
+            };
+
+            self.expect("-")?;
+            let fwd = self.is(">");
+            if fwd {
+                self.pos += 1;
+            }
+            if fwd == rev {
+                return Err("a relationship needs one direction, -> or <-".to_string())
+            }
+
+            let to = self.node()?;
+            let (subject, object) = if rev { (to.clone(), from) } else { (from, to.clone()) };
+            self.group().triples.push(Triple { subject, predicate, object, label: None });
+            from = to;
+        }
+        Ok(())
+    }
+
+    // (name {key: value, ...})
+    fn node(&mut self) -> Result<Term, String> {
+        self.expect("(")?;
+        let var = match self.peek() {
+            Some(Token::Word(_)) | Some(Token::Quoted(_)) => {
+                let name = self.name()?;
+                self.name_var(name)
+            },
+            _ => self.anonymous()
+        };
+        let node = Term::Var(var);
+        if self.is(":") {
+            return Err("node labels are not supported, match a property instead".to_string())
+        }
+        if self.is("{") {
+            self.pos += 1;
+            while !self.is("}") {
+                let key = Value::IRI(self.name()?);
+                self.expect(":")?;
+                let value = self.literal()?;
+                self.group().triples.push(Triple { subject: node.clone(), predicate: Term::Value(key), object: Term::Value(value), label: None });
+                if !self.is(",") {
+                    break
+                }
+                self.pos += 1;
+            }
+            self.expect("}")?;
+        }
+        self.expect(")")?;
+        Ok(node)
+    }
+
+    fn literal(&mut self) -> Result<Value, String> {
+        match self.next() {
+            Some(Token::Str(s)) => Ok(Value::String(s)),
+            Some(Token::Number(n)) => Ok(Value::Number(n)),
+            Some(Token::Punct("-")) => match self.next() {
+                Some(Token::Number(n)) => serde_json::from_str::<Number>(&format!("-{}", n)).map(Value::Number).map_err(|e| e.to_string()),
+                t => Err(format!("expected a number, found {:?}", t))
+            },
+            Some(Token::Word(n)) if n.eq_ignore_ascii_case("true") => Ok(Value::Bool(true)),
+            Some(Token::Word(n)) if n.eq_ignore_ascii_case("false") => Ok(Value::Bool(false)),
+            t => Err(format!("expected a value, found {:?}", t))
+        }
+    }
+
+    // the variable of a node, or of one of its properties, as "node.key"
+    fn item(&mut self, required: bool) -> Result<String, String> {
+        let var = self.name()?;
+        if !self.names.contains(&var) {
+            return Err(format!("unknown variable {}", var))
+        }
+        if !self.is(".") {
+            return Ok(var)
+        }
+        self.pos += 1;
+        let key = self.name()?;
+        Ok(self.property(var, key, required))
+    }
+
+    // Properties in WHERE are patterns of the clause, others are read when they are there,
+    // with an OPTIONAL on the group that matches the node.
+    fn property(&mut self, var: String, key: String, required: bool) -> String {
+        let property = format!("{}.{}", var, key);
+        if self.properties.contains(&property) {
+            return property
+        }
+        self.properties.insert(property.clone());
+
+        let triple = Triple {
+            subject: Term::Var(var.clone()),
+            predicate: Term::Value(Value::IRI(key)),
+            object: Term::Var(property.clone()),
+            label: None
+        };
+        if required {
+            self.group().triples.push(triple);
+            return property
+        }
+        let optional = Group { triples: vec![triple], ..Group::default() };
+        let node = Term::Var(var);
+        let matched = |g: &Group| g.triples.iter().any(|t| t.subject == node || t.object == node);
+        let group = if matched(&self.pattern) { None } else { self.pattern.optionals.iter().position(matched) };
+        match group {
+            Some(i) => self.pattern.optionals[i].optionals.push(optional),
+            None => self.pattern.optionals.push(optional)
+        }
+        property
+    }
+
+    // condition (AND condition)*
+    fn conditions(&mut self) -> Result<(), String> {
+        loop {
+            let var = self.item(true)?;
+            if self.keyword("IS") {
+                self.pos += 1;
+                self.expect_keyword("NOT")?;
+                self.expect_keyword("NULL")?;
+            } else {
+                let op = match self.next() {
+                    Some(Token::Punct(op)) => op,
+                    t => return Err(format!("expected a comparison, found {:?}", t))
+                };
+                let test = match op {
+                    "=~" => match self.next() {
+                        Some(Token::Str(p)) => {
+                            // =~ matches the whole value
+                            let pattern = format!("^(?:{})$", p);
+                            regex::Regex::new(&pattern).map_err(|e| e.to_string())?;
+                            Test::Regex(pattern, true)
+                        },
+                        t => return Err(format!("=~ takes a pattern, found {:?}", t))
+                    },
+                    "=" => Test::Eq(self.literal()?),
+                    "<>" | "!=" => Test::Ne(self.literal()?),
+                    "<" => Test::Compare(Operator::LT, self.literal()?),
+                    "<=" => Test::Compare(Operator::LTE, self.literal()?),
+                    ">" => Test::Compare(Operator::GT, self.literal()?),
+                    ">=" => Test::Compare(Operator::GTE, self.literal()?),
+                    op => return Err(format!("unknown comparison {}", op))
+                };
+                self.group().filters.push(Condition { var, test });
+            }
+
+            if self.keyword("OR") || self.keyword("XOR") {
+                return Err("only AND joins conditions".to_string())
+            }
+            if !self.keyword("AND") {
+                return Ok(())
+            }
+            self.pos += 1;
+        }
+    }
+
+    // RETURN [DISTINCT] (* | item [AS name] (, item [AS name])*) [ORDER BY ...] [SKIP n] [LIMIT n]
+    fn returns(&mut self) -> Result<Query, String> {
+        let distinct = self.keyword("DISTINCT");
+        if distinct {
+            self.pos += 1;
+        }
+
+        let mut returns = Vec::new();
+        if self.is("*") {
+            self.pos += 1;
+            returns = self.names.iter().map(|n| Return { name: n.clone(), var: n.clone() }).collect();
+        } else {
+            loop {
+                let start = self.pos;
+                let var = self.item(false)?;
+                let name = if self.keyword("AS") {
+                    self.pos += 1;
+                    self.name()?
+                } else {
+                    self.tokens[start..self.pos].iter().map(|t| match t {
+                        Token::Word(n) => n.clone(),
+                        Token::Quoted(n) => format!("`{}`", n),
+                        _ => ".".to_string()
+                    }).collect()
+                };
+                returns.push(Return { name, var });
+                if !self.is(",") {
+                    break
+                }
+                self.pos += 1;
+            }
+        }
+
+        let mut order = Vec::new();
+        if self.keyword("ORDER") {
+            self.pos += 1;
+            self.expect_keyword("BY")?;
+            loop {
+                let var = match self.peek() {
+                    Some(Token::Word(n)) if !self.names.contains(n) => match returns.iter().find(|r| r.name == *n) {
+                        Some(r) => {
+                            self.pos += 1;
+                            r.var.clone()
+                        },
+                        None => return Err(format!("unknown variable {}", n))
+                    },
+                    _ => self.item(false)?
+                };
+                let o = if self.keyword("DESC") || self.keyword("DESCENDING") {
+                    self.pos += 1;
+                    Order::Desc
+                } else {
+                    if self.keyword("ASC") || self.keyword("ASCENDING") {
+                        self.pos += 1;
+                    }
+                    Order::Asc
+                };
+                order.push((var, o));
+                if !self.is(",") {
+                    break
+                }
+                self.pos += 1;
+            }
+        }
+
+        let mut offset = 0;
+        if self.keyword("SKIP") {
+            self.pos += 1;
+            offset = self.count("SKIP")?;
+        }
+        let mut limit = 0;
+        if self.keyword("LIMIT") {
+            self.pos += 1;
+            limit = self.count("LIMIT")?;
+        }
+
+        let mut vars: Vec<String> = Vec::new();
+        for r in &returns {
+            if !vars.contains(&r.var) {
+                vars.push(r.var.clone());
+            }
+        }
+        Ok(Query {
+            query: sparql::Query {
+                vars: Some(vars),
+                distinct,
+                pattern: self.pattern.clone(),
+                order,
+                limit,
+                offset
+            },
+            returns
+        })
+    }
+}
@@ -0,0 +1,313 @@
+// The gizmo! macro writes a path in Gizmo syntax and expands to the calls of the
+// gizmo::Path builder, so a path is checked when it is compiled:
+//
+//   gizmo!(g.V("<alice>").Out("<follows>").Tag("friend").Has("<status>", "cool_person").Back("friend"))
+//
+// is g.v("<alice>").out("<follows>", None).tag(vec!["friend"]).has("<status>", "cool_person").back("friend").
+// Morphisms are named as in Gizmo text, see query::ast::gizmo_text, and an unknown name or
+// a wrong number of arguments doesn't compile. Tags are string literals, and Back() must
+// name a tag set before it by Tag() or As(), the only tags it can go back to. Arguments are any
+// expression the builder takes, paths given to And(), Or(), Except(), Follow() and the
+// like can be written as nested Gizmo, g.V(...) or g.M(...), or passed as &Path.
+//
+// All the morphisms gizmo_text writes have an arm, FollowRecursive(), ShortestPath(),
+// AllPaths() and AllPathsBoth() with their max depth and tags optional as in Gizmo. Count()
+// ends the chain with the number of nodes. The other results, Sum(), GroupBy(), the
+// iterators and so on, are called on the Path the macro returns, as is Is() with a Param.
+
+
+/// Builds a `gizmo::Path` from Gizmo syntax, checking tags and morphisms at compile time.
+///
+/// ```
+/// # use gizmo_graph_db::{gizmo, query::gizmo::new_memory_graph};
+/// let graph = new_memory_graph();
+/// let g = graph.g();
+/// let path = gizmo!(g.V("<alice>").Out("<follows>").Tag("friend").Has("<status>", "cool_person").Back("friend"));
+/// ```
+///
+/// Recursions and routes take their max depth and tags after the path they follow, and
+/// Count() ends the chain:
+///
+/// ```
+/// # use gizmo_graph_db::{gizmo, query::gizmo::new_memory_graph, graph::quad::Quad};
+/// let graph = new_memory_graph();
+/// graph.write(vec![
+///     Quad::new("<alice>", "<follows>", "<bob>", ()),
+///     Quad::new("<bob>", "<follows>", "<greg>", ())
+/// ]);
+/// let g = graph.g();
+/// let path = gizmo!(g.V("<alice>").FollowRecursive(g.M().Out("<follows>"), 2, "depth"));
+/// assert_eq!(path.to_json(), g.v("<alice>").follow_recursive_path(&g.m().out("<follows>", None), Some(2), "depth").to_json());
+/// let path = gizmo!(g.V("<alice>").ShortestPath(g.V("<greg>"), "<follows>"));
+/// assert_eq!(path.to_json(), g.v("<alice>").shortest_path(&g.v("<greg>"), "<follows>", None, None).to_json());
+/// let path = gizmo!(g.V("<alice>").AllPaths(g.V("<greg>"), "<follows>", 5, "route"));
+/// assert_eq!(path.to_json(), g.v("<alice>").all_paths(&g.v("<greg>"), "<follows>", Some(5), "route").to_json());
+/// assert_eq!(gizmo!(g.V("<alice>").Out("<follows>").Count()), 1);
+/// ```
+///
+/// A Back() to a tag that was never set doesn't compile:
+///
+/// ```compile_fail
+/// # use gizmo_graph_db::{gizmo, query::gizmo::new_memory_graph};
+/// let graph = new_memory_graph();
+/// let g = graph.g();
+/// let path = gizmo!(g.V("<alice>").Tag("start").Out("<follows>").Back("end"));
+/// ```
+///
+/// Nor does a morphism Gizmo doesn't have:
+///
+/// ```compile_fail
+/// # use gizmo_graph_db::{gizmo, query::gizmo::new_memory_graph};
+/// let graph = new_memory_graph();
+/// let g = graph.g();
+/// let path = gizmo!(g.V("<alice>").Outt("<follows>"));
+/// ```
+#[macro_export]
+macro_rules! gizmo {
+    // the end of the chain
+    (@chain [$($tag:literal)*] ($($p:tt)*)) => {
+        $($p)*
+    };
+
+    // traversals, their tags save the predicates they follow
+    (@chain [$($tag:literal)*] ($($p:tt)*) . Out () $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.out(::std::option::Option::None::<$crate::graph::value::Value>, $crate::query::gizmo::Tags::None)) $($rest)*)
+    };
+    (@chain [$($tag:literal)*] ($($p:tt)*) . Out ($via:expr $(, $t:literal)*) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.out($via, $crate::gizmo!(@tags $($t)*))) $($rest)*)
+    };
+    (@chain [$($tag:literal)*] ($($p:tt)*) . In () $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.r#in(::std::option::Option::None::<$crate::graph::value::Value>, $crate::query::gizmo::Tags::None)) $($rest)*)
+    };
+    (@chain [$($tag:literal)*] ($($p:tt)*) . In ($via:expr $(, $t:literal)*) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.r#in($via, $crate::gizmo!(@tags $($t)*))) $($rest)*)
+    };
+    (@chain [$($tag:literal)*] ($($p:tt)*) . Both () $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.both(::std::option::Option::None::<$crate::graph::value::Value>, $crate::query::gizmo::Tags::None)) $($rest)*)
+    };
+    (@chain [$($tag:literal)*] ($($p:tt)*) . Both ($via:expr $(, $t:literal)*) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.both($via, $crate::gizmo!(@tags $($t)*))) $($rest)*)
+    };
+    (@chain [$($tag:literal)*] ($($p:tt)*) . LabelContext ($via:expr $(, $t:literal)*) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.label_context($via, $crate::gizmo!(@tags $($t)*))) $($rest)*)
+    };
+
+    // tags
+    (@chain [$($tag:literal)*] ($($p:tt)*) . Tag ($($t:literal),+) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)* $($t)*] ($($p)*.tag(vec![$($t),+])) $($rest)*)
+    };
+    (@chain [$($tag:literal)*] ($($p:tt)*) . As ($($t:literal),+) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)* $($t)*] ($($p)*.tag(vec![$($t),+])) $($rest)*)
+    };
+    (@chain [$($tag:literal)*] ($($p:tt)*) . Back ($t:literal) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.back({
+            const _: () = $crate::query::dsl::tagged(&[$($tag),*], $t);
+            $t
+        })) $($rest)*)
+    };
+
+    // filters
+    (@chain [$($tag:literal)*] ($($p:tt)*) . Is ($v:expr) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.is($v)) $($rest)*)
+    };
+    (@chain [$($tag:literal)*] ($($p:tt)*) . Is ($($v:expr),+) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.is(vec![$($crate::graph::value::Value::from($v)),+])) $($rest)*)
+    };
+    (@chain [$($tag:literal)*] ($($p:tt)*) . Has ($via:expr, $o:expr) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.has($via, $o)) $($rest)*)
+    };
+    (@chain [$($tag:literal)*] ($($p:tt)*) . HasR ($via:expr, $o:expr) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.has_r($via, $o)) $($rest)*)
+    };
+    (@chain [$($tag:literal)*] ($($p:tt)*) . Filter ($f:expr) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.filter($f)) $($rest)*)
+    };
+    (@chain [$($tag:literal)*] ($($p:tt)*) . Filter ($($f:expr),+) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.filter(vec![$($f),+])) $($rest)*)
+    };
+
+    // values saved under a tag
+    (@chain [$($tag:literal)*] ($($p:tt)*) . Save ($via:expr, $t:literal) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.save($via, $t)) $($rest)*)
+    };
+    (@chain [$($tag:literal)*] ($($p:tt)*) . SaveR ($via:expr, $t:literal) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.save_r($via, $t)) $($rest)*)
+    };
+    (@chain [$($tag:literal)*] ($($p:tt)*) . SaveOpt ($via:expr, $t:literal) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.save_opt($via, $t)) $($rest)*)
+    };
+    (@chain [$($tag:literal)*] ($($p:tt)*) . SaveOptR ($via:expr, $t:literal) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.save_opt_r($via, $t)) $($rest)*)
+    };
+    (@chain [$($tag:literal)*] ($($p:tt)*) . SaveInPredicates ($t:literal) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.save_in_predicates($t)) $($rest)*)
+    };
+    (@chain [$($tag:literal)*] ($($p:tt)*) . SaveOutPredicates ($t:literal) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.save_out_predicates($t)) $($rest)*)
+    };
+
+    // other paths
+    (@chain [$($tag:literal)*] ($($p:tt)*) . Follow ($($path:tt)+) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.follow($crate::gizmo!(@path $($path)+))) $($rest)*)
+    };
+    (@chain [$($tag:literal)*] ($($p:tt)*) . FollowR ($($path:tt)+) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.follow_r($crate::gizmo!(@path $($path)+))) $($rest)*)
+    };
+    (@chain [$($tag:literal)*] ($($p:tt)*) . And ($($path:tt)+) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.and($crate::gizmo!(@path $($path)+))) $($rest)*)
+    };
+    (@chain [$($tag:literal)*] ($($p:tt)*) . Intersect ($($path:tt)+) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.intersect($crate::gizmo!(@path $($path)+))) $($rest)*)
+    };
+    (@chain [$($tag:literal)*] ($($p:tt)*) . Or ($($path:tt)+) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.or($crate::gizmo!(@path $($path)+))) $($rest)*)
+    };
+    (@chain [$($tag:literal)*] ($($p:tt)*) . Union ($($path:tt)+) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.union($crate::gizmo!(@path $($path)+))) $($rest)*)
+    };
+    (@chain [$($tag:literal)*] ($($p:tt)*) . Except ($($path:tt)+) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.except($crate::gizmo!(@path $($path)+))) $($rest)*)
+    };
+
+    (@chain [$($tag:literal)*] ($($p:tt)*) . Difference ($($path:tt)+) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.difference($crate::gizmo!(@path $($path)+))) $($rest)*)
+    };
+
+    // recursion and routes, their depth and path tags save what they found rather than nodes
+    (@chain [$($tag:literal)*] ($($p:tt)*) . FollowRecursive ($g:ident . $start:ident ($($a:tt)*) $(. $m:ident ($($ma:tt)*))* $(, $depth:expr $(, $t:literal)*)?) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.follow_recursive_path(&$crate::gizmo!($g . $start ($($a)*) $(. $m ($($ma)*))*), $crate::gizmo!(@depth $($depth)?), $crate::gizmo!(@tags $($($t)*)?))) $($rest)*)
+    };
+    (@chain [$($tag:literal)*] ($($p:tt)*) . FollowRecursive ($v:literal $(, $depth:expr $(, $t:literal)*)?) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.follow_recursive_value($crate::graph::value::Value::from($v), $crate::gizmo!(@depth $($depth)?), $crate::gizmo!(@tags $($($t)*)?))) $($rest)*)
+    };
+    (@chain [$($tag:literal)*] ($($p:tt)*) . FollowRecursive ($path:expr $(, $depth:expr $(, $t:literal)*)?) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.follow_recursive_path($path, $crate::gizmo!(@depth $($depth)?), $crate::gizmo!(@tags $($($t)*)?))) $($rest)*)
+    };
+    (@chain [$($tag:literal)*] ($($p:tt)*) . ShortestPath ($g:ident . $start:ident ($($a:tt)*) $(. $m:ident ($($ma:tt)*))*, $via:expr $(, $depth:expr $(, $t:literal)*)?) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.shortest_path(&$crate::gizmo!($g . $start ($($a)*) $(. $m ($($ma)*))*), $via, $crate::gizmo!(@depth $($depth)?), $crate::gizmo!(@tags $($($t)*)?))) $($rest)*)
+    };
+    (@chain [$($tag:literal)*] ($($p:tt)*) . ShortestPath ($to:expr, $via:expr $(, $depth:expr $(, $t:literal)*)?) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.shortest_path($to, $via, $crate::gizmo!(@depth $($depth)?), $crate::gizmo!(@tags $($($t)*)?))) $($rest)*)
+    };
+    (@chain [$($tag:literal)*] ($($p:tt)*) . AllPaths ($g:ident . $start:ident ($($a:tt)*) $(. $m:ident ($($ma:tt)*))*, $via:expr $(, $depth:expr $(, $t:literal)?)?) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.all_paths(&$crate::gizmo!($g . $start ($($a)*) $(. $m ($($ma)*))*), $via, $crate::gizmo!(@depth $($depth)?), $crate::gizmo!(@tag $($($t)?)?))) $($rest)*)
+    };
+    (@chain [$($tag:literal)*] ($($p:tt)*) . AllPaths ($to:expr, $via:expr $(, $depth:expr $(, $t:literal)?)?) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.all_paths($to, $via, $crate::gizmo!(@depth $($depth)?), $crate::gizmo!(@tag $($($t)?)?))) $($rest)*)
+    };
+    (@chain [$($tag:literal)*] ($($p:tt)*) . AllPathsBoth ($g:ident . $start:ident ($($a:tt)*) $(. $m:ident ($($ma:tt)*))*, $via:expr $(, $depth:expr $(, $t:literal)?)?) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.all_paths_both(&$crate::gizmo!($g . $start ($($a)*) $(. $m ($($ma)*))*), $via, $crate::gizmo!(@depth $($depth)?), $crate::gizmo!(@tag $($($t)?)?))) $($rest)*)
+    };
+    (@chain [$($tag:literal)*] ($($p:tt)*) . AllPathsBoth ($to:expr, $via:expr $(, $depth:expr $(, $t:literal)?)?) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.all_paths_both($to, $via, $crate::gizmo!(@depth $($depth)?), $crate::gizmo!(@tag $($($t)?)?))) $($rest)*)
+    };
+
+    // everything else
+    (@chain [$($tag:literal)*] ($($p:tt)*) . Unique () $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.unique()) $($rest)*)
+    };
+    (@chain [$($tag:literal)*] ($($p:tt)*) . Labels () $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.labels()) $($rest)*)
+    };
+    (@chain [$($tag:literal)*] ($($p:tt)*) . InPredicates () $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.in_predicates()) $($rest)*)
+    };
+    (@chain [$($tag:literal)*] ($($p:tt)*) . OutPredicates () $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.out_predicates()) $($rest)*)
+    };
+    (@chain [$($tag:literal)*] ($($p:tt)*) . Limit ($n:expr) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.limit($n)) $($rest)*)
+    };
+    (@chain [$($tag:literal)*] ($($p:tt)*) . Skip ($n:expr) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.skip($n)) $($rest)*)
+    };
+    (@chain [$($tag:literal)*] ($($p:tt)*) . Order () $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.order()) $($rest)*)
+    };
+    (@chain [$($tag:literal)*] ($($p:tt)*) . OrderBy ($keys:expr) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [$($tag)*] ($($p)*.order_by($keys)) $($rest)*)
+    };
+
+    // the count of the nodes ends the chain, it is a number rather than a path
+    (@chain [$($tag:literal)*] ($($p:tt)*) . Count ()) => {
+        $($p)*.count()
+    };
+
+    // the tags of a traversal
+    (@tags) => {
+        $crate::query::gizmo::Tags::None
+    };
+    (@tags $($t:literal)+) => {
+        vec![$($t),+]
+    };
+
+    // the max depth of a recursion or route, the default when it is left out
+    (@depth) => {
+        ::std::option::Option::None
+    };
+    (@depth $depth:expr) => {
+        ::std::option::Option::Some($depth)
+    };
+
+    // the tag of AllPaths
+    (@tag) => {
+        ::std::option::Option::None::<::std::string::String>
+    };
+    (@tag $t:literal) => {
+        $t
+    };
+
+    // a nested path in Gizmo syntax, or an expression for a &Path or a morphism name
+    (@path $g:ident . V $($path:tt)+) => {
+        &$crate::gizmo!($g . V $($path)+)
+    };
+    (@path $g:ident . M $($path:tt)+) => {
+        &$crate::gizmo!($g . M $($path)+)
+    };
+    (@path $path:expr) => {
+        $path
+    };
+
+    // the start of the path
+    ($g:ident . V () $($rest:tt)*) => {
+        $crate::gizmo!(@chain [] ($g.v(::std::option::Option::None::<$crate::graph::value::Value>)) $($rest)*)
+    };
+    ($g:ident . V ($v:expr) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [] ($g.v($v)) $($rest)*)
+    };
+    ($g:ident . V ($($v:expr),+) $($rest:tt)*) => {
+        $crate::gizmo!(@chain [] ($g.v(vec![$($crate::graph::value::Value::from($v)),+])) $($rest)*)
+    };
+    ($g:ident . M () $($rest:tt)*) => {
+        $crate::gizmo!(@chain [] ($g.m()) $($rest)*)
+    };
+}
+
+
+// Fails to compile, where it is called from a constant, when the tag isn't one of the tags.
+#[doc(hidden)]
+pub const fn tagged(tags: &[&str], tag: &str) {
+    let mut i = 0;
+    while i < tags.len() {
+        if same(tags[i], tag) {
+            return
+        }
+        i += 1;
+    }
+    panic!("Back() names a tag that no Tag() or As() before it sets")
+}
+
+const fn same(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false
+    }
+    let mut i = 0;
+    while i < a.len() {
+        if a[i] != b[i] {
+            return false
+        }
+        i += 1;
+    }
+    true
+}
@@ -1,5 +1,14 @@
 use super::path;
 use super::shape;
+use super::plan::PlanCache;
+use super::registry::MorphismRegistry;
+use super::ast;
+use super::graphql;
+use super::mql;
+use super::sparql;
+use super::cypher;
+use super::row;
+use super::schema;
 use std::rc::Rc;
 use std::cell::RefCell;
 use crate::graph::quad::{QuadStore, QuadWriter, IgnoreOptions, Quad};
@@ -7,17 +16,42 @@ use crate::graph::graphmock;
 use crate::graph::memstore;
 use crate::graph::value::Value;
 use crate::graph::iterator;
+use crate::graph::iterator::aggregate::{aggregate, Aggregate};
+use crate::graph::iterator::sort::SortKey;
+pub use crate::graph::iterator::sort::Order;
+pub use crate::graph::iterator::spill::{MemoryLimit, MemoryLimitError};
+use crate::graph::iterator::spill::LimitFailures;
+pub use crate::graph::iterator::all_paths::PathStep;
 use std::collections::HashMap;
-use crate::graph::refs::Ref;
+use std::fmt;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use crate::graph::refs::{Ref, Key, Content, NameCache};
 
+// rows of a result are named together in pages of this many
+const RESULT_PAGE_SIZE: usize = 100;
+const DEFAULT_NAME_CACHE_SIZE: usize = 10_000;
+const DEFAULT_PLAN_CACHE_SIZE: usize = 256;
 
+
+// an empty graph in memory, with the composite indexes so a step fixing two directions
+// of a quad, like has("<follows>", "<bob>"), reads the matching quads directly
 pub fn new_memory_graph() -> GraphWrapper {
-    let qs = Rc::new(RefCell::new(memstore::quadstore::MemStore::new()));
+    let qs = Rc::new(RefCell::new(memstore::quadstore::MemStore::new_with_composite_indexes()));
     //let qs = Rc::new(RefCell::new(graphmock::Store::new()));
+    new_graph(qs)
+}
 
+// a new session over a store, which may already hold data and named morphisms
+pub fn new_graph(qs: Rc<RefCell<dyn QuadStore>>) -> GraphWrapper {
     let s = Rc::new(RefCell::new(Session {
         qs: qs.clone(),
-        qw: QuadWriter::new(qs.clone(), IgnoreOptions{ignore_dup: true, ignore_missing: true})
+        qw: QuadWriter::new(qs.clone(), IgnoreOptions{ignore_dup: true, ignore_missing: true}),
+        names: Rc::new(RefCell::new(NameCache::new(DEFAULT_NAME_CACHE_SIZE))),
+        plans: RefCell::new(PlanCache::new(DEFAULT_PLAN_CACHE_SIZE)),
+        memory_limit: None,
+        limit_failures: LimitFailures::new(),
+        morphisms: MorphismRegistry::new(qs.clone())
     }));
 
     let g = Graph::new(s.clone());
@@ -55,15 +89,125 @@ impl GraphWrapper {
     pub fn delete(&self, quads: Vec<Quad>) {
         self.session.borrow().delete(quads)
     }
+
+    // runs a GraphQL-style query, see query::graphql
+    pub fn graphql(&self, query: &str) -> Result<serde_json::Value, String> {
+        let qs = self.session.borrow().qs.clone();
+        graphql::query(qs, query)
+    }
+
+    // runs an MQL query template, see query::mql
+    pub fn mql(&self, query: &serde_json::Value) -> Result<serde_json::Value, String> {
+        let qs = self.session.borrow().qs.clone();
+        mql::query(qs, query)
+    }
+
+    // runs a SPARQL SELECT query, see query::sparql
+    pub fn sparql(&self, query: &str) -> Result<serde_json::Value, String> {
+        let qs = self.session.borrow().qs.clone();
+        sparql::query(qs, query)
+    }
+
+    // runs a Cypher MATCH query, see query::cypher
+    pub fn cypher(&self, query: &str) -> Result<Vec<HashMap<String, Value>>, String> {
+        let qs = self.session.borrow().qs.clone();
+        cypher::query(qs, query)
+    }
+
+    // writes the object as a node with its fields as quads, replacing the values they had,
+    // and returns the node, see query::schema
+    pub fn write_object<T: Serialize>(&self, object: &T) -> Result<Value, String> {
+        let qs = self.session.borrow().qs.clone();
+        schema::write(&qs, object)
+    }
+
+    // reads the object written as the node back, see query::schema
+    pub fn load_object<T: DeserializeOwned, V: Into<Value>>(&self, node: V) -> Result<T, String> {
+        schema::load(&self.g(), node.into())
+    }
+}
+
+
+// The error that stopped a query run with try_iter or try_iter_values
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryError {
+    MemoryLimit(MemoryLimitError),
+    Other(String)
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryError::MemoryLimit(e) => write!(f, "{}", e),
+            QueryError::Other(e) => f.write_str(e)
+        }
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+impl From<String> for QueryError {
+    fn from(e: String) -> QueryError {
+        QueryError::Other(e)
+    }
 }
 
+impl From<QueryError> for String {
+    fn from(e: QueryError) -> String {
+        e.to_string()
+    }
+}
 
+
+// The session caches the names of the nodes it has returned, this relies on the store
+// never handing out a deleted node's id to a new node. It also caches the plans of the
+// paths it runs, see PlanCache, and holds the morphisms defined by name in the store.
 pub struct Session {
     qs: Rc<RefCell<dyn QuadStore>>,
-    qw: QuadWriter
+    qw: QuadWriter,
+    names: Rc<RefCell<NameCache>>,
+    plans: RefCell<PlanCache>,
+    memory_limit: Option<MemoryLimit>,
+    limit_failures: LimitFailures,
+    morphisms: MorphismRegistry
 }
 
 impl Session {
+    // the most names kept between queries, 0 turns the cache off
+    pub fn set_name_cache_size(&mut self, size: usize) {
+        self.names.borrow_mut().set_capacity(size);
+    }
+
+    pub fn name_cache_len(&self) -> usize {
+        self.names.borrow().len()
+    }
+
+    // the most plans kept, 0 turns the cache off
+    pub fn set_plan_cache_size(&mut self, size: usize) {
+        self.plans.borrow_mut().set_capacity(size);
+    }
+
+    pub fn plan_cache_len(&self) -> usize {
+        self.plans.borrow().len()
+    }
+
+    // how many paths were built from a cached plan, and how many weren't
+    pub fn plan_cache_stats(&self) -> (u64, u64) {
+        let plans = self.plans.borrow();
+        (plans.hits(), plans.misses())
+    }
+
+    // Bounds the memory each Unique and Materialize iterator of a query may use, past the
+    // limit they spill to disk or fail with a MemoryLimitError. None removes the bound.
+    // A Materialize that spilled returns its results in no particular order.
+    pub fn set_memory_limit(&mut self, limit: Option<MemoryLimit>) {
+        self.memory_limit = limit;
+    }
+
+    pub fn memory_limit(&self) -> Option<MemoryLimit> {
+        self.memory_limit
+    }
+
     fn write(&self, quads: Vec<Quad>) {
         for quad in &quads {
             self.qw.add_quad(quad.clone()).unwrap();
@@ -79,6 +223,21 @@ impl Session {
         // TODO: implement
     }
 
+    // the error that stopped a query, typed when an iterator failed at its memory limit
+    fn query_error(&self, err: String) -> QueryError {
+        match self.limit_failures.take() {
+            Some(e) => QueryError::MemoryLimit(e),
+            None => QueryError::Other(err)
+        }
+    }
+
+    fn limit_memory(&self, it: Rc<RefCell<dyn iterator::Shape>>) -> Rc<RefCell<dyn iterator::Shape>> {
+        if self.memory_limit.is_some() {
+            iterator::set_memory_limit(&it, self.memory_limit, &self.limit_failures);
+        }
+        it
+    }
+
     fn run_tag_each_iterator(&mut self, it: Rc<RefCell<dyn iterator::Shape>>) -> iterator::iterate::TagEachIterator {
         iterator::iterate::TagEachIterator::new(it, false, true)
     }
@@ -101,21 +260,47 @@ impl Graph {
     }
 
     pub fn v<V: Into<Values>>(&self, qv: V) -> Path {
-        Path::new(
-            self.session.clone(), 
-            true, 
-            path::Path::start_path(
-                Some(
-                    self.session.borrow().qs.clone()
-                ), 
-                qv.into().to_vec()
-            )
-        )
+        let qs = Some(self.session.borrow().qs.clone());
+        let path = match qv.into().into_values() {
+            Ok(values) => path::Path::start_path(qs, values),
+            Err(name) => path::Path::start_param(qs, name)
+        };
+        Path::new(self.session.clone(), true, path)
     }
 
     pub fn m(&self) -> Path {
         Path::new(self.session.clone(), false, path::Path::start_morphism(Vec::new()))
     }
+
+    // Stores the path in the graph under the name, for later queries to follow by name.
+    // Named morphisms the path follows are kept as they are now.
+    pub fn define_morphism<S: Into<String>>(&self, name: S, path: &Path) -> Result<(), String> {
+        self.session.borrow().morphisms.define(&name.into(), &path.path)
+    }
+
+    pub fn morphism(&self, name: &str) -> Result<Option<Path>, String> {
+        let p = self.session.borrow().morphisms.get(name)?;
+        Ok(p.map(|p| Path::new(self.session.clone(), false, p)))
+    }
+
+    pub fn remove_morphism(&self, name: &str) -> Result<bool, String> {
+        self.session.borrow().morphisms.remove(name)
+    }
+
+    pub fn morphism_names(&self) -> Vec<String> {
+        self.session.borrow().morphisms.names()
+    }
+
+    pub fn path_from_json(&self, json: &str) -> Result<Path, String> {
+        let qs = Some(self.session.borrow().qs.clone());
+        Ok(Path::new(self.session.clone(), true, path::Path::from_json(qs, json)?))
+    }
+
+    // the path written as Gizmo text, as a Path renders itself
+    pub fn path_from_gizmo(&self, text: &str) -> Result<Path, String> {
+        let qs = Some(self.session.borrow().qs.clone());
+        Ok(Path::new(self.session.clone(), true, path::Path::from_gizmo(qs, text)?))
+    }
 }
 
 
@@ -139,7 +324,32 @@ impl Path {
     fn build_iterator_tree(&self) -> Rc<RefCell<dyn iterator::Shape>> {
         let s = self.session.borrow();
         let qs = self.session.borrow().qs.clone();
-        self.path.build_iterator_on(qs)
+        let it = s.plans.borrow_mut().build_iterator(&self.path, qs);
+        s.limit_memory(it)
+    }
+
+    // Builds the shape of the path once, to be run with different values bound to the
+    // params it was given with param().
+    pub fn prepare(&self) -> Prepared {
+        let (shape, lookups) = self.path.recorded_shape();
+        let qs = self.session.borrow().qs.clone();
+        Prepared {
+            session: self.session.clone(),
+            shape: Rc::new(shape::ResolvedShape::new(qs, shape, lookups)),
+            bindings: HashMap::new()
+        }
+    }
+
+
+    // the structure of the path, shared by paths that only differ in the values they start
+    // from or follow, None when the path can't be fingerprinted
+    pub fn fingerprint(&self) -> Option<path::Fingerprint> {
+        self.path.fingerprint()
+    }
+
+    // the path as versioned JSON, read back with Graph::path_from_json
+    pub fn to_json(&self) -> Result<String, String> {
+        self.path.to_json()
     }
 
 
@@ -148,16 +358,31 @@ impl Path {
     ///////////////
 
     pub fn iter(&self) -> impl Iterator<Item = HashMap<String, Value>> {
-        let it = self.build_iterator_tree();
-        let it = iterator::save::tag(&it, &"id");
-        let qs = self.session.borrow().qs.clone();
-        self.session.borrow_mut().run_tag_each_iterator(it).filter_map(move |r| tags_to_value_map(&r, &*qs.borrow()))
+        rows_of(&self.session, self.build_iterator_tree())
     }
 
     pub fn iter_values(&self) -> impl Iterator<Item = Value> {
-        let it = self.build_iterator_tree();
-        let qs = self.session.borrow().qs.clone();
-        self.session.borrow_mut().run_each_iterator(it).filter_map(move |r| ref_to_value(&r, &*qs.borrow()))
+        values_of(&self.session, self.build_iterator_tree())
+    }
+
+    // Like iter, but reports the error that stopped the query, such as an iterator that
+    // failed at its memory limit, instead of returning the rows found before it.
+    pub fn try_iter(&self) -> Result<Vec<HashMap<String, Value>>, QueryError> {
+        try_rows_of(&self.session, self.build_iterator_tree())
+    }
+
+    pub fn try_iter_values(&self) -> Result<Vec<Value>, QueryError> {
+        try_values_of(&self.session, self.build_iterator_tree())
+    }
+
+    // Like iter, but deserializes every row into T, its fields named by the tags, see
+    // query::row for how values convert. A row that doesn't fit T is an error.
+    pub fn iter_as<T: DeserializeOwned>(&self) -> impl Iterator<Item = Result<T, String>> {
+        self.iter().map(|r| row::from_row(&r).map_err(String::from))
+    }
+
+    pub fn try_iter_as<T: DeserializeOwned>(&self) -> Result<Vec<T>, String> {
+        self.try_iter()?.iter().map(|r| row::from_row(r).map_err(String::from)).collect()
     }
 
     pub fn count(&mut self) -> i64 {
@@ -165,6 +390,38 @@ impl Path {
         self.session.borrow_mut().run_each_iterator(it).count() as i64
     }
 
+    pub fn sum<S: Into<String>>(&self, tag: S) -> Value {
+        self.aggregate(None, Some(&tag.into()), Aggregate::Sum).pop().map(|(_, v)| v).unwrap_or(Value::from(0))
+    }
+
+    pub fn min<S: Into<String>>(&self, tag: S) -> Value {
+        self.aggregate(None, Some(&tag.into()), Aggregate::Min).pop().map(|(_, v)| v).unwrap_or(Value::Null)
+    }
+
+    pub fn max<S: Into<String>>(&self, tag: S) -> Value {
+        self.aggregate(None, Some(&tag.into()), Aggregate::Max).pop().map(|(_, v)| v).unwrap_or(Value::Null)
+    }
+
+    pub fn avg<S: Into<String>>(&self, tag: S) -> Value {
+        self.aggregate(None, Some(&tag.into()), Aggregate::Avg).pop().map(|(_, v)| v).unwrap_or(Value::Null)
+    }
+
+    pub fn group_by<S: Into<String>>(&self, tag: S) -> GroupBy {
+        GroupBy {
+            path: self.clone(),
+            tag: tag.into()
+        }
+    }
+
+    fn aggregate(&self, group_tag: Option<&str>, value_tag: Option<&str>, agg: Aggregate) -> Vec<(Value, Value)> {
+        let it = self.build_iterator_tree();
+        let it = iterator::save::tag(&it, &"id");
+        let qs = self.session.borrow().qs.clone();
+        let rows = self.session.borrow_mut().run_tag_each_iterator(it);
+        let res = aggregate(rows, &*qs.borrow(), group_tag, value_tag, agg);
+        res
+    }
+
 
     ///////////////
     // Traversals
@@ -174,7 +431,10 @@ impl Path {
     // Is(nodes: String[])
     ///////////////////////////
     pub fn is<V: Into<Values>>(&mut self, nodes: V) -> Path {
-        self.path.is(nodes.into().to_vec());
+        match nodes.into().into_values() {
+            Ok(nodes) => self.path.is(nodes),
+            Err(name) => self.path.is_param(name)
+        }
         self.clone()
     }
 
@@ -204,18 +464,22 @@ impl Path {
 
     ///////////////////////////
     // Follow(path: Path)
+    // *Follow(name: String)
     ///////////////////////////
-    pub fn follow(&mut self, ep: &Path) -> Path {
-        self.path.follow(ep.path.clone());
+    pub fn follow<F: Into<Follow>>(&mut self, ep: F) -> Path {
+        let p = ep.into().into_path(&self.session);
+        self.path.follow(p);
         self.clone()
     }
 
 
     ///////////////////////////
     // FollowR(path: Path)
+    // *FollowR(name: String)
     ///////////////////////////
-    pub fn follow_r(&mut self, ep: &Path) -> Path {
-        self.path.follow_reverse(ep.path.clone());
+    pub fn follow_r<F: Into<Follow>>(&mut self, ep: F) -> Path {
+        let p = ep.into().into_path(&self.session);
+        self.path.follow_reverse(p);
         self.clone()
     }
 
@@ -242,6 +506,31 @@ impl Path {
     }
 
 
+    ///////////////////////////
+    // ShortestPath(to: Path, via: String[], maxDepth: int, tags: String[])
+    ///////////////////////////
+    pub fn shortest_path<V: Into<path::Via>, T: Into<Tags>>(&mut self, to: &Path, via: V, max_depth: Option<i32>, tags: T) -> Path {
+        self.path.shortest_path(to.path.clone(), via.into(), max_depth.unwrap_or(iterator::shortest_path::DEFAULT_MAX_SHORTEST_PATH_DEPTH), tags.into().to_vec());
+        self.clone()
+    }
+
+    ///////////////////////////
+    // AllPaths(to: Path, via: String[], maxDepth: int, tag: String)
+    ///////////////////////////
+    pub fn all_paths<V: Into<path::Via>, T: Into<Tag>>(&mut self, to: &Path, via: V, max_depth: Option<i32>, tag: T) -> Path {
+        self.path.all_paths(to.path.clone(), via.into(), max_depth.unwrap_or(iterator::all_paths::DEFAULT_MAX_ALL_PATHS_DEPTH), false, all_paths_tag(tag.into()));
+        self.clone()
+    }
+
+    ///////////////////////////
+    // AllPathsBoth(to: Path, via: String[], maxDepth: int, tag: String)
+    ///////////////////////////
+    pub fn all_paths_both<V: Into<path::Via>, T: Into<Tag>>(&mut self, to: &Path, via: V, max_depth: Option<i32>, tag: T) -> Path {
+        self.path.all_paths(to.path.clone(), via.into(), max_depth.unwrap_or(iterator::all_paths::DEFAULT_MAX_ALL_PATHS_DEPTH), true, all_paths_tag(tag.into()));
+        self.clone()
+    }
+
+
     ///////////////////////////
     // And(path: Path)
     ///////////////////////////
@@ -320,8 +609,9 @@ impl Path {
             HasObject::ValueFilters(f) => {
                 self.path.has_filter(predicate.into(), false, f.filters);
             },
-            HasObject::Values(v) => {
-                self.path.has(predicate.into(), false, v.to_vec());
+            HasObject::Values(v) => match v.into_values() {
+                Ok(values) => self.path.has(predicate.into(), false, values),
+                Err(name) => self.path.has_param(predicate.into(), false, name)
             }
         }
         self.clone()
@@ -338,8 +628,9 @@ impl Path {
             HasObject::ValueFilters(f) => {
                 self.path.has_filter(predicate.into(), true, f.filters);
             },
-            HasObject::Values(v) => {
-                self.path.has(predicate.into(), true, v.to_vec());
+            HasObject::Values(v) => match v.into_values() {
+                Ok(values) => self.path.has(predicate.into(), true, values),
+                Err(name) => self.path.has_param(predicate.into(), true, name)
             }
         }
         self.clone()
@@ -492,6 +783,57 @@ impl Path {
         self.path.order();
         self.clone()
     }
+
+    ///////////////////////////
+    // OrderBy(keys: [tag: String, order: Order][])
+    ///////////////////////////
+    pub fn order_by<K: Into<SortKeys>>(&mut self, keys: K) -> Path {
+        self.path.order_by(keys.into().keys);
+        self.clone()
+    }
+}
+
+// Renders the path as Gizmo text, starting from g.M() for paths built with Graph::m.
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let start = if self.finals { "g.V" } else { "g.M" };
+        match self.path.morphism_asts() {
+            Ok(morphisms) => write!(f, "{}", ast::gizmo_text(&morphisms, start)),
+            Err(err) => write!(f, "/* {} */", err)
+        }
+    }
+}
+
+// GroupBy splits the results of a path by the value of a tag, each final returns one entry
+// per distinct value of the tag, in the order the values are first found. Results without
+// the tag are left out.
+pub struct GroupBy {
+    path: Path,
+    tag: String
+}
+
+impl GroupBy {
+    pub fn count(&self) -> Vec<(Value, i64)> {
+        self.path.aggregate(Some(&self.tag), None, Aggregate::Count).into_iter()
+            .map(|(k, v)| (k, v.as_i64().unwrap_or(0)))
+            .collect()
+    }
+
+    pub fn sum<S: Into<String>>(&self, tag: S) -> Vec<(Value, Value)> {
+        self.path.aggregate(Some(&self.tag), Some(&tag.into()), Aggregate::Sum)
+    }
+
+    pub fn min<S: Into<String>>(&self, tag: S) -> Vec<(Value, Value)> {
+        self.path.aggregate(Some(&self.tag), Some(&tag.into()), Aggregate::Min)
+    }
+
+    pub fn max<S: Into<String>>(&self, tag: S) -> Vec<(Value, Value)> {
+        self.path.aggregate(Some(&self.tag), Some(&tag.into()), Aggregate::Max)
+    }
+
+    pub fn avg<S: Into<String>>(&self, tag: S) -> Vec<(Value, Value)> {
+        self.path.aggregate(Some(&self.tag), Some(&tag.into()), Aggregate::Avg)
+    }
 }
 
 fn save_validate(via: &SaveVia, tag: &Tag) -> String {
@@ -513,25 +855,243 @@ fn save_validate(via: &SaveVia, tag: &Tag) -> String {
     }
 }
 
-fn ref_to_value(r: &Ref, qs: &dyn QuadStore) -> Option<Value> {
-    qs.name_of(r) 
+fn all_paths_tag(tag: Tag) -> String {
+    match tag {
+        Tag::Some(t) if !t.is_empty() => t,
+        _ => "path".to_string()
+    }
 }
 
-fn tags_to_value_map(m: &HashMap<String, Ref>, qs: &dyn QuadStore) -> Option<HashMap<String, Value>> {
-    let mut output_map = HashMap::new();
+// A placeholder for values given when a prepared path is run, see Path::prepare. Bound
+// values are only ever looked up as nodes, never parsed as part of a query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param(pub String);
+
+pub fn param<S: Into<String>>(name: S) -> Param {
+    Param(name.into())
+}
+
+// A path whose shape was built and optimized for the store once by Path::prepare. Each run
+// binds the values of its params to the shape's Lookups and builds the iterators from it.
+#[derive(Clone)]
+pub struct Prepared {
+    session: Rc<RefCell<Session>>,
+    shape: Rc<shape::ResolvedShape>,
+    // a param bound to another param is reported when the path is run
+    bindings: HashMap<String, Result<Vec<Value>, String>>
+}
+
+impl Prepared {
+    // the names of the params the path was built with
+    pub fn params(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.shape.lookups().iter().filter_map(|(name, _)| name.clone()).collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    pub fn bind<S: Into<String>, V: Into<Values>>(&self, name: S, values: V) -> Prepared {
+        let mut p = self.clone();
+        let name = name.into();
+        let values = values.into().into_values().map_err(|other| format!("param {} can't be bound to param {}", name, other));
+        p.bindings.insert(name, values);
+        p
+    }
+
+    fn build_iterator_tree(&self) -> Result<Rc<RefCell<dyn iterator::Shape>>, String> {
+        for (name, l) in self.shape.lookups().iter() {
+            if let Some(name) = name {
+                match self.bindings.get(name) {
+                    Some(Ok(values)) => l.borrow_mut().0 = values.clone(),
+                    Some(Err(err)) => return Err(err.clone()),
+                    None => return Err(format!("param {} is not bound", name))
+                }
+            }
+        }
+        let s = self.session.borrow();
+        let it = self.shape.build_iterator(s.qs.clone());
+        Ok(s.limit_memory(it))
+    }
+
+    // iter and iter_values return nothing when a param is not bound, or is bound to another
+    // param, try_iter and try_iter_values report it
+
+    pub fn iter(&self) -> impl Iterator<Item = HashMap<String, Value>> {
+        let it = self.build_iterator_tree().unwrap_or_else(|_| iterator::Null::new());
+        rows_of(&self.session, it)
+    }
+
+    pub fn iter_values(&self) -> impl Iterator<Item = Value> {
+        let it = self.build_iterator_tree().unwrap_or_else(|_| iterator::Null::new());
+        values_of(&self.session, it)
+    }
+
+    pub fn try_iter(&self) -> Result<Vec<HashMap<String, Value>>, QueryError> {
+        try_rows_of(&self.session, self.build_iterator_tree()?)
+    }
+
+    pub fn try_iter_values(&self) -> Result<Vec<Value>, QueryError> {
+        try_values_of(&self.session, self.build_iterator_tree()?)
+    }
+
+    pub fn count(&self) -> i64 {
+        match self.build_iterator_tree() {
+            Ok(it) => self.session.borrow_mut().run_each_iterator(it).count() as i64,
+            Err(_) => 0
+        }
+    }
+}
+
+
+fn rows_of(session: &Rc<RefCell<Session>>, it: Rc<RefCell<dyn iterator::Shape>>) -> impl Iterator<Item = HashMap<String, Value>> {
+    let it = iterator::save::tag(&it, &"id");
+    let qs = session.borrow().qs.clone();
+    let names = session.borrow().names.clone();
+    let rows = session.borrow_mut().run_tag_each_iterator(it);
+    Pages::new(rows, RESULT_PAGE_SIZE).flat_map(move |page| tags_to_value_maps(page, &*qs.borrow(), &mut names.borrow_mut()))
+}
+
+fn values_of(session: &Rc<RefCell<Session>>, it: Rc<RefCell<dyn iterator::Shape>>) -> impl Iterator<Item = Value> {
+    let qs = session.borrow().qs.clone();
+    let names = session.borrow().names.clone();
+    let rows = session.borrow_mut().run_each_iterator(it);
+    Pages::new(rows, RESULT_PAGE_SIZE).flat_map(move |page| refs_to_values(page, &*qs.borrow(), &mut names.borrow_mut()))
+}
+
+fn try_rows_of(session: &Rc<RefCell<Session>>, it: Rc<RefCell<dyn iterator::Shape>>) -> Result<Vec<HashMap<String, Value>>, QueryError> {
+    let it = iterator::save::tag(&it, &"id");
+    session.borrow().limit_failures.take();
+    let mut rows = session.borrow_mut().run_tag_each_iterator(it);
+    let found: Vec<HashMap<String, Ref>> = rows.by_ref().collect();
+    if let Some(err) = rows.err() {
+        return Err(session.borrow().query_error(err))
+    }
+    let qs = session.borrow().qs.clone();
+    let names = session.borrow().names.clone();
+    let mut values = Vec::new();
+    for page in found.chunks(RESULT_PAGE_SIZE) {
+        values.extend(tags_to_value_maps(page.to_vec(), &*qs.borrow(), &mut names.borrow_mut()));
+    }
+    Ok(values)
+}
+
+fn try_values_of(session: &Rc<RefCell<Session>>, it: Rc<RefCell<dyn iterator::Shape>>) -> Result<Vec<Value>, QueryError> {
+    session.borrow().limit_failures.take();
+    let mut rows = session.borrow_mut().run_each_iterator(it);
+    let found: Vec<Ref> = rows.by_ref().collect();
+    if let Some(err) = rows.err() {
+        return Err(session.borrow().query_error(err))
+    }
+    let qs = session.borrow().qs.clone();
+    let names = session.borrow().names.clone();
+    let mut values = Vec::new();
+    for page in found.chunks(RESULT_PAGE_SIZE) {
+        values.extend(refs_to_values(page.to_vec(), &*qs.borrow(), &mut names.borrow_mut()));
+    }
+    Ok(values)
+}
+
+
+// Pages groups the rows of a result so that their names are looked up together
+struct Pages<I: Iterator> {
+    it: I,
+    size: usize,
+    done: bool
+}
+
+impl<I: Iterator> Pages<I> {
+    fn new(it: I, size: usize) -> Pages<I> {
+        Pages {
+            it,
+            size,
+            done: false
+        }
+    }
+}
+
+impl<I: Iterator> Iterator for Pages<I> {
+    type Item = Vec<I::Item>;
+
+    fn next(&mut self) -> Option<Vec<I::Item>> {
+        if self.done {
+            return None
+        }
+        let page: Vec<I::Item> = self.it.by_ref().take(self.size).collect();
+        // the row iterators close themselves when exhausted, don't poll them again
+        self.done = page.len() < self.size;
+        if page.is_empty() {
+            return None
+        }
+        Some(page)
+    }
+}
 
-    for (key, value) in m {
-        match qs.name_of(value) {
-            Some(v) => { output_map.insert(key.clone(), v); },
-            None => {}
+// names every ref with one names_of call on the store, refs that carry their value or
+// whose name is cached are not sent to the store
+fn names_of(refs: &[&Ref], qs: &dyn QuadStore, cache: &mut NameCache) -> Vec<Option<Value>> {
+    let mut names = vec![None; refs.len()];
+    let mut lookups: Vec<Ref> = Vec::new();
+    let mut positions: HashMap<&Key, usize> = HashMap::new();
+    let mut wanted: Vec<(usize, usize)> = Vec::new();
+
+    for (i, r) in refs.iter().enumerate() {
+        if let Content::Value(v) = &r.content {
+            names[i] = Some(v.clone());
+            continue
+        }
+        if let Some(v) = r.key().and_then(|k| cache.get(k)) {
+            names[i] = Some(v);
+            continue
+        }
+        let pos = match r.key().and_then(|k| positions.get(k)) {
+            Some(pos) => *pos,
+            None => {
+                lookups.push((*r).clone());
+                if let Some(k) = r.key() {
+                    positions.insert(k, lookups.len() - 1);
+                }
+                lookups.len() - 1
+            }
         };
+        wanted.push((i, pos));
     }
-    
-    if output_map.is_empty() {
-        return None
+
+    if lookups.is_empty() {
+        return names
     }
 
-    return Some(output_map)
+    let found = qs.names_of(&lookups);
+    for (r, v) in lookups.iter().zip(found.iter()) {
+        if let (Some(k), Some(v)) = (r.key(), v) {
+            cache.insert(k.clone(), v.clone());
+        }
+    }
+    for (i, pos) in wanted {
+        names[i] = found[pos].clone();
+    }
+    names
+}
+
+fn refs_to_values(rows: Vec<Ref>, qs: &dyn QuadStore, cache: &mut NameCache) -> Vec<Value> {
+    let refs: Vec<&Ref> = rows.iter().collect();
+    names_of(&refs, qs, cache).into_iter().flatten().collect()
+}
+
+fn tags_to_value_maps(rows: Vec<HashMap<String, Ref>>, qs: &dyn QuadStore, cache: &mut NameCache) -> Vec<HashMap<String, Value>> {
+    let refs: Vec<&Ref> = rows.iter().flat_map(|row| row.values()).collect();
+    let mut names = names_of(&refs, qs, cache).into_iter();
+
+    rows.iter().filter_map(|row| {
+        let output_map: HashMap<String, Value> = row.keys()
+            .zip(names.by_ref())
+            .filter_map(|(k, v)| v.map(|v| (k.clone(), v)))
+            .collect();
+
+        if output_map.is_empty() {
+            return None
+        }
+        Some(output_map)
+    }).collect()
 }
 
 
@@ -580,18 +1140,27 @@ impl From<&str> for Tags {
 
 pub enum Values {
     None,
-    Some(Vec<Value>)
+    Some(Vec<Value>),
+    Param(String)
 }
 
 impl Values {
-    pub fn to_vec(self) -> Vec<Value> {
+    // the values, or the name of the param they stand for
+    pub fn into_values(self) -> Result<Vec<Value>, String> {
         match self {
-            Values::None => Vec::new(),
-            Values::Some(v) => v
+            Values::None => Ok(Vec::new()),
+            Values::Some(v) => Ok(v),
+            Values::Param(name) => Err(name)
         }
     }
 }
 
+impl From<Param> for Values {
+    fn from(p: Param) -> Self {
+        Values::Param(p.0)
+    }
+}
+
 impl From<Option<Value>> for Values {
     fn from(v: Option<Value>) -> Self {
         match v {
@@ -632,11 +1201,57 @@ impl From<String> for Values {
 }
 
 
+// A path to follow, or the name of a morphism defined with Graph::define_morphism.
+pub enum Follow {
+    Path(path::Path),
+    Named(String)
+}
+
+impl Follow {
+    // a name that isn't defined, or doesn't load, follows a path that finds nothing and
+    // reports why through try_iter
+    fn into_path(self, session: &Rc<RefCell<Session>>) -> path::Path {
+        match self {
+            Follow::Path(p) => p,
+            Follow::Named(name) => match session.borrow().morphisms.get(&name) {
+                Ok(Some(p)) => p,
+                Ok(None) => path::Path::error(format!("no morphism named {}", name)),
+                Err(err) => path::Path::error(format!("can't load morphism {}: {}", name, err))
+            }
+        }
+    }
+}
+
+impl From<&Path> for Follow {
+    fn from(p: &Path) -> Self {
+        Follow::Path(p.path.clone())
+    }
+}
+
+impl From<&str> for Follow {
+    fn from(name: &str) -> Self {
+        Follow::Named(name.to_string())
+    }
+}
+
+impl From<String> for Follow {
+    fn from(name: String) -> Self {
+        Follow::Named(name)
+    }
+}
+
+
 pub enum HasObject {
     ValueFilters(ValueFilters),
     Values(Values)
 }
 
+impl From<Param> for HasObject {
+    fn from(p: Param) -> Self {
+        HasObject::Values(Values::Param(p.0))
+    }
+}
+
 impl From<Rc<dyn shape::ValueFilter>> for HasObject {
     fn from(f: Rc<dyn shape::ValueFilter>) -> Self {
         HasObject::ValueFilters(
@@ -760,6 +1375,35 @@ impl From<&str> for Tag {
 
 
 
+// The tag "id" names the current node, as it does in the results of iter().
+pub struct SortKeys {
+    pub keys: Vec<SortKey>
+}
+
+fn sort_key(tag: &str, order: Order) -> SortKey {
+    if tag == "id" { SortKey::node(order) } else { SortKey::tag(tag, order) }
+}
+
+impl From<&str> for SortKeys {
+    fn from(v: &str) -> Self {
+        SortKeys { keys: vec![sort_key(v, Order::Asc)] }
+    }
+}
+
+impl From<(&str, Order)> for SortKeys {
+    fn from(v: (&str, Order)) -> Self {
+        SortKeys { keys: vec![sort_key(v.0, v.1)] }
+    }
+}
+
+impl From<Vec<(&str, Order)>> for SortKeys {
+    fn from(v: Vec<(&str, Order)>) -> Self {
+        SortKeys { keys: v.into_iter().map(|(t, o)| sort_key(t, o)).collect() }
+    }
+}
+
+
+
 #[derive(Clone)]
 pub enum SaveVia {
     Value(Value),
@@ -824,4 +1468,17 @@ pub fn regex<S: Into<String>>(pattern: S, iri: bool) -> Rc<dyn shape::ValueFilte
 
 pub fn like<S: Into<String>>(pattern: S) -> Rc<dyn shape::ValueFilter> {
     Rc::new(shape::Wildcard::new(pattern.into()))
-}
\ No newline at end of file
+}
+
+
+
+/////////////////////
+// Path Steps
+/////////////////////
+
+// Reads the route saved by AllPaths under `tag` from a result row. The first step is the
+// start node and has no predicate, every following step holds the quad used to reach it.
+// A row with no route, or one missing a step, is an error.
+pub fn path_steps(row: &HashMap<String, Value>, tag: &str) -> Result<Vec<PathStep>, String> {
+    iterator::all_paths::route_steps(row, tag)
+}
@@ -0,0 +1,456 @@
+use crate::graph::value::Value;
+use crate::graph::quad::QuadStore;
+use super::shape::{Shape, AllNodes, Lookup, Unique, Page, Save, new_in_out, has_labels, save_via_labels, intersect_shapes};
+use super::results::{PARENT_TAG, rows, json_value};
+use serde_json::{Map, Number};
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::cell::RefCell;
+
+
+// The GraphQL-style language of Cayley. Every field is a predicate, written as an IRI
+// (`<follows>`) or a bare name read as the IRI of that name, and the fields of a field
+// are looked up on the nodes it reaches:
+//
+//   {
+//     nodes(id: "<alice>") {
+//       id
+//       <follows> @rev { id }
+//       status: <status> @opt
+//     }
+//   }
+//
+// `id` returns the node itself, or picks the nodes when given as an argument. `first` and
+// `offset` page the nodes of a field, a nested field for each node above it, other
+// arguments keep the nodes that have those values for the predicate. @rev follows the predicate from object to subject, @opt keeps
+// the nodes that miss the field, and @label(v: "<graph>") restricts the field and the
+// fields below it to the quads of those labels, @label alone lifts the restriction.
+// A field with more than one value returns them as an array.
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    // the key of the field in the results, its alias when it has one
+    pub name: String,
+    // the field as written, for root fields just a name for the results
+    pub field: String,
+    pub via: Value,
+    pub rev: bool,
+    pub opt: bool,
+    // None keeps the labels of the parent field, an empty list allows any label
+    pub labels: Option<Vec<Value>>,
+    pub ids: Vec<Value>,
+    pub has: Vec<(Value, Vec<Value>)>,
+    pub limit: i64,
+    pub skip: i64,
+    pub fields: Vec<Field>
+}
+
+impl Field {
+    fn new(name: String, field: String, via: Value) -> Field {
+        Field {
+            name,
+            field,
+            via,
+            rev: false,
+            opt: false,
+            labels: None,
+            ids: Vec::new(),
+            has: Vec::new(),
+            limit: 0,
+            skip: 0,
+            fields: Vec::new()
+        }
+    }
+
+    fn is_id(&self) -> bool {
+        self.field == "id" && self.fields.is_empty()
+    }
+
+    fn is_object(&self) -> bool {
+        !self.fields.is_empty()
+    }
+}
+
+
+// Runs the query against the store, returning an object with a list of results for each
+// root field.
+pub fn query(qs: Rc<RefCell<dyn QuadStore>>, text: &str) -> Result<serde_json::Value, String> {
+    let fields = parse(text)?;
+    let mut out = Map::new();
+    for f in &fields {
+        let objects = objects(&qs, AllNodes::new(), f, None, false)?.remove("").unwrap_or_default();
+        out.insert(f.name.clone(), serde_json::Value::Array(objects));
+    }
+    Ok(serde_json::Value::Object(out))
+}
+
+
+///////////////////////////////////////////////
+// execution
+///////////////////////////////////////////////
+
+fn label_shape(labels: &Option<Vec<Value>>) -> Option<Rc<RefCell<dyn Shape>>> {
+    labels.as_ref().map(|l| Lookup::new(l.clone()) as Rc<RefCell<dyn Shape>>)
+}
+
+fn values_shape(values: &[Value]) -> Rc<RefCell<dyn Shape>> {
+    if values.is_empty() {
+        AllNodes::new()
+    } else {
+        Lookup::new(values.to_vec())
+    }
+}
+
+fn field_labels(f: &Field, parent: &Option<Vec<Value>>) -> Option<Vec<Value>> {
+    match &f.labels {
+        None => parent.clone(),
+        Some(l) if l.is_empty() => None,
+        Some(l) => Some(l.clone())
+    }
+}
+
+// the nodes a field may reach, as far as its own arguments tell
+fn filter_shape(f: &Field, labels: &Option<Vec<Value>>) -> Rc<RefCell<dyn Shape>> {
+    let mut shape = values_shape(&f.ids);
+    for (via, values) in &f.has {
+        shape = has_labels(shape, Lookup::new(vec![via.clone()]), values_shape(values), label_shape(labels), false);
+    }
+    shape
+}
+
+// The objects of the field for the nodes of `from`, by the parent node saved under
+// PARENT_TAG, or under "" for a root field. A nested field is one query for the nodes of
+// every parent, its first and offset page the nodes of each parent.
+fn objects(qs: &Rc<RefCell<dyn QuadStore>>, from: Rc<RefCell<dyn Shape>>, f: &Field, parent_labels: Option<Vec<Value>>, nested: bool) -> Result<HashMap<String, Vec<serde_json::Value>>, String> {
+    let labels = field_labels(f, &parent_labels);
+
+    let mut shape = intersect_shapes(from, filter_shape(f, &labels));
+    // fields that aren't optional drop the nodes that miss them before the page is taken
+    for c in f.fields.iter().filter(|c| !c.opt && !c.is_id()) {
+        let child_labels = field_labels(c, &labels);
+        let nodes = if c.is_object() { filter_shape(c, &child_labels) } else { AllNodes::new() };
+        shape = has_labels(shape, Lookup::new(vec![c.via.clone()]), nodes, label_shape(&child_labels), c.rev);
+    }
+    if !nested && (f.limit > 0 || f.skip > 0) {
+        shape = Rc::new(RefCell::new(Page {
+            from: Rc::new(RefCell::new(Unique { from: shape })),
+            limit: f.limit,
+            skip: f.skip
+        }));
+    }
+    for c in f.fields.iter().filter(|c| !c.is_object() && !c.is_id()) {
+        let child_labels = field_labels(c, &labels);
+        shape = save_via_labels(shape, Lookup::new(vec![c.via.clone()]), label_shape(&child_labels), c.name.clone(), c.rev, c.opt);
+    }
+
+    let mut parents = rows(qs, shape)?;
+
+    if nested {
+        for (_, nodes) in parents.iter_mut() {
+            let skip = (f.skip as usize).min(nodes.len());
+            nodes.drain(..skip);
+            if f.limit > 0 {
+                nodes.truncate(f.limit as usize);
+            }
+        }
+    }
+
+    // each object field is one query, for the nodes of all the parents
+    let mut children = HashMap::new();
+    for c in f.fields.iter().filter(|c| c.is_object()) {
+        let nodes: Vec<Value> = parents.iter().flat_map(|(_, nodes)| nodes.iter().map(|(n, _)| n.clone())).collect();
+        let child_labels = field_labels(c, &labels);
+        let from = Save::new(vec![PARENT_TAG.to_string()], Some(Lookup::new(nodes)));
+        let from = new_in_out(from, Lookup::new(vec![c.via.clone()]), label_shape(&child_labels), None, c.rev);
+        children.insert(c.name.clone(), objects(qs, from, c, labels.clone(), true)?);
+    }
+
+    let mut out = HashMap::new();
+    for (parent, nodes) in parents {
+        let mut objs = Vec::new();
+        for (node, tags) in nodes {
+            let mut obj = Map::new();
+            for c in &f.fields {
+                let value = if c.is_id() {
+                    json_value(&node)
+                } else if c.is_object() {
+                    let found = children[&c.name].get(&node.to_string()).cloned().unwrap_or_default();
+                    match one_or_many(found) {
+                        Some(v) => v,
+                        None => continue
+                    }
+                } else {
+                    let values = tags.get(&c.name).map(|v| v.iter().map(json_value).collect()).unwrap_or_default();
+                    match one_or_many(values) {
+                        Some(v) => v,
+                        None => continue
+                    }
+                };
+                obj.insert(c.name.clone(), value);
+            }
+            objs.push(serde_json::Value::Object(obj));
+        }
+        out.insert(parent, objs);
+    }
+    Ok(out)
+}
+
+fn one_or_many(mut values: Vec<serde_json::Value>) -> Option<serde_json::Value> {
+    match values.len() {
+        0 => None,
+        1 => values.pop(),
+        _ => Some(serde_json::Value::Array(values))
+    }
+}
+
+
+
+///////////////////////////////////////////////
+// parsing
+///////////////////////////////////////////////
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Punct(char),
+    Name(String),
+    Iri(String),
+    Str(String),
+    Number(Number)
+}
+
+fn tokens(text: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() || c == ',' {
+            i += 1;
+        } else if c == '#' {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if "{}():@[]".contains(c) {
+            out.push(Token::Punct(c));
+            i += 1;
+        } else if c == '<' {
+            let end = (i..chars.len()).find(|&j| chars[j] == '>').ok_or_else(|| format!("unterminated IRI at {}", i))?;
+            out.push(Token::Iri(chars[i + 1..end].iter().collect()));
+            i = end + 1;
+        } else if c == '"' {
+            let mut s = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err("unterminated string".to_string()),
+                    Some('"') => break,
+                    Some('\\') => {
+                        match chars.get(i + 1) {
+                            Some('n') => s.push('\n'),
+                            Some('t') => s.push('\t'),
+                            Some(e) => s.push(*e),
+                            None => return Err("unterminated string".to_string())
+                        }
+                        i += 2;
+                    },
+                    Some(ch) => {
+                        s.push(*ch);
+                        i += 1;
+                    }
+                }
+            }
+            out.push(Token::Str(s));
+            i += 1;
+        } else if c == '-' || c.is_ascii_digit() {
+            let start = i;
+            i += 1;
+            while i < chars.len() && (chars[i].is_ascii_digit() || ".eE+-".contains(chars[i])) {
+                i += 1;
+            }
+            let s: String = chars[start..i].iter().collect();
+            let n = serde_json::from_str::<Number>(&s).map_err(|_| format!("bad number {}", s))?;
+            out.push(Token::Number(n));
+        } else if c == '_' || c.is_alphabetic() {
+            let start = i;
+            while i < chars.len() && (chars[i] == '_' || chars[i].is_alphanumeric()) {
+                i += 1;
+            }
+            out.push(Token::Name(chars[start..i].iter().collect()));
+        } else {
+            return Err(format!("unexpected character {:?} at {}", c, i))
+        }
+    }
+
+    Ok(out)
+}
+
+// the most selections and lists that can be nested in each other, deeper queries are an
+// error rather than a stack overflow
+const MAX_DEPTH: usize = 64;
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    depth: usize
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let t = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        t
+    }
+
+    fn is(&self, c: char) -> bool {
+        self.peek() == Some(&Token::Punct(c))
+    }
+
+    fn enter(&mut self) -> Result<(), String> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(format!("query nested deeper than {} levels", MAX_DEPTH))
+        }
+        Ok(())
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        match self.next() {
+            Some(Token::Punct(p)) if p == c => Ok(()),
+            t => Err(format!("expected {:?}, found {:?}", c, t))
+        }
+    }
+
+    // a field or argument name, with the predicate it stands for
+    fn name(&mut self) -> Result<(String, Value), String> {
+        match self.next() {
+            Some(Token::Name(n)) => Ok((n.clone(), Value::IRI(n))),
+            Some(Token::Iri(iri)) => Ok((format!("<{}>", iri), Value::IRI(iri))),
+            t => Err(format!("expected a name, found {:?}", t))
+        }
+    }
+
+    fn value(&mut self) -> Result<Vec<Value>, String> {
+        match self.next() {
+            Some(Token::Str(s)) => Ok(vec![Value::from(s)]),
+            Some(Token::Iri(iri)) => Ok(vec![Value::IRI(iri)]),
+            Some(Token::Number(n)) => Ok(vec![Value::Number(n)]),
+            Some(Token::Name(n)) if n == "true" => Ok(vec![Value::Bool(true)]),
+            Some(Token::Name(n)) if n == "false" => Ok(vec![Value::Bool(false)]),
+            Some(Token::Name(n)) if n == "null" => Ok(Vec::new()),
+            Some(Token::Punct('[')) => {
+                self.enter()?;
+                let mut values = Vec::new();
+                while !self.is(']') {
+                    if self.peek().is_none() {
+                        return Err("unterminated list".to_string())
+                    }
+                    values.extend(self.value()?);
+                }
+                self.pos += 1;
+                self.depth -= 1;
+                Ok(values)
+            },
+            t => Err(format!("expected a value, found {:?}", t))
+        }
+    }
+
+    fn arguments(&mut self) -> Result<Vec<(String, Value, Vec<Value>)>, String> {
+        let mut args = Vec::new();
+        if !self.is('(') {
+            return Ok(args)
+        }
+        self.pos += 1;
+        while !self.is(')') {
+            let (name, via) = self.name()?;
+            self.expect(':')?;
+            args.push((name, via, self.value()?));
+        }
+        self.pos += 1;
+        Ok(args)
+    }
+
+    fn selections(&mut self) -> Result<Vec<Field>, String> {
+        self.expect('{')?;
+        self.enter()?;
+        let mut fields = Vec::new();
+        while !self.is('}') {
+            if self.peek().is_none() {
+                return Err("expected }".to_string())
+            }
+            fields.push(self.field()?);
+        }
+        self.pos += 1;
+        self.depth -= 1;
+        Ok(fields)
+    }
+
+    fn field(&mut self) -> Result<Field, String> {
+        let (name, via) = self.name()?;
+        let mut f = if self.is(':') {
+            self.pos += 1;
+            let (field, via) = self.name()?;
+            Field::new(name, field, via)
+        } else {
+            Field::new(name.clone(), name, via)
+        };
+
+        for (arg, via, values) in self.arguments()? {
+            match arg.as_str() {
+                "id" => f.ids = values,
+                "first" => f.limit = count(&arg, &values)?,
+                "offset" => f.skip = count(&arg, &values)?,
+                _ => f.has.push((via, values))
+            }
+        }
+
+        while self.is('@') {
+            self.pos += 1;
+            let (directive, _) = self.name()?;
+            let args = self.arguments()?;
+            match directive.as_str() {
+                "rev" | "reverse" => f.rev = true,
+                "opt" | "optional" => f.opt = true,
+                "label" => f.labels = Some(args.into_iter().flat_map(|(_, _, v)| v).collect()),
+                _ => return Err(format!("unknown directive @{}", directive))
+            }
+        }
+
+        if self.is('{') {
+            f.fields = self.selections()?;
+        }
+        Ok(f)
+    }
+}
+
+fn count(arg: &str, values: &[Value]) -> Result<i64, String> {
+    match values {
+        [v] => v.as_i64().filter(|n| *n >= 0).ok_or_else(|| format!("{} must be a count", arg)),
+        _ => Err(format!("{} must be a count", arg))
+    }
+}
+
+// Parses the query into its root fields. The query may be named, as `query name { ... }`.
+pub fn parse(text: &str) -> Result<Vec<Field>, String> {
+    let mut p = Parser {
+        tokens: tokens(text)?,
+        pos: 0,
+        depth: 0
+    };
+    if p.peek() == Some(&Token::Name("query".to_string())) {
+        p.pos += 1;
+        if let Some(Token::Name(_)) = p.peek() {
+            p.pos += 1;
+        }
+    }
+    let fields = p.selections()?;
+    if let Some(t) = p.peek() {
+        return Err(format!("unexpected {:?} after the query", t))
+    }
+    Ok(fields)
+}
@@ -0,0 +1,109 @@
+use serde_json::Number;
+
+
+// The tokens of the SPARQL and Cypher queries and of Gizmo text, each reading the ones of
+// its syntax.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Token {
+    // a name or keyword
+    Word(String),
+    // a name in backticks, never a keyword
+    Quoted(String),
+    Var(String),
+    Iri(String),
+    Prefixed(String, String),
+    Str(String),
+    Number(Number),
+    Punct(&'static str)
+}
+
+// The string quoted by chars[start], with \n, \t and other escaped characters read, and
+// the position after its closing quote.
+pub(crate) fn string(chars: &[char], start: usize) -> Result<(String, usize), String> {
+    let quote = chars[start];
+    let mut s = String::new();
+    let mut i = start + 1;
+    loop {
+        match chars.get(i) {
+            None => return Err("unterminated string".to_string()),
+            Some(q) if *q == quote => break,
+            Some('\\') => {
+                match chars.get(i + 1) {
+                    Some('n') => s.push('\n'),
+                    Some('t') => s.push('\t'),
+                    Some(e) => s.push(*e),
+                    None => return Err("unterminated string".to_string())
+                }
+                i += 2;
+            },
+            Some(ch) => {
+                s.push(*ch);
+                i += 1;
+            }
+        }
+    }
+    Ok((s, i + 1))
+}
+
+// The number at chars[start], after an optional sign, and the position after it.
+pub(crate) fn number(chars: &[char], start: usize) -> Result<(Number, usize), String> {
+    let mut i = start;
+    if chars[i] == '-' || chars[i] == '+' {
+        i += 1;
+    }
+    while i < chars.len() && (chars[i].is_ascii_digit() || (chars[i] == '.' && chars.get(i + 1).is_some_and(|d| d.is_ascii_digit()))) {
+        i += 1;
+    }
+    let s: String = chars[start..i].iter().collect();
+    let n = serde_json::from_str::<Number>(s.trim_start_matches('+')).map_err(|_| format!("bad number {}", s))?;
+    Ok((n, i))
+}
+
+// Reading the tokens of a parser one at a time, keywords being case insensitive words.
+pub(crate) trait Tokens {
+    fn tokens(&self) -> &[Token];
+    fn pos(&self) -> usize;
+    fn set_pos(&mut self, pos: usize);
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens().get(self.pos())
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let t = self.peek().cloned();
+        self.set_pos(self.pos() + 1);
+        t
+    }
+
+    fn is(&self, p: &str) -> bool {
+        matches!(self.peek(), Some(Token::Punct(t)) if *t == p)
+    }
+
+    fn keyword(&self, k: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(w)) if w.eq_ignore_ascii_case(k))
+    }
+
+    fn expect(&mut self, p: &str) -> Result<(), String> {
+        match self.next() {
+            Some(Token::Punct(t)) if t == p => Ok(()),
+            t => Err(format!("expected {:?}, found {:?}", p, t))
+        }
+    }
+
+    fn expect_keyword(&mut self, k: &str) -> Result<(), String> {
+        if !self.keyword(k) {
+            return Err(format!("expected {}, found {:?}", k, self.peek()))
+        }
+        self.set_pos(self.pos() + 1);
+        Ok(())
+    }
+
+    // the count given to the keyword k, like LIMIT
+    fn count(&mut self, k: &str) -> Result<u64, String> {
+        match self.next() {
+            Some(Token::Number(n)) if n.is_u64() => Ok(n.as_u64().unwrap()),
+            Some(Token::Number(n)) => Err(format!("{} takes a count from 0 to {}, found {}", k, u64::MAX, n)),
+            t => Err(format!("{} takes a count, found {:?}", k, t))
+        }
+    }
+}
@@ -2,4 +2,16 @@ pub mod gizmo;
 // pub mod gizmo_wasm;
 pub mod path;
 pub mod shape;
-mod morphism;
\ No newline at end of file
+pub mod plan;
+pub mod ast;
+pub mod registry;
+pub mod graphql;
+pub mod mql;
+pub mod sparql;
+pub mod cypher;
+pub mod dsl;
+pub mod row;
+pub mod schema;
+mod lexer;
+mod morphism;
+mod results;
\ No newline at end of file
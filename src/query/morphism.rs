@@ -1,10 +1,12 @@
 
 use crate::graph::value::Value;
+use crate::graph::iterator::sort::SortKey;
 use std::cell::RefCell;
 use std::rc::Rc;
-use super::path::PathContext;
+use super::path::{PathContext, Fingerprint};
 use crate::query::shape::*;
 use crate::query::path::{Via, Path};
+use crate::query::ast::{MorphismAst, HasNodesAst};
 
 
 fn join(its: Vec<Rc<RefCell<dyn Shape>>>) -> Rc<RefCell<dyn Shape>> {
@@ -19,6 +21,10 @@ fn join(its: Vec<Rc<RefCell<dyn Shape>>>) -> Rc<RefCell<dyn Shape>> {
     return Intersect::new(its)
 }
 
+fn is_all_nodes(s: &Rc<RefCell<dyn Shape>>) -> bool {
+    matches!(s.borrow_mut().shape_type(), ShapeType::AllNodes)
+}
+
 
 //////////////////////////////////////////////////////////
 
@@ -27,37 +33,62 @@ pub trait Morphism {
     fn apply(&self, shape: Rc<RefCell<dyn Shape>>, ctx: &mut PathContext) -> (Rc<RefCell<dyn Shape>>, Option<PathContext>);
     fn is_tag(&self) -> bool { false }
     fn tags(&self) -> Option<Vec<String>> { None }
+    // adds the morphism to the fingerprint, returning false when it can't be fingerprinted
+    fn fingerprint(&self, _fp: &mut Fingerprint) -> bool { false }
+    fn ast(&self) -> Result<MorphismAst, String>;
 }
 
 pub struct IsMorphism {
-    nodes: Vec<Value>
+    nodes: Vec<Value>,
+    param: Option<String>
 }
 
 impl IsMorphism {
     pub fn new(nodes: Vec<Value>) -> Rc<dyn Morphism> {
         Rc::new(IsMorphism {
-            nodes
+            nodes,
+            param: None
+        })
+    }
+
+    pub fn new_param(name: String) -> Rc<dyn Morphism> {
+        Rc::new(IsMorphism {
+            nodes: Vec::new(),
+            param: Some(name)
         })
     }
 }
 
 impl Morphism for IsMorphism {
     fn reversal(&self, ctx: &mut PathContext) -> (Rc<dyn Morphism>, Option<PathContext>) {
-        (IsMorphism::new(self.nodes.clone()), None)
+        (Rc::new(IsMorphism { nodes: self.nodes.clone(), param: self.param.clone() }), None)
     }
 
     fn apply(&self, shape: Rc<RefCell<dyn Shape>>, ctx: &mut PathContext) -> (Rc<RefCell<dyn Shape>>, Option<PathContext>) {
         println!("IsMorphism apply() {:?}", self.nodes);
-        if self.nodes.is_empty() {
-            return (shape, None)
-        }
-        let s = Lookup::new(self.nodes.clone());
+        let s = match &self.param {
+            Some(name) => ctx.param(name),
+            None if self.nodes.is_empty() => return (shape, None),
+            None => ctx.lookup(self.nodes.clone())
+        };
         if let ShapeType::AllNodes = shape.borrow_mut().shape_type() {
             println!("IsMorphism AllNodes Shape type");
             return (s, None)
         }
         return (join(vec![s, shape]), None)
     }
+
+    fn fingerprint(&self, fp: &mut Fingerprint) -> bool {
+        fp.push("is");
+        if self.param.is_some() || !self.nodes.is_empty() {
+            fp.param(&self.nodes);
+        }
+        true
+    }
+
+    fn ast(&self) -> Result<MorphismAst, String> {
+        Ok(MorphismAst::Is { nodes: self.nodes.clone(), param: self.param.clone() })
+    }
 }
 
 //////////////////////////////////////////////////////////
@@ -83,12 +114,21 @@ impl Morphism for InMorphism {
 
     fn apply(&self, shape: Rc<RefCell<dyn Shape>>, ctx: &mut PathContext) -> (Rc<RefCell<dyn Shape>>, Option<PathContext>) {
         println!("InMorphism apply()");
-        return (new_in_out(shape, self.via.as_shape(), ctx.label_set.clone(), self.tags.clone(), true), None)
+        (new_in_out(shape, self.via.as_shape_in(ctx), ctx.label_set.clone(), self.tags.clone(), true), None)
     }
 
     fn tags(&self) -> Option<Vec<String>> { 
         self.tags.clone()
     }
+
+    fn fingerprint(&self, fp: &mut Fingerprint) -> bool {
+        fp.push(&format!("in{:?}", self.tags));
+        fp.via(&self.via)
+    }
+
+    fn ast(&self) -> Result<MorphismAst, String> {
+        Ok(MorphismAst::In { tags: self.tags.clone(), via: self.via.ast()? })
+    }
 }
 
 //////////////////////////////////////////////////////////
@@ -114,12 +154,21 @@ impl Morphism for OutMorphism {
 
     fn apply(&self, shape: Rc<RefCell<dyn Shape>>, ctx: &mut PathContext) -> (Rc<RefCell<dyn Shape>>, Option<PathContext>) {
         println!("OutMorphism apply()");
-        return (new_in_out(shape, self.via.as_shape(), ctx.label_set.clone(), self.tags.clone(), false), None)
+        (new_in_out(shape, self.via.as_shape_in(ctx), ctx.label_set.clone(), self.tags.clone(), false), None)
     }
 
     fn tags(&self) -> Option<Vec<String>> { 
         self.tags.clone()
     }
+
+    fn fingerprint(&self, fp: &mut Fingerprint) -> bool {
+        fp.push(&format!("out{:?}", self.tags));
+        fp.via(&self.via)
+    }
+
+    fn ast(&self) -> Result<MorphismAst, String> {
+        Ok(MorphismAst::Out { tags: self.tags.clone(), via: self.via.ast()? })
+    }
 }
 
 //////////////////////////////////////////////////////////
@@ -145,7 +194,7 @@ impl Morphism for BothMorphism {
 
     fn apply(&self, shape: Rc<RefCell<dyn Shape>>, ctx: &mut PathContext) -> (Rc<RefCell<dyn Shape>>, Option<PathContext>) {
         println!("BothMorphism apply()");
-        let via = self.via.as_shape();
+        let via = self.via.as_shape_in(ctx);
         return (Rc::new(RefCell::new(Union(vec![
             new_in_out(shape.clone(), via.clone(), ctx.label_set.clone(), self.tags.clone(), true),
             new_in_out(shape.clone(), via.clone(), ctx.label_set.clone(), self.tags.clone(), false)
@@ -155,6 +204,15 @@ impl Morphism for BothMorphism {
     fn tags(&self) -> Option<Vec<String>> { 
         self.tags.clone()
     }
+
+    fn fingerprint(&self, fp: &mut Fingerprint) -> bool {
+        fp.push(&format!("both{:?}", self.tags));
+        fp.via(&self.via)
+    }
+
+    fn ast(&self) -> Result<MorphismAst, String> {
+        Ok(MorphismAst::Both { tags: self.tags.clone(), via: self.via.ast()? })
+    }
 }
 
 //////////////////////////////////////////////////////////
@@ -178,7 +236,45 @@ impl Morphism for FollowMorphism {
 
     fn apply(&self, shape: Rc<RefCell<dyn Shape>>, ctx: &mut PathContext) -> (Rc<RefCell<dyn Shape>>, Option<PathContext>) {
         println!("FollowMorphism apply()");
-        (self.path.clone().shape_from(shape), None)
+        (self.path.clone().shape_from_in(shape, ctx), None)
+    }
+
+    fn fingerprint(&self, fp: &mut Fingerprint) -> bool {
+        fp.push("follow");
+        fp.path(&self.path)
+    }
+
+    fn ast(&self) -> Result<MorphismAst, String> {
+        Ok(MorphismAst::Follow { path: self.path.morphism_asts()? })
+    }
+}
+
+//////////////////////////////////////////////////////////
+
+// stands for a path that couldn't be made, such as a named morphism that isn't defined
+pub struct ErrorMorphism {
+    err: String
+}
+
+impl ErrorMorphism {
+    pub fn new(err: String) -> Rc<dyn Morphism> {
+        Rc::new(ErrorMorphism {
+            err
+        })
+    }
+}
+
+impl Morphism for ErrorMorphism {
+    fn reversal(&self, _ctx: &mut PathContext) -> (Rc<dyn Morphism>, Option<PathContext>) {
+        (ErrorMorphism::new(self.err.clone()), None)
+    }
+
+    fn apply(&self, _shape: Rc<RefCell<dyn Shape>>, _ctx: &mut PathContext) -> (Rc<RefCell<dyn Shape>>, Option<PathContext>) {
+        (Error::new(self.err.clone()), None)
+    }
+
+    fn ast(&self) -> Result<MorphismAst, String> {
+        Err(self.err.clone())
     }
 }
 
@@ -208,7 +304,149 @@ impl Morphism for FollowRecursiveMorphism {
 
     fn apply(&self, shape: Rc<RefCell<dyn Shape>>, ctx: &mut PathContext) -> (Rc<RefCell<dyn Shape>>, Option<PathContext>) {
         println!("FollowRecursiveMorphism apply()");
-        (self.path.clone().shape_from(shape), None)
+        (self.path.clone().shape_from_in(shape, ctx), None)
+    }
+
+    fn ast(&self) -> Result<MorphismAst, String> {
+        Ok(MorphismAst::FollowRecursive {
+            path: self.path.morphism_asts()?,
+            max_depth: self.max_depth,
+            depth_tags: self.depth_tags.clone()
+        })
+    }
+}
+
+//////////////////////////////////////////////////////////
+
+pub struct ShortestPathMorphism {
+    to: Path,
+    via: Via,
+    max_depth: i32,
+    rev: bool,
+    tags: Vec<String>
+}
+
+impl ShortestPathMorphism {
+    pub fn new(to: Path, via: Via, max_depth: i32, rev: bool, tags: Vec<String>) -> Rc<dyn Morphism> {
+        Rc::new(ShortestPathMorphism {
+            to,
+            via,
+            max_depth,
+            rev,
+            tags
+        })
+    }
+}
+
+impl Morphism for ShortestPathMorphism {
+    // the same search against the direction of the quads
+    fn reversal(&self, _ctx: &mut PathContext) -> (Rc<dyn Morphism>, Option<PathContext>) {
+        (ShortestPathMorphism::new(self.to.clone(), self.via.clone(), self.max_depth, !self.rev, self.tags.clone()), None)
+    }
+
+    fn apply(&self, shape: Rc<RefCell<dyn Shape>>, ctx: &mut PathContext) -> (Rc<RefCell<dyn Shape>>, Option<PathContext>) {
+        (
+            Rc::new(RefCell::new(ShortestPath {
+                route: Route {
+                    from: shape,
+                    to: self.to.shape_in(ctx),
+                    via: self.via.as_shape_in(ctx),
+                    labels: ctx.label_set.clone()
+                },
+                max_depth: self.max_depth,
+                rev: self.rev,
+                tags: self.tags.clone()
+            })),
+            None
+        )
+    }
+
+    fn tags(&self) -> Option<Vec<String>> {
+        Some(self.tags.clone())
+    }
+
+    fn fingerprint(&self, fp: &mut Fingerprint) -> bool {
+        fp.push(&format!("shortest_path{}{}{:?}", self.max_depth, self.rev, self.tags));
+        fp.path(&self.to) && fp.via(&self.via)
+    }
+
+    fn ast(&self) -> Result<MorphismAst, String> {
+        Ok(MorphismAst::ShortestPath {
+            to: self.to.morphism_asts()?,
+            via: self.via.ast()?,
+            max_depth: self.max_depth,
+            rev: self.rev,
+            tags: self.tags.clone()
+        })
+    }
+}
+
+//////////////////////////////////////////////////////////
+
+pub struct AllPathsMorphism {
+    to: Path,
+    via: Via,
+    max_depth: i32,
+    both: bool,
+    rev: bool,
+    tag: String
+}
+
+impl AllPathsMorphism {
+    pub fn new(to: Path, via: Via, max_depth: i32, both: bool, rev: bool, tag: String) -> Rc<dyn Morphism> {
+        Rc::new(AllPathsMorphism {
+            to,
+            via,
+            max_depth,
+            both,
+            rev,
+            tag
+        })
+    }
+}
+
+impl Morphism for AllPathsMorphism {
+    // the same search against the direction of the quads
+    fn reversal(&self, _ctx: &mut PathContext) -> (Rc<dyn Morphism>, Option<PathContext>) {
+        (AllPathsMorphism::new(self.to.clone(), self.via.clone(), self.max_depth, self.both, !self.rev, self.tag.clone()), None)
+    }
+
+    fn apply(&self, shape: Rc<RefCell<dyn Shape>>, ctx: &mut PathContext) -> (Rc<RefCell<dyn Shape>>, Option<PathContext>) {
+        (
+            Rc::new(RefCell::new(AllPaths {
+                route: Route {
+                    from: shape,
+                    to: self.to.shape_in(ctx),
+                    via: self.via.as_shape_in(ctx),
+                    labels: ctx.label_set.clone()
+                },
+                max_depth: self.max_depth,
+                both: self.both,
+                rev: self.rev,
+                tag: self.tag.clone()
+            })),
+            None
+        )
+    }
+
+    fn tags(&self) -> Option<Vec<String>> {
+        Some(vec![self.tag.clone()])
+    }
+
+    fn fingerprint(&self, fp: &mut Fingerprint) -> bool {
+        fp.push(&format!("all_paths{}{}{}{:?}", self.max_depth, self.both, self.rev, self.tag));
+        fp.path(&self.to) && fp.via(&self.via)
+    }
+
+    fn ast(&self) -> Result<MorphismAst, String> {
+        Ok(MorphismAst::AllPaths {
+            to: self.to.morphism_asts()?,
+            via: self.via.ast()?,
+            max_depth: self.max_depth,
+            both: self.both,
+            rev: self.rev,
+            tag: self.tag.clone()
+        })
     }
 }
 
@@ -233,7 +471,16 @@ impl Morphism for AndMorphism {
 
     fn apply(&self, shape: Rc<RefCell<dyn Shape>>, ctx: &mut PathContext) -> (Rc<RefCell<dyn Shape>>, Option<PathContext>) {
         println!("AndMorphism apply()");
-        (join(vec![shape, self.path.clone().shape()]), None)
+        (join(vec![shape, self.path.clone().shape_in(ctx)]), None)
+    }
+
+    fn fingerprint(&self, fp: &mut Fingerprint) -> bool {
+        fp.push("and");
+        fp.path(&self.path)
+    }
+
+    fn ast(&self) -> Result<MorphismAst, String> {
+        Ok(MorphismAst::And { path: self.path.morphism_asts()? })
     }
 }
 
@@ -258,7 +505,16 @@ impl Morphism for OrMorphism {
 
     fn apply(&self, shape: Rc<RefCell<dyn Shape>>, ctx: &mut PathContext) -> (Rc<RefCell<dyn Shape>>, Option<PathContext>) {
         println!("OrMorphism apply()");
-       (Rc::new(RefCell::new(Union(vec![shape, self.path.clone().shape()]))), None)
+       (Rc::new(RefCell::new(Union(vec![shape, self.path.clone().shape_in(ctx)]))), None)
+    }
+
+    fn fingerprint(&self, fp: &mut Fingerprint) -> bool {
+        fp.push("or");
+        fp.path(&self.path)
+    }
+
+    fn ast(&self) -> Result<MorphismAst, String> {
+        Ok(MorphismAst::Or { path: self.path.morphism_asts()? })
     }
 }
 
@@ -285,6 +541,10 @@ impl Morphism for FilterMorphism {
         println!("FilterMorphism apply()");
         (Filter::new(shape, self.filters.clone()), None)
     }
+
+    fn ast(&self) -> Result<MorphismAst, String> {
+        Ok(MorphismAst::Filter { filters: self.filters.iter().map(|f| f.ast()).collect() })
+    }
 }
 
 //////////////////////////////////////////////////////////
@@ -318,6 +578,15 @@ impl Morphism for TagMorphism {
     fn tags(&self) -> Option<Vec<String>> { 
         Some(self.tags.clone())
     }
+
+    fn fingerprint(&self, fp: &mut Fingerprint) -> bool {
+        fp.push(&format!("tag{:?}", self.tags));
+        true
+    }
+
+    fn ast(&self) -> Result<MorphismAst, String> {
+        Ok(MorphismAst::Tag { tags: self.tags.clone() })
+    }
 }
 
 //////////////////////////////////////////////////////////
@@ -347,13 +616,22 @@ impl Morphism for ExceptMorphism {
                     shape, 
                     Rc::new(RefCell::new(Except{
                         from: Some(AllNodes::new()), 
-                        exclude: Some(self.path.shape())
+                        exclude: Some(self.path.shape_in(ctx))
                     }))
                 ]
             ), 
             None
         )
     }
+
+    fn fingerprint(&self, fp: &mut Fingerprint) -> bool {
+        fp.push("except");
+        fp.path(&self.path)
+    }
+
+    fn ast(&self) -> Result<MorphismAst, String> {
+        Ok(MorphismAst::Except { path: self.path.morphism_asts()? })
+    }
 }
 
 //////////////////////////////////////////////////////////
@@ -380,6 +658,15 @@ impl Morphism for UniqueMorphism {
             None
         )
     }
+
+    fn fingerprint(&self, fp: &mut Fingerprint) -> bool {
+        fp.push("unique");
+        true
+    }
+
+    fn ast(&self) -> Result<MorphismAst, String> {
+        Ok(MorphismAst::Unique)
+    }
 }
 
 //////////////////////////////////////////////////////////
@@ -387,7 +674,8 @@ impl Morphism for UniqueMorphism {
 pub struct HasShapeMorphism {
     via: Via,
     rev: bool,
-    nodes: Rc<RefCell<dyn Shape>>
+    nodes: Rc<RefCell<dyn Shape>>,
+    param: Option<String>
 }
 
 impl HasShapeMorphism {
@@ -401,6 +689,15 @@ impl HasShapeMorphism {
         HasShapeMorphism::new(via, rev, node) 
     }
 
+    pub fn new_has_param_morphism(via: Via, rev: bool, name: String) -> Rc<dyn Morphism> {
+        Rc::new(HasShapeMorphism {
+            via,
+            rev,
+            nodes: Lookup::new(Vec::new()),
+            param: Some(name)
+        })
+    }
+
     pub fn new_has_filter_morphism(via: Via, rev: bool, nodes: Vec<Rc<dyn ValueFilter>>) -> Rc<dyn Morphism> {
         HasShapeMorphism::new(via, rev, Filter::new(AllNodes::new(), nodes)) 
     }
@@ -409,29 +706,72 @@ impl HasShapeMorphism {
         Rc::new(HasShapeMorphism {
             via,
             rev,
-            nodes
+            nodes,
+            param: None
         })
     }
 }
 
 impl Morphism for HasShapeMorphism {
     fn reversal(&self, ctx: &mut PathContext) -> (Rc<dyn Morphism>, Option<PathContext>) {
-        (HasShapeMorphism::new(self.via.clone(), self.rev, self.nodes.clone()), None)
+        (Rc::new(HasShapeMorphism {
+            via: self.via.clone(),
+            rev: self.rev,
+            nodes: self.nodes.clone(),
+            param: self.param.clone()
+        }), None)
     }
 
     fn apply(&self, r#in: Rc<RefCell<dyn Shape>>, ctx: &mut PathContext) -> (Rc<RefCell<dyn Shape>>, Option<PathContext>) {
         println!("HasShapeMorphism apply()");
+        let via = self.via.as_shape_in(ctx);
+        // a Lookup is built afresh so shapes built from the path don't share it
+        let looked_up = match self.nodes.borrow_mut().shape_type() {
+            ShapeType::Lookup(l) => Some(l.0.clone()),
+            _ => None
+        };
+        let nodes: Rc<RefCell<dyn Shape>> = match (&self.param, looked_up) {
+            (Some(name), _) => ctx.param(name),
+            (None, Some(values)) => ctx.lookup(values),
+            (None, None) => self.nodes.clone()
+        };
         ( 
             has_labels(
                 r#in,
-                self.via.as_shape(),
-                self.nodes.clone(),
+                via,
+                nodes,
                 ctx.label_set.clone(),
                 self.rev
             ), 
             None
         )
     }
+
+    fn fingerprint(&self, fp: &mut Fingerprint) -> bool {
+        fp.push(&format!("has{}", self.rev));
+        if !fp.via(&self.via) {
+            return false
+        }
+        match self.nodes.borrow_mut().shape_type() {
+            ShapeType::AllNodes => fp.push("*"),
+            ShapeType::Lookup(l) => fp.param(&l.0),
+            _ => return false
+        }
+        true
+    }
+
+    fn ast(&self) -> Result<MorphismAst, String> {
+        let nodes = match (&self.param, self.nodes.borrow_mut().shape_type()) {
+            (Some(name), _) => HasNodesAst::Param { name: name.clone() },
+            (None, ShapeType::AllNodes) => HasNodesAst::Any,
+            (None, ShapeType::Lookup(l)) => HasNodesAst::Values { values: l.0.clone() },
+            (None, ShapeType::Filter(f)) if is_all_nodes(&f.from) => HasNodesAst::Filters {
+                filters: f.filters.iter().map(|f| f.ast()).collect()
+            },
+            (None, other) => return Err(format!("can't serialize Has on a {} shape", other))
+        };
+        Ok(MorphismAst::Has { via: self.via.ast()?, rev: self.rev, nodes })
+    }
 }
 
 //////////////////////////////////////////////////////////
@@ -465,6 +805,15 @@ impl Morphism for LimitMorphism {
             None
         )
     }
+
+    fn fingerprint(&self, fp: &mut Fingerprint) -> bool {
+        fp.push(&format!("limit{}", self.limit));
+        true
+    }
+
+    fn ast(&self) -> Result<MorphismAst, String> {
+        Ok(MorphismAst::Limit { limit: self.limit })
+    }
 }
 
 //////////////////////////////////////////////////////////
@@ -498,30 +847,52 @@ impl Morphism for SkipMorphism {
             None
         )
     }
+
+    fn fingerprint(&self, fp: &mut Fingerprint) -> bool {
+        fp.push(&format!("skip{}", self.offset));
+        true
+    }
+
+    fn ast(&self) -> Result<MorphismAst, String> {
+        Ok(MorphismAst::Skip { offset: self.offset })
+    }
 }
 
 //////////////////////////////////////////////////////////
 
-pub struct OrderMorphism ();
+pub struct OrderMorphism {
+    keys: Vec<SortKey>
+}
 
 impl OrderMorphism {
-    pub fn new() -> Rc<dyn Morphism> {
-        Rc::new(OrderMorphism())
+    pub fn new(keys: Vec<SortKey>) -> Rc<dyn Morphism> {
+        Rc::new(OrderMorphism {
+            keys
+        })
     }
 }
 
 impl Morphism for OrderMorphism {
     fn reversal(&self, ctx: &mut PathContext) -> (Rc<dyn Morphism>, Option<PathContext>) {
-        (OrderMorphism::new(), None)
+        (OrderMorphism::new(self.keys.clone()), None)
     }
 
     fn apply(&self, shape: Rc<RefCell<dyn Shape>>, ctx: &mut PathContext) -> (Rc<RefCell<dyn Shape>>, Option<PathContext>) {
         println!("OrderMorphism apply()");
         ( 
-            Rc::new(RefCell::new(Sort{from: shape})), 
+            Rc::new(RefCell::new(Sort{from: shape, keys: self.keys.clone()})), 
             None
         )
     }
+
+    fn fingerprint(&self, fp: &mut Fingerprint) -> bool {
+        fp.push(&format!("order{:?}", self.keys));
+        true
+    }
+
+    fn ast(&self) -> Result<MorphismAst, String> {
+        Ok(MorphismAst::Order { keys: self.keys.clone() })
+    }
 }
 
 //////////////////////////////////////////////////////////
@@ -552,10 +923,19 @@ impl Morphism for SaveMorphism {
     fn apply(&self, shape: Rc<RefCell<dyn Shape>>, ctx: &mut PathContext) -> (Rc<RefCell<dyn Shape>>, Option<PathContext>) {
         println!("SaveMorphism apply()");
         ( 
-            save_via_labels(shape, self.via.as_shape(), ctx.label_set.clone(), self.tag.clone(), self.rev, self.opt), 
+            save_via_labels(shape, self.via.as_shape_in(ctx), ctx.label_set.clone(), self.tag.clone(), self.rev, self.opt), 
             None
         )
     }
+
+    fn fingerprint(&self, fp: &mut Fingerprint) -> bool {
+        fp.push(&format!("save{:?}{}{}", self.tag, self.rev, self.opt));
+        fp.via(&self.via)
+    }
+
+    fn ast(&self) -> Result<MorphismAst, String> {
+        Ok(MorphismAst::Save { via: self.via.ast()?, tag: self.tag.clone(), rev: self.rev, opt: self.opt })
+    }
 }
 
 //////////////////////////////////////////////////////////
@@ -584,6 +964,15 @@ impl Morphism for PredicatesMorphism {
             None
         )
     }
+
+    fn fingerprint(&self, fp: &mut Fingerprint) -> bool {
+        fp.push(&format!("predicates{}", self.rev));
+        true
+    }
+
+    fn ast(&self) -> Result<MorphismAst, String> {
+        Ok(MorphismAst::Predicates { rev: self.rev })
+    }
 }
 
 //////////////////////////////////////////////////////////
@@ -614,6 +1003,15 @@ impl Morphism for SavePredicatesMorphism {
             None
         )
     }
+
+    fn fingerprint(&self, fp: &mut Fingerprint) -> bool {
+        fp.push(&format!("save_predicates{:?}{}", self.tag, self.rev));
+        true
+    }
+
+    fn ast(&self) -> Result<MorphismAst, String> {
+        Ok(MorphismAst::SavePredicates { tag: self.tag.clone(), rev: self.rev })
+    }
 }
 
 //////////////////////////////////////////////////////////
@@ -638,6 +1036,15 @@ impl Morphism for LabelsMorphism {
             None
         )
     }
+
+    fn fingerprint(&self, fp: &mut Fingerprint) -> bool {
+        fp.push("labels");
+        true
+    }
+
+    fn ast(&self) -> Result<MorphismAst, String> {
+        Ok(MorphismAst::Labels)
+    }
 }
 
 //////////////////////////////////////////////////////////
@@ -674,9 +1081,21 @@ impl Morphism for LabelContextMorphism {
     fn apply(&self, shape: Rc<RefCell<dyn Shape>>, ctx: &mut PathContext) -> (Rc<RefCell<dyn Shape>>, Option<PathContext>) {
         println!("LabelContextMorphism apply()");
         let mut out = ctx.clone();
-        out.label_set = self.path.clone();
+        out.label_set = match self.via {
+            Via::None => None,
+            _ => Some(self.via.as_shape_in(ctx))
+        };
         ( shape, Some(out) )
     }
+
+    fn fingerprint(&self, fp: &mut Fingerprint) -> bool {
+        fp.push(&format!("label_context{:?}", self.tags));
+        fp.via(&self.via)
+    }
+
+    fn ast(&self) -> Result<MorphismAst, String> {
+        Ok(MorphismAst::LabelContext { via: self.via.ast()?, tags: self.tags.clone() })
+    }
 }
 
 //////////////////////////////////////////////////////////
\ No newline at end of file
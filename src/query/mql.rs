@@ -0,0 +1,270 @@
+use crate::graph::value::Value;
+use crate::graph::quad::QuadStore;
+use super::shape::{Shape, AllNodes, Lookup, Unique, Page, Save, new_in_out, has_labels, save_via_labels, intersect_shapes};
+use super::results::{PARENT_TAG, rows, json_value};
+use serde_json::Map;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::cell::RefCell;
+
+
+// MQL, the Metaweb Query Language of Cayley. A query is a JSON template of the objects
+// to find, and the results are the template filled in for every node that matches:
+//
+//   [{"id": null, "<status>": "cool_person", "<follows>": [{"id": null}], "name": null}]
+//
+// Keys are predicates, read like the strings of a quad so "<follows>" is an IRI, and a
+// key starting with ! follows its predicate from object to subject. The value of a key
+// says what is wanted:
+//
+//   null          the value of the predicate, null when the node has none
+//   []            all the values of the predicate
+//   "value"       only nodes with that value, a string, number or bool
+//   {...}         the object the predicate leads to, which must match the template
+//   [{...}]       all the objects the predicate leads to that match the template
+//
+// "id" is the node itself, given a value it picks the node. In an object template
+// "optional": true keeps the nodes that have no such object, and "limit": n returns at
+// most n objects. A template asking for one value or object is an error when there are
+// more than one, like it is in MQL.
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    // a query written as [{...}] returns a list, {...} a single object or null
+    pub list: bool,
+    pub root: Object
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Object {
+    pub id: Option<Value>,
+    pub optional: bool,
+    pub limit: i64,
+    pub fields: Vec<Field>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    // the key as written in the template
+    pub key: String,
+    pub via: Value,
+    pub rev: bool,
+    pub template: Template
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Template {
+    Id,
+    // None asks for the value, Some only keeps the nodes that have it
+    Value(Option<Value>),
+    Values,
+    Object(Object),
+    Objects(Object)
+}
+
+impl Template {
+    fn object(&self) -> Option<&Object> {
+        match self {
+            Template::Object(o) | Template::Objects(o) => Some(o),
+            _ => None
+        }
+    }
+}
+
+
+// Runs the query against the store, returning the template filled in with the results.
+pub fn query(qs: Rc<RefCell<dyn QuadStore>>, template: &serde_json::Value) -> Result<serde_json::Value, String> {
+    let q = parse(template)?;
+    let mut objects = objects(&qs, AllNodes::new(), &q.root, false)?.remove("").unwrap_or_default();
+    if q.list {
+        return Ok(serde_json::Value::Array(objects))
+    }
+    match objects.len() {
+        0 => Ok(serde_json::Value::Null),
+        1 => Ok(objects.pop().unwrap()),
+        n => Err(format!("expected one result, found {}, use [{{...}}] to get a list", n))
+    }
+}
+
+
+///////////////////////////////////////////////
+// parsing
+///////////////////////////////////////////////
+
+pub fn parse(template: &serde_json::Value) -> Result<Query, String> {
+    match template {
+        serde_json::Value::Object(o) => Ok(Query { list: false, root: object(o)? }),
+        serde_json::Value::Array(a) => match a.as_slice() {
+            [serde_json::Value::Object(o)] => Ok(Query { list: true, root: object(o)? }),
+            _ => Err("a list query holds a single object".to_string())
+        },
+        t => Err(format!("expected an object or a list of one object, found {}", t))
+    }
+}
+
+fn object(o: &Map<String, serde_json::Value>) -> Result<Object, String> {
+    let mut obj = Object {
+        id: None,
+        optional: false,
+        limit: 0,
+        fields: Vec::new()
+    };
+
+    for (key, t) in o {
+        match key.as_str() {
+            "optional" => {
+                obj.optional = t.as_bool().ok_or_else(|| format!("optional takes a bool, found {}", t))?;
+                continue
+            },
+            "limit" => {
+                obj.limit = t.as_u64().ok_or_else(|| format!("limit takes a count, found {}", t))? as i64;
+                continue
+            },
+            "id" => {
+                if !t.is_null() {
+                    obj.id = Some(value(t)?);
+                }
+                obj.fields.push(Field { key: key.clone(), via: Value::None, rev: false, template: Template::Id });
+                continue
+            },
+            _ => {}
+        }
+
+        let (pred, rev) = match key.strip_prefix('!') {
+            Some(pred) => (pred, true),
+            None => (key.as_str(), false)
+        };
+        if pred.is_empty() {
+            return Err(format!("{:?} is not a predicate", key))
+        }
+
+        let template = match t {
+            serde_json::Value::Null => Template::Value(None),
+            serde_json::Value::Object(o) => Template::Object(object(o)?),
+            serde_json::Value::Array(a) => match a.as_slice() {
+                [] => Template::Values,
+                [serde_json::Value::Object(o)] => Template::Objects(object(o)?),
+                _ => return Err(format!("{} takes [] or a list of one object", key))
+            },
+            v => Template::Value(Some(value(v)?))
+        };
+        obj.fields.push(Field { key: key.clone(), via: Value::from(pred.to_string()), rev, template });
+    }
+
+    Ok(obj)
+}
+
+fn value(v: &serde_json::Value) -> Result<Value, String> {
+    match v {
+        serde_json::Value::String(s) => Ok(Value::from(s.clone())),
+        serde_json::Value::Number(n) => Ok(Value::Number(n.clone())),
+        serde_json::Value::Bool(b) => Ok(Value::Bool(*b)),
+        v => Err(format!("expected a string, number or bool, found {}", v))
+    }
+}
+
+
+///////////////////////////////////////////////
+// compiling
+///////////////////////////////////////////////
+
+// The nodes that match the constraints of the object: its id, the values it asks for and
+// the objects that aren't optional, checked all the way down.
+pub fn compile(obj: &Object) -> Rc<RefCell<dyn Shape>> {
+    let mut shape = match &obj.id {
+        Some(id) => Lookup::new(vec![id.clone()]) as Rc<RefCell<dyn Shape>>,
+        None => AllNodes::new()
+    };
+    for f in &obj.fields {
+        let nodes = match &f.template {
+            Template::Value(Some(v)) => Lookup::new(vec![v.clone()]),
+            Template::Object(o) | Template::Objects(o) if !o.optional => compile(o),
+            _ => continue
+        };
+        shape = has_labels(shape, Lookup::new(vec![f.via.clone()]), nodes, None, f.rev);
+    }
+    shape
+}
+
+
+///////////////////////////////////////////////
+// results
+///////////////////////////////////////////////
+
+// The template filled in for each node of `from` that matches the object, by the parent
+// node saved under PARENT_TAG, or under "" for the root. A nested object is one query for
+// the nodes of every parent, its limit applies to the objects of each parent.
+fn objects(qs: &Rc<RefCell<dyn QuadStore>>, from: Rc<RefCell<dyn Shape>>, obj: &Object, nested: bool) -> Result<HashMap<String, Vec<serde_json::Value>>, String> {
+    let mut shape = intersect_shapes(from, compile(obj));
+    if !nested && obj.limit > 0 {
+        shape = Rc::new(RefCell::new(Page {
+            from: Rc::new(RefCell::new(Unique { from: shape })),
+            limit: obj.limit,
+            skip: 0
+        }));
+    }
+    for f in &obj.fields {
+        if let Template::Value(None) | Template::Values = f.template {
+            shape = save_via_labels(shape, Lookup::new(vec![f.via.clone()]), None, f.key.clone(), f.rev, true);
+        }
+    }
+
+    let mut parents = rows(qs, shape)?;
+    if nested && obj.limit > 0 {
+        for (_, nodes) in parents.iter_mut() {
+            nodes.truncate(obj.limit as usize);
+        }
+    }
+
+    // each object field is one query, for the nodes of all the parents
+    let mut children = HashMap::new();
+    for f in &obj.fields {
+        if let Template::Object(o) | Template::Objects(o) = &f.template {
+            let nodes: Vec<Value> = parents.iter().flat_map(|(_, nodes)| nodes.iter().map(|(n, _)| n.clone())).collect();
+            let from = Save::new(vec![PARENT_TAG.to_string()], Some(Lookup::new(nodes)));
+            let from = new_in_out(from, Lookup::new(vec![f.via.clone()]), None, None, f.rev);
+            children.insert(f.key.clone(), objects(qs, from, o, true)?);
+        }
+    }
+
+    let mut out = HashMap::new();
+    for (parent, nodes) in parents {
+        let mut objs = Vec::new();
+        for (node, tags) in nodes {
+            let mut result = Map::new();
+            for f in &obj.fields {
+                let value = match &f.template {
+                    Template::Id => json_value(&node),
+                    Template::Value(Some(v)) => json_value(v),
+                    Template::Value(None) | Template::Values => {
+                        let values = tags.get(&f.key).map(|v| v.iter().map(json_value).collect()).unwrap_or_default();
+                        fill(f, values)?
+                    },
+                    Template::Object(_) | Template::Objects(_) => {
+                        let found = children[&f.key].get(&node.to_string()).cloned().unwrap_or_default();
+                        fill(f, found)?
+                    }
+                };
+                result.insert(f.key.clone(), value);
+            }
+            objs.push(serde_json::Value::Object(result));
+        }
+        out.insert(parent, objs);
+    }
+    Ok(out)
+}
+
+fn fill(f: &Field, mut values: Vec<serde_json::Value>) -> Result<serde_json::Value, String> {
+    if let Template::Values | Template::Objects(_) = f.template {
+        return Ok(serde_json::Value::Array(values))
+    }
+    match values.len() {
+        0 => Ok(serde_json::Value::Null),
+        1 => Ok(values.pop().unwrap()),
+        n => {
+            let list = if f.template.object().is_some() { "[{...}]" } else { "[]" };
+            Err(format!("{} has {} values, use {} to get a list", f.key, n, list))
+        }
+    }
+}
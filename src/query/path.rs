@@ -1,21 +1,117 @@
 use crate::graph::value::Value;
 use crate::graph::iterator;
+use crate::graph::iterator::sort::{SortKey, Order};
 use crate::graph::quad::{Direction, QuadStore};
 //use crate::graph::iterator::Shape;
 use crate::query::shape::{Shape, AllNodes, Lookup, IteratorShape, build_iterator, ValueFilter};
 use std::rc::Rc;
 use std::cell::RefCell;
+use std::fmt;
 use super::morphism;
 use crate::query::gizmo;
+use crate::query::ast::{self, PathAst, MorphismAst, ViaAst, PATH_AST_VERSION};
 
 
 
 
 
 
-#[derive(Clone)]
+// the Lookups built for a path, with the name of the param each one is bound to
+pub type Lookups = Vec<(Option<String>, Rc<RefCell<Lookup>>)>;
+
+#[derive(Clone, Default)]
 pub struct PathContext {
-    pub label_set: Option<Rc<RefCell<dyn Shape>>>
+    pub label_set: Option<Rc<RefCell<dyn Shape>>>,
+    // when set, every Lookup built from the path's values is noted here, in build order
+    pub lookups: Option<Rc<RefCell<Lookups>>>
+}
+
+impl PathContext {
+    pub fn new() -> PathContext {
+        PathContext {
+            label_set: None,
+            lookups: None
+        }
+    }
+
+    pub fn lookup(&self, values: Vec<Value>) -> Rc<RefCell<Lookup>> {
+        let l = Lookup::new(values);
+        if let Some(lookups) = &self.lookups {
+            lookups.borrow_mut().push((None, l.clone()));
+        }
+        l
+    }
+
+    // an empty Lookup, standing for the values later bound to the param
+    pub fn param(&self, name: &str) -> Rc<RefCell<Lookup>> {
+        let l = Lookup::new(Vec::new());
+        if let Some(lookups) = &self.lookups {
+            lookups.borrow_mut().push((Some(name.to_string()), l.clone()));
+        }
+        l
+    }
+}
+
+
+// Fingerprint describes the structure of a path. The values the path looks up are left out
+// of the text and kept as params, in the order their Lookups are built, so paths that only
+// differ in those values share a fingerprint.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Fingerprint {
+    pub text: String,
+    pub params: Vec<Vec<Value>>
+}
+
+impl Fingerprint {
+    pub fn new() -> Fingerprint {
+        Fingerprint {
+            text: String::new(),
+            params: Vec::new()
+        }
+    }
+
+    pub fn push(&mut self, s: &str) {
+        self.text.push_str(s);
+    }
+
+    pub fn param(&mut self, values: &[Value]) {
+        self.text.push('?');
+        self.params.push(values.to_vec());
+    }
+
+    pub fn via(&mut self, via: &Via) -> bool {
+        match via {
+            Via::None => {
+                self.push("*");
+                true
+            },
+            Via::Values(values) => {
+                self.param(values);
+                true
+            },
+            Via::Param(_) => {
+                self.param(&[]);
+                true
+            },
+            Via::Path(path) => self.path(path)
+        }
+    }
+
+    // false when some part of the path can't be fingerprinted
+    pub fn path(&mut self, path: &Path) -> bool {
+        if path.base_context.label_set.is_some() {
+            return false
+        }
+        self.push("(");
+        for m in &path.stack {
+            if !m.fingerprint(self) {
+                return false
+            }
+            self.push(";");
+        }
+        self.push(")");
+        true
+    }
 }
 
 
@@ -28,6 +124,11 @@ pub struct Path {
 
 
 impl Path {
+    // a path that finds nothing, its iterators report err
+    pub fn error(err: String) -> Path {
+        Path::new(None, vec![morphism::ErrorMorphism::new(err)])
+    }
+
     pub fn start_morphism(nodes: Vec<Value>) -> Path {
         Path::start_path(None, nodes)
     }
@@ -36,11 +137,15 @@ impl Path {
         Path::new(qs, vec![morphism::IsMorphism::new(nodes)])
     }
 
+    pub fn start_param(qs: Option<Rc<RefCell<dyn QuadStore>>>, name: String) -> Path {
+        Path::new(qs, vec![morphism::IsMorphism::new_param(name)])
+    }
+
     pub fn new(qs: Option<Rc<RefCell<dyn QuadStore>>>, stack: Vec<Rc<dyn morphism::Morphism>>) -> Path {
         Path {
             stack,
             qs,
-            base_context: PathContext{ label_set: None }
+            base_context: PathContext::new()
         }   
     }
 
@@ -50,6 +155,10 @@ impl Path {
         self.stack.push(morphism::IsMorphism::new(nodes));
     }
 
+    pub fn is_param(&mut self, name: String) {
+        self.stack.push(morphism::IsMorphism::new_param(name));
+    }
+
     pub fn r#in(&mut self, via: Via) {
         self.stack.push(morphism::InMorphism::new(None, via));
     }
@@ -86,11 +195,28 @@ impl Path {
         let path = match via {
             Via::Values(v) => Path::start_morphism(v),
             Via::Path(p) => p,
+            Via::Param(name) => Path::start_param(None, name),
             Via::None => panic!("did not pass a predicate or a Path to FollowRecursive"),
         };
         self.stack.push(morphism::FollowRecursiveMorphism::new(path, max_depth, tags));
     }
 
+    pub fn shortest_path(&mut self, to: Path, via: Via, max_depth: i32, tags: Vec<String>) {
+        self.stack.push(morphism::ShortestPathMorphism::new(to, via, max_depth, false, tags));
+    }
+
+    pub fn shortest_path_reverse(&mut self, to: Path, via: Via, max_depth: i32, tags: Vec<String>) {
+        self.stack.push(morphism::ShortestPathMorphism::new(to, via, max_depth, true, tags));
+    }
+
+    pub fn all_paths(&mut self, to: Path, via: Via, max_depth: i32, both: bool, tag: String) {
+        self.stack.push(morphism::AllPathsMorphism::new(to, via, max_depth, both, false, tag));
+    }
+
+    pub fn all_paths_reverse(&mut self, to: Path, via: Via, max_depth: i32, both: bool, tag: String) {
+        self.stack.push(morphism::AllPathsMorphism::new(to, via, max_depth, both, true, tag));
+    }
+
     pub fn and(&mut self, path: Path) {
         self.stack.push(morphism::AndMorphism::new(path));
     }
@@ -109,7 +235,7 @@ impl Path {
     }
 
     pub fn back(&mut self, tag: String) -> Option<Path> {
-        let mut new_path = Path::new(self.qs.clone(), Vec::new());
+        let mut new_path = Path::start_path(self.qs.clone(), Vec::new());
         let mut i = (self.stack.len() - 1) as i64;
         loop {
             println!("{}", i);
@@ -146,6 +272,10 @@ impl Path {
         self.stack.push(morphism::HasShapeMorphism::new_has_morphism(via, rev, nodes));
     }
 
+    pub fn has_param(&mut self, via: Via, rev: bool, name: String) {
+        self.stack.push(morphism::HasShapeMorphism::new_has_param_morphism(via, rev, name));
+    }
+
     pub fn has_filter(&mut self, via: Via, rev: bool, nodes: Vec<Rc<dyn ValueFilter>>) {
         self.stack.push(morphism::HasShapeMorphism::new_has_filter_morphism(via, rev, nodes));
     }
@@ -159,7 +289,11 @@ impl Path {
     }
 
     pub fn order(&mut self) {
-        self.stack.push(morphism::OrderMorphism::new());
+        self.stack.push(morphism::OrderMorphism::new(vec![SortKey::node(Order::Asc)]));
+    }
+
+    pub fn order_by(&mut self, keys: Vec<SortKey>) {
+        self.stack.push(morphism::OrderMorphism::new(keys));
     }
 
     // pub fn count(&mut self) {
@@ -189,8 +323,10 @@ impl Path {
     ///////
  
 
+    // The reversed path starts from every node like g.M(), with an Is that matches anything,
+    // so it reads back the same from its Gizmo text.
     pub fn reverse(&mut self) -> Path {
-        let mut new_path = Path::new(self.qs.clone(), Vec::new());
+        let mut new_path = Path::start_path(self.qs.clone(), Vec::new());
         let mut ctx = new_path.base_context.clone();
 
         for x in self.stack.iter().rev() {
@@ -207,8 +343,74 @@ impl Path {
     }
 
     pub fn shape_from(&self, from: Rc<RefCell<dyn Shape>>) -> Rc<RefCell<dyn Shape>> {
-        let mut s = from;
+        self.build_shape(from, self.base_context.clone())
+    }
+
+    // the shape of a path nested in another, noting its Lookups wherever the outer path does
+    pub fn shape_in(&self, ctx: &PathContext) -> Rc<RefCell<dyn Shape>> {
+        self.shape_from_in(Rc::new(RefCell::new(AllNodes())), ctx)
+    }
+
+    pub fn shape_from_in(&self, from: Rc<RefCell<dyn Shape>>, ctx: &PathContext) -> Rc<RefCell<dyn Shape>> {
+        let mut base = self.base_context.clone();
+        base.lookups = ctx.lookups.clone();
+        self.build_shape(from, base)
+    }
+
+    // the shape of the path with the Lookups it built, in the order of the fingerprint params
+    pub fn recorded_shape(&self) -> (Rc<RefCell<dyn Shape>>, Lookups) {
+        let lookups = Rc::new(RefCell::new(Vec::new()));
         let mut ctx = self.base_context.clone();
+        ctx.lookups = Some(lookups.clone());
+        let s = self.build_shape(Rc::new(RefCell::new(AllNodes())), ctx);
+        let lookups = lookups.borrow().clone();
+        (s, lookups)
+    }
+
+    pub fn fingerprint(&self) -> Option<Fingerprint> {
+        let mut fp = Fingerprint::new();
+        if fp.path(self) {
+            return Some(fp)
+        }
+        None
+    }
+
+    pub fn to_ast(&self) -> Result<PathAst, String> {
+        Ok(PathAst {
+            version: PATH_AST_VERSION,
+            morphisms: self.morphism_asts()?
+        })
+    }
+
+    pub fn morphism_asts(&self) -> Result<Vec<MorphismAst>, String> {
+        // the label context a reversed path starts in only exists as a shape
+        if self.base_context.label_set.is_some() {
+            return Err("can't serialize a path that starts in a label context".to_string())
+        }
+        self.stack.iter().map(|m| m.ast()).collect()
+    }
+
+    pub fn from_ast(qs: Option<Rc<RefCell<dyn QuadStore>>>, path: &PathAst) -> Result<Path, String> {
+        if path.version == 0 || path.version > PATH_AST_VERSION {
+            return Err(format!("unsupported path version {}", path.version))
+        }
+        ast::build_path(&qs, &path.morphisms)
+    }
+
+    pub fn to_json(&self) -> Result<String, String> {
+        self.to_ast()?.to_json()
+    }
+
+    pub fn from_json(qs: Option<Rc<RefCell<dyn QuadStore>>>, json: &str) -> Result<Path, String> {
+        Path::from_ast(qs, &PathAst::from_json(json)?)
+    }
+
+    pub fn from_gizmo(qs: Option<Rc<RefCell<dyn QuadStore>>>, text: &str) -> Result<Path, String> {
+        Path::from_ast(qs, &text.parse()?)
+    }
+
+    fn build_shape(&self, from: Rc<RefCell<dyn Shape>>, mut ctx: PathContext) -> Rc<RefCell<dyn Shape>> {
+        let mut s = from;
 
         for m in &self.stack {
             let r = m.apply(s, &mut ctx);
@@ -227,6 +429,16 @@ impl Path {
 }
 
 
+// the path as Gizmo text
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.to_ast() {
+            Ok(ast) => write!(f, "{}", ast),
+            Err(err) => write!(f, "/* {} */", err)
+        }
+    }
+}
+
 
 pub struct MorphismForPath {
     path: Path,
@@ -256,16 +468,32 @@ pub enum Via {
     None,
     Values(Vec<Value>),
     Path(Path),
+    // values bound to the named param when a prepared path is run
+    Param(String),
 }
 
 impl Via {
     pub fn as_shape(&self) -> Rc<RefCell<dyn Shape>> {
+        self.as_shape_in(&PathContext::new())
+    }
+
+    pub fn as_shape_in(&self, ctx: &PathContext) -> Rc<RefCell<dyn Shape>> {
         return match self {
             Via::None => Rc::new(RefCell::new(AllNodes())),
-            Via::Path(path) => path.shape(),
-            Via::Values(values) => Rc::new(RefCell::new(Lookup(values.clone())))
+            Via::Path(path) => path.shape_in(ctx),
+            Via::Values(values) => ctx.lookup(values.clone()),
+            Via::Param(name) => ctx.param(name)
         };
     }
+
+    pub fn ast(&self) -> Result<ViaAst, String> {
+        Ok(match self {
+            Via::None => ViaAst::Any,
+            Via::Values(values) => ViaAst::Values { values: values.clone() },
+            Via::Path(path) => ViaAst::Path { morphisms: path.morphism_asts()? },
+            Via::Param(name) => ViaAst::Param { name: name.clone() }
+        })
+    }
 }
 
 impl From<&mut dyn Iterator<Item = Value>> for Via {
@@ -314,3 +542,9 @@ impl From<&gizmo::Path> for Via {
     }
 }
 
+
+impl From<gizmo::Param> for Via {
+    fn from(p: gizmo::Param) -> Self {
+        Via::Param(p.0)
+    }
+}
@@ -0,0 +1,148 @@
+use super::path::Path;
+use super::shape::{ResolvedShape, build_iterator};
+use crate::graph::iterator;
+use crate::graph::quad::QuadStore;
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+use std::cell::RefCell;
+
+// store sizes below this are treated as this big when deciding if a plan went stale, so
+// small stores don't drop their plans on every write
+const PLAN_STATS_FLOOR: i64 = 1000;
+
+struct Plan {
+    // its Lookups are in the order of the fingerprint params they are bound to
+    shape: ResolvedShape,
+    nodes: i64,
+    quads: i64
+}
+
+// PlanCache keeps the optimized shape of the paths it has built, keyed by their fingerprint.
+// A path with a known fingerprint reuses the shape with its own values bound to the Lookups,
+// which the store resolves again, so it builds the same iterators as the path on its own.
+// Plans are rebuilt once the store's node or quad count moves by more than half.
+pub struct PlanCache {
+    capacity: usize,
+    plans: HashMap<String, Plan>,
+    order: VecDeque<String>,
+    hits: u64,
+    misses: u64
+}
+
+impl PlanCache {
+    pub fn new(capacity: usize) -> PlanCache {
+        PlanCache {
+            capacity,
+            plans: HashMap::new(),
+            order: VecDeque::new(),
+            hits: 0,
+            misses: 0
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.plans.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.plans.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    // 0 turns the cache off, the oldest plans are dropped when shrinking
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.plans.len() > capacity {
+            self.evict();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.plans.clear();
+        self.order.clear();
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    // Builds the iterator tree of the path, from a cached plan when there is one. Paths that
+    // can't be fingerprinted are built as usual.
+    pub fn build_iterator(&mut self, path: &Path, qs: Rc<RefCell<dyn QuadStore>>) -> Rc<RefCell<dyn iterator::Shape>> {
+        if self.capacity == 0 {
+            return path.build_iterator_on(qs)
+        }
+        let fp = match path.fingerprint() {
+            Some(fp) => fp,
+            None => return path.build_iterator_on(qs)
+        };
+        let (nodes, quads) = store_size(&qs);
+
+        let fresh = match self.plans.get(&fp.text) {
+            Some(plan) => !drifted(plan.nodes, nodes) && !drifted(plan.quads, quads),
+            None => false
+        };
+        if fresh {
+            self.hits += 1;
+            let plan = &self.plans[&fp.text];
+            for ((_, l), values) in plan.shape.lookups().iter().zip(fp.params) {
+                l.borrow_mut().0 = values;
+            }
+            return plan.shape.build_iterator(qs)
+        }
+        self.misses += 1;
+        self.remove(&fp.text);
+
+        let (shape, lookups) = path.recorded_shape();
+        // morphisms that build their Lookups in another order than they fingerprint them
+        // can't have their values rebound
+        let bound = lookups.len() == fp.params.len() && lookups.iter().zip(fp.params.iter()).all(|((_, l), p)| &l.borrow().0 == p);
+        if !bound {
+            return build_iterator(qs, shape)
+        }
+
+        let shape = ResolvedShape::new(qs.clone(), shape, lookups);
+        let it = shape.build_iterator(qs);
+
+        if self.plans.len() >= self.capacity {
+            self.evict();
+        }
+        self.order.push_back(fp.text.clone());
+        self.plans.insert(fp.text, Plan {
+            shape,
+            nodes,
+            quads
+        });
+        it
+    }
+
+    fn remove(&mut self, key: &str) {
+        if self.plans.remove(key).is_some() {
+            self.order.retain(|k| k != key);
+        }
+    }
+
+    fn evict(&mut self) {
+        if let Some(k) = self.order.pop_front() {
+            self.plans.remove(&k);
+        }
+    }
+}
+
+fn store_size(qs: &Rc<RefCell<dyn QuadStore>>) -> (i64, i64) {
+    match qs.borrow().stats(false) {
+        Ok(stats) => (stats.nodes.value, stats.quads.value),
+        Err(_) => (0, 0)
+    }
+}
+
+fn drifted(old: i64, new: i64) -> bool {
+    (new - old).abs() * 2 > old.max(new).max(PLAN_STATS_FLOOR)
+}
@@ -0,0 +1,62 @@
+use crate::graph::quad::QuadStore;
+use super::path::Path;
+use std::rc::Rc;
+use std::cell::RefCell;
+
+
+// Definitions are kept in the metadata of the store, one entry per name, so they are saved
+// and loaded with the store while staying out of its quads and of every query over them.
+const KEY_PREFIX: &str = "gizmo:morphism:";
+
+// MorphismRegistry stores paths under a name as their JSON AST, paths that follow other
+// named morphisms hold those definitions as they were when the path was built.
+pub struct MorphismRegistry {
+    qs: Rc<RefCell<dyn QuadStore>>
+}
+
+impl MorphismRegistry {
+    pub fn new(qs: Rc<RefCell<dyn QuadStore>>) -> MorphismRegistry {
+        MorphismRegistry {
+            qs
+        }
+    }
+
+    // Replaces the definition the name had, if any. A path that can't be saved leaves the
+    // old definition in place.
+    pub fn define(&self, name: &str, path: &Path) -> Result<(), String> {
+        if name.is_empty() {
+            return Err("a morphism needs a name".to_string())
+        }
+        let json = path.to_json()?;
+        self.qs.borrow_mut().set_metadata(&key(name), Some(json))
+    }
+
+    // returns false when there was no morphism with the name
+    pub fn remove(&self, name: &str) -> Result<bool, String> {
+        if self.qs.borrow().metadata(&key(name)).is_none() {
+            return Ok(false)
+        }
+        self.qs.borrow_mut().set_metadata(&key(name), None)?;
+        Ok(true)
+    }
+
+    pub fn get(&self, name: &str) -> Result<Option<Path>, String> {
+        let json = self.qs.borrow().metadata(&key(name));
+        match json {
+            Some(json) => Path::from_json(Some(self.qs.clone()), &json).map(Some),
+            None => Ok(None)
+        }
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.qs.borrow().metadata_keys(KEY_PREFIX).iter()
+            .filter_map(|k| k.strip_prefix(KEY_PREFIX))
+            .map(|name| name.to_string())
+            .collect()
+    }
+}
+
+// the metadata key of the definition of the name
+pub fn key(name: &str) -> String {
+    format!("{}{}", KEY_PREFIX, name)
+}
@@ -0,0 +1,72 @@
+use crate::graph::value::Value;
+use crate::graph::quad::QuadStore;
+use crate::graph::iterator;
+use crate::graph::iterator::iterate::TagEachIterator;
+use super::shape::{Shape, build_iterator};
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::cell::RefCell;
+
+
+// The rows of the GraphQL and MQL queries, grouped by node. The node of a row and the node
+// it was reached from are saved under these tags, no field name can hold a NUL.
+pub(crate) const NODE_TAG: &str = "\u{0}node";
+pub(crate) const PARENT_TAG: &str = "\u{0}parent";
+
+// a node with the values saved for it under each tag
+pub(crate) type Row = (Value, HashMap<String, Vec<Value>>);
+
+// The nodes of the shape by the parent saved under PARENT_TAG, or under "" when there is
+// none, each in the order they were found with the values of their other tags.
+pub(crate) fn rows(qs: &Rc<RefCell<dyn QuadStore>>, shape: Rc<RefCell<dyn Shape>>) -> Result<Vec<(String, Vec<Row>)>, String> {
+    let mut parents: Vec<(String, Vec<Row>)> = Vec::new();
+    let mut seen: HashMap<(String, String), (usize, usize)> = HashMap::new();
+
+    let it = build_iterator(qs.clone(), shape);
+    let it = iterator::save::tag(&it, &NODE_TAG);
+    let mut rows = TagEachIterator::new(it, false, true);
+    for row in rows.by_ref() {
+        let qs = qs.borrow();
+        let node = match row.get(NODE_TAG).and_then(|r| qs.name_of(r)) {
+            Some(n) => n,
+            None => continue
+        };
+        let parent = row.get(PARENT_TAG).and_then(|r| qs.name_of(r)).map(|p| p.to_string()).unwrap_or_default();
+        let (p, i) = *seen.entry((parent.clone(), node.to_string())).or_insert_with(|| {
+            let p = match parents.iter().position(|(name, _)| *name == parent) {
+                Some(p) => p,
+                None => {
+                    parents.push((parent, Vec::new()));
+                    parents.len() - 1
+                }
+            };
+            parents[p].1.push((node.clone(), HashMap::new()));
+            (p, parents[p].1.len() - 1)
+        });
+        for (tag, r) in &row {
+            if tag == NODE_TAG || tag == PARENT_TAG {
+                continue
+            }
+            if let Some(v) = qs.name_of(r) {
+                let values = parents[p].1[i].1.entry(tag.clone()).or_default();
+                if !values.contains(&v) {
+                    values.push(v);
+                }
+            }
+        }
+    }
+    if let Some(err) = rows.err() {
+        return Err(err)
+    }
+    Ok(parents)
+}
+
+// IRIs are written as <iri>, like the results of Gizmo
+pub(crate) fn json_value(v: &Value) -> serde_json::Value {
+    match v {
+        Value::None | Value::Null => serde_json::Value::Null,
+        Value::Bool(b) => serde_json::Value::Bool(*b),
+        Value::Number(n) => serde_json::Value::Number(n.clone()),
+        Value::IRI(_) | Value::String(_) => serde_json::Value::String(v.to_string())
+    }
+}
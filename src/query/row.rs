@@ -0,0 +1,213 @@
+use crate::graph::value::Value;
+use serde::de::{self, Deserialize, DeserializeSeed, IntoDeserializer, Visitor, MapAccess, EnumAccess, VariantAccess};
+use std::collections::HashMap;
+use std::collections::hash_map;
+use std::fmt;
+
+
+// Deserializes the rows of a path, maps of tag to value, into Rust types. Each tag is
+// a field, and a field is converted from the value saved under it:
+//
+//   Bool          bool
+//   Number        any integer or float type it fits in
+//   IRI, String   String, an IRI written like it is in a quad, "<alice>"
+//   Null, None    None of an Option, or ()
+//   String        a unit variant of an enum, by its name
+//
+// An Option field is None when its tag is missing from the row, any other missing field
+// is an error, as is a value that doesn't convert to its field. A field of type Value
+// takes the value as it is. Tags the type has no field for are ignored.
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error(String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error(msg.to_string())
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        Error(format!("tag {:?} is missing from the row", field))
+    }
+}
+
+impl From<Error> for String {
+    fn from(e: Error) -> String {
+        e.0
+    }
+}
+
+
+pub fn from_row<'de, T: Deserialize<'de>>(row: &'de HashMap<String, Value>) -> Result<T, Error> {
+    T::deserialize(RowDeserializer { row })
+}
+
+
+///////////////////////////////////////////////
+// rows
+///////////////////////////////////////////////
+
+struct RowDeserializer<'de> {
+    row: &'de HashMap<String, Value>
+}
+
+impl<'de> de::Deserializer<'de> for RowDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_map(Tags { tags: self.row.iter(), value: None })
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+struct Tags<'de> {
+    tags: hash_map::Iter<'de, String, Value>,
+    // the tag and value of the key read last
+    value: Option<(&'de str, &'de Value)>
+}
+
+impl<'de> MapAccess<'de> for Tags<'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Error> {
+        match self.tags.next() {
+            Some((tag, value)) => {
+                self.value = Some((tag, value));
+                seed.deserialize(tag.as_str().into_deserializer()).map(Some)
+            },
+            None => Ok(None)
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let (tag, value) = self.value.take().ok_or_else(|| Error("a value was read before its tag".to_string()))?;
+        seed.deserialize(ValueDeserializer::new(value.clone()))
+            .map_err(|e| Error(format!("tag {:?}: {}", tag, e)))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.tags.len())
+    }
+}
+
+
+///////////////////////////////////////////////
+// values
+///////////////////////////////////////////////
+
+pub(crate) struct ValueDeserializer {
+    value: Value,
+    // an IRI without its brackets, as Value::IRI holds it
+    bare: bool
+}
+
+impl ValueDeserializer {
+    pub(crate) fn new(value: Value) -> ValueDeserializer {
+        ValueDeserializer { value, bare: false }
+    }
+
+    fn variant_name(&self) -> &'static str {
+        match self.value {
+            Value::None => "None",
+            Value::Null => "Null",
+            Value::Bool(_) => "Bool",
+            Value::Number(_) => "Number",
+            Value::IRI(_) => "IRI",
+            Value::String(_) => "String"
+        }
+    }
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match &self.value {
+            Value::None | Value::Null => visitor.visit_unit(),
+            Value::Bool(b) => visitor.visit_bool(*b),
+            Value::Number(n) => {
+                if let Some(u) = n.as_u64() {
+                    visitor.visit_u64(u)
+                } else if let Some(i) = n.as_i64() {
+                    visitor.visit_i64(i)
+                } else {
+                    visitor.visit_f64(n.as_f64().unwrap_or(f64::NAN))
+                }
+            },
+            Value::IRI(s) if self.bare => visitor.visit_str(s),
+            Value::IRI(s) => visitor.visit_string(format!("<{}>", s)),
+            Value::String(s) => visitor.visit_str(s)
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.value {
+            Value::None | Value::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self)
+        }
+    }
+
+    // a Value field, which serde reads as an enum of its variants, or a unit variant of
+    // any other enum named by a string
+    fn deserialize_enum<V: Visitor<'de>>(self, name: &'static str, _variants: &'static [&'static str], visitor: V) -> Result<V::Value, Error> {
+        if name == "Value" {
+            return visitor.visit_enum(self)
+        }
+        let variant = match &self.value {
+            Value::IRI(s) if !self.bare => format!("<{}>", s),
+            Value::IRI(s) | Value::String(s) => s.clone(),
+            _ => return self.deserialize_any(visitor)
+        };
+        visitor.visit_enum(IntoDeserializer::<Error>::into_deserializer(variant))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+impl<'de> EnumAccess<'de> for ValueDeserializer {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), Error> {
+        let variant = seed.deserialize(self.variant_name().into_deserializer())?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de> VariantAccess<'de> for ValueDeserializer {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(ValueDeserializer { value: self.value, bare: true })
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, _visitor: V) -> Result<V::Value, Error> {
+        Err(Error(format!("{} is not a tuple", self.value)))
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self, _fields: &'static [&'static str], _visitor: V) -> Result<V::Value, Error> {
+        Err(Error(format!("{} is not a struct", self.value)))
+    }
+}
@@ -0,0 +1,371 @@
+use crate::graph::value::Value;
+use crate::graph::quad::{QuadStore, Quad, Direction, Delta, Procedure, IgnoreOptions};
+use super::gizmo::Graph;
+use super::row::{Error, ValueDeserializer};
+use serde::Serialize;
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, Visitor, MapAccess, SeqAccess};
+use serde_json::Map;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::slice;
+use std::vec;
+
+
+// Maps Rust types to nodes of the graph and back, like the schema package of Cayley. A
+// struct is a node, and each of its fields a predicate from it:
+//
+//   #[derive(Serialize, Deserialize)]
+//   struct Person {
+//       id: String,
+//       #[serde(rename = "<name>")]
+//       name: String,
+//       follows: Vec<String>,
+//       address: Option<Address>
+//   }
+//
+// The id field holds the IRI of the node, "<alice>". The name of a field is the IRI of
+// its predicate, follows is <follows>, and serde's rename gives a field another one. Each
+// value of a field is a quad from the node: None writes nothing, a Vec a quad for every
+// element, and a nested struct a quad to the node it is written as. A nested struct with
+// no id is named after the field that holds it, <alice/address>, or <alice/address/0>
+// for the first of a Vec. Strings are read like the strings of a quad so "<bob>" links
+// to the node bob, numbers, bools and the unit variants of an enum are written as values.
+//
+// Writing an object again replaces the values its fields had, and deletes the quads of the
+// nested structs with no id it no longer holds, like <alice/address/1> when a Vec shrinks.
+// A Vec is a set of values, like
+// the quads it is written as: a value in it twice is written once, and it loads in the order
+// the store gives, not the order it was written in.
+//
+// Loading a node reads each field back from the values the node has for its predicate,
+// a field that isn't an Option or a Vec must have exactly one. A node can't load into a
+// struct nested in the one it loads into, nodes linking in a cycle would never end.
+
+
+// the field holding the IRI of a node
+const ID: &str = "id";
+
+// the predicate of a field, its name as an IRI unless it is written as one already
+fn predicate(field: &str) -> &str {
+    match field.strip_prefix('<').and_then(|f| f.strip_suffix('>')) {
+        Some(iri) => iri,
+        None => field
+    }
+}
+
+
+///////////////////////////////////////////////
+// writing
+///////////////////////////////////////////////
+
+// The quads of the object, and the node it is written as.
+pub fn to_quads<T: Serialize>(object: &T) -> Result<(Value, Vec<Quad>), String> {
+    let (node, quads, _) = fields_of(object)?;
+    Ok((node, quads))
+}
+
+// Writes the object as its node, replacing the values its fields had in the default graph
+// with the new ones in one write, so a field that changed isn't left with both.
+pub fn write<T: Serialize>(qs: &Rc<RefCell<dyn QuadStore>>, object: &T) -> Result<Value, String> {
+    let (node, quads, predicates) = fields_of(object)?;
+    let new: HashSet<Quad> = quads.iter().cloned().collect();
+
+    let mut deltas = Vec::new();
+    // the nested structs the object no longer holds
+    let mut removed = Vec::new();
+    for quad in field_quads(qs, &predicates) {
+        if !new.contains(&quad) {
+            if generated(&quad) && !predicates.contains_key(&quad.object) {
+                removed.push(quad.object.clone());
+            }
+            deltas.push(Delta{action: Procedure::Delete, quad});
+        }
+    }
+    while let Some(node) = removed.pop() {
+        for quad in node_quads(qs, &node) {
+            if generated(&quad) {
+                removed.push(quad.object.clone());
+            }
+            deltas.push(Delta{action: Procedure::Delete, quad});
+        }
+    }
+    for quad in quads {
+        deltas.push(Delta{action: Procedure::Add, quad});
+    }
+    qs.borrow_mut().apply_deltas(deltas, &IgnoreOptions{ignore_dup: true, ignore_missing: true})?;
+    Ok(node)
+}
+
+// the node of the object, its quads, and the predicates of each node it writes
+fn fields_of<T: Serialize>(object: &T) -> Result<(Value, Vec<Quad>, Predicates), String> {
+    let fields = match serde_json::to_value(object).map_err(|e| e.to_string())? {
+        serde_json::Value::Object(o) => o,
+        v => return Err(format!("expected a struct, found {}", v))
+    };
+    let id = match fields.get(ID) {
+        Some(id) => node_id(id)?,
+        None => return Err("the object has no id".to_string())
+    };
+    let mut w = Writer { quads: Vec::new(), predicates: HashMap::new() };
+    w.node(&id, &fields)?;
+    Ok((Value::IRI(id), w.quads, w.predicates))
+}
+
+fn node_id(id: &serde_json::Value) -> Result<String, String> {
+    if let serde_json::Value::String(s) = id {
+        if let Value::IRI(iri) = Value::from(s.clone()) {
+            return Ok(iri)
+        }
+    }
+    Err(format!("the id {} is not an IRI like <alice>", id))
+}
+
+// the predicates written for each node, those of fields with no value too
+type Predicates = HashMap<Value, HashSet<Value>>;
+
+struct Writer {
+    quads: Vec<Quad>,
+    predicates: Predicates
+}
+
+impl Writer {
+    fn node(&mut self, node: &str, fields: &Map<String, serde_json::Value>) -> Result<(), String> {
+        for (field, v) in fields {
+            if field == ID {
+                continue
+            }
+            self.predicates.entry(Value::IRI(node.to_string())).or_default().insert(Value::IRI(predicate(field).to_string()));
+            match v {
+                serde_json::Value::Array(a) => {
+                    for (i, v) in a.iter().enumerate() {
+                        self.value(node, predicate(field), v, Some(i))?;
+                    }
+                },
+                v => self.value(node, predicate(field), v, None)?
+            }
+        }
+        Ok(())
+    }
+
+    fn value(&mut self, node: &str, pred: &str, v: &serde_json::Value, index: Option<usize>) -> Result<(), String> {
+        let object = match v {
+            serde_json::Value::Null => return Ok(()),
+            serde_json::Value::Bool(b) => Value::Bool(*b),
+            serde_json::Value::Number(n) => Value::Number(n.clone()),
+            serde_json::Value::String(s) => Value::from(s.clone()),
+            serde_json::Value::Array(_) => return Err(format!("<{}> of <{}> holds a list of lists", pred, node)),
+            serde_json::Value::Object(o) => {
+                let id = match (o.get(ID), index) {
+                    (Some(id), _) => node_id(id)?,
+                    (None, Some(i)) => format!("{}/{}/{}", node, pred, i),
+                    (None, None) => format!("{}/{}", node, pred)
+                };
+                self.node(&id, o)?;
+                Value::IRI(id)
+            }
+        };
+        let quad = Quad::new(Value::IRI(node.to_string()), Value::IRI(pred.to_string()), object, Value::None);
+        if !self.quads.contains(&quad) {
+            self.quads.push(quad);
+        }
+        Ok(())
+    }
+}
+
+// whether the object of the quad is the node of a nested struct with no id, named after
+// the field that holds it
+fn generated(quad: &Quad) -> bool {
+    if let (Value::IRI(node), Value::IRI(pred), Value::IRI(object)) = (&quad.subject, &quad.predicate, &quad.object) {
+        return match object.strip_prefix(&format!("{}/{}", node, pred)) {
+            Some("") => true,
+            Some(rest) => rest.strip_prefix('/').is_some_and(|i| i.parse::<usize>().is_ok()),
+            None => false
+        }
+    }
+    false
+}
+
+// the quads the fields have in the default graph
+fn field_quads(qs: &Rc<RefCell<dyn QuadStore>>, predicates: &Predicates) -> Vec<Quad> {
+    let mut quads = Vec::new();
+    for (node, preds) in predicates {
+        quads.extend(node_quads(qs, node).into_iter().filter(|q| preds.contains(&q.predicate)));
+    }
+    quads
+}
+
+// the quads from the node in the default graph
+fn node_quads(qs: &Rc<RefCell<dyn QuadStore>>, node: &Value) -> Vec<Quad> {
+    let qs = qs.borrow();
+    let mut quads = Vec::new();
+    let r = match qs.value_of(node) {
+        Some(r) => r,
+        None => return quads
+    };
+    let it = qs.quad_iterator(&Direction::Subject, &r).borrow().iterate();
+    while it.borrow_mut().next() {
+        if let Some(quad) = it.borrow().result().and_then(|q| qs.quad(&q)) {
+            if quad.label == Value::None {
+                quads.push(quad);
+            }
+        }
+    }
+    let _ = it.borrow_mut().close();
+    quads
+}
+
+
+///////////////////////////////////////////////
+// loading
+///////////////////////////////////////////////
+
+// Reads the object written as the node back from the graph.
+pub fn load<T: DeserializeOwned>(g: &Graph, node: Value) -> Result<T, String> {
+    T::deserialize(NodeDeserializer { g, node, path: Vec::new() }).map_err(String::from)
+}
+
+struct NodeDeserializer<'a> {
+    g: &'a Graph,
+    node: Value,
+    // the nodes loading into the structs this one is nested in
+    path: Vec<Value>
+}
+
+impl<'de, 'a> de::Deserializer<'de> for NodeDeserializer<'a> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Error> {
+        Err(de::Error::custom(format!("{} is a node, it loads into a struct", self.node)))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(self, _name: &'static str, fields: &'static [&'static str], visitor: V) -> Result<V::Value, Error> {
+        if self.path.contains(&self.node) {
+            return Err(de::Error::custom(format!("{} links back to itself, it can't load into a struct nested in its own", self.node)))
+        }
+        let mut path = self.path;
+        path.push(self.node.clone());
+        visitor.visit_map(Fields { g: self.g, node: self.node, path, fields: fields.iter(), field: None })
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+struct Fields<'a> {
+    g: &'a Graph,
+    node: Value,
+    // the nodes loading from the root to this one
+    path: Vec<Value>,
+    fields: slice::Iter<'static, &'static str>,
+    // the field of the key read last
+    field: Option<&'static str>
+}
+
+impl<'de, 'a> MapAccess<'de> for Fields<'a> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Error> {
+        match self.fields.next() {
+            Some(field) => {
+                self.field = Some(field);
+                seed.deserialize(field.into_deserializer()).map(Some)
+            },
+            None => Ok(None)
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let field = self.field.take().ok_or_else(|| de::Error::custom("a value was read before its field"))?;
+        let values = if field == ID {
+            vec![self.node.clone()]
+        } else {
+            let pred = Value::IRI(predicate(field).to_string());
+            self.g.v(self.node.clone()).out(pred, None).try_iter_values().map_err(de::Error::custom)?
+        };
+        seed.deserialize(Values { g: self.g, values, path: self.path.clone() })
+            .map_err(|e| de::Error::custom(format!("{} {:?}: {}", self.node, field, e)))
+    }
+}
+
+// the values of a field
+struct Values<'a> {
+    g: &'a Graph,
+    values: Vec<Value>,
+    path: Vec<Value>
+}
+
+impl<'a> Values<'a> {
+    fn one(mut self) -> Result<Value, Error> {
+        match self.values.len() {
+            0 => Err(de::Error::custom("the node has no value for it")),
+            1 => Ok(self.values.pop().unwrap()),
+            n => Err(de::Error::custom(format!("the node has {} values for it, load them into a Vec", n)))
+        }
+    }
+}
+
+impl<'de, 'a> de::Deserializer<'de> for Values<'a> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        ValueDeserializer::new(self.one()?).deserialize_any(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if self.values.is_empty() {
+            return visitor.visit_none()
+        }
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_seq(Each { g: self.g, values: self.values.into_iter(), path: self.path })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(self, name: &'static str, fields: &'static [&'static str], visitor: V) -> Result<V::Value, Error> {
+        let (g, path) = (self.g, self.path.clone());
+        NodeDeserializer { g, node: self.one()?, path }.deserialize_struct(name, fields, visitor)
+    }
+
+    // the unit variants of an enum are written as their names
+    fn deserialize_enum<V: Visitor<'de>>(self, _name: &'static str, _variants: &'static [&'static str], visitor: V) -> Result<V::Value, Error> {
+        match self.one()? {
+            Value::String(s) => visitor.visit_enum(s.into_deserializer()),
+            v => Err(de::Error::custom(format!("expected the name of a variant, found {}", v)))
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct tuple tuple_struct map identifier ignored_any
+    }
+}
+
+struct Each<'a> {
+    g: &'a Graph,
+    values: vec::IntoIter<Value>,
+    path: Vec<Value>
+}
+
+impl<'de, 'a> SeqAccess<'de> for Each<'a> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, Error> {
+        match self.values.next() {
+            Some(v) => seed.deserialize(Values { g: self.g, values: vec![v], path: self.path.clone() }).map(Some),
+            None => Ok(None)
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.values.len())
+    }
+}
@@ -1,6 +1,7 @@
 use std::rc::Rc;
 use std::cell::RefCell;
 use super::path;
+use super::ast::ValueFilterAst;
 use super::super::graph::iterator;
 use super::super::graph::hasa::HasA;
 use super::super::graph::value::Value;
@@ -23,12 +24,15 @@ pub enum ShapeType<'a> {
     Save,
     Union,
     Recursive,
+    ShortestPath,
+    AllPaths,
     IteratorShape,
     Filter(&'a mut Filter),
     Except,
     Unique,
-    Page,
-    Sort
+    Page(&'a mut Page),
+    Sort(&'a mut Sort),
+    Error
 }
 
 impl<'a> fmt::Display for ShapeType<'a> {
@@ -46,12 +50,15 @@ impl<'a> fmt::Display for ShapeType<'a> {
             ShapeType::Save => write!(f, "Save"),
             ShapeType::Union => write!(f, "Union"),
             ShapeType::Recursive => write!(f, "Recursive"),
+            ShapeType::ShortestPath => write!(f, "ShortestPath"),
+            ShapeType::AllPaths => write!(f, "AllPaths"),
             ShapeType::IteratorShape => write!(f, "IteratorShape"),
             ShapeType::Filter(_) => write!(f, "Filter"),
             ShapeType::Except => write!(f, "Except"),
             ShapeType::Unique => write!(f, "Unique"),
-            ShapeType::Page => write!(f, "Page"),
-            ShapeType::Sort => write!(f, "Sort")
+            ShapeType::Page(_) => write!(f, "Page"),
+            ShapeType::Sort(_) => write!(f, "Sort"),
+            ShapeType::Error => write!(f, "Error")
         }
     }
 }
@@ -97,6 +104,77 @@ impl Optimizer for ResolveValues {
 
 
 
+// each Lookup of a shape with the Fixed it was resolved into
+type Resolved = Vec<(Rc<RefCell<Lookup>>, Rc<RefCell<Fixed>>)>;
+
+// Resolves the Lookups of a shape like ResolveValues, noting the Fixed each one became
+struct RecordValues {
+    qs: Rc<RefCell<dyn QuadStore>>,
+    lookups: path::Lookups,
+    resolved: RefCell<Resolved>
+}
+
+impl Optimizer for RecordValues {
+    fn optimize_shape(&self, shape: &mut dyn Shape) -> Option<Rc<RefCell<dyn Shape>>> {
+        if let ShapeType::Lookup(l) = shape.shape_type() {
+            // Lookups that aren't bound to a param keep the values they were built with
+            let lookup = match self.lookups.iter().find(|(_, lk)| std::ptr::eq(lk.as_ptr(), l)) {
+                Some((_, lk)) => lk.clone(),
+                None => Lookup::new(l.0.clone())
+            };
+            let f = Rc::new(RefCell::new(Fixed(l.refs(&self.qs))));
+            self.resolved.borrow_mut().push((lookup, f.clone()));
+            return Some(f)
+        }
+        return None
+    }
+
+    fn quad_store(&self) -> Option<Rc<RefCell<dyn QuadStore>>> {
+        return Some(self.qs.clone())
+    }
+}
+
+
+// A shape optimized for a store like build_iterator does, which can be built again after
+// its Lookups are bound to other values: the Fixed each Lookup was resolved into is
+// resolved again from it every time the iterators are built.
+pub struct ResolvedShape {
+    shape: Rc<RefCell<dyn Shape>>,
+    lookups: path::Lookups,
+    resolved: Resolved
+}
+
+impl ResolvedShape {
+    pub fn new(qs: Rc<RefCell<dyn QuadStore>>, shape: Rc<RefCell<dyn Shape>>, lookups: path::Lookups) -> ResolvedShape {
+        let o = RecordValues {
+            qs,
+            lookups,
+            resolved: RefCell::new(Vec::new())
+        };
+        let optimized = shape.borrow_mut().optimize(Some(&o));
+        ResolvedShape {
+            shape: optimized.unwrap_or(shape),
+            lookups: o.lookups,
+            resolved: o.resolved.into_inner()
+        }
+    }
+
+    // the Lookups of the shape, bound to new values before building it
+    pub fn lookups(&self) -> &path::Lookups {
+        &self.lookups
+    }
+
+    pub fn build_iterator(&self, qs: Rc<RefCell<dyn QuadStore>>) -> Rc<RefCell<dyn iterator::Shape>> {
+        for (l, f) in &self.resolved {
+            f.borrow_mut().0 = l.borrow().refs(&qs);
+        }
+        self.shape.borrow().build_iterator(qs)
+    }
+}
+
+
+
+
 ///////////////////////////////////////////////
 
 
@@ -115,14 +193,13 @@ impl Lookup {
         self.0.extend(values);
     }
 
+    // the refs of the values the store has
+    fn refs(&self, qs: &Rc<RefCell<dyn QuadStore>>) -> Vec<Ref> {
+        self.0.iter().filter_map(|v| qs.borrow().value_of(v)).collect()
+    }
+
     fn resolve(&self, qs: Rc<RefCell<dyn QuadStore>>) -> Option<Rc<RefCell<dyn Shape>>> {
-        let mut vals:Vec<Ref> = Vec::new();
-        for v in &self.0 {
-            let gv = qs.borrow().value_of(v);
-            if gv.is_some() {
-                vals.push(gv.unwrap());
-            }
-        }
+        let vals = self.refs(&qs);
         if vals.is_empty() {
             return None
         }
@@ -177,14 +254,21 @@ impl Fixed {
 
 impl Shape for Fixed {
     fn build_iterator(&self, qs: Rc<RefCell<dyn QuadStore>>) -> Rc<RefCell<dyn iterator::Shape>> {
-        let it = iterator::fixed::Fixed::new(vec![]);
         for v in &self.0 {
             if let Content::Quad(_) = v.content {
                 panic!("quad value in fixed iterator")
             }
-            it.borrow_mut().add(v.clone());
         }
-        return it;
+        // a Lookup resolved again to values the store doesn't have
+        if self.0.is_empty() {
+            return iterator::Null::new()
+        }
+        // nodes the store resolved in ascending id order can be merge joined
+        let ids: Vec<Option<u64>> = self.0.iter().map(|v| iterator::result_id(Some(v))).collect();
+        if ids.iter().all(Option::is_some) && ids.windows(2).all(|w| w[0] < w[1]) {
+            return iterator::fixed::Fixed::new_sorted(self.0.clone(), iterator::SortOrder::NodeIds)
+        }
+        return iterator::fixed::Fixed::new(self.0.clone());
     }
 
     fn optimize(&mut self, r: Option<&dyn Optimizer>) -> Option<Rc<RefCell<dyn Shape>>> {
@@ -286,7 +370,7 @@ impl Shape for Intersect {
         if sub.len() == 1 {
             return sub[0].clone()
         }
-        return iterator::and::And::new(sub)
+        return iterator::and::And::new_merged(sub)
     }
 
     fn optimize(&mut self, r: Option<&dyn Optimizer>) -> Option<Rc<RefCell<dyn Shape>>> {
@@ -437,15 +521,29 @@ impl Shape for Quads {
 
         let mut its:Vec<Rc<RefCell<dyn iterator::Shape>>> = Vec::new();
 
+        // filters fixed to a single value can be answered by one multi-direction lookup
+        let fixed:Vec<(Direction, Ref)> = self.0.iter()
+            .filter_map(|f| f.values.clone().and_then(one).map(|v| (f.dir.clone(), v)))
+            .collect();
+        let multi = if fixed.len() > 1 { qs.borrow().quad_iterator_multi(&fixed) } else { None };
+
         for f in &self.0 {
+            let is_fixed = f.values.clone().and_then(one).is_some();
+            if multi.is_some() && is_fixed {
+                continue
+            }
             its.push(f.build_iterator(qs.clone()));
         }
 
+        if let Some(m) = multi {
+            its.insert(0, m);
+        }
+
         if its.len() == 1 {
             return its[0].clone()
         }
 
-        return iterator::and::And::new(its)
+        return iterator::and::And::new_merged(its)
     }
 
     fn optimize(&mut self, r: Option<&dyn Optimizer>) -> Option<Rc<RefCell<dyn Shape>>> {
@@ -585,6 +683,147 @@ impl Shape for Recursive {
     }
 }
 
+///////////////////////////////////////////////
+
+
+// The shapes of a route search: the nodes it goes from and to, the predicates it may
+// follow and the labels of the quads it may follow.
+pub struct Route {
+    pub from: Rc<RefCell<dyn Shape>>,
+    pub to: Rc<RefCell<dyn Shape>>,
+    pub via: Rc<RefCell<dyn Shape>>,
+    pub labels: Option<Rc<RefCell<dyn Shape>>>
+}
+
+// the iterators of the route, via and labels are None when they allow anything
+type RouteIterators = (
+    Rc<RefCell<dyn iterator::Shape>>,
+    Rc<RefCell<dyn iterator::Shape>>,
+    Option<Rc<RefCell<dyn iterator::Shape>>>,
+    Option<Rc<RefCell<dyn iterator::Shape>>>
+);
+
+impl Route {
+    fn build_iterators(&self, qs: &Rc<RefCell<dyn QuadStore>>) -> RouteIterators {
+        let from = self.from.borrow().build_iterator(qs.clone());
+        let to = self.to.borrow().build_iterator(qs.clone());
+
+        let any_via = matches!(self.via.borrow_mut().shape_type(), ShapeType::AllNodes);
+        let via = if any_via { None } else { Some(self.via.borrow().build_iterator(qs.clone())) };
+
+        let labels = self.labels.as_ref().and_then(|l| {
+            let all = matches!(l.borrow_mut().shape_type(), ShapeType::AllNodes);
+            if all { None } else { Some(l.borrow().build_iterator(qs.clone())) }
+        });
+
+        (from, to, via, labels)
+    }
+
+    fn optimize(&mut self, r: Option<&dyn Optimizer>) {
+        let from = self.from.borrow_mut().optimize(r);
+        if let Some(s) = from {
+            self.from = s;
+        }
+        let to = self.to.borrow_mut().optimize(r);
+        if let Some(s) = to {
+            self.to = s;
+        }
+        let via = self.via.borrow_mut().optimize(r);
+        if let Some(s) = via {
+            self.via = s;
+        }
+    }
+}
+
+pub struct ShortestPath {
+    pub route: Route,
+    pub max_depth: i32,
+    pub rev: bool,
+    pub tags: Vec<String>
+}
+
+impl Shape for ShortestPath {
+    fn build_iterator(&self, qs: Rc<RefCell<dyn QuadStore>>) -> Rc<RefCell<dyn iterator::Shape>> {
+        let (from, to, via, labels) = self.route.build_iterators(&qs);
+        let it = iterator::shortest_path::ShortestPath::new(qs, from, to, via, self.max_depth);
+        it.borrow_mut().set_reversed(self.rev);
+        if let Some(l) = labels {
+            it.borrow_mut().set_labels(l);
+        }
+        for s in &self.tags {
+            it.borrow_mut().add_tag(s.clone());
+        }
+        it
+    }
+
+    fn optimize(&mut self, r: Option<&dyn Optimizer>) -> Option<Rc<RefCell<dyn Shape>>> {
+        self.route.optimize(r);
+        None
+    }
+
+    fn shape_type(&mut self) -> ShapeType<'_> {
+        ShapeType::ShortestPath
+    }
+}
+
+///////////////////////////////////////////////
+
+pub struct AllPaths {
+    pub route: Route,
+    pub max_depth: i32,
+    pub both: bool,
+    pub rev: bool,
+    pub tag: String
+}
+
+impl Shape for AllPaths {
+    fn build_iterator(&self, qs: Rc<RefCell<dyn QuadStore>>) -> Rc<RefCell<dyn iterator::Shape>> {
+        let (from, to, via, labels) = self.route.build_iterators(&qs);
+        let it = iterator::all_paths::AllPaths::new(qs, from, to, via, self.max_depth, self.both, self.tag.clone());
+        it.borrow_mut().set_reversed(self.rev);
+        if let Some(l) = labels {
+            it.borrow_mut().set_labels(l);
+        }
+        it
+    }
+
+    fn optimize(&mut self, r: Option<&dyn Optimizer>) -> Option<Rc<RefCell<dyn Shape>>> {
+        self.route.optimize(r);
+        None
+    }
+
+    fn shape_type(&mut self) -> ShapeType<'_> {
+        ShapeType::AllPaths
+    }
+}
+
+///////////////////////////////////////////////
+
+
+// a part of a path that couldn't be built, its iterator finds nothing and reports err
+pub struct Error(pub String);
+
+impl Error {
+    pub fn new(err: String) -> Rc<RefCell<Error>> {
+        Rc::new(RefCell::new(Error(err)))
+    }
+}
+
+impl Shape for Error {
+    fn build_iterator(&self, _qs: Rc<RefCell<dyn QuadStore>>) -> Rc<RefCell<dyn iterator::Shape>> {
+        iterator::Error::new(self.0.clone())
+    }
+
+    fn optimize(&mut self, _r: Option<&dyn Optimizer>) -> Option<Rc<RefCell<dyn Shape>>> {
+        None
+    }
+
+    fn shape_type(&mut self) -> ShapeType<'_> {
+        ShapeType::Error
+    }
+}
+
+
 ///////////////////////////////////////////////
 #[derive(Clone)]
 pub struct IteratorShape {
@@ -717,7 +956,17 @@ impl Shape for Page {
             return iterator::Null::new() 
         }
 
-        let mut it = self.from.borrow_mut().build_iterator(qs);
+        // a sort that is only read up to the page's end can keep just that many results
+        let top = if self.limit > 0 { Some((self.skip + self.limit) as usize) } else { None };
+        let sorted = match self.from.borrow_mut().shape_type() {
+            ShapeType::Sort(sort) if top.is_some() => Some(sort.build_top(qs.clone(), top)),
+            _ => None
+        };
+
+        let mut it = match sorted {
+            Some(it) => it,
+            None => self.from.borrow().build_iterator(qs)
+        };
 
         if self.skip > 0 {
             it = iterator::skip::Skip::new(it, self.skip);
@@ -730,12 +979,34 @@ impl Shape for Page {
     }
 
     fn optimize(&mut self, r: Option<&dyn Optimizer>) -> Option<Rc<RefCell<dyn Shape>>> {
-        // TODO: Implement
-        return None
+        let from = self.from.borrow_mut().optimize(r);
+        if let Some(s) = from {
+            self.from = s;
+        }
+
+        // fold a page of a page into a single one so a sort below it sees the whole window
+        let inner = match self.from.borrow_mut().shape_type() {
+            ShapeType::Page(p) => Some((p.from.clone(), p.skip, p.limit)),
+            _ => None
+        };
+        if let Some((from, skip, limit)) = inner {
+            let mut new_limit = self.limit;
+            if limit > 0 {
+                let rest = limit - self.skip;
+                if rest <= 0 {
+                    return Some(Rc::new(RefCell::new(Null())))
+                }
+                new_limit = if self.limit > 0 { rest.min(self.limit) } else { rest };
+            }
+            self.from = from;
+            self.skip += skip;
+            self.limit = new_limit;
+        }
+        None
     }
 
-    fn shape_type(&mut self) -> ShapeType {
-        ShapeType::Page
+    fn shape_type(&mut self) -> ShapeType<'_> {
+        ShapeType::Page(self)
     }
 }
 
@@ -745,26 +1016,36 @@ impl Shape for Page {
 #[derive(Clone)]
 pub struct Sort {
     pub from: Rc<RefCell<dyn Shape>>,
+    pub keys: Vec<iterator::sort::SortKey>
 }
 
-impl Shape for Sort {
-    fn build_iterator(&self, qs: Rc<RefCell<dyn QuadStore>>) -> Rc<RefCell<dyn iterator::Shape>> {
+impl Sort {
+    fn build_top(&self, qs: Rc<RefCell<dyn QuadStore>>, top: Option<usize>) -> Rc<RefCell<dyn iterator::Shape>> {
         if let ShapeType::Null = self.from.borrow_mut().shape_type() {
             return iterator::Null::new() 
         }
 
-        let it = self.from.borrow_mut().build_iterator(qs.clone());
+        let it = self.from.borrow().build_iterator(qs.clone());
+
+        iterator::sort::Sort::new_by(qs, it, self.keys.clone(), top)
+    }
+}
 
-        return iterator::sort::Sort::new(qs.clone(), it)
+impl Shape for Sort {
+    fn build_iterator(&self, qs: Rc<RefCell<dyn QuadStore>>) -> Rc<RefCell<dyn iterator::Shape>> {
+        self.build_top(qs, None)
     }
 
     fn optimize(&mut self, r: Option<&dyn Optimizer>) -> Option<Rc<RefCell<dyn Shape>>> {
-        // TODO: Implement
-        return None
+        let from = self.from.borrow_mut().optimize(r);
+        if let Some(s) = from {
+            self.from = s;
+        }
+        None
     }
 
-    fn shape_type(&mut self) -> ShapeType {
-        ShapeType::Sort
+    fn shape_type(&mut self) -> ShapeType<'_> {
+        ShapeType::Sort(self)
     }
 }
 
@@ -773,6 +1054,7 @@ impl Shape for Sort {
 
 pub trait ValueFilter {
     fn build_iterator(&self, qs: Rc<RefCell<dyn QuadStore>>, shape: Rc<RefCell<dyn iterator::Shape>>) -> Rc<RefCell<dyn iterator::Shape>>;
+    fn ast(&self) -> ValueFilterAst;
 }
 
 pub struct Regexp {
@@ -794,6 +1076,10 @@ impl ValueFilter for Regexp {
     fn build_iterator(&self, qs: Rc<RefCell<dyn QuadStore>>, it: Rc<RefCell<dyn iterator::Shape>>) -> Rc<RefCell<dyn iterator::Shape>> {
         iterator::value_filter::RegexValueFilter::new(it, qs, self.re.clone(), self.iri)
     }
+
+    fn ast(&self) -> ValueFilterAst {
+        ValueFilterAst::Regexp { pattern: self.re.as_str().to_string(), iri: self.iri }
+    }
 }
 
 
@@ -856,6 +1142,10 @@ impl ValueFilter for Wildcard {
 
         iterator::value_filter::RegexValueFilter::new(it, qs, re, true)
     }
+
+    fn ast(&self) -> ValueFilterAst {
+        ValueFilterAst::Wildcard { pattern: self.pattern.clone() }
+    }
 }
 
 
@@ -877,12 +1167,16 @@ impl ValueFilter for Comparison {
     fn build_iterator(&self, qs: Rc<RefCell<dyn QuadStore>>, it: Rc<RefCell<dyn iterator::Shape>>) -> Rc<RefCell<dyn iterator::Shape>> {
         iterator::value_filter::ComparisonValueFilter::new(it, self.op.clone(), self.val.clone(), qs)
     }
+
+    fn ast(&self) -> ValueFilterAst {
+        ValueFilterAst::Comparison { op: self.op.clone(), value: self.val.clone() }
+    }
 }
 
 
 pub struct Filter {
-    from: Rc<RefCell<dyn Shape>>,
-    filters: Vec<Rc<dyn ValueFilter>>
+    pub from: Rc<RefCell<dyn Shape>>,
+    pub filters: Vec<Rc<dyn ValueFilter>>
 }
 
 impl Filter {
@@ -938,8 +1232,9 @@ pub fn save_via_labels(from: Rc<RefCell<dyn Shape>>, via: Rc<RefCell<dyn Shape>>
     ]);
 
     if let Some(l) = labels {
-        if let ShapeType::AllNodes = l.borrow_mut().shape_type() {
-            quads.borrow_mut().0.push(QuadFilter::new_struct(Direction::Label, Some(l.clone())));
+        match l.borrow_mut().shape_type() {
+            ShapeType::AllNodes => {},
+            _ => quads.borrow_mut().0.push(QuadFilter::new_struct(Direction::Label, Some(l.clone())))
         }
     }
 
@@ -0,0 +1,838 @@
+use crate::graph::value::Value;
+use crate::graph::quad::QuadStore;
+use crate::graph::iterator::iterate::TagEachIterator;
+use crate::graph::iterator::sort::{SortKey, Order};
+use crate::graph::iterator::value_filter::Operator;
+use crate::graph::refs::Ref;
+use super::lexer::{self, Token, Tokens};
+use super::shape::{Shape, ValueFilter, AllNodes, Lookup, Save, Union, Except, Filter, Comparison, Regexp, Page, Sort, has_labels, intersect_shapes, intersect_optional, build_iterator};
+use serde_json::{json, Map};
+use std::collections::{HashMap, HashSet};
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::rc::Rc;
+use std::cell::RefCell;
+
+
+// A subset of SPARQL 1.1 SELECT queries:
+//
+//   PREFIX ex: <http://example.org/>
+//   SELECT DISTINCT ?a ?name WHERE {
+//     ?a <follows> ?b ; ex:status "cool_person" .
+//     OPTIONAL { ?a <name> ?name }
+//     GRAPH ?g { ?b <status> ?s }
+//     FILTER (?s != "smart_person" && regex(str(?b), "^[a-f]"))
+//   } ORDER BY DESC(?a) LIMIT 10 OFFSET 2
+//
+// A group is either basic graph patterns, with OPTIONAL, GRAPH and FILTER, or the UNION
+// of groups. The patterns of a group are compiled to a tree of shapes from one variable:
+// every pattern is a has() on the variable it hangs from, variables are Save tags, and
+// OPTIONAL groups are joined with IntersectOpt on the variable they share with the group.
+// A variable that is reached a second time, as in a cycle, is saved under another tag
+// and the rows where the two differ are dropped. FILTER compares a variable with a
+// constant (=, !=, <, <=, >, >=) or matches it with regex(), str() letting it match IRIs
+// as well, and is applied to the shape of that variable.
+//
+// The tree is rooted at the first variable of ORDER BY when it can be, so the Sort shape
+// orders the solutions, and LIMIT and OFFSET then bound the nodes read with a Page. The
+// solutions themselves are sorted, paged and made DISTINCT when they are built.
+
+
+const RDF_TYPE: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#type";
+const XSD: &str = "http://www.w3.org/2001/XMLSchema#";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Term {
+    Var(String),
+    Value(Value)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Triple {
+    pub subject: Term,
+    pub predicate: Term,
+    pub object: Term,
+    // set inside GRAPH
+    pub label: Option<Term>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Test {
+    Eq(Value),
+    Ne(Value),
+    Compare(Operator, Value),
+    // the pattern, and whether IRIs match too, as with str()
+    Regex(String, bool)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    pub var: String,
+    pub test: Test
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Group {
+    pub triples: Vec<Triple>,
+    pub filters: Vec<Condition>,
+    pub optionals: Vec<Group>,
+    // when not empty the group is the union of these, and has nothing else
+    pub unions: Vec<Group>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    // None for SELECT *
+    pub vars: Option<Vec<String>>,
+    pub distinct: bool,
+    pub pattern: Group,
+    pub order: Vec<(String, Order)>,
+    // 0 for no LIMIT
+    pub limit: u64,
+    pub offset: u64
+}
+
+
+// Runs the query against the store, returning the solutions in the SPARQL 1.1 JSON
+// results format.
+pub fn query(qs: Rc<RefCell<dyn QuadStore>>, text: &str) -> Result<serde_json::Value, String> {
+    let q = parse(text)?;
+    let vars = q.vars.clone().unwrap_or_else(|| q.pattern.vars());
+
+    let bindings: Vec<serde_json::Value> = run(qs, &q)?.into_iter().map(|s| {
+        let b: Map<String, serde_json::Value> = vars.iter()
+            .filter_map(|v| s.get(v).and_then(binding).map(|b| (v.clone(), b)))
+            .collect();
+        serde_json::Value::Object(b)
+    }).collect();
+
+    Ok(json!({
+        "head": {"vars": vars},
+        "results": {"bindings": bindings}
+    }))
+}
+
+// The solutions of the query, each with the values of the variables it selects that
+// are bound.
+pub fn run(qs: Rc<RefCell<dyn QuadStore>>, q: &Query) -> Result<Vec<HashMap<String, Value>>, String> {
+    let vars = q.vars.clone().unwrap_or_else(|| q.pattern.vars());
+
+    let mut c = Compiler { rooted: true, ..Compiler::default() };
+    let root = q.order.first().map(|(v, _)| v.as_str());
+    let mut shape = c.group(&q.pattern, root)?;
+    for v in q.pattern.filter_vars() {
+        if !c.seen.contains(&v) {
+            return Err(format!("FILTER uses ?{} which is in no pattern", v))
+        }
+    }
+
+    if !q.order.is_empty() {
+        shape = Rc::new(RefCell::new(Sort {
+            from: shape,
+            keys: q.order.iter().map(|(v, o)| SortKey { tag: Some(v.clone()), order: *o }).collect()
+        }));
+    }
+    // every node gives at least one solution, so the first offset + limit nodes hold the
+    // page, unless solutions are dropped after they are read
+    if q.limit > 0 && !q.distinct && c.aliases.is_empty() && (q.order.is_empty() || c.rooted) {
+        let limit = i64::try_from(q.offset.saturating_add(q.limit)).unwrap_or(i64::MAX);
+        shape = Rc::new(RefCell::new(Page { from: shape, skip: 0, limit }));
+    }
+
+    let mut solutions = solutions(&qs, shape, &c.aliases)?;
+    if !q.order.is_empty() {
+        solutions.sort_by(|a, b| compare(a, b, &q.order));
+    }
+
+    let mut selected: Vec<HashMap<String, Value>> = Vec::new();
+    let mut seen: HashSet<Vec<(String, Value)>> = HashSet::new();
+    for mut s in solutions {
+        s.retain(|v, _| vars.contains(v));
+        if q.distinct && !seen.insert(distinct_key(&s)) {
+            continue
+        }
+        selected.push(s);
+    }
+    let offset = usize::try_from(q.offset).unwrap_or(usize::MAX);
+    let limit = if q.limit > 0 { usize::try_from(q.limit).unwrap_or(usize::MAX) } else { usize::MAX };
+    Ok(selected.into_iter().skip(offset).take(limit).collect())
+}
+
+// the bindings of a solution in the order of their variables, equal for equal solutions
+fn distinct_key(s: &HashMap<String, Value>) -> Vec<(String, Value)> {
+    let mut key: Vec<(String, Value)> = s.iter().map(|(v, value)| (v.clone(), value.clone())).collect();
+    key.sort_by(|a, b| a.0.cmp(&b.0));
+    key
+}
+
+// the rows of the shape with their refs named, all of them in one batch
+fn solutions(qs: &Rc<RefCell<dyn QuadStore>>, shape: Rc<RefCell<dyn Shape>>, aliases: &[(String, String)]) -> Result<Vec<HashMap<String, Value>>, String> {
+    let mut tagged: Vec<Vec<String>> = Vec::new();
+    let mut refs: Vec<Ref> = Vec::new();
+    let it = build_iterator(qs.clone(), shape);
+    let mut rows = TagEachIterator::new(it, false, true);
+    for row in rows.by_ref() {
+        if !aliases.iter().all(|(alias, var)| same(&row, alias, var)) {
+            continue
+        }
+        let mut tags = Vec::new();
+        for (tag, r) in row {
+            if !aliases.iter().any(|(alias, _)| *alias == tag) {
+                tags.push(tag);
+                refs.push(r);
+            }
+        }
+        tagged.push(tags);
+    }
+    if let Some(err) = rows.err() {
+        return Err(err)
+    }
+
+    let mut names = qs.borrow().names_of(&refs).into_iter();
+    Ok(tagged.into_iter().map(|tags| {
+        tags.into_iter().zip(names.by_ref()).filter_map(|(tag, v)| v.map(|v| (tag, v))).collect()
+    }).collect())
+}
+
+fn same(row: &HashMap<String, Ref>, alias: &str, var: &str) -> bool {
+    match (row.get(alias), row.get(var)) {
+        (Some(a), Some(v)) => a.key() == v.key(),
+        _ => true
+    }
+}
+
+fn compare(a: &HashMap<String, Value>, b: &HashMap<String, Value>, order: &[(String, Order)]) -> Ordering {
+    for (v, o) in order {
+        let x = a.get(v).unwrap_or(&Value::None);
+        let y = b.get(v).unwrap_or(&Value::None);
+        let c = if *o == Order::Desc { y.compare(x) } else { x.compare(y) };
+        if c != Ordering::Equal {
+            return c
+        }
+    }
+    Ordering::Equal
+}
+
+// a term of the SPARQL JSON results
+fn binding(v: &Value) -> Option<serde_json::Value> {
+    match v {
+        Value::None | Value::Null => None,
+        Value::IRI(s) => Some(json!({"type": "uri", "value": s})),
+        Value::String(s) => Some(json!({"type": "literal", "value": s})),
+        Value::Number(n) => {
+            let t = if n.is_f64() { "double" } else { "integer" };
+            Some(json!({"type": "literal", "value": n.to_string(), "datatype": format!("{}{}", XSD, t)}))
+        },
+        Value::Bool(b) => Some(json!({"type": "literal", "value": b.to_string(), "datatype": format!("{}boolean", XSD)}))
+    }
+}
+
+
+impl Group {
+    // the variables of the group in the order they are written
+    pub fn vars(&self) -> Vec<String> {
+        let mut vars = Vec::new();
+        self.collect_vars(&mut vars);
+        vars
+    }
+
+    fn collect_vars(&self, vars: &mut Vec<String>) {
+        for t in &self.triples {
+            for term in [Some(&t.subject), Some(&t.predicate), Some(&t.object), t.label.as_ref()].iter().flatten() {
+                if let Term::Var(v) = term {
+                    if !vars.contains(v) {
+                        vars.push(v.clone());
+                    }
+                }
+            }
+        }
+        for g in self.optionals.iter().chain(self.unions.iter()) {
+            g.collect_vars(vars);
+        }
+    }
+
+    fn filter_vars(&self) -> Vec<String> {
+        let mut vars: Vec<String> = self.filters.iter().map(|c| c.var.clone()).collect();
+        for g in self.optionals.iter().chain(self.unions.iter()) {
+            vars.extend(g.filter_vars());
+        }
+        vars
+    }
+
+    // the variables in the subject or object of the patterns
+    fn node_vars(&self) -> Vec<String> {
+        let mut vars = Vec::new();
+        for t in &self.triples {
+            for term in [&t.subject, &t.object] {
+                if let Term::Var(v) = term {
+                    if !vars.contains(v) {
+                        vars.push(v.clone());
+                    }
+                }
+            }
+        }
+        vars
+    }
+}
+
+
+///////////////////////////////////////////////
+// compiling
+///////////////////////////////////////////////
+
+#[derive(Default)]
+struct Compiler<'a> {
+    // the variables saved so far on the current branch
+    bound: HashSet<String>,
+    // every variable that was saved on any branch
+    seen: HashSet<String>,
+    // (tag, variable) for the variables saved a second time
+    aliases: Vec<(String, String)>,
+    filters: Vec<&'a Condition>,
+    // false once a group, or a branch of a UNION, is not rooted at the variable asked for
+    rooted: bool
+}
+
+impl<'a> Compiler<'a> {
+    fn group(&mut self, g: &'a Group, root: Option<&str>) -> Result<Rc<RefCell<dyn Shape>>, String> {
+        if !g.unions.is_empty() {
+            let bound = self.bound.clone();
+            let mut branches = Vec::new();
+            for b in &g.unions {
+                self.bound = bound.clone();
+                branches.push(self.group(b, root)?);
+            }
+            return Ok(Rc::new(RefCell::new(Union(branches))))
+        }
+
+        let nodes = g.node_vars();
+        let start = match root.filter(|r| nodes.iter().any(|n| n == r)) {
+            Some(r) => r.to_string(),
+            None => nodes.first().cloned().ok_or("a group needs a variable subject or object")?
+        };
+        self.rooted &= root == Some(start.as_str());
+
+        let filters = self.filters.len();
+        self.filters.extend(g.filters.iter());
+        let mut used = vec![false; g.triples.len()];
+        let shape = self.node(g, &mut used, &Term::Var(start))?;
+        if used.contains(&false) {
+            return Err("the patterns of a group must be joined by variables".to_string())
+        }
+        self.filters.truncate(filters);
+        Ok(shape)
+    }
+
+    // the nodes of the term that match the patterns of the group hanging from it
+    fn node(&mut self, g: &'a Group, used: &mut [bool], term: &Term) -> Result<Rc<RefCell<dyn Shape>>, String> {
+        let var = match term {
+            Term::Value(v) => return Ok(Lookup::new(vec![v.clone()])),
+            Term::Var(var) => var
+        };
+        if self.bound.contains(var) {
+            return Ok(self.alias(var))
+        }
+        self.bind(var);
+
+        let mut shape = self.var_shape(var);
+        shape = self.edges(g, used, var, shape)?;
+        for o in &g.optionals {
+            if anchor(o, g)? == *var {
+                shape = intersect_optional(shape, self.optional(o, var)?);
+            }
+        }
+        Ok(Save::new(vec![var.clone()], Some(shape)))
+    }
+
+    fn edges(&mut self, g: &'a Group, used: &mut [bool], var: &str, mut shape: Rc<RefCell<dyn Shape>>) -> Result<Rc<RefCell<dyn Shape>>, String> {
+        let this = Term::Var(var.to_string());
+        for (i, t) in g.triples.iter().enumerate() {
+            if used[i] || (t.subject != this && t.object != this) {
+                continue
+            }
+            used[i] = true;
+            let rev = t.subject != this;
+            let other = if rev { &t.subject } else { &t.object };
+
+            let via = self.term(&t.predicate);
+            let labels = t.label.as_ref().map(|l| self.term(l));
+            let nodes = self.node(g, used, other)?;
+            shape = has_labels(shape, via, nodes, labels, rev);
+        }
+        Ok(shape)
+    }
+
+    // the nodes of `var` that match the optional group, which saves its own variables
+    fn optional(&mut self, o: &'a Group, var: &str) -> Result<Rc<RefCell<dyn Shape>>, String> {
+        if !o.unions.is_empty() {
+            return Err("UNION inside OPTIONAL is not supported".to_string())
+        }
+        let filters = self.filters.len();
+        self.filters.extend(o.filters.iter());
+
+        let mut used = vec![false; o.triples.len()];
+        let mut shape = self.edges(o, &mut used, var, AllNodes::new())?;
+        for inner in &o.optionals {
+            if anchor(inner, o)? == var {
+                shape = intersect_optional(shape, self.optional(inner, var)?);
+            }
+        }
+        if used.contains(&false) {
+            return Err("the patterns of an OPTIONAL must be joined by variables".to_string())
+        }
+        self.filters.truncate(filters);
+        Ok(shape)
+    }
+
+    // a predicate or label
+    fn term(&mut self, term: &Term) -> Rc<RefCell<dyn Shape>> {
+        match term {
+            Term::Value(v) => Lookup::new(vec![v.clone()]),
+            Term::Var(var) if self.bound.contains(var) => self.alias(var),
+            Term::Var(var) => {
+                self.bind(var);
+                Save::new(vec![var.clone()], Some(self.var_shape(var)))
+            }
+        }
+    }
+
+    fn bind(&mut self, var: &str) {
+        self.bound.insert(var.to_string());
+        self.seen.insert(var.to_string());
+    }
+
+    fn alias(&mut self, var: &str) -> Rc<RefCell<dyn Shape>> {
+        // no variable name holds a NUL
+        let tag = format!("{}\u{0}{}", var, self.aliases.len());
+        self.aliases.push((tag.clone(), var.to_string()));
+        Save::new(vec![tag], Some(AllNodes::new()))
+    }
+
+    // the nodes the filters allow for the variable
+    fn var_shape(&self, var: &str) -> Rc<RefCell<dyn Shape>> {
+        let mut shape: Rc<RefCell<dyn Shape>> = AllNodes::new();
+        let mut filters: Vec<Rc<dyn ValueFilter>> = Vec::new();
+        for c in self.filters.iter().filter(|c| c.var == var) {
+            match &c.test {
+                Test::Eq(v) => shape = intersect_shapes(shape, Lookup::new(vec![v.clone()])),
+                Test::Ne(v) => shape = Rc::new(RefCell::new(Except {
+                    exclude: Some(Lookup::new(vec![v.clone()])),
+                    from: Some(shape)
+                })),
+                Test::Compare(op, v) => filters.push(Rc::new(Comparison::new(op.clone(), v.clone()))),
+                Test::Regex(p, iri) => filters.push(Rc::new(Regexp::new(p.clone(), *iri)))
+            }
+        }
+        Filter::new(shape, filters)
+    }
+}
+
+// the variable an optional group shares with the patterns of its group
+fn anchor(o: &Group, g: &Group) -> Result<String, String> {
+    let outer = Group { triples: g.triples.clone(), ..Group::default() }.vars();
+    let shared: Vec<String> = o.vars().into_iter().filter(|v| outer.contains(v)).collect();
+    match shared.as_slice() {
+        [v] if g.node_vars().contains(v) && o.node_vars().contains(v) => Ok(v.clone()),
+        [] => Err("an OPTIONAL must share a variable with its group".to_string()),
+        _ => Err(format!("an OPTIONAL must share one subject or object variable with its group, found {}", shared.iter().map(|v| format!("?{}", v)).collect::<Vec<_>>().join(" ")))
+    }
+}
+
+
+///////////////////////////////////////////////
+// parsing
+///////////////////////////////////////////////
+
+const PUNCTS: [&str; 15] = ["&&", "||", "!=", "<=", ">=", "{", "}", "(", ")", ".", ";", ",", "*", "=", ">"];
+
+fn tokens(text: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let name = |c: char| c == '_' || c == '-' || c.is_alphanumeric();
+    let mut out = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '#' {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '?' || c == '$' {
+            let start = i + 1;
+            i = start;
+            while i < chars.len() && name(chars[i]) {
+                i += 1;
+            }
+            if i == start {
+                return Err(format!("expected a variable name at {}", start))
+            }
+            out.push(Token::Var(chars[start..i].iter().collect()));
+        } else if c == '<' && rest != "<=" {
+            // an IRI holds no spaces, otherwise this is less than
+            match (i + 1..chars.len()).find(|&j| chars[j] == '>' || chars[j].is_whitespace()) {
+                Some(end) if chars[end] == '>' => {
+                    out.push(Token::Iri(chars[i + 1..end].iter().collect()));
+                    i = end + 1;
+                },
+                _ => {
+                    out.push(Token::Punct("<"));
+                    i += 1;
+                }
+            }
+        } else if let Some(p) = PUNCTS.iter().find(|p| rest.starts_with(**p)) {
+            out.push(Token::Punct(p));
+            i += p.len();
+        } else if c == '"' || c == '\'' {
+            let (s, end) = lexer::string(&chars, i)?;
+            i = end;
+            if matches!(chars.get(i), Some('@') | Some('^')) {
+                return Err("language tags and datatypes are not supported".to_string())
+            }
+            out.push(Token::Str(s));
+        } else if c.is_ascii_digit() || ((c == '-' || c == '+') && chars.get(i + 1).is_some_and(|d| d.is_ascii_digit())) {
+            let (n, end) = lexer::number(&chars, i)?;
+            out.push(Token::Number(n));
+            i = end;
+        } else if c == ':' || name(c) {
+            let start = i;
+            while i < chars.len() && name(chars[i]) {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect();
+            if chars.get(i) == Some(&':') {
+                i += 1;
+                let local = i;
+                // a local name doesn't end with the dot that ends a pattern
+                while i < chars.len() && (name(chars[i]) || (chars[i] == '.' && chars.get(i + 1).is_some_and(|c| name(*c)))) {
+                    i += 1;
+                }
+                out.push(Token::Prefixed(word, chars[local..i].iter().collect()));
+            } else {
+                out.push(Token::Word(word));
+            }
+        } else {
+            return Err(format!("unexpected character {:?} at {}", c, i))
+        }
+    }
+
+    Ok(out)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    prefixes: HashMap<String, String>
+}
+
+pub fn parse(text: &str) -> Result<Query, String> {
+    let mut p = Parser {
+        tokens: tokens(text)?,
+        pos: 0,
+        prefixes: HashMap::new()
+    };
+    let q = p.query()?;
+    if let Some(t) = p.peek() {
+        return Err(format!("unexpected {:?} after the query", t))
+    }
+    Ok(q)
+}
+
+impl Tokens for Parser {
+    fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    fn pos(&self) -> usize {
+        self.pos
+    }
+
+    fn set_pos(&mut self, pos: usize) {
+        self.pos = pos;
+    }
+}
+
+impl Parser {
+    fn var(&mut self) -> Result<String, String> {
+        match self.next() {
+            Some(Token::Var(v)) => Ok(v),
+            t => Err(format!("expected a variable, found {:?}", t))
+        }
+    }
+
+    fn query(&mut self) -> Result<Query, String> {
+        while self.keyword("PREFIX") {
+            self.pos += 1;
+            let prefix = match self.next() {
+                Some(Token::Prefixed(p, l)) if l.is_empty() => p,
+                t => return Err(format!("expected a prefix, found {:?}", t))
+            };
+            match self.next() {
+                Some(Token::Iri(iri)) => self.prefixes.insert(prefix, iri),
+                t => return Err(format!("expected an IRI, found {:?}", t))
+            };
+        }
+
+        self.expect_keyword("SELECT")?;
+        let distinct = self.keyword("DISTINCT");
+        if distinct {
+            self.pos += 1;
+        }
+        let vars = if self.is("*") {
+            self.pos += 1;
+            None
+        } else {
+            let mut vars = Vec::new();
+            while let Some(Token::Var(v)) = self.peek() {
+                vars.push(v.clone());
+                self.pos += 1;
+            }
+            if vars.is_empty() {
+                return Err("SELECT needs * or variables".to_string())
+            }
+            Some(vars)
+        };
+
+        if self.keyword("WHERE") {
+            self.pos += 1;
+        }
+        let pattern = self.group()?;
+
+        let mut q = Query { vars, distinct, pattern, order: Vec::new(), limit: 0, offset: 0 };
+        loop {
+            if self.keyword("ORDER") {
+                self.pos += 1;
+                self.expect_keyword("BY")?;
+                while matches!(self.peek(), Some(Token::Var(_))) || self.keyword("ASC") || self.keyword("DESC") {
+                    q.order.push(self.order_key()?);
+                }
+                if q.order.is_empty() {
+                    return Err("ORDER BY needs a variable".to_string())
+                }
+            } else if self.keyword("LIMIT") {
+                self.pos += 1;
+                q.limit = self.count("LIMIT")?;
+            } else if self.keyword("OFFSET") {
+                self.pos += 1;
+                q.offset = self.count("OFFSET")?;
+            } else {
+                return Ok(q)
+            }
+        }
+    }
+
+    fn order_key(&mut self) -> Result<(String, Order), String> {
+        if let Some(Token::Var(v)) = self.peek().cloned() {
+            self.pos += 1;
+            return Ok((v, Order::Asc))
+        }
+        let order = if self.keyword("DESC") { Order::Desc } else { Order::Asc };
+        self.pos += 1;
+        self.expect("(")?;
+        let v = self.var()?;
+        self.expect(")")?;
+        Ok((v, order))
+    }
+
+    fn group(&mut self) -> Result<Group, String> {
+        self.expect("{")?;
+        let mut g = Group::default();
+        let mut patterns = false;
+
+        loop {
+            if self.is("}") {
+                self.pos += 1;
+                break
+            } else if self.is(".") {
+                self.pos += 1;
+            } else if self.peek().is_none() {
+                return Err("expected }".to_string())
+            } else if self.keyword("FILTER") {
+                self.pos += 1;
+                let filters = self.filter()?;
+                g.filters.extend(filters);
+            } else if self.keyword("OPTIONAL") {
+                self.pos += 1;
+                g.optionals.push(self.group()?);
+                patterns = true;
+            } else if self.keyword("GRAPH") {
+                self.pos += 1;
+                let label = self.term()?;
+                let inner = self.group()?;
+                if !inner.optionals.is_empty() || !inner.unions.is_empty() || inner.triples.iter().any(|t| t.label.is_some()) {
+                    return Err("GRAPH only holds patterns and filters".to_string())
+                }
+                g.triples.extend(inner.triples.into_iter().map(|t| Triple { label: Some(label.clone()), ..t }));
+                g.filters.extend(inner.filters);
+                patterns = true;
+            } else if self.is("{") {
+                let mut branches = vec![self.group()?];
+                while self.keyword("UNION") {
+                    self.pos += 1;
+                    branches.push(self.group()?);
+                }
+                if branches.len() == 1 {
+                    let inner = branches.pop().unwrap();
+                    g.triples.extend(inner.triples);
+                    g.filters.extend(inner.filters);
+                    g.optionals.extend(inner.optionals);
+                    g.unions.extend(inner.unions);
+                } else {
+                    g.unions.extend(branches);
+                }
+                patterns = true;
+            } else {
+                self.triples(&mut g.triples)?;
+                patterns = true;
+            }
+        }
+
+        if !g.unions.is_empty() && (!g.triples.is_empty() || !g.optionals.is_empty() || !g.filters.is_empty()) {
+            return Err("a UNION must be the whole of its group".to_string())
+        }
+        if !patterns {
+            return Err("a group needs a pattern".to_string())
+        }
+        Ok(g)
+    }
+
+    // subject verb object (, object)* (; verb object (, object)*)*
+    fn triples(&mut self, out: &mut Vec<Triple>) -> Result<(), String> {
+        let subject = self.term()?;
+        loop {
+            let predicate = if self.keyword("a") {
+                self.pos += 1;
+                Term::Value(Value::IRI(RDF_TYPE.to_string()))
+            } else {
+                self.term()?
+            };
+            loop {
+                let object = self.term()?;
+                out.push(Triple { subject: subject.clone(), predicate: predicate.clone(), object, label: None });
+                if !self.is(",") {
+                    break
+                }
+                self.pos += 1;
+            }
+            if !self.is(";") {
+                return Ok(())
+            }
+            self.pos += 1;
+            // a ; may end the list
+            if self.is(".") || self.is("}") {
+                return Ok(())
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<Term, String> {
+        match self.next() {
+            Some(Token::Var(v)) => Ok(Term::Var(v)),
+            t => self.value(t).map(Term::Value)
+        }
+    }
+
+    fn value(&mut self, t: Option<Token>) -> Result<Value, String> {
+        match t {
+            Some(Token::Iri(iri)) => Ok(Value::IRI(iri)),
+            Some(Token::Prefixed(p, local)) => match self.prefixes.get(&p) {
+                Some(iri) => Ok(Value::IRI(format!("{}{}", iri, local))),
+                None => Err(format!("unknown prefix {}:", p))
+            },
+            Some(Token::Str(s)) => Ok(Value::String(s)),
+            Some(Token::Number(n)) => Ok(Value::Number(n)),
+            Some(Token::Word(w)) if w == "true" => Ok(Value::Bool(true)),
+            Some(Token::Word(w)) if w == "false" => Ok(Value::Bool(false)),
+            t => Err(format!("expected a term, found {:?}", t))
+        }
+    }
+
+    // FILTER (a && b ...) or FILTER regex(...)
+    fn filter(&mut self) -> Result<Vec<Condition>, String> {
+        if self.keyword("regex") {
+            return Ok(vec![self.regex()?])
+        }
+        self.expect("(")?;
+        let mut conditions = self.conjunction()?;
+        while self.is("&&") {
+            self.pos += 1;
+            conditions.extend(self.conjunction()?);
+        }
+        if self.is("||") {
+            return Err("|| is not supported in FILTER".to_string())
+        }
+        self.expect(")")?;
+        Ok(conditions)
+    }
+
+    fn conjunction(&mut self) -> Result<Vec<Condition>, String> {
+        if self.is("(") {
+            return self.filter()
+        }
+        if self.keyword("regex") {
+            return Ok(vec![self.regex()?])
+        }
+
+        let (left, op, right) = (self.next(), self.next(), self.next());
+        let op = match op {
+            Some(Token::Punct(op)) => op,
+            t => return Err(format!("expected a comparison, found {:?}", t))
+        };
+        // constant op ?var is turned around
+        let (var, op, value) = match (left, right) {
+            (Some(Token::Var(v)), t) => (v, op, self.value(t)?),
+            (t, Some(Token::Var(v))) => {
+                let flipped = match op { "<" => ">", ">" => "<", "<=" => ">=", ">=" => "<=", op => op };
+                (v, flipped, self.value(t)?)
+            },
+            _ => return Err("a comparison needs a variable and a constant".to_string())
+        };
+        let test = match op {
+            "=" => Test::Eq(value),
+            "!=" => Test::Ne(value),
+            "<" => Test::Compare(Operator::LT, value),
+            "<=" => Test::Compare(Operator::LTE, value),
+            ">" => Test::Compare(Operator::GT, value),
+            ">=" => Test::Compare(Operator::GTE, value),
+            op => return Err(format!("unknown comparison {}", op))
+        };
+        Ok(vec![Condition { var, test }])
+    }
+
+    // regex(?x, "pattern") or regex(str(?x), "pattern", "i")
+    fn regex(&mut self) -> Result<Condition, String> {
+        self.pos += 1;
+        self.expect("(")?;
+        let iri = self.keyword("str");
+        let var = if iri {
+            self.pos += 1;
+            self.expect("(")?;
+            let v = self.var()?;
+            self.expect(")")?;
+            v
+        } else {
+            self.var()?
+        };
+        self.expect(",")?;
+        let mut pattern = match self.next() {
+            Some(Token::Str(s)) => s,
+            t => return Err(format!("regex takes a pattern, found {:?}", t))
+        };
+        if self.is(",") {
+            self.pos += 1;
+            match self.next() {
+                Some(Token::Str(f)) if f.chars().all(|c| "imsx".contains(c)) => {
+                    if !f.is_empty() {
+                        pattern = format!("(?{}){}", f, pattern);
+                    }
+                },
+                t => return Err(format!("bad regex flags {:?}", t))
+            }
+        }
+        self.expect(")")?;
+        regex::Regex::new(&pattern).map_err(|e| e.to_string())?;
+        Ok(Condition { var, test: Test::Regex(pattern, iri) })
+    }
+}
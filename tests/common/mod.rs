@@ -4,6 +4,9 @@ use std::cell::RefCell;
 use gizmo_graph_db::graph::iterator::{Shape, Scanner, Index, Costs, Base, ShapeType};
 use gizmo_graph_db::graph::refs;
 use gizmo_graph_db::graph::value::Value;
+use gizmo_graph_db::graph::quad::{Quad, QuadStore, Direction, Delta, IgnoreOptions, Stats};
+use gizmo_graph_db::graph::graphmock::Store;
+use gizmo_graph_db::query::gizmo;
 use std::collections::HashMap;
 use std::fmt;
 
@@ -139,195 +142,151 @@ impl Index for TestContains {
 
 
 
-
-pub struct Int64 {
-    node: bool,
-    min: i64,
-    max: i64
+// FailsOn looks up its refs like Fixed, but contains of `fail` fails with an error
+pub struct FailsOn {
+    shape: Rc<RefCell<dyn Shape>>,
+    fail: refs::Ref
 }
 
-impl Int64 {
-    pub fn new(min: i64, max: i64, node: bool) -> Rc<RefCell<Int64>> {
-        Rc::new(RefCell::new(Int64 {
-            min,
-            max, 
-            node
+impl FailsOn {
+    pub fn new(refs: Vec<refs::Ref>, fail: refs::Ref) -> Rc<RefCell<FailsOn>> {
+        Rc::new(RefCell::new(FailsOn {
+            shape: Fixed::new(refs),
+            fail
         }))
     }
-
-    fn size(&self) -> refs::Size {
-        refs::Size {
-            value: (self.max - self.min) + 1,
-            exact: true
-        }
-    }
 }
 
-
-impl fmt::Display for Int64 {
+impl fmt::Display for FailsOn {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Int64")
+        write!(f, "FailsOn")
     }
 }
 
-
-impl Shape for Int64 {
+impl Shape for FailsOn {
     fn iterate(&self) -> Rc<RefCell<dyn Scanner>> {
-        Rc::new(RefCell::new(Int64Next {
-            node: self.node,
-            min: self.min,
-            max: self.max,
-            at: self.min,
-            result: 0
-        }))
+        self.shape.borrow().iterate()
     }
-
     fn lookup(&self) -> Rc<RefCell<dyn Index>> {
-        Rc::new(RefCell::new(Int64Contains {
-            node: self.node,
-            min: self.min,
-            max: self.max,
-            at: self.min,
-            result: 0
+        Rc::new(RefCell::new(FailsOnContains {
+            index: self.shape.borrow().lookup(),
+            fail: self.fail.clone(),
+            err: None
         }))
     }
-
-    #[allow(unused)]
     fn stats(&mut self) -> Result<Costs, String> {
-        let s = self.size();
-        return Ok(Costs {
-            contains_cost: 1,
-            next_cost: 1,
-            size: s
-        })
+        self.shape.borrow_mut().stats()
     }
-
-    #[allow(unused)]
     fn optimize(&mut self) -> Option<Rc<RefCell<dyn Shape>>> {
         None
     }
-
     fn sub_iterators(&self) -> Option<Vec<Rc<RefCell<dyn Shape>>>> {
         None
     }
-
-    fn shape_type(&mut self) -> ShapeType {
-        ShapeType::Int64
-    }
-}
-
-fn val_to_int_64(v: &refs::Ref) -> i64 {
-    if let refs::Content::Value(c) = &v.content {
-        if let Value::Number(n) = c {
-            return n.as_i64().unwrap()
-        }
+    fn shape_type(&mut self) -> ShapeType<'_> {
+        ShapeType::Test
     }
-    panic!("Not i64 value")
 }
 
-struct Int64Next {
-    node: bool,
-    min: i64,
-    max: i64,
-    at: i64,
-    result: i64
+struct FailsOnContains {
+    index: Rc<RefCell<dyn Index>>,
+    fail: refs::Ref,
+    err: Option<String>
 }
 
-impl fmt::Display for Int64Next {
+impl fmt::Display for FailsOnContains {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Int64Next")
+        write!(f, "FailsOnContains")
     }
 }
 
-impl Base for Int64Next {
-
-    #[allow(unused)]
-    fn tag_results(&self, tags: &mut HashMap<String, refs::Ref>) {}
-
+impl Base for FailsOnContains {
+    fn tag_results(&self, tags: &mut HashMap<String, refs::Ref>) {
+        self.index.borrow().tag_results(tags)
+    }
     fn result(&self) -> Option<refs::Ref> {
-        // TODO: NODE AND QUAD
-        Some(refs::Ref::new_i64_node(self.result))
+        self.index.borrow().result()
     }
-
-    #[allow(unused)]
     fn next_path(&mut self) -> bool {
         false
     }
-
     fn err(&self) -> Option<String> {
-        None
+        self.err.clone()
     }
-
     fn close(&mut self) -> Result<(), String> {
-        Ok(())
+        self.index.borrow_mut().close()
     }
 }
 
-impl Scanner for Int64Next {
-    #[allow(unused)]
-    fn next(&mut self) -> bool {
-        if self.at == -1 {
+impl Index for FailsOnContains {
+    fn contains(&mut self, v:&refs::Ref) -> bool {
+        if *v == self.fail {
+            self.err = Some("failed".to_string());
             return false
         }
-        let val = self.at;
-        self.at = self.at + 1;
-        if self.at > self.max {
-            self.at = -1;
-        }
-        self.result = val;
-        return true
+        self.index.borrow_mut().contains(v)
     }
 }
 
 
-struct Int64Contains {
-    node: bool,
-    min: i64,
-    max: i64,
-    at: i64,
-    result: i64
+
+// A store whose quad iterator fails for one node, like a store that lost its connection.
+pub struct FailsOnQuads {
+    pub store: Store,
+    pub fail: Value
 }
 
-impl fmt::Display for Int64Contains {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Int64Contains")
+impl refs::Namer for FailsOnQuads {
+    fn value_of(&self, v: &Value) -> Option<refs::Ref> {
+        self.store.value_of(v)
+    }
+    fn name_of(&self, key: &refs::Ref) -> Option<Value> {
+        self.store.name_of(key)
     }
 }
 
-impl Base for Int64Contains {
-
-    #[allow(unused)]
-    fn tag_results(&self, tags: &mut HashMap<String, refs::Ref>) {}
-
-    fn result(&self) -> Option<refs::Ref> {
-        // TODO: NODE AND QUAD
-        Some(refs::Ref::new_i64_node(self.result))
+impl QuadStore for FailsOnQuads {
+    fn quad(&self, r: &refs::Ref) -> Option<Quad> {
+        self.store.quad(r)
     }
-
-    #[allow(unused)]
-    fn next_path(&mut self) -> bool {
-        false
+    fn quad_iterator(&self, d: &Direction, r: &refs::Ref) -> Rc<RefCell<dyn Shape>> {
+        if matches!(r.key(), Some(refs::Key::Value(v)) if *v == self.fail) {
+            return Test::new(false, Some("failed".to_string()))
+        }
+        self.store.quad_iterator(d, r)
     }
-
-    fn err(&self) -> Option<String> {
-        None
+    fn quad_iterator_size(&self, d: &Direction, r: &refs::Ref) -> Result<refs::Size, String> {
+        self.store.quad_iterator_size(d, r)
     }
-
-    fn close(&mut self) -> Result<(), String> {
-        Ok(())
+    fn quad_direction(&self, r: &refs::Ref, d: &Direction) -> Option<refs::Ref> {
+        self.store.quad_direction(r, d)
+    }
+    fn stats(&self, exact: bool) -> Result<Stats, String> {
+        self.store.stats(exact)
+    }
+    fn apply_deltas(&mut self, deltas: Vec<Delta>, ignore_opts: &IgnoreOptions) -> Result<(), String> {
+        self.store.apply_deltas(deltas, ignore_opts)
+    }
+    fn nodes_all_iterator(&self) -> Rc<RefCell<dyn Shape>> {
+        self.store.nodes_all_iterator()
+    }
+    fn quads_all_iterator(&self) -> Rc<RefCell<dyn Shape>> {
+        self.store.quads_all_iterator()
+    }
+    fn close(&self) -> Option<String> {
+        self.store.close()
     }
 }
 
-impl Index for Int64Contains {
-    #[allow(unused)]
-    fn contains(&mut self, v:&refs::Ref) -> bool {
-        let v = val_to_int_64(v);
-        if self.min <= v && v <= self.max {
-            self.result = v;
-            return true
+
+
+pub fn val_to_int_64(v: &refs::Ref) -> i64 {
+    if let refs::Content::Value(c) = &v.content {
+        if let Value::Number(n) = c {
+            return n.as_i64().unwrap()
         }
-        return false
     }
+    panic!("Not i64 value")
 }
 
 
@@ -341,4 +300,59 @@ pub fn iterated(s: Rc<RefCell<dyn Shape>>) -> Vec<i64> {
     }
     let _ = it.borrow_mut().close();
     return res
-}
\ No newline at end of file
+}
+
+
+// the graph the query language tests run on, with the quads a test needs on top
+pub fn simple_graph(extra: Vec<Quad>) -> gizmo::GraphWrapper {
+    let simple_graph = gizmo::new_memory_graph();
+
+    let mut quads = vec![
+        Quad::new("<alice>", "<follows>", "<bob>", ()),
+        Quad::new("<bob>", "<follows>", "<fred>", ()),
+        Quad::new("<bob>", "<status>", "cool_person", ()),
+        Quad::new("<dani>", "<follows>", "<bob>", ()),
+        Quad::new("<charlie>", "<follows>", "<bob>", ()),
+        Quad::new("<charlie>", "<follows>", "<dani>", ()),
+        Quad::new("<dani>", "<follows>", "<greg>", ()),
+        Quad::new("<dani>", "<status>", "cool_person", ()),
+        Quad::new("<emily>", "<follows>", "<fred>", ()),
+        Quad::new("<fred>", "<follows>", "<greg>", ()),
+        Quad::new("<greg>", "<status>", "cool_person", ()),
+        Quad::new("<emily>", "<status>", "smart_person", "<smart_graph>"),
+        Quad::new("<greg>", "<status>", "smart_person", "<smart_graph>")
+    ];
+    quads.extend(extra);
+    simple_graph.write(quads);
+
+    simple_graph
+}
+
+
+// results come in store order, the list at the JSON pointer is sorted by id to compare them
+pub fn sorted_by_id(mut v: serde_json::Value, pointer: &str) -> serde_json::Value {
+    if let Some(list) = v.pointer_mut(pointer).and_then(|l| l.as_array_mut()) {
+        list.sort_by_key(|o| o["id"].to_string());
+    }
+    v
+}
+
+// the SPARQL solutions as "value value ..." for the variables, missing values as "-"
+pub fn binding_rows(r: &serde_json::Value, vars: &[&str]) -> Vec<String> {
+    r["results"]["bindings"].as_array().unwrap().iter().map(|b| {
+        vars.iter().map(|v| b[*v]["value"].as_str().unwrap_or("-").to_string()).collect::<Vec<_>>().join(" ")
+    }).collect()
+}
+
+// the rows as "value value ..." for the names, missing values as "-"
+pub fn rows(results: &[HashMap<String, Value>], names: &[&str]) -> Vec<String> {
+    results.iter().map(|r| {
+        names.iter().map(|n| r.get(*n).map(|v| v.to_string()).unwrap_or_else(|| "-".to_string())).collect::<Vec<_>>().join(" ")
+    }).collect()
+}
+
+// rows that come in store order, sorted to compare them
+pub fn sorted(mut rows: Vec<String>) -> Vec<String> {
+    rows.sort();
+    rows
+}
@@ -0,0 +1,86 @@
+use gizmo_graph_db::graph::algo::{Projection, Scope};
+use gizmo_graph_db::graph::algo::pagerank::{page_rank, PageRankOptions};
+use gizmo_graph_db::graph::algo::components::{weakly_connected_components, strongly_connected_components};
+use gizmo_graph_db::graph::algo::degree::{in_degree, out_degree, degree};
+use gizmo_graph_db::graph::memstore::quadstore::MemStore;
+use gizmo_graph_db::graph::quad::{Quad, QuadStore, Delta, Procedure, IgnoreOptions};
+use gizmo_graph_db::graph::value::Value;
+
+
+fn algo_test_qs() -> MemStore {
+    let mut qs = MemStore::new();
+    let quads = vec![
+        Quad::new("alice", "follows", "bob", ()),
+        Quad::new("bob", "follows", "charlie", ()),
+        Quad::new("charlie", "follows", "alice", ()),
+        Quad::new("charlie", "follows", "dani", ()),
+        Quad::new("emily", "follows", "fred", ()),
+        Quad::new("dani", "knows", "emily", ()),
+        Quad::new("greg", "follows", "alice", "archive"),
+    ];
+    qs.apply_deltas(
+        quads.into_iter().map(|quad| Delta { quad, action: Procedure::Add }).collect(),
+        &IgnoreOptions { ignore_dup: true, ignore_missing: true }
+    ).unwrap();
+    qs
+}
+
+fn ids(scores: &gizmo_graph_db::graph::algo::Scores, nodes: Vec<&str>) -> Vec<i64> {
+    nodes.into_iter().map(|n| scores.get(&Value::from(n)).unwrap().as_i64().unwrap()).collect()
+}
+
+
+#[test]
+fn test_degree() {
+    let qs = algo_test_qs();
+    let g = Projection::load(&qs, &Scope::new(Some(vec!["follows".into()]), Some(Value::None))).unwrap();
+
+    assert_eq!(g.len(), 6);
+    assert_eq!(ids(&in_degree(&g), vec!["alice", "dani", "emily"]), vec![1, 1, 0]);
+    assert_eq!(ids(&out_degree(&g), vec!["alice", "charlie", "fred"]), vec![1, 2, 0]);
+    assert_eq!(ids(&degree(&g), vec!["alice", "charlie"]), vec![2, 3]);
+
+    let g = Projection::load(&qs, &Scope::all()).unwrap();
+    assert_eq!(g.len(), 7);
+    assert_eq!(ids(&in_degree(&g), vec!["alice", "emily"]), vec![2, 1]);
+}
+
+
+#[test]
+fn test_connected_components() {
+    let qs = algo_test_qs();
+    let g = Projection::load(&qs, &Scope::new(Some(vec!["follows".into()]), Some(Value::None))).unwrap();
+
+    let wcc = weakly_connected_components(&g);
+    assert_eq!(ids(&wcc, vec!["alice", "bob", "charlie", "dani", "emily", "fred"]), vec![0, 0, 0, 0, 1, 1]);
+
+    let scc = strongly_connected_components(&g);
+    assert_eq!(ids(&scc, vec!["alice", "bob", "charlie", "dani", "emily", "fred"]), vec![0, 0, 0, 1, 2, 3]);
+
+    let g = Projection::load(&qs, &Scope::all()).unwrap();
+    let wcc = weakly_connected_components(&g);
+    assert!(wcc.iter().all(|(_, c)| c.as_i64() == Some(0)));
+}
+
+
+#[test]
+fn test_page_rank_and_write() {
+    let mut qs = algo_test_qs();
+    let g = Projection::load(&qs, &Scope::all()).unwrap();
+
+    let ranks = page_rank(&g, &PageRankOptions::default());
+    let total: f64 = ranks.iter().map(|(_, r)| if let Value::Number(n) = r { n.as_f64().unwrap() } else { 0.0 }).sum();
+    assert!((total - 1.0).abs() < 1e-6);
+
+    let rank_of = |n: &str| if let Some(Value::Number(r)) = ranks.get(&Value::from(n)) { r.as_f64().unwrap() } else { 0.0 };
+    assert!(rank_of("alice") > rank_of("greg"));
+    assert!(rank_of("fred") > rank_of("emily"));
+
+    let before = qs.stats(true).unwrap().quads.value;
+    out_degree(&g).write(&mut qs, &"out_degree".into(), &"stats".into()).unwrap();
+    assert_eq!(qs.stats(true).unwrap().quads.value, before + 7);
+
+    let written = Projection::load(&qs, &Scope::new(Some(vec!["out_degree".into()]), Some("stats".into()))).unwrap();
+    let charlie = written.nodes().iter().position(|n| n == &Value::from("charlie")).unwrap();
+    assert_eq!(written.nodes()[written.out_edges(charlie)[0]], Value::from(2));
+}
@@ -0,0 +1,142 @@
+use gizmo_graph_db::graph::iterator::fixed::{Fixed};
+use gizmo_graph_db::graph::iterator::all_paths::{AllPaths, route_steps};
+use gizmo_graph_db::graph::iterator::{Shape, Scanner};
+use gizmo_graph_db::graph::refs::{pre_fetched};
+use gizmo_graph_db::graph::value::{Value};
+use gizmo_graph_db::graph::graphmock::{Store};
+use gizmo_graph_db::graph::quad::{Quad};
+use gizmo_graph_db::graph::refs::{Namer};
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use super::common;
+
+
+fn path_test_qs() -> Store {
+    Store {
+        data: vec![
+            Quad::new("alice", "follows", "bob", ""),
+            Quad::new("bob", "follows", "charlie", ""),
+            Quad::new("charlie", "follows", "dani", ""),
+            Quad::new("alice", "knows", "dani", ""),
+            Quad::new("bob", "knows", "dani", ""),
+            Quad::new("dani", "follows", "alice", ""),
+        ].into_iter().collect()
+    }
+}
+
+fn fixed(vals: Vec<&str>) -> Rc<RefCell<Fixed>> {
+    Fixed::new(vals.into_iter().map(|v| pre_fetched(Value::from(v))).collect())
+}
+
+// the tags of the result named by the store
+fn named_tags(qs: &Rc<RefCell<Store>>, it: &Rc<RefCell<dyn Scanner>>) -> HashMap<String, Value> {
+    let mut tags = HashMap::new();
+    it.borrow().tag_results(&mut tags);
+    tags.into_iter().filter_map(|(k, r)| Some((k, qs.borrow().name_of(&r)?))).collect()
+}
+
+fn routes(qs: &Rc<RefCell<Store>>, it: &Rc<RefCell<dyn Scanner>>) -> Vec<String> {
+    let mut out = Vec::new();
+    while it.borrow_mut().next() {
+        let route = route_steps(&named_tags(qs, it), "path").unwrap();
+        let steps: Vec<String> = route.iter().map(|s| match &s.predicate {
+            Some(p) => format!("{}:{} {}", if s.forward { "out" } else { "in" }, p, s.node),
+            None => s.node.to_string()
+        }).collect();
+        out.push(steps.join(" "));
+    }
+    out.sort();
+    out
+}
+
+
+#[test]
+fn test_all_paths_next() {
+    let qs = Rc::new(RefCell::new(path_test_qs()));
+
+    let ap = AllPaths::new(qs.clone(), fixed(vec!["alice"]), fixed(vec!["dani"]), None, 0, false, "path".to_string());
+    let it = ap.borrow().iterate();
+
+    assert_eq!(routes(&qs, &it), vec![
+        "alice out:follows bob out:follows charlie out:follows dani",
+        "alice out:follows bob out:knows dani",
+        "alice out:knows dani",
+    ]);
+
+    // a start node is not a route to itself, nor are the cycles back to it
+    let ap = AllPaths::new(qs.clone(), fixed(vec!["alice"]), fixed(vec!["alice", "bob"]), None, 0, false, "path".to_string());
+    let it = ap.borrow().iterate();
+    assert_eq!(routes(&qs, &it), vec!["alice out:follows bob"]);
+}
+
+
+#[test]
+fn test_all_paths_via_depth_and_both() {
+    let qs = Rc::new(RefCell::new(path_test_qs()));
+
+    let ap = AllPaths::new(qs.clone(), fixed(vec!["alice"]), fixed(vec!["dani"]), Some(fixed(vec!["follows"])), 0, false, "path".to_string());
+    let it = ap.borrow().iterate();
+    assert_eq!(routes(&qs, &it), vec!["alice out:follows bob out:follows charlie out:follows dani"]);
+
+    let ap = AllPaths::new(qs.clone(), fixed(vec!["alice"]), fixed(vec!["dani"]), None, 2, false, "path".to_string());
+    let it = ap.borrow().iterate();
+    assert_eq!(routes(&qs, &it), vec![
+        "alice out:follows bob out:knows dani",
+        "alice out:knows dani",
+    ]);
+
+    let ap = AllPaths::new(qs.clone(), fixed(vec!["charlie"]), fixed(vec!["alice"]), Some(fixed(vec!["follows"])), 0, true, "path".to_string());
+    let it = ap.borrow().iterate();
+    assert_eq!(routes(&qs, &it), vec![
+        "charlie in:follows bob in:follows alice",
+        "charlie out:follows dani out:follows alice",
+    ]);
+}
+
+
+#[test]
+fn test_all_paths_contains() {
+    let qs = Rc::new(RefCell::new(path_test_qs()));
+
+    let ap = AllPaths::new(qs.clone(), fixed(vec!["alice", "charlie"]), fixed(vec!["bob", "dani"]), Some(fixed(vec!["knows"])), 0, false, "path".to_string());
+    let it = ap.borrow().lookup();
+
+    assert!(it.borrow_mut().contains(&pre_fetched(Value::from("dani"))));
+    assert!(!it.borrow_mut().contains(&pre_fetched(Value::from("bob"))));
+    assert!(it.borrow_mut().contains(&pre_fetched(Value::from("dani"))));
+
+    // every route to the node is a path of the result, found by a single search
+    let ap = AllPaths::new(qs.clone(), fixed(vec!["alice"]), fixed(vec!["dani"]), None, 0, false, "path".to_string());
+    let it = ap.borrow().lookup();
+    assert!(it.borrow_mut().contains(&pre_fetched(Value::from("dani"))));
+    let mut lengths = Vec::new();
+    loop {
+        let mut tags = HashMap::new();
+        it.borrow().tag_results(&mut tags);
+        let tags = tags.into_iter().filter_map(|(k, r)| Some((k, qs.borrow().name_of(&r)?))).collect();
+        lengths.push(route_steps(&tags, "path").unwrap().len());
+        if !it.borrow_mut().next_path() {
+            break
+        }
+    }
+    lengths.sort();
+    assert_eq!(lengths, vec![2, 3, 4]);
+    assert!(!it.borrow_mut().contains(&pre_fetched(Value::from("alice"))));
+}
+
+
+#[test]
+fn test_all_paths_store_error() {
+    // the routes found before the store failed are returned, then the error
+    let qs = Rc::new(RefCell::new(common::FailsOnQuads { store: path_test_qs(), fail: Value::from("charlie") }));
+
+    let ap = AllPaths::new(qs.clone(), fixed(vec!["alice"]), fixed(vec!["dani"]), None, 0, false, "path".to_string());
+    let it = ap.borrow().iterate();
+    let mut found = 0;
+    while it.borrow_mut().next() {
+        found += 1;
+    }
+    assert!(found < 3);
+    assert_eq!(it.borrow().err(), Some("failed".to_string()));
+}
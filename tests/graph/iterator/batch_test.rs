@@ -0,0 +1,159 @@
+use gizmo_graph_db::graph::iterator::fixed::{Fixed};
+use gizmo_graph_db::graph::iterator::and::{And};
+use gizmo_graph_db::graph::iterator::{Shape, BATCH_SIZE};
+use gizmo_graph_db::graph::memstore::quadstore::MemStore;
+use gizmo_graph_db::graph::quad::{Quad, QuadStore, Direction, Delta, Procedure, IgnoreOptions};
+use gizmo_graph_db::graph::refs::{Namer, Ref};
+use gizmo_graph_db::graph::value::Value;
+use gizmo_graph_db::graph::hasa::{HasA};
+use gizmo_graph_db::graph::linksto::{LinksTo};
+use std::rc::Rc;
+use std::cell::RefCell;
+use super::common;
+
+
+fn by_next(s: &Rc<RefCell<dyn Shape>>) -> Vec<Ref> {
+    let it = s.borrow().iterate();
+    let mut res = Vec::new();
+    while it.borrow_mut().next() {
+        res.push(it.borrow().result().unwrap());
+    }
+    res
+}
+
+fn by_batch(s: &Rc<RefCell<dyn Shape>>) -> Vec<Ref> {
+    let it = s.borrow().iterate();
+    let mut res = Vec::new();
+    loop {
+        let n = it.borrow_mut().next_batch(&mut res);
+        assert!(n <= BATCH_SIZE);
+        if n == 0 {
+            break
+        }
+    }
+    assert_eq!(it.borrow_mut().next_batch(&mut res), 0);
+    res
+}
+
+fn store() -> Rc<RefCell<MemStore>> {
+    let mut qs = MemStore::new();
+    let mut deltas = Vec::new();
+    for i in 0..600 {
+        deltas.push(Delta { quad: Quad::new(format!("n{}", i), "in", "group", ()), action: Procedure::Add });
+        if i % 3 == 0 {
+            deltas.push(Delta { quad: Quad::new(format!("n{}", i), "is", "odd", ()), action: Procedure::Add });
+        }
+    }
+    qs.apply_deltas(deltas, &IgnoreOptions { ignore_dup: true, ignore_missing: true }).unwrap();
+    Rc::new(RefCell::new(qs))
+}
+
+
+#[test]
+fn test_fixed_batch() {
+    let fixed: Rc<RefCell<dyn Shape>> = Fixed::new((1..=600).map(Ref::new_i64_node).collect());
+    let res = by_batch(&fixed);
+    assert_eq!(res.len(), 600);
+    assert_eq!(res, by_next(&fixed));
+}
+
+
+#[test]
+fn test_memstore_batch() {
+    let qs = store();
+    let group = qs.borrow().value_of(&Value::from("group")).unwrap();
+
+    let quads = qs.borrow().quad_iterator(&Direction::Object, &group);
+    assert_eq!(by_batch(&quads).len(), 600);
+    assert_eq!(by_batch(&quads), by_next(&quads));
+
+    let nodes = qs.borrow().nodes_all_iterator();
+    assert_eq!(by_batch(&nodes).len(), 604);
+    assert_eq!(by_batch(&nodes), by_next(&nodes));
+
+    let all_quads = qs.borrow().quads_all_iterator();
+    assert_eq!(by_batch(&all_quads).len(), 800);
+    assert_eq!(by_batch(&all_quads), by_next(&all_quads));
+}
+
+
+#[test]
+fn test_hasa_linksto_and_batch() {
+    let qs = store();
+    let group = qs.borrow().value_of(&Value::from("group")).unwrap();
+    let odd = qs.borrow().value_of(&Value::from("odd")).unwrap();
+
+    // every node in the group
+    let members: Rc<RefCell<dyn Shape>> = HasA::new(
+        qs.clone(),
+        LinksTo::new(qs.clone(), Fixed::new(vec![group]), Direction::Object),
+        Direction::Subject
+    );
+    assert_eq!(by_batch(&members).len(), 600);
+    assert_eq!(by_batch(&members), by_next(&members));
+
+    // the ones that are also odd, few enough that most batches of the primary match nothing
+    let odd_members: Rc<RefCell<dyn Shape>> = HasA::new(
+        qs.clone(),
+        LinksTo::new(qs.clone(), Fixed::new(vec![odd]), Direction::Object),
+        Direction::Subject
+    );
+    let and: Rc<RefCell<dyn Shape>> = And::new(vec![members, odd_members]);
+    assert_eq!(by_batch(&and).len(), 200);
+    assert_eq!(by_batch(&and), by_next(&and));
+
+    let names: Vec<Value> = by_batch(&and).iter().filter_map(|r| qs.borrow().name_of(r)).collect();
+    assert_eq!(names[0], Value::from("n0"));
+    assert_eq!(names[1], Value::from("n3"));
+}
+
+
+#[test]
+fn test_linksto_batch_size() {
+    // two primary nodes of 200 quads each, more than one batch between them
+    let mut qs = MemStore::new();
+    let mut deltas = Vec::new();
+    for i in 0..200 {
+        for from in &["a", "b"] {
+            deltas.push(Delta { quad: Quad::new(*from, "to", format!("n{}", i), ()), action: Procedure::Add });
+        }
+    }
+    qs.apply_deltas(deltas, &IgnoreOptions { ignore_dup: true, ignore_missing: true }).unwrap();
+    let a = qs.value_of(&Value::from("a")).unwrap();
+    let b = qs.value_of(&Value::from("b")).unwrap();
+    let qs = Rc::new(RefCell::new(qs));
+
+    let links: Rc<RefCell<dyn Shape>> = LinksTo::new(qs.clone(), Fixed::new(vec![a, b]), Direction::Subject);
+    let it = links.borrow().iterate();
+    let mut res = Vec::new();
+    assert_eq!(it.borrow_mut().next_batch(&mut res), BATCH_SIZE);
+
+    // next picks up where the batch stopped
+    assert!(it.borrow_mut().next());
+    res.push(it.borrow().result().unwrap());
+    assert_eq!(it.borrow_mut().next_batch(&mut res), 400 - BATCH_SIZE - 1);
+    assert_eq!(it.borrow_mut().next_batch(&mut res), 0);
+    assert_eq!(res, by_next(&links));
+}
+
+
+#[test]
+fn test_and_batch_err() {
+    let refs: Vec<Ref> = (1..=3).map(Ref::new_i64_node).collect();
+    let failing = || common::FailsOn::new(refs.clone(), Ref::new_i64_node(2));
+
+    let and: Rc<RefCell<dyn Shape>> = And::new(vec![Fixed::new(refs.clone()), failing()]);
+    let it = and.borrow().iterate();
+    assert!(it.borrow_mut().next());
+    assert!(!it.borrow_mut().next());
+    assert_eq!(it.borrow().err(), Some("failed".to_string()));
+
+    // the batch stops at the failure, not returning 3 after it
+    let and: Rc<RefCell<dyn Shape>> = And::new(vec![Fixed::new(refs.clone()), failing()]);
+    let it = and.borrow().iterate();
+    let mut res = Vec::new();
+    assert_eq!(it.borrow_mut().next_batch(&mut res), 1);
+    assert_eq!(it.borrow_mut().next_batch(&mut res), 0);
+    assert_eq!(res, vec![Ref::new_i64_node(1)]);
+    assert_eq!(it.borrow().err(), Some("failed".to_string()));
+}
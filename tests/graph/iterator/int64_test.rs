@@ -0,0 +1,57 @@
+use gizmo_graph_db::graph::iterator::int64::{Int64};
+use gizmo_graph_db::graph::iterator::{Shape};
+use gizmo_graph_db::graph::refs::{Ref};
+use super::common;
+
+
+#[test]
+fn test_int64_iterator_basics() {
+    let all = Int64::new(3, 7, true);
+    let stats = all.borrow_mut().stats().unwrap();
+    assert_eq!(5, stats.size.value);
+    assert!(stats.size.exact);
+    assert_eq!(vec![3, 4, 5, 6, 7], common::iterated(all.clone()));
+
+    let empty = Int64::new(5, 4, true);
+    assert_eq!(0, empty.borrow_mut().stats().unwrap().size.value);
+    assert!(common::iterated(empty).is_empty());
+
+    let last = Int64::new(i64::MAX - 1, i64::MAX, true);
+    assert_eq!(vec![i64::MAX - 1, i64::MAX], common::iterated(last));
+}
+
+
+#[test]
+fn test_int64_iterator_contains() {
+    let all = Int64::new(3, 7, true);
+    let c = all.borrow().lookup();
+
+    assert!(c.borrow_mut().contains(&Ref::new_i64_node(3)));
+    assert_eq!(Some(Ref::new_i64_node(3)), c.borrow().result());
+    assert!(c.borrow_mut().contains(&Ref::new_i64_quad(7)));
+    assert!(!c.borrow_mut().contains(&Ref::new_i64_node(8)));
+    assert!(!c.borrow_mut().contains(&Ref::new_i64_node(2)));
+    assert_eq!(None, c.borrow().result());
+
+    let quads = Int64::new(1, 2, false);
+    let it = quads.borrow().iterate();
+    assert!(it.borrow_mut().next());
+    assert_eq!(Some(Ref::new_i64_quad(1)), it.borrow().result());
+}
+
+
+#[test]
+fn test_int64_iterator_negative() {
+    let all = Int64::new(-3, 2, true);
+    assert_eq!(vec![-3, -2, -1, 0, 1, 2], common::iterated(all.clone()));
+
+    let it = all.borrow().iterate();
+    let c = all.borrow().lookup();
+    while it.borrow_mut().next() {
+        let r = it.borrow().result().unwrap();
+        assert!(c.borrow_mut().contains(&r), "{:?}", r);
+        assert_eq!(Some(r), c.borrow().result());
+    }
+    assert!(c.borrow_mut().contains(&Ref::new_i64_quad(-3)));
+    assert!(!c.borrow_mut().contains(&Ref::new_i64_node(-4)));
+}
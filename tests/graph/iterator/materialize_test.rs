@@ -1,6 +1,13 @@
+use gizmo_graph_db::graph::iterator::int64::{Int64};
 use gizmo_graph_db::graph::iterator::materialize::{Materialize, MATERIALIZE_LIMIT};
 use gizmo_graph_db::graph::iterator::or::{Or};
+use gizmo_graph_db::graph::iterator::fixed::{Fixed};
+use gizmo_graph_db::graph::iterator::save::{tag};
 use gizmo_graph_db::graph::iterator::{Shape};
+use gizmo_graph_db::graph::refs::{Ref};
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::cell::RefCell;
 use super::common;
 
 
@@ -19,7 +26,7 @@ fn test_materialize_iterator_error() {
 fn test_materialize_iterator_error_abort() {
     let err_it = common::Test::new(false, Some("unique".to_string()));
 
-    let or = Or::new(vec![common::Int64::new(1, (MATERIALIZE_LIMIT+1) as i64, true), err_it]);
+    let or = Or::new(vec![Int64::new(1, (MATERIALIZE_LIMIT+1) as i64, true), err_it]);
 
     let m_it = Materialize::new(or).borrow().iterate();
 
@@ -30,4 +37,19 @@ fn test_materialize_iterator_error_abort() {
 
     assert!(!m_it.borrow_mut().next());
     assert_eq!(Some("unique".to_string()), m_it.borrow().err());
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_materialize_iterator_tags() {
+    let fixed: Rc<RefCell<dyn Shape>> = Fixed::new(vec![Ref::new_i64_node(1), Ref::new_i64_node(2)]);
+    let m_it = Materialize::new(tag(&fixed, &"t")).borrow().iterate();
+
+    let mut found = Vec::new();
+    while m_it.borrow_mut().next() {
+        let mut tags = HashMap::new();
+        m_it.borrow().tag_results(&mut tags);
+        assert_eq!(tags.get("t"), m_it.borrow().result().as_ref());
+        found.push(common::val_to_int_64(&tags["t"]));
+    }
+    assert_eq!(found, vec![1, 2]);
+}
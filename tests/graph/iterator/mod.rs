@@ -1,5 +1,6 @@
 mod and_test;
 mod count_test;
+mod int64_test;
 mod limit_test;
 mod materialize_test;
 mod not_tests;
@@ -8,5 +9,10 @@ mod recursive_test;
 mod resolver_test;
 mod unique_test;
 mod skip_test;
+mod sort_test;
+mod shortest_path_test;
+mod all_paths_test;
+mod batch_test;
+mod spill_test;
 
 use super::common;
\ No newline at end of file
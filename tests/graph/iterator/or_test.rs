@@ -1,3 +1,4 @@
+use gizmo_graph_db::graph::iterator::int64::{Int64};
 use gizmo_graph_db::graph::iterator::fixed::{Fixed};
 use gizmo_graph_db::graph::iterator::or::{Or};
 use gizmo_graph_db::graph::iterator::{Shape};
@@ -138,7 +139,7 @@ fn test_or_iterator_err() {
 
     let fix1 = Fixed::new(vec![Ref::new_i64_node(1)]);
 
-    let or = Or::new( vec![fix1, or_err, common::Int64::new(1, 5, true)] ).borrow().iterate();
+    let or = Or::new( vec![fix1, or_err, Int64::new(1, 5, true)] ).borrow().iterate();
 
     assert!(or.borrow_mut().next());
     assert_eq!(Ref::new_i64_node(1), or.borrow().result().unwrap());
@@ -152,7 +153,7 @@ fn test_or_iterator_err() {
 fn test_short_circuit_or_iterator_err() {
     let or_err = common::Test::new(false, Some("unique".to_string()));
 
-    let or = Or::new( vec![or_err, common::Int64::new(1, 5, true)] ).borrow().iterate();
+    let or = Or::new( vec![or_err, Int64::new(1, 5, true)] ).borrow().iterate();
 
     assert!(!or.borrow_mut().next());
     assert_eq!("unique", or.borrow().err().unwrap());
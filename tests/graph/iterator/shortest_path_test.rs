@@ -0,0 +1,97 @@
+use gizmo_graph_db::graph::iterator::fixed::{Fixed};
+use gizmo_graph_db::graph::iterator::shortest_path::{ShortestPath};
+use gizmo_graph_db::graph::iterator::{Shape};
+use gizmo_graph_db::graph::refs::{pre_fetched};
+use gizmo_graph_db::graph::value::{Value};
+use gizmo_graph_db::graph::graphmock::{Store};
+use gizmo_graph_db::graph::quad::{Quad};
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use super::common;
+
+
+fn path_test_qs() -> Store {
+    Store {
+        data: vec![
+            Quad::new("alice", "follows", "bob", ""),
+            Quad::new("bob", "follows", "charlie", ""),
+            Quad::new("charlie", "follows", "dani", ""),
+            Quad::new("dani", "follows", "emily", ""),
+            Quad::new("alice", "knows", "dani", ""),
+            Quad::new("fred", "follows", "alice", ""),
+        ].into_iter().collect()
+    }
+}
+
+fn fixed(vals: Vec<&str>) -> Rc<RefCell<Fixed>> {
+    Fixed::new(vals.into_iter().map(|v| pre_fetched(Value::from(v))).collect())
+}
+
+
+#[test]
+fn test_shortest_path_next() {
+    let qs = Rc::new(RefCell::new(path_test_qs()));
+
+    let sp = ShortestPath::new(qs.clone(), fixed(vec!["alice"]), fixed(vec!["emily"]), None, 0);
+    sp.borrow_mut().add_tag("pred".to_string());
+    let it = sp.borrow().iterate();
+
+    let mut nodes = Vec::new();
+    let mut preds = Vec::new();
+    while it.borrow_mut().next() {
+        nodes.push(it.borrow().result().unwrap().key().unwrap().to_string());
+        let mut tags = HashMap::new();
+        it.borrow().tag_results(&mut tags);
+        preds.push(tags.get("pred").map(|p| p.key().unwrap().to_string()));
+    }
+
+    assert_eq!(nodes, vec!["alice", "dani", "emily"]);
+    assert_eq!(preds, vec![None, Some("knows".to_string()), Some("follows".to_string())]);
+}
+
+
+#[test]
+fn test_shortest_path_via_and_depth() {
+    let qs = Rc::new(RefCell::new(path_test_qs()));
+
+    let sp = ShortestPath::new(qs.clone(), fixed(vec!["fred"]), fixed(vec!["emily"]), Some(fixed(vec!["follows"])), 0);
+    let it = sp.borrow().iterate();
+    let mut nodes = Vec::new();
+    while it.borrow_mut().next() {
+        nodes.push(it.borrow().result().unwrap().key().unwrap().to_string());
+    }
+    assert_eq!(nodes, vec!["fred", "alice", "bob", "charlie", "dani", "emily"]);
+
+    let sp = ShortestPath::new(qs.clone(), fixed(vec!["fred"]), fixed(vec!["emily"]), Some(fixed(vec!["follows"])), 4);
+    let it = sp.borrow().iterate();
+    assert!(!it.borrow_mut().next());
+
+    let sp = ShortestPath::new(qs.clone(), fixed(vec!["emily"]), fixed(vec!["alice"]), None, 0);
+    let it = sp.borrow().iterate();
+    assert!(!it.borrow_mut().next());
+}
+
+
+#[test]
+fn test_shortest_path_contains() {
+    let qs = Rc::new(RefCell::new(path_test_qs()));
+
+    let sp = ShortestPath::new(qs.clone(), fixed(vec!["alice"]), fixed(vec!["emily"]), None, 0);
+    let it = sp.borrow().lookup();
+
+    assert!(it.borrow_mut().contains(&pre_fetched(Value::from("dani"))));
+    assert!(!it.borrow_mut().contains(&pre_fetched(Value::from("bob"))));
+}
+
+
+#[test]
+fn test_shortest_path_store_error() {
+    // a store failing to read the quads of a node fails the search, it doesn't end it short
+    let qs = Rc::new(RefCell::new(common::FailsOnQuads { store: path_test_qs(), fail: Value::from("alice") }));
+
+    let sp = ShortestPath::new(qs.clone(), fixed(vec!["alice"]), fixed(vec!["emily"]), None, 0);
+    let it = sp.borrow().iterate();
+    assert!(!it.borrow_mut().next());
+    assert_eq!(it.borrow().err(), Some("failed".to_string()));
+}
@@ -0,0 +1,69 @@
+use gizmo_graph_db::graph::iterator::fixed::{Fixed};
+use gizmo_graph_db::graph::iterator::sort::{Sort, SortKey, Order};
+use gizmo_graph_db::graph::iterator::save::{tag};
+use gizmo_graph_db::graph::iterator::{Shape};
+use gizmo_graph_db::graph::refs::{Ref, pre_fetched};
+use gizmo_graph_db::graph::value::{Value};
+use gizmo_graph_db::graph::graphmock::{Store};
+use std::rc::Rc;
+use std::cell::RefCell;
+use super::common;
+
+
+fn numbers() -> Rc<RefCell<Fixed>> {
+    Fixed::new(vec![
+        Ref::new_i64_node(3),
+        Ref::new_i64_node(10),
+        Ref::new_i64_node(1),
+        Ref::new_i64_node(7),
+        Ref::new_i64_node(2),
+    ])
+}
+
+
+#[test]
+fn test_sort_iterator_order() {
+    let qs = Rc::new(RefCell::new(Store::new()));
+
+    let s = Sort::new(qs.clone(), numbers());
+    assert_eq!(vec![1, 2, 3, 7, 10], common::iterated(s));
+
+    let s = Sort::new_by(qs.clone(), numbers(), vec![SortKey::node(Order::Desc)], None);
+    assert_eq!(vec![10, 7, 3, 2, 1], common::iterated(s));
+}
+
+
+#[test]
+fn test_sort_iterator_top() {
+    let qs = Rc::new(RefCell::new(Store::new()));
+
+    let s = Sort::new_by(qs.clone(), numbers(), vec![SortKey::node(Order::Asc)], Some(2));
+    assert_eq!(vec![1, 2], common::iterated(s));
+
+    let s = Sort::new_by(qs.clone(), numbers(), vec![SortKey::node(Order::Desc)], Some(10));
+    assert_eq!(vec![10, 7, 3, 2, 1], common::iterated(s));
+
+    let s = Sort::new_by(qs.clone(), numbers(), vec![SortKey::node(Order::Asc)], Some(0));
+    assert!(common::iterated(s).is_empty());
+}
+
+
+#[test]
+fn test_sort_iterator_by_tag() {
+    let qs = Rc::new(RefCell::new(Store::new()));
+
+    let names = Fixed::new(vec![
+        pre_fetched(Value::from("b")),
+        pre_fetched(Value::from("a")),
+        pre_fetched(Value::from("c")),
+    ]);
+    let tagged = tag(&(names as Rc<RefCell<dyn Shape>>), &"name");
+
+    let s = Sort::new_by(qs.clone(), tagged, vec![SortKey::tag("name", Order::Desc)], None);
+    let it = s.borrow().iterate();
+    let mut res = Vec::new();
+    while it.borrow_mut().next() {
+        res.push(it.borrow().result().unwrap().key().unwrap().to_string());
+    }
+    assert_eq!(res, vec!["c", "b", "a"]);
+}
@@ -0,0 +1,104 @@
+use gizmo_graph_db::graph::iterator::unique::{Unique};
+use gizmo_graph_db::graph::iterator::materialize::{Materialize};
+use gizmo_graph_db::graph::iterator::fixed::{Fixed};
+use gizmo_graph_db::graph::iterator::spill::{MemoryLimit, MemoryLimitError, LimitFailures, KeySet};
+use gizmo_graph_db::graph::iterator::{Shape};
+use gizmo_graph_db::graph::refs::{Ref, Key};
+use super::common;
+
+
+fn repeated(n: i64) -> Vec<Ref> {
+    (1..=n).chain(1..=n).map(Ref::new_i64_node).collect()
+}
+
+
+#[test]
+fn test_key_set_spill() {
+    let mut seen = KeySet::new("test", Some(MemoryLimit::spill(256)), LimitFailures::new());
+
+    for i in 0..500u64 {
+        assert_eq!(Ok(true), seen.insert(&Key::Id(i)));
+    }
+    assert!(seen.has_spilled());
+    for i in 0..500u64 {
+        assert_eq!(Ok(false), seen.insert(&Key::Id(i)));
+    }
+    assert_eq!(Ok(false), seen.contains(&Key::Id(500)));
+}
+
+
+#[test]
+fn test_unique_iterator_spill() {
+    let u = Unique::new(Fixed::new(repeated(300)));
+    u.borrow_mut().set_memory_limit(Some(MemoryLimit::spill(512)), LimitFailures::new());
+
+    let expect: Vec<i64> = (1..=300).collect();
+    for _ in 0..2 {
+        assert_eq!(expect, common::iterated(u.clone()));
+    }
+}
+
+
+#[test]
+fn test_unique_iterator_limit_error() {
+    let u = Unique::new(Fixed::new(repeated(300)));
+    let failures = LimitFailures::new();
+    u.borrow_mut().set_memory_limit(Some(MemoryLimit::fail(512)), failures.clone());
+
+    let it = u.borrow().iterate();
+    let mut n = 0;
+    while it.borrow_mut().next() {
+        n += 1;
+    }
+    assert!(n < 300);
+
+    let err = it.borrow().err().unwrap();
+    assert_eq!(MemoryLimitError::new("Unique", 512).to_string(), err);
+    assert_eq!(Some(MemoryLimitError::new("Unique", 512)), failures.take());
+    assert_eq!(None, failures.take());
+}
+
+
+#[test]
+fn test_materialize_iterator_spill() {
+    let m = Materialize::new(Fixed::new(repeated(300)));
+    m.borrow_mut().set_memory_limit(Some(MemoryLimit::spill(1024)), LimitFailures::new());
+
+    let it = m.borrow().iterate();
+    let mut res = Vec::new();
+    while it.borrow_mut().next() {
+        res.push(common::val_to_int_64(it.borrow().result().as_ref().unwrap()));
+        // both copies of a node come back as paths of the same result
+        assert!(it.borrow_mut().next_path());
+        assert!(!it.borrow_mut().next_path());
+    }
+    assert!(it.borrow().err().is_none());
+    let _ = it.borrow_mut().close();
+
+    // spilled results come back by bucket, not in the order they were found
+    assert_ne!((1..=300).collect::<Vec<i64>>(), res);
+    res.sort();
+    assert_eq!((1..=300).collect::<Vec<i64>>(), res);
+
+    let mc = m.borrow().lookup();
+    for v in 1..=300 {
+        assert!(mc.borrow_mut().contains(&Ref::new_i64_node(v)));
+    }
+    assert!(!mc.borrow_mut().contains(&Ref::new_i64_node(301)));
+}
+
+
+#[test]
+fn test_materialize_iterator_limit_error() {
+    let m = Materialize::new(Fixed::new(repeated(300)));
+    let failures = LimitFailures::new();
+    m.borrow_mut().set_memory_limit(Some(MemoryLimit::fail(1024)), failures.clone());
+
+    let it = m.borrow().iterate();
+    assert!(!it.borrow_mut().next());
+
+    let err = it.borrow().err().unwrap();
+    assert_eq!(MemoryLimitError::new("Materialize", 1024).to_string(), err);
+    assert_eq!(Some(MemoryLimitError::new("Materialize", 1024)), failures.take());
+    assert_eq!(None, failures.take());
+}
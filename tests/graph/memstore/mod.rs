@@ -0,0 +1 @@
+mod quadstore_test;
@@ -0,0 +1,286 @@
+use gizmo_graph_db::graph::memstore::quadstore::MemStore;
+use gizmo_graph_db::graph::quad::{Quad, QuadStore, QuadWriter, Direction, Delta, Procedure, IgnoreOptions};
+use gizmo_graph_db::graph::refs::{Namer, Ref, Size};
+use gizmo_graph_db::graph::value::Value;
+
+
+fn write(qs: &mut MemStore, quads: Vec<Quad>, action: fn() -> Procedure) {
+    qs.apply_deltas(
+        quads.into_iter().map(|quad| Delta { quad, action: action() }).collect(),
+        &IgnoreOptions { ignore_dup: true, ignore_missing: true }
+    ).unwrap();
+}
+
+fn test_quads() -> Vec<Quad> {
+    vec![
+        Quad::new("alice", "follows", "bob", ()),
+        Quad::new("charlie", "follows", "bob", ()),
+        Quad::new("charlie", "follows", "dani", ()),
+        Quad::new("alice", "knows", "bob", ()),
+        Quad::new("dani", "follows", "bob", "archive"),
+    ]
+}
+
+fn lookup(qs: &MemStore, lookups: Vec<(Direction, &str)>) -> Vec<String> {
+    let refs: Vec<(Direction, Ref)> = lookups.into_iter()
+        .map(|(d, v)| (d, qs.value_of(&Value::from(v)).unwrap_or_else(Ref::none)))
+        .collect();
+    let shape = qs.quad_iterator_multi(&refs).unwrap();
+    let it = shape.borrow().iterate();
+    let mut res = Vec::new();
+    while it.borrow_mut().next() {
+        let q = qs.quad(&it.borrow().result().unwrap()).unwrap();
+        res.push(format!("{} {} {}", q.subject, q.predicate, q.object));
+    }
+    res.sort();
+    res
+}
+
+
+#[test]
+fn test_quad_iterator_multi() {
+    // with no composite indexes the shapes intersect single direction iterators
+    let mut qs = MemStore::new();
+    write(&mut qs, test_quads(), || Procedure::Add);
+    let follows = qs.value_of(&Value::from("follows")).unwrap();
+    let bob = qs.value_of(&Value::from("bob")).unwrap();
+    assert!(qs.quad_iterator_multi(&[(Direction::Predicate, follows), (Direction::Object, bob)]).is_none());
+
+    {
+        let mut qs = MemStore::new_with_composite_indexes();
+        write(&mut qs, test_quads(), || Procedure::Add);
+
+        assert_eq!(lookup(&qs, vec![(Direction::Predicate, "follows"), (Direction::Object, "bob")]), vec![
+            "alice follows bob",
+            "charlie follows bob",
+            "dani follows bob",
+        ]);
+        assert_eq!(lookup(&qs, vec![(Direction::Object, "bob"), (Direction::Subject, "alice")]), vec![
+            "alice follows bob",
+            "alice knows bob",
+        ]);
+        assert_eq!(lookup(&qs, vec![(Direction::Label, "archive"), (Direction::Predicate, "follows")]), vec![
+            "dani follows bob",
+        ]);
+        assert_eq!(lookup(&qs, vec![(Direction::Subject, "charlie"), (Direction::Predicate, "follows"), (Direction::Object, "dani")]), vec![
+            "charlie follows dani",
+        ]);
+        assert!(lookup(&qs, vec![(Direction::Subject, "bob"), (Direction::Predicate, "follows")]).is_empty());
+        assert!(lookup(&qs, vec![(Direction::Subject, "nobody"), (Direction::Predicate, "follows")]).is_empty());
+
+        write(&mut qs, vec![Quad::new("charlie", "follows", "bob", ())], || Procedure::Delete);
+        assert_eq!(lookup(&qs, vec![(Direction::Predicate, "follows"), (Direction::Object, "bob")]), vec![
+            "alice follows bob",
+            "dani follows bob",
+        ]);
+    }
+}
+
+
+#[test]
+fn test_quad_iterator_direction() {
+    let mut qs = MemStore::new();
+    write(&mut qs, vec![
+        Quad::new("a", "b", "c", ()),
+        Quad::new("b", "d", "e", ()),
+    ], || Procedure::Add);
+
+    // "b" is both the last subject and the first predicate in the index
+    let b = qs.value_of(&Value::from("b")).unwrap();
+    let it = qs.quad_iterator(&Direction::Subject, &b).borrow().iterate();
+    let mut subjects = Vec::new();
+    while it.borrow_mut().next() {
+        subjects.push(qs.quad(&it.borrow().result().unwrap()).unwrap().subject);
+    }
+    assert_eq!(subjects, vec![Value::from("b")]);
+    assert_eq!(qs.quad_iterator_size(&Direction::Subject, &b).unwrap().value, 1);
+}
+
+
+#[test]
+fn test_quad_iterator_direction_boundaries() {
+    // "x" is the last value of the first direction and the first value of the next one
+    let cases = vec![
+        (Direction::Subject, Direction::Predicate, vec![Quad::new("s", "x", "o", "l"), Quad::new("x", "p", "c", ())]),
+        (Direction::Predicate, Direction::Object, vec![Quad::new("a", "p", "x", ()), Quad::new("c", "x", "d", ())]),
+        (Direction::Object, Direction::Label, vec![Quad::new("a", "p", "b", "x"), Quad::new("c", "p", "x", "l")]),
+    ];
+
+    for (first, next, quads) in cases {
+        let mut qs = MemStore::new();
+        write(&mut qs, quads, || Procedure::Add);
+        let x = qs.value_of(&Value::from("x")).unwrap();
+
+        for (d, other) in [(&first, &next), (&next, &first)] {
+            let shape = qs.quad_iterator(d, &x);
+            let it = shape.borrow().iterate();
+            let mut found = Vec::new();
+            while it.borrow_mut().next() {
+                found.push(it.borrow().result().unwrap());
+            }
+            assert_eq!(found.len(), 1, "{:?} of x", d);
+            assert_eq!(qs.quad_direction(&found[0], d).and_then(|r| qs.name_of(&r)), Some(Value::from("x")));
+            assert_eq!(qs.quad_iterator_size(d, &x).unwrap().value, 1);
+
+            // the quad holding x in the other direction is not in this one
+            let wrong = qs.quad_iterator(other, &x).borrow().iterate();
+            assert!(wrong.borrow_mut().next());
+            assert!(!shape.borrow().lookup().borrow_mut().contains(&wrong.borrow().result().unwrap()));
+        }
+    }
+}
+
+
+fn subjects(qs: &MemStore, it: &std::rc::Rc<std::cell::RefCell<dyn gizmo_graph_db::graph::iterator::Scanner>>) -> Vec<String> {
+    let mut res = Vec::new();
+    while it.borrow_mut().next() {
+        let q = qs.quad(&it.borrow().result().unwrap()).unwrap();
+        res.push(q.subject.to_string());
+    }
+    res.sort();
+    res
+}
+
+
+#[test]
+fn test_quad_iterator_snapshot() {
+    let mut qs = MemStore::new();
+    write(&mut qs, test_quads(), || Procedure::Add);
+
+    let bob = qs.value_of(&Value::from("bob")).unwrap();
+    let shape = qs.quad_iterator(&Direction::Object, &bob);
+    assert_eq!(qs.quad_iterator_size(&Direction::Object, &bob).unwrap().value, 4);
+    assert_eq!(shape.borrow_mut().stats().unwrap().size.value, 4);
+
+    let it = shape.borrow().iterate();
+    let contains = shape.borrow().lookup();
+
+    // quads written after the scanner was created are not seen by it
+    write(&mut qs, vec![Quad::new("erin", "follows", "bob", ())], || Procedure::Add);
+    assert_eq!(subjects(&qs, &it), vec!["alice", "alice", "charlie", "dani"]);
+
+    let after = qs.quad_iterator(&Direction::Object, &bob).borrow().iterate();
+    assert_eq!(subjects(&qs, &after), vec!["alice", "alice", "charlie", "dani", "erin"]);
+
+    let erin = qs.quad_iterator(&Direction::Subject, &qs.value_of(&Value::from("erin")).unwrap()).borrow().iterate();
+    assert!(erin.borrow_mut().next());
+    assert!(!contains.borrow_mut().contains(&erin.borrow().result().unwrap()));
+
+    // the counts follow deletes
+    write(&mut qs, vec![Quad::new("alice", "knows", "bob", ())], || Procedure::Delete);
+    assert_eq!(qs.quad_iterator_size(&Direction::Object, &bob).unwrap().value, 4);
+    assert!(qs.value_of(&Value::from("knows")).is_none());
+}
+
+
+#[test]
+fn test_quad_iterator_delete_during_scan() {
+    let mut qs = MemStore::new();
+    write(&mut qs, (0..300).map(|i| Quad::new("alice", "knows", i, ())).collect(), || Procedure::Add);
+
+    let alice = qs.value_of(&Value::from("alice")).unwrap();
+    let it = qs.quad_iterator(&Direction::Subject, &alice).borrow().iterate();
+    assert!(it.borrow_mut().next());
+
+    // the ids are read a batch at a time: a quad deleted from the batch already read is
+    // still returned, with no quad left for its id, one from a later batch is not
+    write(&mut qs, vec![Quad::new("alice", "knows", 1, ()), Quad::new("alice", "knows", 299, ())], || Procedure::Delete);
+    let mut objects = Vec::new();
+    let mut missing = 0;
+    while it.borrow_mut().next() {
+        match qs.quad(&it.borrow().result().unwrap()) {
+            Some(q) => objects.push(q.object),
+            None => missing += 1
+        }
+    }
+    assert_eq!(missing, 1);
+    assert_eq!(objects.len(), 297);
+    assert!(!objects.contains(&Value::from(299)));
+
+    let after = qs.quad_iterator(&Direction::Subject, &alice).borrow().iterate();
+    let mut n = 0;
+    while after.borrow_mut().next() {
+        n += 1;
+    }
+    assert_eq!(n, 298);
+}
+
+
+#[test]
+fn test_all_iterators() {
+    let mut qs = MemStore::new();
+    write(&mut qs, test_quads(), || Procedure::Add);
+
+    // the sizes are the exact counts of each kind of id, not the range of ids they share
+    let nodes = qs.nodes_all_iterator();
+    let quads = qs.quads_all_iterator();
+    assert_eq!(nodes.borrow_mut().stats().unwrap().size, Size { value: 7, exact: true });
+    assert_eq!(quads.borrow_mut().stats().unwrap().size, Size { value: 5, exact: true });
+
+    let it = nodes.borrow().iterate();
+    write(&mut qs, vec![Quad::new("erin", "follows", "bob", ())], || Procedure::Add);
+    let mut names = Vec::new();
+    while it.borrow_mut().next() {
+        names.push(qs.name_of(&it.borrow().result().unwrap()).unwrap().to_string());
+    }
+    names.sort();
+    assert_eq!(names, vec!["alice", "archive", "bob", "charlie", "dani", "follows", "knows"]);
+
+    // a seek to a quad id lands on the next node
+    let bob = qs.value_of(&Value::from("bob")).unwrap().k.as_id().unwrap();
+    let it = nodes.borrow().iterate();
+    assert!(it.borrow_mut().seek(bob + 1));
+    let found = it.borrow().result().unwrap();
+    assert!(found.k.as_id().unwrap() > bob);
+    assert!(qs.name_of(&found).is_some());
+
+    // nor does the lookup hold quads
+    let quad = qs.quads_all_iterator().borrow().iterate();
+    assert!(quad.borrow_mut().next());
+    assert!(!nodes.borrow().lookup().borrow_mut().contains(&quad.borrow().result().unwrap()));
+}
+
+
+#[test]
+fn test_quad_iterator_merge_join() {
+    let mut qs = MemStore::new();
+    write(&mut qs, test_quads(), || Procedure::Add);
+
+    let follows = qs.value_of(&Value::from("follows")).unwrap();
+    let bob = qs.value_of(&Value::from("bob")).unwrap();
+    let and: std::rc::Rc<std::cell::RefCell<dyn gizmo_graph_db::graph::iterator::Shape>> = gizmo_graph_db::graph::iterator::and::And::new(vec![
+        qs.quad_iterator(&Direction::Predicate, &follows),
+        qs.quad_iterator(&Direction::Object, &bob),
+    ]);
+    let and = and.borrow_mut().optimize().unwrap();
+    assert_eq!(and.borrow().to_string(), "And(merge)");
+
+    let it = and.borrow().iterate();
+    assert_eq!(subjects(&qs, &it), vec!["alice", "charlie", "dani"]);
+}
+
+
+#[test]
+fn test_quad_writer_remove_quad() {
+    let qs = std::rc::Rc::new(std::cell::RefCell::new(MemStore::new_with_composite_indexes()));
+    let qw = QuadWriter::new(qs.clone(), IgnoreOptions { ignore_dup: true, ignore_missing: true });
+    for q in test_quads() {
+        qw.add_quad(q).unwrap();
+    }
+
+    qw.remove_quad(Quad::new("charlie", "follows", "bob", ())).unwrap();
+    assert_eq!(lookup(&qs.borrow(), vec![(Direction::Predicate, "follows"), (Direction::Object, "bob")]), vec![
+        "alice follows bob",
+        "dani follows bob",
+    ]);
+    // the label is part of the quad
+    qw.remove_quad(Quad::new("dani", "follows", "bob", ())).unwrap();
+    assert_eq!(lookup(&qs.borrow(), vec![(Direction::Subject, "dani")]), vec!["dani follows bob"]);
+    qw.remove_quad(Quad::new("dani", "follows", "bob", "archive")).unwrap();
+    assert!(lookup(&qs.borrow(), vec![(Direction::Subject, "dani")]).is_empty());
+
+    // a quad that isn't there is only an error when missing quads aren't ignored
+    qw.remove_quad(Quad::new("charlie", "follows", "bob", ())).unwrap();
+    let strict = QuadWriter::new(qs.clone(), IgnoreOptions { ignore_dup: true, ignore_missing: false });
+    assert!(strict.remove_quad(Quad::new("charlie", "follows", "bob", ())).is_err());
+}
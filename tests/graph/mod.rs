@@ -1,5 +1,8 @@
 mod iterator;
 mod hasa_test;
 mod linksto_test;
+mod memstore;
+mod algo_test;
+mod refs_test;
 
 use super::common;
\ No newline at end of file
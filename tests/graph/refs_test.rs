@@ -0,0 +1,94 @@
+use gizmo_graph_db::graph::memstore::quadstore::MemStore;
+use gizmo_graph_db::graph::graphmock;
+use gizmo_graph_db::graph::quad::{Quad, QuadStore, Direction, Delta, Procedure, IgnoreOptions};
+use gizmo_graph_db::graph::refs::{Namer, NameCache, Ref, Key, pre_fetched};
+use gizmo_graph_db::graph::value::Value;
+
+
+#[test]
+fn test_key() {
+    assert_eq!(Key::Id(7).as_i64(), Some(7));
+    assert_eq!(Key::Value(Value::from(7)).as_i64(), Some(7));
+    assert_eq!(Key::Value(Value::from("a")).as_i64(), None);
+    assert_eq!(Key::Id(7).to_value(), Value::from(7));
+    assert_eq!(Key::from(Value::None), Key::None);
+
+    assert!(Ref::none().key().is_none());
+    assert_eq!(Ref::new_id(3).key(), Some(&Key::Id(3)));
+    assert_eq!(Ref::new_i64_node(3).key(), Some(&Key::Id(3)));
+    assert_eq!(Ref::new_i64_node(-3).key().and_then(|k| k.as_i64()), Some(-3));
+    assert_eq!(Ref::new_i64_quad(-3).key().and_then(|k| k.as_i64()), Some(-3));
+    assert_eq!(Key::from_i64(i64::MIN).as_i64(), Some(i64::MIN));
+    // id keyed, so not the same ref as the value keyed one
+    assert_ne!(Ref::new_i64_node(3), pre_fetched(Value::from(3)));
+    assert_eq!(pre_fetched(Value::from("a")).key(), Some(&Key::Value(Value::from("a"))));
+}
+
+
+#[test]
+fn test_store_keys() {
+    let mut qs = MemStore::new();
+    qs.apply_deltas(
+        vec![Delta { quad: Quad::new("alice", "follows", "bob", ()), action: Procedure::Add }],
+        &IgnoreOptions { ignore_dup: true, ignore_missing: true }
+    ).unwrap();
+
+    // the memstore hands out id keys and resolves names through its own tables
+    let alice = qs.value_of(&Value::from("alice")).unwrap();
+    assert!(alice.key().unwrap().as_id().is_some());
+    assert_eq!(qs.name_of(&alice), Some(Value::from("alice")));
+
+    let quads = qs.quad_iterator(&Direction::Subject, &alice).borrow().iterate();
+    assert!(quads.borrow_mut().next());
+    let object = qs.quad_direction(&quads.borrow().result().unwrap(), &Direction::Object).unwrap();
+    assert!(object.key().unwrap().as_id().is_some());
+    assert_eq!(qs.name_of(&object), Some(Value::from("bob")));
+
+    // the mock store keys refs by value
+    let mock = graphmock::Store { data: vec![Quad::new("alice", "follows", "bob", ())] };
+    let alice = mock.value_of(&Value::from("alice")).unwrap();
+    assert_eq!(alice.key(), Some(&Key::Value(Value::from("alice"))));
+    let quads = mock.quad_iterator(&Direction::Subject, &alice).borrow().iterate();
+    assert!(quads.borrow_mut().next());
+    let object = mock.quad_direction(&quads.borrow().result().unwrap(), &Direction::Object).unwrap();
+    assert_eq!(mock.name_of(&object), Some(Value::from("bob")));
+}
+
+
+#[test]
+fn test_name_cache() {
+    let mut cache = NameCache::new(2);
+    cache.insert(Key::Id(1), Value::from("a"));
+    cache.insert(Key::Id(2), Value::from("b"));
+    assert_eq!(cache.get(&Key::Id(1)), Some(Value::from("a")));
+
+    // 2 is now the least recently used
+    cache.insert(Key::Id(3), Value::from("c"));
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.get(&Key::Id(2)), None);
+    assert_eq!(cache.get(&Key::Id(1)), Some(Value::from("a")));
+    assert_eq!(cache.get(&Key::Id(3)), Some(Value::from("c")));
+
+    cache.set_capacity(1);
+    assert_eq!(cache.len(), 1);
+    assert_eq!(cache.get(&Key::Id(3)), Some(Value::from("c")));
+
+    cache.set_capacity(0);
+    cache.insert(Key::Id(4), Value::from("d"));
+    assert!(cache.is_empty());
+}
+
+
+#[test]
+fn test_names_of() {
+    let mut qs = MemStore::new();
+    qs.apply_deltas(
+        vec![Delta { quad: Quad::new("alice", "follows", "bob", ()), action: Procedure::Add }],
+        &IgnoreOptions { ignore_dup: true, ignore_missing: true }
+    ).unwrap();
+
+    let alice = qs.value_of(&Value::from("alice")).unwrap();
+    let bob = qs.value_of(&Value::from("bob")).unwrap();
+    let names = qs.names_of(&[alice, Ref::none(), pre_fetched(Value::from(5)), bob, Ref::new_id(1000)]);
+    assert_eq!(names, vec![Some(Value::from("alice")), None, Some(Value::from(5)), Some(Value::from("bob")), None]);
+}
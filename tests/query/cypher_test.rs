@@ -0,0 +1,66 @@
+use gizmo_graph_db::query::gizmo;
+use gizmo_graph_db::query::cypher;
+use gizmo_graph_db::graph::quad::Quad;
+use super::common;
+
+
+#[test]
+fn cypher_parse_test() {
+    let q = cypher::parse(r#"
+        MATCH (a {status: "cool"})-[:follows]->(b)<-[r]-()
+        OPTIONAL MATCH (b)-[:`<likes>`]->(c)
+        WHERE c.age > -3
+        RETURN DISTINCT a, b.name AS name, c.age
+        ORDER BY name DESC LIMIT 2
+    "#).unwrap();
+
+    let names: Vec<&str> = q.returns.iter().map(|r| r.name.as_str()).collect();
+    assert_eq!(names, vec!["a", "name", "c.age"]);
+    assert_eq!(q.query.pattern.triples.len(), 3);
+    assert_eq!(q.query.pattern.optionals.len(), 2);
+    assert_eq!(q.query.pattern.optionals[0].triples.len(), 2);
+    assert_eq!(q.query.pattern.optionals[0].filters.len(), 1);
+    assert_eq!(q.query.order, vec![("b.name".to_string(), gizmo::Order::Desc)]);
+    assert!(q.query.distinct);
+    assert_eq!(q.query.limit, 2);
+
+    assert!(cypher::parse("MATCH (a:Person) RETURN a").is_err());
+    assert!(cypher::parse("MATCH (a)-[:follows]-(b) RETURN a").is_err());
+    assert!(cypher::parse("MATCH (a)-[:follows*2]->(b) RETURN a").is_err());
+    assert!(cypher::parse("MATCH (a)-[:follows]->(b) WHERE a.x = 1 OR a.y = 2 RETURN a").is_err());
+    assert!(cypher::parse("MATCH (a)-[:follows]->(b) RETURN c").is_err());
+}
+
+
+#[test]
+fn cypher_query_test() {
+    let g = common::simple_graph(vec![
+        Quad::new("<alice>", "<age>", 30, ()),
+        Quad::new("<bob>", "<age>", 25, ())
+    ]);
+
+    let r = g.cypher(r#"MATCH (a)-[:follows]->(b)-[:follows]->(c) WHERE a.status = "cool_person" RETURN a, c"#).unwrap();
+    assert_eq!(common::sorted(common::rows(&r, &["a", "c"])), vec!["<bob> <greg>", "<dani> <fred>"]);
+    assert_eq!(r[0].len(), 2);
+
+    let r = g.cypher(r#"MATCH (a)<-[:follows]-(b {status: "cool_person"}) RETURN a"#).unwrap();
+    assert_eq!(common::sorted(common::rows(&r, &["a"])), vec!["<bob>", "<fred>", "<greg>"]);
+
+    // properties only returned are optional
+    let r = g.cypher("MATCH (a)-[:follows]->({age: 25}) RETURN a, a.age AS age ORDER BY a").unwrap();
+    assert_eq!(common::rows(&r, &["a", "age"]), vec!["<alice> 30", "<charlie> -", "<dani> -"]);
+
+    let r = g.cypher("MATCH (a)-[r]->(b) WHERE b.age >= 25 AND a.age IS NOT NULL RETURN a, r, b, b.age").unwrap();
+    assert_eq!(common::rows(&r, &["a", "r", "b", "b.age"]), vec!["<alice> <follows> <bob> 25"]);
+
+    let r = g.cypher(r#"MATCH (a)-[:status]->(s) WHERE s =~ "smart.*" RETURN *"#).unwrap();
+    assert_eq!(common::sorted(common::rows(&r, &["a", "s"])), vec!["<emily> smart_person", "<greg> smart_person"]);
+
+    let r = g.cypher("MATCH (a)-[:follows]->(b) OPTIONAL MATCH (b)-[:follows]->(c) WHERE c.status = 'cool_person' RETURN b, c").unwrap();
+    let mut bc = common::sorted(common::rows(&r, &["b", "c"]));
+    bc.dedup();
+    assert_eq!(bc, vec!["<bob> -", "<dani> <bob>", "<dani> <greg>", "<fred> <greg>", "<greg> -"]);
+
+    let r = g.cypher("MATCH (a)-[:follows]->(b) RETURN DISTINCT b ORDER BY b DESC SKIP 1 LIMIT 2").unwrap();
+    assert_eq!(common::rows(&r, &["b"]), vec!["<fred>", "<dani>"]);
+}
@@ -1,8 +1,20 @@
 use gizmo_graph_db::query::gizmo;
 use gizmo_graph_db::graph::quad::Quad;
+use gizmo_graph_db::query::gizmo::MemoryLimitError;
+use gizmo_graph_db::query::plan::PlanCache;
 
 use gizmo_graph_db::graph::value::Value;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use std::cell::RefCell;
+use serde_derive::Deserialize;
+use gizmo_graph_db::graph::memstore::quadstore::MemStore;
+use gizmo_graph_db::graph::quad::QuadStore;
+use gizmo_graph_db::graph::algo::{Projection, Scope};
+use gizmo_graph_db::graph::algo::degree::degree;
+use gizmo_graph_db::graph::iterator::sort::{SortKey, Order};
+use gizmo_graph_db::graph::iterator::value_filter::Operator;
+use gizmo_graph_db::query::ast::{PathAst, MorphismAst, ViaAst, HasNodesAst, ValueFilterAst};
 
 fn sort_and_compare(a:&mut Vec<String>, b:&mut Vec<String>) -> bool {
     a.sort();
@@ -888,6 +900,34 @@ fn simple_query_tests() {
     assert!(sort_and_compare(&mut r, &mut f));
 
 
+    /////////////////////////
+    // save within a LabelContext
+    /////////////////////////
+
+    let r:Vec<HashMap<String, Value>> = g.v(vec!["<greg>", "<bob>"])
+        .label_context("<smart_graph>", None)
+        .save("<status>", "s")
+        .iter().collect();
+
+    assert_eq!(r.len(), 1);
+    assert_eq!(r[0]["id"], Value::from("<greg>"));
+    assert_eq!(r[0]["s"], Value::from("smart_person"));
+
+    let mut r:Vec<String> = g.v(vec!["<greg>", "<bob>"])
+        .label_context("<smart_graph>", None)
+        .label_context(None, None)
+        .save("<status>", "s")
+        .iter().map(|row| row["s"].to_string()).collect();
+
+    let mut f:Vec<String> = vec![
+        "cool_person".into(),
+        "cool_person".into(),
+        "smart_person".into(),
+    ];
+
+    assert!(sort_and_compare(&mut r, &mut f));
+
+
     /////////////////////////
     // open and close a LabelContext
     /////////////////////////
@@ -926,3 +966,1117 @@ fn simple_query_tests() {
 
 }
 
+
+
+fn simple_graph() -> gizmo::GraphWrapper {
+    let simple_graph = gizmo::new_memory_graph();
+
+    simple_graph.write(vec![
+        Quad::new("<alice>", "<follows>", "<bob>", ()),
+        Quad::new("<bob>", "<follows>", "<fred>", ()),
+        Quad::new("<bob>", "<status>", "cool_person", ()),
+
+        Quad::new("<dani>", "<follows>", "<bob>", ()),
+        Quad::new("<charlie>", "<follows>", "<bob>", ()),
+        Quad::new("<charlie>", "<follows>", "<dani>", ()),
+
+        Quad::new("<dani>", "<follows>", "<greg>", ()),
+        Quad::new("<dani>", "<status>", "cool_person", ()),
+        Quad::new("<emily>", "<follows>", "<fred>", ()),
+
+        Quad::new("<fred>", "<follows>", "<greg>", ()),
+        Quad::new("<greg>", "<status>", "cool_person", ()),
+        Quad::new("<predicates>", "<are>", "<follows>", ()),
+
+        Quad::new("<predicates>", "<are>", "<status>", ()),
+        Quad::new("<emily>", "<status>", "smart_person", "<smart_graph>"),
+        Quad::new("<greg>", "<status>", "smart_person", "<smart_graph>")
+    ]);
+
+    simple_graph
+}
+
+
+#[test]
+fn shortest_path_tests() {
+    let simple_graph = simple_graph();
+    let g = simple_graph.g();
+
+    /////////////////////////
+    // follow the shortest route
+    /////////////////////////
+
+    let r:Vec<String> = g.v("<charlie>")
+        .shortest_path(&g.v("<greg>"), "<follows>", None, None)
+        .iter_values().map(|v| v.to_string()).collect();
+
+    let f:Vec<String> = vec![
+        "<charlie>".into(),
+        "<dani>".into(),
+        "<greg>".into()
+    ];
+
+    assert_eq!(r, f);
+
+    /////////////////////////
+    // tag the predicate of each hop
+    /////////////////////////
+
+    let r:Vec<String> = g.v("<alice>")
+        .shortest_path(&g.v("cool_person"), None, None, "pred")
+        .iter().map(|x| format!("{} {}", x["id"], x.get("pred").map(|p| p.to_string()).unwrap_or_default())).collect();
+
+    let f:Vec<String> = vec![
+        "<alice> ".into(),
+        "<bob> <follows>".into(),
+        "cool_person <status>".into()
+    ];
+
+    assert_eq!(r, f);
+
+    /////////////////////////
+    // respect the max depth
+    /////////////////////////
+
+    let c = g.v("<alice>")
+        .shortest_path(&g.v("<greg>"), "<follows>", Some(2), None)
+        .count();
+
+    assert_eq!(c, 0);
+
+    let c = g.v("<alice>")
+        .shortest_path(&g.v("<greg>"), "<follows>", Some(3), None)
+        .count();
+
+    assert_eq!(c, 4);
+
+    /////////////////////////
+    // reversed, the route follows quads from object to subject
+    /////////////////////////
+
+    let p = g.v("<greg>")
+        .follow_r(&g.m().shortest_path(&g.v("<alice>"), "<follows>", None, None));
+
+    let r:Vec<String> = p.iter_values().map(|v| v.to_string()).collect();
+
+    let f:Vec<String> = vec![
+        "<greg>".into(),
+        "<fred>".into(),
+        "<bob>".into(),
+        "<alice>".into()
+    ];
+
+    assert_eq!(r, f);
+    assert_eq!(p.to_string(), r#"g.V("<greg>").Follow(g.M().FollowR(g.M().ShortestPath(g.V("<alice>"), "<follows>", 50)).Is())"#);
+
+    let p = g.path_from_json(&p.to_json().unwrap()).unwrap();
+    let r:Vec<String> = p.iter_values().map(|v| v.to_string()).collect();
+    assert_eq!(r, f);
+}
+
+
+#[test]
+fn all_paths_tests() {
+    let simple_graph = simple_graph();
+    let g = simple_graph.g();
+
+    /////////////////////////
+    // enumerate every route
+    /////////////////////////
+
+    let mut r:Vec<String> = g.v("<charlie>")
+        .all_paths(&g.v("<greg>"), "<follows>", None, None)
+        .iter()
+        .map(|x| gizmo::path_steps(&x, "path").unwrap().iter().map(|s| s.node.to_string()).collect::<Vec<String>>().join(" "))
+        .collect();
+
+    let mut f:Vec<String> = vec![
+        "<charlie> <dani> <greg>".into(),
+        "<charlie> <bob> <fred> <greg>".into(),
+        "<charlie> <dani> <bob> <fred> <greg>".into()
+    ];
+
+    assert!(sort_and_compare(&mut r, &mut f));
+
+    /////////////////////////
+    // respect the max depth
+    /////////////////////////
+
+    let c = g.v("<charlie>")
+        .all_paths(&g.v("<greg>"), "<follows>", Some(3), None)
+        .count();
+
+    assert_eq!(c, 2);
+
+    /////////////////////////
+    // walk both directions and decode the steps
+    /////////////////////////
+
+    let r:Vec<Vec<gizmo::PathStep>> = g.v("<alice>")
+        .all_paths_both(&g.v("<charlie>"), "<follows>", Some(2), "route")
+        .iter()
+        .map(|x| gizmo::path_steps(&x, "route").unwrap())
+        .collect();
+
+    let f = vec![vec![
+        gizmo::PathStep { node: "<alice>".into(), predicate: None, forward: true },
+        gizmo::PathStep { node: "<bob>".into(), predicate: Some("<follows>".into()), forward: true },
+        gizmo::PathStep { node: "<charlie>".into(), predicate: Some("<follows>".into()), forward: false }
+    ]];
+
+    assert_eq!(r, f);
+
+    /////////////////////////
+    // the route is the step count and the nodes and predicates of its steps, each a value
+    // of the graph, a row missing one of them has no route
+    /////////////////////////
+
+    let mut r = g.v("<alice>").all_paths(&g.v("<fred>"), "<follows>", None, None).iter().collect::<Vec<_>>();
+    assert_eq!(r.len(), 1);
+    let mut tags: Vec<&String> = r[0].keys().collect();
+    tags.sort();
+    assert_eq!(tags, vec!["id", "path", "path.0", "path.1", "path.1.out", "path.2", "path.2.out"]);
+    assert_eq!(r[0]["path"], Value::from(3));
+    assert_eq!(r[0]["path.1"], Value::from("<bob>"));
+
+    r[0].remove("path.1");
+    assert_eq!(gizmo::path_steps(&r[0], "path").unwrap_err(), "step 1 of the route \"path\" has no node");
+    assert!(gizmo::path_steps(&r[0], "route").is_err());
+
+    /////////////////////////
+    // reversed, routes follow quads from object to subject
+    /////////////////////////
+
+    let p = g.v("<greg>").follow_r(&g.m().all_paths(&g.v("<alice>"), "<follows>", None, None));
+    let r:Vec<Vec<gizmo::PathStep>> = p.iter().map(|x| gizmo::path_steps(&x, "path").unwrap()).collect();
+
+    let f = vec![vec![
+        gizmo::PathStep { node: "<greg>".into(), predicate: None, forward: true },
+        gizmo::PathStep { node: "<fred>".into(), predicate: Some("<follows>".into()), forward: false },
+        gizmo::PathStep { node: "<bob>".into(), predicate: Some("<follows>".into()), forward: false },
+        gizmo::PathStep { node: "<alice>".into(), predicate: Some("<follows>".into()), forward: false }
+    ]];
+
+    assert_eq!(r, f);
+    assert_eq!(p.to_string(), r#"g.V("<greg>").Follow(g.M().FollowR(g.M().AllPaths(g.V("<alice>"), "<follows>", 10, "path")).Is())"#);
+}
+
+
+#[test]
+fn aggregate_tests() {
+    let books = gizmo::new_memory_graph();
+
+    books.write(vec![
+        Quad::new("<book1>", "<author>", "<ann>", ()),
+        Quad::new("<book1>", "<price>", 10, ()),
+        Quad::new("<book2>", "<author>", "<ann>", ()),
+        Quad::new("<book2>", "<price>", 15, ()),
+        Quad::new("<book3>", "<author>", "<bob>", ()),
+        Quad::new("<book3>", "<price>", 7.5, ()),
+        Quad::new("<book4>", "<author>", "<bob>", ()),
+        Quad::new("<book4>", "<price>", 2, ()),
+        Quad::new("<book5>", "<author>", "<cat>", ()),
+    ]);
+
+    let g = books.g();
+    let all = || g.v(vec!["<book1>", "<book2>", "<book3>", "<book4>", "<book5>"]).save("<author>", "author").save_opt("<price>", "price");
+
+    /////////////////////////
+    // group by a tag
+    /////////////////////////
+
+    let r = all().group_by("author").count();
+    assert_eq!(r, vec![
+        (Value::from("<ann>"), 2),
+        (Value::from("<bob>"), 2),
+        (Value::from("<cat>"), 1)
+    ]);
+
+    let r = all().group_by("author").sum("price");
+    assert_eq!(r, vec![
+        (Value::from("<ann>"), Value::from(25)),
+        (Value::from("<bob>"), Value::from(9.5)),
+        (Value::from("<cat>"), Value::from(0))
+    ]);
+
+    let r = all().group_by("author").avg("price");
+    assert_eq!(r[0], (Value::from("<ann>"), Value::from(12.5)));
+    assert_eq!(r[2], (Value::from("<cat>"), Value::Null));
+
+    let r = all().group_by("author").min("price");
+    assert_eq!(r[1], (Value::from("<bob>"), Value::from(2)));
+
+    let r = all().group_by("author").max("price");
+    assert_eq!(r[1], (Value::from("<bob>"), Value::from(7.5)));
+
+    // groups come in the order their values are first found
+    let r = g.v(vec!["<book5>", "<book3>", "<book1>", "<book4>"]).save("<author>", "author").group_by("author").count();
+    assert_eq!(r, vec![
+        (Value::from("<cat>"), 1),
+        (Value::from("<bob>"), 2),
+        (Value::from("<ann>"), 1)
+    ]);
+
+    /////////////////////////
+    // aggregate without groups
+    /////////////////////////
+
+    assert_eq!(all().sum("price"), Value::from(34.5));
+    assert_eq!(all().max("price"), Value::from(15));
+    assert_eq!(all().min("author"), Value::from("<ann>"));
+    assert_eq!(g.v("<nobody>").avg("price"), Value::Null);
+}
+
+
+#[test]
+fn order_by_tests() {
+    let people = gizmo::new_memory_graph();
+
+    people.write(vec![
+        Quad::new("<dani>", "<age>", 31, ()),
+        Quad::new("<bob>", "<age>", 9, ()),
+        Quad::new("<alice>", "<age>", 31, ()),
+        Quad::new("<emily>", "<age>", 100, ()),
+        Quad::new("<charlie>", "<age>", 9.5, ()),
+        Quad::new("<fred>", "<age>", 31, ()),
+    ]);
+
+    let g = people.g();
+    let all = || g.v(vec!["<dani>", "<bob>", "<alice>", "<emily>", "<charlie>", "<fred>"]).save("<age>", "age");
+
+    /////////////////////////
+    // numbers compare as numbers
+    /////////////////////////
+
+    let r:Vec<String> = all().order_by("age").iter_values().map(|v| v.to_string()).collect();
+    assert_eq!(r, vec!["<bob>", "<charlie>", "<dani>", "<alice>", "<fred>", "<emily>"]);
+
+    /////////////////////////
+    // descending keeps ties in their original order
+    /////////////////////////
+
+    let r:Vec<String> = all().order_by(("age", gizmo::Order::Desc)).iter_values().map(|v| v.to_string()).collect();
+    assert_eq!(r, vec!["<emily>", "<dani>", "<alice>", "<fred>", "<charlie>", "<bob>"]);
+
+    /////////////////////////
+    // several keys
+    /////////////////////////
+
+    let r:Vec<String> = all().order_by(vec![("age", gizmo::Order::Desc), ("id", gizmo::Order::Asc)]).iter_values().map(|v| v.to_string()).collect();
+    assert_eq!(r, vec!["<emily>", "<alice>", "<dani>", "<fred>", "<charlie>", "<bob>"]);
+
+    /////////////////////////
+    // top-k with skip and limit
+    /////////////////////////
+
+    let r:Vec<String> = all().order_by(vec![("age", gizmo::Order::Desc), ("id", gizmo::Order::Asc)]).skip(1).limit(3)
+        .iter().map(|x| format!("{} {}", x["id"], x["age"])).collect();
+    assert_eq!(r, vec!["<alice> 31", "<dani> 31", "<fred> 31"]);
+
+    let r:Vec<String> = all().order_by("id").limit(2).skip(1).iter_values().map(|v| v.to_string()).collect();
+    assert_eq!(r, vec!["<bob>"]);
+
+    let c = all().order_by("id").limit(2).skip(2).count();
+    assert_eq!(c, 0);
+
+    let r:Vec<String> = all().order().limit(2).iter_values().map(|v| v.to_string()).collect();
+    assert_eq!(r, vec!["<alice>", "<bob>"]);
+}
+
+
+#[test]
+fn name_cache_tests() {
+    let g = gizmo::new_memory_graph();
+
+    // more rows than fit in one page of name lookups
+    let quads: Vec<Quad> = (0..250).map(|i| Quad::new(format!("<n{}>", i), "<in>", "<group>", ())).collect();
+    g.write(quads);
+
+    let r:Vec<String> = g.g().v("<group>").r#in("<in>", None).order().iter_values().map(|v| v.to_string()).collect();
+    assert_eq!(r.len(), 250);
+    assert_eq!(r[0], "<n0>");
+    assert_eq!(r[249], "<n99>");
+
+    let r:Vec<HashMap<String, Value>> = g.g().v("<group>").tag("g").r#in("<in>", None).iter().collect();
+    assert_eq!(r.len(), 250);
+    assert!(r.iter().all(|x| x["g"] == Value::from("<group>")));
+    let mut ids:Vec<String> = r.iter().map(|x| x["id"].to_string()).collect();
+    ids.sort();
+    ids.dedup();
+    assert_eq!(ids.len(), 250);
+
+    assert_eq!(g.session.borrow().name_cache_len(), 251);
+
+    g.session.borrow_mut().set_name_cache_size(10);
+    assert_eq!(g.session.borrow().name_cache_len(), 10);
+    assert_eq!(g.g().v("<group>").r#in("<in>", None).count(), 250);
+    assert_eq!(g.g().v("<group>").r#in("<in>", None).iter_values().count(), 250);
+    assert_eq!(g.session.borrow().name_cache_len(), 10);
+
+    g.session.borrow_mut().set_name_cache_size(0);
+    assert_eq!(g.g().v("<group>").r#in("<in>", None).iter_values().count(), 250);
+    assert_eq!(g.session.borrow().name_cache_len(), 0);
+}
+
+
+#[test]
+fn memory_limit_tests() {
+    let g = gizmo::new_memory_graph();
+
+    let mut quads: Vec<Quad> = (0..250).map(|i| Quad::new(format!("<n{}>", i), "<in>", "<a>", ())).collect();
+    quads.extend((0..250).map(|i| Quad::new(format!("<n{}>", i), "<in>", "<b>", ())));
+    g.write(quads);
+
+    let r = g.g().v(vec!["<a>", "<b>"]).r#in("<in>", None).unique().try_iter_values().unwrap();
+    assert_eq!(r.len(), 250);
+
+    g.session.borrow_mut().set_memory_limit(Some(gizmo::MemoryLimit::spill(1024)));
+    let r:Vec<String> = g.g().v(vec!["<a>", "<b>"]).r#in("<in>", None).unique().iter_values().map(|v| v.to_string()).collect();
+    assert_eq!(r.len(), 250);
+    let mut expected: Vec<String> = (0..250).map(|i| format!("<n{}>", i)).collect();
+    let mut sorted = r.clone();
+    sorted.sort();
+    expected.sort();
+    assert_eq!(sorted, expected);
+
+    g.session.borrow_mut().set_memory_limit(Some(gizmo::MemoryLimit::fail(1024)));
+    let err = g.g().v(vec!["<a>", "<b>"]).r#in("<in>", None).unique().try_iter_values().unwrap_err();
+    assert_eq!(err, gizmo::QueryError::MemoryLimit(MemoryLimitError::new("Unique", 1024)));
+    let err = g.g().v(vec!["<a>", "<b>"]).r#in("<in>", None).unique().try_iter().unwrap_err();
+    assert_eq!(err, gizmo::QueryError::MemoryLimit(MemoryLimitError::new("Unique", 1024)));
+    // the failure is not reported again by the next query
+    assert!(g.g().v(vec!["<a>", "<b>"]).r#in("<in>", None).try_iter().is_ok());
+
+    g.session.borrow_mut().set_memory_limit(None);
+    assert_eq!(g.g().v(vec!["<a>", "<b>"]).r#in("<in>", None).unique().try_iter().unwrap().len(), 250);
+}
+
+
+#[test]
+fn plan_cache_tests() {
+    let simple_graph = simple_graph();
+    let g = simple_graph.g();
+
+    let a = g.v("<alice>").out("<follows>", None).fingerprint().unwrap();
+    let b = g.v("<dani>").out("<status>", None).fingerprint().unwrap();
+    assert_eq!(a.text, b.text);
+    assert_eq!(a.params, vec![vec![Value::from("<alice>")], vec![Value::from("<follows>")]]);
+    assert_eq!(b.params, vec![vec![Value::from("<dani>")], vec![Value::from("<status>")]]);
+    assert_ne!(a.text, g.v("<alice>").r#in("<follows>", None).fingerprint().unwrap().text);
+    assert_ne!(a.text, g.v("<alice>").out("<follows>", "f").fingerprint().unwrap().text);
+    assert!(g.v(None).filter(gizmo::regex("ar?li.*e", false)).fingerprint().is_none());
+
+    let follows = |start: &str| -> Vec<String> {
+        let mut r: Vec<String> = g.v(start).out("<follows>", None).iter_values().map(|v| v.to_string()).collect();
+        r.sort();
+        r
+    };
+    assert_eq!(follows("<alice>"), vec!["<bob>"]);
+    assert_eq!(follows("<dani>"), vec!["<bob>", "<greg>"]);
+    assert_eq!(follows("<charlie>"), vec!["<bob>", "<dani>"]);
+    assert_eq!(follows("<nobody>"), Vec::<String>::new());
+    assert_eq!(simple_graph.session.borrow().plan_cache_stats(), (3, 1));
+
+    // values in nested paths and has() are rebound too
+    let cool = |start: &str| -> Vec<String> {
+        g.v(start).out("<follows>", None).has("<status>", "cool_person").and(&g.v("<bob>").r#in("<follows>", None).out("<follows>", None)).unique().iter_values().map(|v| v.to_string()).collect()
+    };
+    assert_eq!(cool("<alice>"), vec!["<bob>"]);
+    assert_eq!(cool("<fred>"), vec!["<greg>"]);
+    assert_eq!(cool("<emily>"), Vec::<String>::new());
+    assert_eq!(simple_graph.session.borrow().plan_cache_stats(), (5, 2));
+    assert_eq!(simple_graph.session.borrow().plan_cache_len(), 2);
+
+    // plans are rebuilt once the store has grown
+    let quads: Vec<Quad> = (0..1000).map(|i| Quad::new(format!("<n{}>", i), "<follows>", "<alice>", ())).collect();
+    simple_graph.write(quads);
+    assert_eq!(follows("<alice>"), vec!["<bob>"]);
+    assert_eq!(simple_graph.session.borrow().plan_cache_stats(), (5, 3));
+    assert_eq!(g.v("<alice>").r#in("<follows>", None).count(), 1000);
+
+    simple_graph.session.borrow_mut().set_plan_cache_size(0);
+    assert_eq!(simple_graph.session.borrow().plan_cache_len(), 0);
+    assert_eq!(follows("<dani>"), vec!["<bob>", "<greg>"]);
+    assert_eq!(simple_graph.session.borrow().plan_cache_stats(), (5, 4));
+}
+
+
+// the iterator tree as text, each iterator followed by its sub iterators
+fn iterator_tree(it: &Rc<RefCell<dyn gizmo_graph_db::graph::iterator::Shape>>) -> String {
+    let subs = it.borrow().sub_iterators().unwrap_or_default();
+    if subs.is_empty() {
+        return it.borrow().to_string()
+    }
+    let subs: Vec<String> = subs.iter().map(iterator_tree).collect();
+    format!("{}({})", it.borrow(), subs.join(", "))
+}
+
+
+#[test]
+fn plan_cache_iterator_tests() {
+    let qs = Rc::new(RefCell::new(MemStore::new_with_composite_indexes()));
+    let graph = gizmo::new_graph(qs.clone());
+    graph.write(vec![
+        Quad::new("<alice>", "<follows>", "<bob>", ()),
+        Quad::new("<bob>", "<follows>", "<fred>", ()),
+        Quad::new("<bob>", "<status>", "cool_person", ()),
+        Quad::new("<fred>", "<follows>", "<greg>", ()),
+        Quad::new("<greg>", "<status>", "cool_person", ()),
+    ]);
+    let g = graph.g();
+    let mut plans = PlanCache::new(16);
+
+    // a cached plan builds the same iterators as the path does without one, for the values
+    // it was made with and for the values bound to it later
+    let paths = vec![
+        g.v(vec!["<alice>", "<bob>", "<fred>"]).and(&g.v(vec!["<bob>", "<fred>", "<greg>"])),
+        g.v(vec!["<fred>", "<bob>"]).and(&g.v(vec!["<bob>", "<fred>", "<greg>"])),
+        g.v(vec!["<alice>", "<nobody>"]).and(&g.v(vec!["<nobody>"])),
+        g.v("<alice>").out("<follows>", None).has("<status>", "cool_person"),
+        g.v("<fred>").out("<follows>", None).has("<status>", "cool_person"),
+        g.v("<nobody>").out("<follows>", None).has("<status>", "cool_person"),
+        g.v(None).has("<follows>", "<bob>").and(&g.v("<alice>")),
+        g.v(None).has("<follows>", "<greg>").and(&g.v("<fred>")),
+    ];
+    for p in &paths {
+        let cached = plans.build_iterator(&p.path, qs.clone());
+        let uncached = p.path.build_iterator_on(qs.clone());
+        assert_eq!(iterator_tree(&cached), iterator_tree(&uncached), "{}", p.path);
+    }
+    assert_eq!((plans.hits(), plans.misses()), (5, 3));
+
+    for p in &paths {
+        let cached: Vec<String> = p.clone().iter_values().map(|v| v.to_string()).collect();
+        graph.session.borrow_mut().set_plan_cache_size(0);
+        let uncached: Vec<String> = p.clone().iter_values().map(|v| v.to_string()).collect();
+        graph.session.borrow_mut().set_plan_cache_size(16);
+        assert_eq!(cached, uncached, "{}", p.path);
+    }
+}
+
+
+#[test]
+fn merge_join_tests() {
+    let qs = Rc::new(RefCell::new(MemStore::new()));
+    let graph = gizmo::new_graph(qs.clone());
+    graph.write(vec![
+        Quad::new("<alice>", "<follows>", "<bob>", ()),
+        Quad::new("<bob>", "<follows>", "<fred>", ()),
+        Quad::new("<fred>", "<follows>", "<greg>", ()),
+    ]);
+    let g = graph.g();
+
+    // both sides are nodes in the order the store numbered them, so they are merge joined
+    let p = g.v(vec!["<alice>", "<bob>", "<fred>"]).and(&g.v(vec!["<bob>", "<fred>", "<greg>"]));
+    assert_eq!(p.path.build_iterator_on(qs.clone()).borrow().to_string(), "And(merge)");
+    let r: Vec<String> = p.clone().iter_values().map(|v| v.to_string()).collect();
+    assert_eq!(r, vec!["<bob>", "<fred>"]);
+
+    // out of order, so checked with contains
+    let p = g.v(vec!["<fred>", "<bob>"]).and(&g.v(vec!["<bob>", "<fred>", "<greg>"]));
+    assert_eq!(p.path.build_iterator_on(qs.clone()).borrow().to_string(), "And");
+    let r: Vec<String> = p.clone().iter_values().map(|v| v.to_string()).collect();
+    assert_eq!(r, vec!["<fred>", "<bob>"]);
+
+    // neither is out(), which is in no id order
+    let p = g.v(vec!["<alice>", "<bob>"]).and(&g.v("<alice>").out("<follows>", None));
+    assert_eq!(p.path.build_iterator_on(qs).borrow().to_string(), "And");
+    let r: Vec<String> = p.clone().iter_values().map(|v| v.to_string()).collect();
+    assert_eq!(r, vec!["<bob>"]);
+}
+
+
+#[test]
+fn prepared_query_tests() {
+    let simple_graph = simple_graph();
+    let g = simple_graph.g();
+
+    let values = |p: &gizmo::Prepared| -> Vec<String> {
+        let mut r: Vec<String> = p.iter_values().map(|v| v.to_string()).collect();
+        r.sort();
+        r
+    };
+
+    let follows = g.v(gizmo::param("start")).out("<follows>", None).prepare();
+    assert_eq!(follows.params(), vec!["start"]);
+    assert_eq!(values(&follows.bind("start", "<alice>")), vec!["<bob>"]);
+    assert_eq!(values(&follows.bind("start", "<dani>")), vec!["<bob>", "<greg>"]);
+    assert_eq!(values(&follows.bind("start", vec!["<alice>", "<emily>"])), vec!["<bob>", "<fred>"]);
+    assert_eq!(follows.bind("start", "<charlie>").count(), 2);
+    assert_eq!(follows.bind("start", "<alice>").iter().count(), 1);
+
+    // a bound value is only ever looked up as a node
+    assert_eq!(values(&follows.bind("start", "<alice>\").out(\"<follows>")), Vec::<String>::new());
+
+    // unbound params run nothing, try_ reports them
+    assert_eq!(values(&follows), Vec::<String>::new());
+    assert_eq!(follows.count(), 0);
+    assert_eq!(follows.try_iter_values(), Err(gizmo::QueryError::Other("param start is not bound".to_string())));
+    assert!(follows.bind("start", "<alice>").try_iter().is_ok());
+
+    let cool = g.v(None).has("<status>", gizmo::param("status")).prepare();
+    assert_eq!(values(&cool.bind("status", "cool_person")), vec!["<bob>", "<dani>", "<greg>"]);
+    assert_eq!(values(&cool.bind("status", "smart_person")), vec!["<emily>", "<greg>"]);
+
+    let via = g.v("<dani>").out(gizmo::param("predicate"), None).prepare();
+    assert_eq!(values(&via.bind("predicate", "<follows>")), vec!["<bob>", "<greg>"]);
+    assert_eq!(values(&via.bind("predicate", "<status>")), vec!["cool_person"]);
+
+    let both = g.v(gizmo::param("start")).out("<follows>", None).is(gizmo::param("end")).prepare();
+    assert_eq!(both.params(), vec!["end", "start"]);
+    assert_eq!(both.bind("start", "<dani>").bind("end", "<greg>").count(), 1);
+    assert_eq!(both.bind("start", "<alice>").bind("end", "<greg>").count(), 0);
+    assert!(both.bind("start", "<dani>").try_iter().is_err());
+
+    // a param can't be bound to another param
+    let other = follows.bind("start", gizmo::param("end"));
+    assert_eq!(values(&other), Vec::<String>::new());
+    assert_eq!(other.try_iter(), Err(gizmo::QueryError::Other("param start can't be bound to param end".to_string())));
+    assert_eq!(values(&other.bind("start", "<alice>")), vec!["<bob>"]);
+
+    // nor does a param given to follow_recursive panic, it is bound like the others
+    let mut recursive = g.v(None);
+    recursive.path.follow_recursive(gizmo::param("node").into(), 2, Vec::new());
+    let recursive = recursive.prepare();
+    assert_eq!(recursive.params(), vec!["node"]);
+    assert_eq!(values(&recursive.bind("node", "<alice>")), vec!["<alice>"]);
+    assert_eq!(values(&recursive.bind("node", "<nobody>")), Vec::<String>::new());
+    assert!(recursive.try_iter().is_err());
+
+    // the values of a prepared path are looked up in the store on each run, so nodes
+    // written after it was prepared are found
+    let later = g.v("<zed>").out("<follows>", None).is(gizmo::param("end")).prepare();
+    assert_eq!(values(&later.bind("end", "<alice>")), Vec::<String>::new());
+    simple_graph.write(vec![Quad::new("<zed>", "<follows>", "<alice>", ())]);
+    assert_eq!(values(&later.bind("end", "<alice>")), vec!["<alice>"]);
+    assert_eq!(values(&later.bind("end", vec!["<alice>", "<bob>"])), vec!["<alice>"]);
+}
+
+
+#[test]
+fn json_path_tests() {
+    let simple_graph = simple_graph();
+    let g = simple_graph.g();
+
+    let sorted = |p: &gizmo::Path| -> Vec<String> {
+        let mut r: Vec<String> = p.iter().map(|row| {
+            let mut kv: Vec<String> = row.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+            kv.sort();
+            kv.join(",")
+        }).collect();
+        r.sort();
+        r
+    };
+
+    let paths = vec![
+        g.v("<alice>").out("<follows>", "f").r#in("<follows>", None).both(None, None),
+        g.v(None).has("<status>", "cool_person").has_r("<follows>", None).unique(),
+        g.v(None).has("<status>", vec![gizmo::regex("^cool", false)]).tag("x"),
+        g.v(None).filter(vec![gizmo::like("%e%"), gizmo::regex("^[a-f]", true)]).order().skip(1).limit(3),
+        g.v(None).has("<status>", vec![gizmo::lt("d")]),
+        g.v("<dani>").follow(&g.m().out("<follows>", None)).and(&g.v("<bob>").r#in("<follows>", None).out("<follows>", None)),
+        g.v("<charlie>").follow_recursive_value(Value::from("<follows>"), Some(2), "depth"),
+        g.v("<alice>").or(&g.v("<bob>")).except(&g.v("<bob>")),
+        g.v("<bob>").save("<status>", "s").save_r("<follows>", "by").save_opt("<status>", "o"),
+        g.v("<bob>").save_out_predicates("p").in_predicates(),
+        g.v("<greg>").label_context("<smart_graph>", None).out("<status>", None).labels(),
+        g.v("<alice>").shortest_path(&g.v("<greg>"), "<follows>", None, "d"),
+        g.v("<alice>").all_paths(&g.v("<greg>"), "<follows>", Some(4), "route"),
+    ];
+
+    for p in &paths {
+        let json = p.to_json().unwrap();
+        let back = g.path_from_json(&json).unwrap();
+        assert_eq!(json, back.to_json().unwrap());
+        assert_eq!(sorted(p), sorted(&back));
+    }
+
+    // the JSON is versioned and tagged by morphism
+    let json = g.v("<alice>").out("<follows>", None).to_json().unwrap();
+    let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed["version"], 1);
+    assert_eq!(parsed["morphisms"][1]["type"], "out");
+    assert!(g.path_from_json(&json.replace("\"version\":1", "\"version\":2")).is_err());
+    assert!(g.path_from_json("{\"version\":1,\"morphisms\":[{\"type\":\"teleport\"}]}").is_err());
+
+    // params survive the trip and can still be bound
+    let p = g.v(gizmo::param("start")).out("<follows>", None);
+    let back = g.path_from_json(&p.to_json().unwrap()).unwrap().prepare();
+    assert_eq!(back.bind("start", "<dani>").count(), 2);
+}
+
+
+#[test]
+fn gizmo_text_tests() {
+    let simple_graph = simple_graph();
+    let g = simple_graph.g();
+
+    // these check the text against the Gizmo a person would write, the round trip tests
+    // below that it reads back
+
+    assert_eq!(
+        g.v("<alice>").out("<follows>", None).has("<status>", "cool_person").to_string(),
+        r#"g.V("<alice>").Out("<follows>").Has("<status>", "cool_person")"#
+    );
+    assert_eq!(g.v(None).to_string(), "g.V()");
+    assert_eq!(g.m().out(None, None).to_string(), "g.M().Out()");
+    assert_eq!(
+        g.v(vec!["<alice>", "<bob>"]).r#in(None, "x").both(vec![Value::from("<a>"), Value::from("<b>")], vec!["y", "z"]).to_string(),
+        r#"g.V("<alice>", "<bob>").In(null, "x").Both(["<a>", "<b>"], ["y", "z"])"#
+    );
+    assert_eq!(
+        g.v(None).filter(vec![gizmo::regex("^a", false), gizmo::like("b%"), gizmo::lt(5), gizmo::gte("<c>")]).has_r("<follows>", vec![gizmo::regex("e", true)]).to_string(),
+        r#"g.V().Filter(regex("^a"), like("b%"), lt(5), gte("<c>")).HasR("<follows>", regex("e", true))"#
+    );
+    assert_eq!(
+        g.v("<dani>").follow(&g.m().out("<follows>", None)).and(&g.v("<bob>").r#in("<follows>", None)).or(&g.v("<greg>")).except(&g.v("<fred>")).to_string(),
+        r#"g.V("<dani>").Follow(g.M().Out("<follows>")).And(g.V("<bob>").In("<follows>")).Or(g.V("<greg>")).Except(g.V("<fred>"))"#
+    );
+    assert_eq!(
+        g.v("<charlie>").follow_recursive_value(Value::from("<follows>"), Some(3), "d").out(&g.v("<status>"), None).to_string(),
+        r#"g.V("<charlie>").FollowRecursive("<follows>", 3, "d").Out(g.V("<status>"))"#
+    );
+    assert_eq!(
+        g.v("<bob>").save("<status>", "s").save_opt_r("<follows>", "f").tag("t").unique().skip(1).limit(2).order().to_string(),
+        r#"g.V("<bob>").Save("<status>", "s").SaveOptR("<follows>", "f").Tag("t").Unique().Skip(1).Limit(2).Order()"#
+    );
+    assert_eq!(
+        g.v(None).order_by(vec![("s", gizmo::Order::Desc), ("id", gizmo::Order::Asc)]).in_predicates().save_out_predicates("p").labels().label_context("<g>", None).to_string(),
+        r#"g.V().OrderBy([["s", "desc"], ["id", "asc"]]).InPredicates().SaveOutPredicates("p").Labels().LabelContext("<g>")"#
+    );
+    assert_eq!(
+        g.v(gizmo::param("start")).has("<status>", gizmo::param("status")).is(Value::from("quote \" me")).to_string(),
+        r#"g.V(param("start")).Has("<status>", param("status")).Is("quote \" me")"#
+    );
+
+    // the path layer renders the same text, nested g.M() paths included
+    let p = g.v("<alice>").follow(&g.m().out("<follows>", None));
+    assert_eq!(p.path.to_string(), p.to_string());
+}
+
+
+// every variant the round trip covers, a new one doesn't compile until it is added
+fn variant(m: &MorphismAst) -> &'static str {
+    match m {
+        MorphismAst::Is { .. } => "is",
+        MorphismAst::In { .. } => "in",
+        MorphismAst::Out { .. } => "out",
+        MorphismAst::Both { .. } => "both",
+        MorphismAst::Follow { .. } => "follow",
+        MorphismAst::FollowRecursive { .. } => "follow_recursive",
+        MorphismAst::ShortestPath { .. } => "shortest_path",
+        MorphismAst::AllPaths { .. } => "all_paths",
+        MorphismAst::And { .. } => "and",
+        MorphismAst::Or { .. } => "or",
+        MorphismAst::Filter { .. } => "filter",
+        MorphismAst::Tag { .. } => "tag",
+        MorphismAst::Except { .. } => "except",
+        MorphismAst::Unique => "unique",
+        MorphismAst::Has { .. } => "has",
+        MorphismAst::Limit { .. } => "limit",
+        MorphismAst::Skip { .. } => "skip",
+        MorphismAst::Order { .. } => "order",
+        MorphismAst::Save { .. } => "save",
+        MorphismAst::Predicates { .. } => "predicates",
+        MorphismAst::SavePredicates { .. } => "save_predicates",
+        MorphismAst::Labels => "labels",
+        MorphismAst::LabelContext { .. } => "label_context"
+    }
+}
+
+#[test]
+fn gizmo_text_round_trip_tests() {
+    let simple_graph = simple_graph();
+    let g = simple_graph.g();
+
+    let all = || MorphismAst::Is { nodes: Vec::new(), param: None };
+    let values = |v: &[&str]| v.iter().map(|s| Value::from(*s)).collect::<Vec<Value>>();
+    let follows = || ViaAst::Values { values: values(&["<follows>"]) };
+    let nested = || vec![all(), MorphismAst::Out { tags: Some(Vec::new()), via: follows() }];
+    let regex = |iri| ValueFilterAst::Regexp { pattern: "^a\\\\d \"x\"".to_string(), iri };
+    let lt = || ValueFilterAst::Comparison { op: Operator::LT, value: Value::from(5) };
+
+    let paths = vec![
+        vec![
+            MorphismAst::Is { nodes: values(&["<alice>", "<bob>"]), param: None },
+            all(),
+            MorphismAst::Is { nodes: Vec::new(), param: Some("p".to_string()) },
+            MorphismAst::Is { nodes: vec![Value::from(1), Value::from(-2.5), Value::from(1e300), Value::from(true), Value::Null, Value::from("tab\tline\n\u{1}")], param: None }
+        ],
+        vec![
+            MorphismAst::Is { nodes: Vec::new(), param: Some("start".to_string()) },
+            MorphismAst::In { tags: Some(Vec::new()), via: ViaAst::Any },
+            MorphismAst::Out { tags: None, via: ViaAst::Any },
+            MorphismAst::In { tags: None, via: follows() },
+            MorphismAst::Both { tags: Some(vec!["a".to_string(), "b".to_string()]), via: ViaAst::Values { values: values(&["<a>", "<b>"]) } },
+            MorphismAst::In { tags: Some(vec!["t".to_string()]), via: ViaAst::Path { morphisms: nested() } },
+            MorphismAst::Out { tags: Some(Vec::new()), via: ViaAst::Param { name: "p".to_string() } },
+            MorphismAst::Both { tags: Some(Vec::new()), via: ViaAst::Values { values: Vec::new() } }
+        ],
+        vec![
+            all(),
+            MorphismAst::Follow { path: nested() },
+            MorphismAst::Follow { path: vec![all(), MorphismAst::In { tags: Some(Vec::new()), via: follows() }, all()] },
+            MorphismAst::FollowRecursive { path: nested(), max_depth: 3, depth_tags: Vec::new() },
+            MorphismAst::FollowRecursive { path: vec![MorphismAst::Is { nodes: values(&["<follows>"]), param: None }], max_depth: 2, depth_tags: vec!["d".to_string()] },
+            MorphismAst::FollowRecursive { path: vec![MorphismAst::Is { nodes: values(&["<a>", "<b>"]), param: None }], max_depth: 50, depth_tags: vec!["d".to_string(), "e".to_string()] },
+            MorphismAst::FollowRecursive { path: vec![MorphismAst::Is { nodes: Vec::new(), param: Some("p".to_string()) }], max_depth: 1, depth_tags: Vec::new() }
+        ],
+        vec![
+            MorphismAst::Is { nodes: values(&["<alice>"]), param: None },
+            MorphismAst::ShortestPath { to: nested(), via: follows(), max_depth: 50, rev: false, tags: Vec::new() },
+            MorphismAst::ShortestPath { to: nested(), via: ViaAst::Any, max_depth: 4, rev: true, tags: vec!["a".to_string(), "b".to_string()] },
+            MorphismAst::AllPaths { to: vec![all()], via: follows(), max_depth: 10, both: false, rev: false, tag: "path".to_string() },
+            MorphismAst::AllPaths { to: nested(), via: ViaAst::Path { morphisms: nested() }, max_depth: 2, both: true, rev: true, tag: "route".to_string() }
+        ],
+        vec![
+            all(),
+            MorphismAst::And { path: nested() },
+            MorphismAst::Or { path: vec![MorphismAst::Is { nodes: values(&["<greg>"]), param: None }] },
+            MorphismAst::Except { path: nested() },
+            MorphismAst::Filter { filters: Vec::new() },
+            MorphismAst::Filter { filters: vec![
+                regex(false),
+                regex(true),
+                ValueFilterAst::Wildcard { pattern: "b%".to_string() },
+                lt(),
+                ValueFilterAst::Comparison { op: Operator::LTE, value: Value::from(1.5) },
+                ValueFilterAst::Comparison { op: Operator::GT, value: Value::from("<c>") },
+                ValueFilterAst::Comparison { op: Operator::GTE, value: Value::from("c") }
+            ] },
+            MorphismAst::Tag { tags: vec!["a".to_string()] },
+            MorphismAst::Tag { tags: vec!["a".to_string(), "b".to_string()] },
+            MorphismAst::Unique,
+            MorphismAst::Labels
+        ],
+        vec![
+            all(),
+            MorphismAst::Has { via: follows(), rev: false, nodes: HasNodesAst::Any },
+            MorphismAst::Has { via: ViaAst::Any, rev: true, nodes: HasNodesAst::Values { values: values(&["cool_person"]) } },
+            MorphismAst::Has { via: follows(), rev: false, nodes: HasNodesAst::Values { values: values(&["<a>", "b"]) } },
+            MorphismAst::Has { via: follows(), rev: true, nodes: HasNodesAst::Filters { filters: vec![regex(true)] } },
+            MorphismAst::Has { via: ViaAst::Path { morphisms: nested() }, rev: false, nodes: HasNodesAst::Filters { filters: vec![lt(), regex(false)] } },
+            MorphismAst::Has { via: follows(), rev: false, nodes: HasNodesAst::Param { name: "status".to_string() } }
+        ],
+        vec![
+            all(),
+            MorphismAst::Skip { offset: 1 },
+            MorphismAst::Limit { limit: -1 },
+            MorphismAst::Order { keys: vec![SortKey::node(Order::Asc)] },
+            MorphismAst::Order { keys: vec![SortKey::tag("s", Order::Desc), SortKey::node(Order::Desc)] },
+            MorphismAst::Save { via: follows(), tag: "s".to_string(), rev: false, opt: false },
+            MorphismAst::Save { via: ViaAst::Any, tag: "r".to_string(), rev: true, opt: false },
+            MorphismAst::Save { via: ViaAst::Path { morphisms: nested() }, tag: "o".to_string(), rev: false, opt: true },
+            MorphismAst::Save { via: follows(), tag: "or".to_string(), rev: true, opt: true },
+            MorphismAst::Predicates { rev: true },
+            MorphismAst::Predicates { rev: false },
+            MorphismAst::SavePredicates { tag: "in".to_string(), rev: true },
+            MorphismAst::SavePredicates { tag: "out".to_string(), rev: false },
+            MorphismAst::LabelContext { via: ViaAst::Any, tags: Vec::new() },
+            MorphismAst::LabelContext { via: ViaAst::Any, tags: vec!["t".to_string()] },
+            MorphismAst::LabelContext { via: ViaAst::Values { values: values(&["<g>"]) }, tags: vec!["a".to_string(), "b".to_string()] }
+        ]
+    ];
+
+    let mut seen = HashSet::new();
+    for morphisms in paths {
+        seen.extend(morphisms.iter().map(variant));
+        let ast = PathAst { version: 1, morphisms };
+        assert_eq!(ast.to_string().parse::<PathAst>(), Ok(ast.clone()), "{}", ast);
+    }
+    assert_eq!(seen.len(), 23);
+
+    // paths from the builder, reversals and Back() included, read back to the same path
+    let paths = vec![
+        g.v("<alice>").out("<follows>", "f").r#in("<follows>", None).both(None, None),
+        g.v(None).has("<status>", vec![gizmo::regex("^cool", false)]).tag("x").back("x"),
+        g.v("<dani>").follow(&g.m().out("<follows>", None)).follow_r(&g.m().out("<follows>", None).tag("t")),
+        g.v("<charlie>").follow_recursive_value(Value::from("<follows>"), Some(2), "depth"),
+        g.v("<greg>").follow_r(&g.m().shortest_path(&g.v("<alice>"), "<follows>", None, None)),
+        g.v("<greg>").follow_r(&g.m().all_paths_both(&g.v("<alice>"), "<follows>", Some(3), "route")),
+        g.v(gizmo::param("start")).order_by(vec![("s", gizmo::Order::Desc), ("id", gizmo::Order::Asc)]).label_context("<g>", None),
+        g.m().out("<follows>", None).out("<follows>", None)
+    ];
+    for p in &paths {
+        let back = g.path_from_gizmo(&p.to_string()).unwrap();
+        assert_eq!(back.to_json(), p.to_json(), "{}", p);
+    }
+
+    // Gizmo written by hand, with the other names of its morphisms
+    let p = g.path_from_gizmo(r#"g.V("<charlie>").As("c").Out("<follows>").Intersect(g.V("<bob>", "<dani>")).Union(g.V("<greg>")).Difference(g.V("<greg>"))"#).unwrap();
+    let mut r: Vec<String> = p.iter_values().map(|v| v.to_string()).collect();
+    r.sort();
+    assert_eq!(r, vec!["<bob>", "<dani>"]);
+    let p = g.path_from_gizmo(r#"g.V( "<fred>" ) .FollowR( g.M().Out("<follows>") )"#).unwrap();
+    let mut r: Vec<String> = p.iter_values().map(|v| v.to_string()).collect();
+    r.sort();
+    assert_eq!(r, vec!["<bob>", "<emily>"]);
+
+    for (text, err) in [
+        (r#"g.V().Outt("<follows>")"#, "unknown morphism Outt()"),
+        (r#"g.V().Out("<follows>") trailing"#, r#"unexpected Word("trailing") after the path"#),
+        (r#"g.V().Limit()"#, "Limit() is missing an argument"),
+        (r#"g.V().Unique(1)"#, "Unique() has too many arguments"),
+        (r#"g.X()"#, "a path starts with g.V() or g.M(), not g.X()"),
+        (r#"g.V().Filter(lt())"#, "lt() is not a filter with those arguments"),
+        (r#"g.V("<alice>"#, "unterminated string"),
+        (r#"g.V().FollowRecursive("<follows>", 3000000000)"#, "the max depth is out of range")
+    ] {
+        assert_eq!(g.path_from_gizmo(text).err(), Some(err.to_string()), "{}", text);
+    }
+}
+
+
+#[test]
+fn named_morphism_tests() {
+    let simple_graph = simple_graph();
+    let g = simple_graph.g();
+
+    let values = |p: gizmo::Path| -> Vec<String> {
+        let mut r: Vec<String> = p.iter_values().map(|v| v.to_string()).collect();
+        r.sort();
+        r
+    };
+
+    let nodes = values(g.v(None));
+    let with_status = values(g.v(None).except(&g.v(None).has("<status>", "cool_person")));
+
+    assert!(g.morphism("friendsOfFriends").unwrap().is_none());
+    g.define_morphism("friendsOfFriends", &g.m().out("<follows>", None).out("<follows>", None)).unwrap();
+    g.define_morphism("cool", &g.m().has("<status>", "cool_person")).unwrap();
+    assert_eq!(g.morphism_names(), vec!["cool", "friendsOfFriends"]);
+
+    // the definitions are kept apart from the quads of the store, g.V() is unchanged
+    assert_eq!(values(g.v(None)), nodes);
+    assert_eq!(values(g.v(None).except(&g.v(None).has("<status>", "cool_person"))), with_status);
+    assert_eq!(g.v(None).count(), nodes.len() as i64);
+
+    assert_eq!(values(g.v("<alice>").follow("friendsOfFriends")), vec!["<fred>"]);
+    assert_eq!(values(g.v("<charlie>").follow("friendsOfFriends").follow("cool")), vec!["<bob>", "<greg>"]);
+    assert_eq!(values(g.v("<fred>").follow_r("friendsOfFriends")), vec!["<alice>", "<charlie>", "<dani>"]);
+    assert_eq!(g.morphism("cool").unwrap().unwrap().to_string(), r#"g.M().Has("<status>", "cool_person")"#);
+
+    // definitions that follow others keep them as they were defined
+    g.define_morphism("coolFriends", &g.m().follow("friendsOfFriends").follow("cool")).unwrap();
+    g.define_morphism("friendsOfFriends", &g.m().out("<follows>", None)).unwrap();
+    assert_eq!(values(g.v("<alice>").follow("friendsOfFriends")), vec!["<bob>"]);
+    assert_eq!(values(g.v("<charlie>").follow("coolFriends")), vec!["<bob>", "<greg>"]);
+
+    assert_eq!(g.remove_morphism("cool"), Ok(true));
+    assert_eq!(g.remove_morphism("cool"), Ok(false));
+    assert_eq!(g.morphism_names(), vec!["coolFriends", "friendsOfFriends"]);
+
+    // defining a name again with the same path keeps it, and a path that can't be saved
+    // leaves the old definition in place
+    g.define_morphism("friendsOfFriends", &g.m().out("<follows>", None)).unwrap();
+    assert_eq!(values(g.v("<alice>").follow("friendsOfFriends")), vec!["<bob>"]);
+    assert!(g.define_morphism("friendsOfFriends", &g.m().follow("nothing")).is_err());
+    assert_eq!(values(g.v("<alice>").follow("friendsOfFriends")), vec!["<bob>"]);
+}
+
+
+#[test]
+fn named_morphism_store_tests() {
+    let qs = Rc::new(RefCell::new(MemStore::new()));
+    let first = gizmo::new_graph(qs.clone());
+    first.write(vec![
+        Quad::new("<alice>", "<follows>", "<bob>", ()),
+        Quad::new("<bob>", "<follows>", "<fred>", ())
+    ]);
+    first.g().define_morphism("fof", &first.g().m().out("<follows>", None).out("<follows>", None)).unwrap();
+
+    // the definition is kept in the store, where other sessions find it
+    let second = gizmo::new_graph(qs);
+    let g = second.g();
+    assert_eq!(g.morphism_names(), vec!["fof"]);
+    let r: Vec<String> = g.v("<alice>").follow("fof").iter_values().map(|v| v.to_string()).collect();
+    assert_eq!(r, vec!["<fred>"]);
+}
+
+
+#[test]
+fn named_morphism_hidden_tests() {
+    let qs = Rc::new(RefCell::new(MemStore::new()));
+    let graph = gizmo::new_graph(qs.clone());
+    graph.write(vec![
+        Quad::new("<alice>", "<follows>", "<bob>", ()),
+        Quad::new("<alice>", "<status>", "cool_person", ()),
+        Quad::new("<bob>", "<follows>", "<fred>", ())
+    ]);
+    let g = graph.g();
+
+    let sparql = |q: &str| {
+        let r = graph.sparql(q).unwrap();
+        let mut rows: Vec<String> = r["results"]["bindings"].as_array().unwrap().iter().map(|b| b.to_string()).collect();
+        rows.sort();
+        rows
+    };
+    let degrees = || {
+        let p = Projection::load(&*qs.borrow(), &Scope::all()).unwrap();
+        let mut d: Vec<String> = degree(&p).scores.iter().map(|(n, d)| format!("{} {}", n, d)).collect();
+        d.sort();
+        d
+    };
+    let all = "SELECT ?s ?p ?o WHERE { ?s ?p ?o }";
+    let predicates = "SELECT ?p WHERE { <alice> ?p ?o }";
+    let (before, before_predicates, before_degrees) = (sparql(all), sparql(predicates), degrees());
+    assert_eq!((before.len(), before_predicates.len(), before_degrees.len()), (3, 2, 4));
+    let stats = qs.borrow().stats(true).unwrap();
+
+    // registering morphisms writes no quads, nothing reading the graph sees them
+    g.define_morphism("fof", &g.m().out("<follows>", None).out("<follows>", None)).unwrap();
+    assert_eq!(sparql(all), before);
+    assert_eq!(sparql(predicates), before_predicates);
+    assert_eq!(degrees(), before_degrees);
+    assert_eq!(qs.borrow().stats(true).unwrap().quads, stats.quads);
+    assert_eq!(g.v("<alice>").out_predicates().count(), 2);
+}
+
+
+#[test]
+fn named_morphism_missing_test() {
+    let simple_graph = simple_graph();
+    let g = simple_graph.g();
+
+    // an unknown name finds nothing, try_iter says why
+    let missing = gizmo::QueryError::Other("no morphism named nothing".to_string());
+    assert_eq!(g.v("<alice>").follow("nothing").iter().count(), 0);
+    assert_eq!(g.v("<alice>").follow("nothing").try_iter_values(), Err(missing.clone()));
+    assert_eq!(g.v("<alice>").follow("nothing").out("<follows>", None).try_iter(), Err(missing.clone()));
+    assert_eq!(g.v("<alice>").follow_r("nothing").try_iter(), Err(missing));
+    assert!(g.v("<alice>").follow("nothing").to_json().is_err());
+
+    // as does a definition that isn't a path
+    let qs = Rc::new(RefCell::new(MemStore::new()));
+    qs.borrow_mut().set_metadata(&gizmo_graph_db::query::registry::key("broken"), Some("{\"version\": 1, \"morphisms\": 3}".to_string())).unwrap();
+    let broken = gizmo::new_graph(qs);
+    assert_eq!(broken.g().morphism_names(), vec!["broken"]);
+    assert!(broken.g().v("<alice>").follow("broken").try_iter().is_err());
+}
+
+
+#[test]
+fn gizmo_macro_tests() {
+    use gizmo_graph_db::gizmo as gizmo_path;
+
+    let simple_graph = simple_graph();
+    let g = simple_graph.g();
+
+    // the macro expands to the builder calls that render back to the same text
+    assert_eq!(
+        gizmo_path!(g.V("<alice>").Out("<follows>").Has("<status>", "cool_person")).to_string(),
+        r#"g.V("<alice>").Out("<follows>").Has("<status>", "cool_person")"#
+    );
+    assert_eq!(gizmo_path!(g.V()).to_string(), "g.V()");
+    assert_eq!(gizmo_path!(g.M().Out()).to_string(), "g.M().Out()");
+    assert_eq!(
+        gizmo_path!(g.V("<alice>", "<bob>").In(None, "x").Both(vec![Value::from("<a>"), Value::from("<b>")], "y", "z")).to_string(),
+        r#"g.V("<alice>", "<bob>").In(null, "x").Both(["<a>", "<b>"], ["y", "z"])"#
+    );
+    assert_eq!(
+        gizmo_path!(g.V().Filter(gizmo::regex("^a", false), gizmo::lt(5)).HasR("<follows>", gizmo::regex("e", true))).to_string(),
+        r#"g.V().Filter(regex("^a"), lt(5)).HasR("<follows>", regex("e", true))"#
+    );
+    let bob = g.v("<bob>").r#in("<follows>", None);
+    assert_eq!(
+        gizmo_path!(g.V("<dani>").Follow(g.M().Out("<follows>")).And(&bob).Or(g.V("<greg>")).Except(g.V("<fred>"))).to_string(),
+        r#"g.V("<dani>").Follow(g.M().Out("<follows>")).And(g.V("<bob>").In("<follows>")).Or(g.V("<greg>")).Except(g.V("<fred>"))"#
+    );
+    assert_eq!(
+        gizmo_path!(g.V("<bob>").Save("<status>", "s").SaveOptR("<follows>", "f").As("t").Unique().Skip(1).Limit(2).Order()).to_string(),
+        r#"g.V("<bob>").Save("<status>", "s").SaveOptR("<follows>", "f").Tag("t").Unique().Skip(1).Limit(2).Order()"#
+    );
+    assert_eq!(
+        gizmo_path!(g.V().OrderBy(vec![("s", gizmo::Order::Desc)]).InPredicates().SaveOutPredicates("p").Labels().LabelContext("<g>")).to_string(),
+        r#"g.V().OrderBy([["s", "desc"]]).InPredicates().SaveOutPredicates("p").Labels().LabelContext("<g>")"#
+    );
+    assert_eq!(
+        gizmo_path!(g.V(gizmo::param("start")).Has("<status>", gizmo::param("status")).Is("<bob>")).to_string(),
+        r#"g.V(param("start")).Has("<status>", param("status")).Is("<bob>")"#
+    );
+
+    // Back() goes to the tags set by Tag() or As()
+    let values = |p: gizmo::Path| -> Vec<String> {
+        let mut r: Vec<String> = p.iter_values().map(|v| v.to_string()).collect();
+        r.sort();
+        r
+    };
+    assert_eq!(values(gizmo_path!(g.V("<charlie>").Out("<follows>").As("f").Has("<status>", "cool_person").Out("<follows>").Back("f"))), vec!["<bob>", "<dani>", "<dani>"]);
+    assert_eq!(values(gizmo_path!(g.V().Tag("a", "b").Out("<status>").Is("smart_person").Back("b"))), vec!["<emily>", "<greg>"]);
+
+    g.define_morphism("fof", &gizmo_path!(g.M().Out("<follows>").Out("<follows>"))).unwrap();
+    assert_eq!(values(gizmo_path!(g.V("<alice>").Follow("fof"))), vec!["<fred>"]);
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct Follower {
+    id: String,
+    target: String,
+    status: Option<String>
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct Aged {
+    id: Value,
+    age: i64,
+    score: f64,
+    cool: bool
+}
+
+#[derive(Debug, Deserialize)]
+struct Wrong {
+    #[allow(dead_code)]
+    age: String
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+enum Status {
+    CoolPerson,
+    SmartPerson
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct Statused {
+    status: Status
+}
+
+#[test]
+fn typed_row_tests() {
+    let simple_graph = gizmo::new_memory_graph();
+    simple_graph.write(vec![
+        Quad::new("<alice>", "<follows>", "<bob>", ()),
+        Quad::new("<bob>", "<follows>", "<fred>", ()),
+        Quad::new("<bob>", "<status>", "cool_person", ()),
+        Quad::new("<alice>", "<age>", 30, ()),
+        Quad::new("<alice>", "<score>", 7.5, ()),
+        Quad::new("<alice>", "<cool>", true, ()),
+        Quad::new("<fred>", "<status>", "CoolPerson", ()),
+        Quad::new("<greg>", "<status>", "SmartPerson", ()),
+    ]);
+    let g = simple_graph.g();
+
+    let mut followers: Vec<Follower> = g.v(None).save("<follows>", "target").save_opt("<status>", "status").try_iter_as().unwrap();
+    followers.sort_by(|a, b| a.id.cmp(&b.id));
+    assert_eq!(followers, vec![
+        Follower { id: "<alice>".into(), target: "<bob>".into(), status: None },
+        Follower { id: "<bob>".into(), target: "<fred>".into(), status: Some("cool_person".into()) }
+    ]);
+
+    let aged: Vec<Aged> = g.v("<alice>").save("<age>", "age").save("<score>", "score").save("<cool>", "cool")
+        .iter_as().collect::<Result<_, _>>().unwrap();
+    assert_eq!(aged, vec![Aged { id: Value::from("<alice>"), age: 30, score: 7.5, cool: true }]);
+
+    // a number saved under a tag read as a string, and a tag the row doesn't have
+    let err = g.v("<alice>").save("<age>", "age").try_iter_as::<Wrong>().unwrap_err();
+    assert!(err.contains("tag \"age\"") && err.contains("invalid type: integer `30`"), "{}", err);
+    let err = g.v("<bob>").try_iter_as::<Aged>().unwrap_err();
+    assert_eq!(err, "tag \"age\" is missing from the row");
+
+    // a string saved under a tag names a unit variant of an enum
+    let statused: Vec<Statused> = g.v(vec!["<fred>", "<greg>"]).save("<status>", "status").try_iter_as().unwrap();
+    assert_eq!(statused.len(), 2);
+    assert!(statused.contains(&Statused { status: Status::CoolPerson }));
+    assert!(statused.contains(&Statused { status: Status::SmartPerson }));
+    let err = g.v("<bob>").save("<status>", "status").try_iter_as::<Statused>().unwrap_err();
+    assert!(err.contains("unknown variant `cool_person`"), "{}", err);
+}
@@ -0,0 +1,120 @@
+use gizmo_graph_db::query::graphql;
+use gizmo_graph_db::graph::quad::Quad;
+use serde_json::json;
+use super::common;
+
+
+#[test]
+fn graphql_parse_test() {
+    let fields = graphql::parse(r#"
+        query people {
+            nodes(id: ["<alice>", "<bob>"], first: 2) @label(v: "<g>") {
+                id
+                friend: <follows> @rev @opt { id }
+                <status>(offset: 1, name: "x")
+            }
+        }
+    "#).unwrap();
+
+    assert_eq!(fields.len(), 1);
+    let nodes = &fields[0];
+    assert_eq!(nodes.name, "nodes");
+    assert_eq!(nodes.ids.len(), 2);
+    assert_eq!(nodes.limit, 2);
+    assert_eq!(nodes.labels.as_ref().unwrap().len(), 1);
+    assert_eq!(nodes.fields.len(), 3);
+
+    let friend = &nodes.fields[1];
+    assert_eq!((friend.name.as_str(), friend.field.as_str()), ("friend", "<follows>"));
+    assert!(friend.rev && friend.opt);
+    assert_eq!(nodes.fields[2].name, "<status>");
+    assert_eq!(nodes.fields[2].skip, 1);
+    assert_eq!(nodes.fields[2].has.len(), 1);
+
+    assert!(graphql::parse("{ nodes { id }").is_err());
+    assert!(graphql::parse("{ nodes @nope { id } }").is_err());
+    assert!(graphql::parse("{ nodes(first: \"a\") { id } }").is_err());
+}
+
+
+#[test]
+fn graphql_query_test() {
+    let g = common::simple_graph(vec![
+        Quad::new("<alice>", "<name>", "Alice", ()),
+        Quad::new("<alice>", "<age>", 30, ())
+    ]);
+
+    // fields with one value are not wrapped in a list
+    let r = g.graphql(r#"{ nodes(id: "<alice>") { id name age <follows> { id <status> } } }"#).unwrap();
+    assert_eq!(r, json!({"nodes": [
+        {"id": "<alice>", "name": "Alice", "age": 30, "<follows>": {"id": "<bob>", "<status>": "cool_person"}}
+    ]}));
+
+    // fields that aren't optional filter the nodes
+    let r = g.graphql(r#"{ nodes(<status>: "cool_person") { id follows: <follows> { id } } }"#).unwrap();
+    assert_eq!(common::sorted_by_id(r, "/nodes"), json!({"nodes": [
+        {"id": "<bob>", "follows": {"id": "<fred>"}},
+        {"id": "<dani>", "follows": [{"id": "<bob>"}, {"id": "<greg>"}]}
+    ]}));
+
+    let r = g.graphql(r#"{ nodes(id: ["<bob>", "<fred>"]) { id status: <status> @opt } }"#).unwrap();
+    assert_eq!(common::sorted_by_id(r, "/nodes"), json!({"nodes": [
+        {"id": "<bob>", "status": "cool_person"},
+        {"id": "<fred>"}
+    ]}));
+
+    // @rev follows quads from object to subject
+    let r = g.graphql(r#"{ nodes(id: "<bob>") { followers: <follows> @rev { id } } }"#).unwrap();
+    let mut followers: Vec<String> = r["nodes"][0]["followers"].as_array().unwrap().iter().map(|f| f["id"].to_string()).collect();
+    followers.sort();
+    assert_eq!(followers, vec!["\"<alice>\"", "\"<charlie>\"", "\"<dani>\""]);
+
+    // @label keeps to the quads of a graph
+    let r = g.graphql(r#"{ nodes(id: "<greg>") { id <status> @label(v: "<smart_graph>") } }"#).unwrap();
+    assert_eq!(r, json!({"nodes": [{"id": "<greg>", "<status>": "smart_person"}]}));
+
+    let r = g.graphql(r#"{ nodes(id: "<greg>") { id <status> } }"#).unwrap();
+    let mut status: Vec<String> = r["nodes"][0]["<status>"].as_array().unwrap().iter().map(|s| s.to_string()).collect();
+    status.sort();
+    assert_eq!(status, vec!["\"cool_person\"", "\"smart_person\""]);
+
+    // first and offset page the nodes of a field
+    let r = g.graphql(r#"{ nodes(<follows>: "<bob>", first: 2) { id } }"#).unwrap();
+    assert_eq!(r["nodes"].as_array().unwrap().len(), 2);
+    let r = g.graphql(r#"{ nodes(<follows>: "<bob>", offset: 1) { id } }"#).unwrap();
+    assert_eq!(r["nodes"].as_array().unwrap().len(), 2);
+
+    let r = g.graphql(r#"{ nodes(id: "<nobody>") { id } }"#).unwrap();
+    assert_eq!(r, json!({"nodes": []}));
+    // nested fields are found for every parent at once, and paged for each parent
+    let r = g.graphql(r#"{ nodes(id: ["<alice>", "<charlie>", "<dani>"]) { id <follows> { id <follows> { id } } } }"#).unwrap();
+    assert_eq!(common::sorted_by_id(r, "/nodes"), json!({"nodes": [
+        {"id": "<alice>", "<follows>": {"id": "<bob>", "<follows>": {"id": "<fred>"}}},
+        {"id": "<charlie>", "<follows>": [
+            {"id": "<bob>", "<follows>": {"id": "<fred>"}},
+            {"id": "<dani>", "<follows>": [{"id": "<bob>"}, {"id": "<greg>"}]}
+        ]},
+        // <greg> follows no one, so the nested <follows> leaves it out
+        {"id": "<dani>", "<follows>": {"id": "<bob>", "<follows>": {"id": "<fred>"}}}
+    ]}));
+    let r = g.graphql(r#"{ nodes(id: ["<charlie>", "<dani>"]) { id <follows>(first: 1) { id } } }"#).unwrap();
+    for node in r["nodes"].as_array().unwrap() {
+        assert!(node["<follows>"].is_object());
+    }
+    let r = g.graphql(r#"{ nodes(id: ["<charlie>", "<dani>", "<alice>"]) { id <follows>(offset: 1) { id } } }"#).unwrap();
+    let paged: Vec<bool> = common::sorted_by_id(r, "/nodes")["nodes"].as_array().unwrap().iter().map(|n| n["<follows>"].is_object()).collect();
+    assert_eq!(paged, vec![false, true, true]);
+}
+
+
+#[test]
+fn graphql_depth_test() {
+    let nested = |n: usize| format!("{}{}", "{ a ".repeat(n), "}".repeat(n));
+    assert!(graphql::parse(&nested(64)).is_ok());
+    assert_eq!(graphql::parse(&nested(65)), Err("query nested deeper than 64 levels".to_string()));
+    assert!(graphql::parse(&nested(100_000)).is_err());
+
+    let list = |n: usize| format!("{{ nodes(id: {}{}) {{ id }} }}", "[".repeat(n), "]".repeat(n));
+    assert!(graphql::parse(&list(63)).is_ok());
+    assert!(graphql::parse(&list(100_000)).is_err());
+}
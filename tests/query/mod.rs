@@ -1,4 +1,9 @@
 mod gizmo_test;
 mod path_test;
+mod graphql_test;
+mod mql_test;
+mod sparql_test;
+mod cypher_test;
+mod schema_test;
 
 use super::common;
\ No newline at end of file
@@ -0,0 +1,85 @@
+use gizmo_graph_db::query::mql;
+use gizmo_graph_db::graph::quad::Quad;
+use serde_json::json;
+use super::common;
+
+
+#[test]
+fn mql_parse_test() {
+    let q = mql::parse(&json!([{"id": "<alice>", "!<follows>": [{"id": null, "optional": true, "limit": 2}], "name": null, "age": []}])).unwrap();
+    assert!(q.list);
+    assert_eq!(q.root.id, Some("<alice>".into()));
+
+    let follows = q.root.fields.iter().find(|f| f.key == "!<follows>").unwrap();
+    assert!(follows.rev);
+    assert_eq!(follows.via, "<follows>".into());
+    match &follows.template {
+        mql::Template::Objects(o) => assert!(o.optional && o.limit == 2),
+        t => panic!("expected a list of objects, found {:?}", t)
+    }
+    assert!(q.root.fields.iter().any(|f| f.key == "age" && f.template == mql::Template::Values));
+
+    assert!(mql::parse(&json!("<alice>")).is_err());
+    assert!(mql::parse(&json!([{"id": null}, {"id": null}])).is_err());
+    assert!(mql::parse(&json!({"<follows>": ["<bob>"]})).is_err());
+    assert!(mql::parse(&json!({"limit": "two"})).is_err());
+}
+
+
+#[test]
+fn mql_query_test() {
+    let g = common::simple_graph(vec![
+        Quad::new("<alice>", "name", "Alice", ()),
+        Quad::new("<alice>", "age", 30, ())
+    ]);
+
+    let r = g.mql(&json!({"id": "<alice>", "name": null, "age": null, "<follows>": {"id": null, "<status>": null}})).unwrap();
+    assert_eq!(r, json!({"id": "<alice>", "name": "Alice", "age": 30, "<follows>": {"id": "<bob>", "<status>": "cool_person"}}));
+
+    // nested templates pick the nodes that match all the way down
+    let r = g.mql(&json!([{"id": null, "<follows>": [{"id": "<bob>"}]}])).unwrap();
+    assert_eq!(common::sorted_by_id(r, ""), json!([
+        {"id": "<alice>", "<follows>": [{"id": "<bob>"}]},
+        {"id": "<charlie>", "<follows>": [{"id": "<bob>"}]},
+        {"id": "<dani>", "<follows>": [{"id": "<bob>"}]}
+    ]));
+
+    let r = g.mql(&json!([{"id": null, "<status>": "cool_person", "<follows>": [{"<status>": "cool_person", "id": null}]}])).unwrap();
+    assert_eq!(common::sorted_by_id(r, ""), json!([
+        {"id": "<dani>", "<status>": "cool_person", "<follows>": [{"id": "<bob>", "<status>": "cool_person"}, {"id": "<greg>", "<status>": "cool_person"}]}
+    ]));
+
+    // values missing from a node are null or an empty list
+    let r = g.mql(&json!([{"id": null, "!<follows>": [{"id": "<charlie>"}], "<status>": null, "name": []}])).unwrap();
+    assert_eq!(common::sorted_by_id(r, ""), json!([
+        {"id": "<bob>", "!<follows>": [{"id": "<charlie>"}], "<status>": "cool_person", "name": []},
+        {"id": "<dani>", "!<follows>": [{"id": "<charlie>"}], "<status>": "cool_person", "name": []}
+    ]));
+
+    let r = g.mql(&json!({"id": "<greg>", "<status>": []})).unwrap();
+    let mut status: Vec<String> = r["<status>"].as_array().unwrap().iter().map(|s| s.to_string()).collect();
+    status.sort();
+    assert_eq!(status, vec!["\"cool_person\"", "\"smart_person\""]);
+    assert!(g.mql(&json!({"id": "<greg>", "<status>": null})).is_err());
+
+    let r = g.mql(&json!([{"id": null, "<follows>": {"id": "<greg>"}, "<status>": {"id": null, "optional": true}}])).unwrap();
+    assert_eq!(common::sorted_by_id(r, ""), json!([
+        {"id": "<dani>", "<follows>": {"id": "<greg>"}, "<status>": {"id": "cool_person"}},
+        {"id": "<fred>", "<follows>": {"id": "<greg>"}, "<status>": null}
+    ]));
+
+    let r = g.mql(&json!([{"id": null, "<follows>": {"id": "<bob>"}, "limit": 2}])).unwrap();
+    assert_eq!(r.as_array().unwrap().len(), 2);
+
+    // a nested template is found for every parent at once, and limited for each parent
+    let r = g.mql(&json!([{"id": null, "<follows>": [{"id": null, "limit": 1}]}])).unwrap();
+    let r = common::sorted_by_id(r, "");
+    let ids: Vec<&str> = r.as_array().unwrap().iter().map(|o| o["id"].as_str().unwrap()).collect();
+    assert_eq!(ids, vec!["<alice>", "<bob>", "<charlie>", "<dani>", "<emily>", "<fred>"]);
+    for o in r.as_array().unwrap() {
+        assert_eq!(o["<follows>"].as_array().unwrap().len(), 1, "{}", o);
+    }
+
+    assert_eq!(g.mql(&json!({"id": "<nobody>"})).unwrap(), json!(null));
+    assert!(g.mql(&json!({"id": null, "<follows>": {"id": "<bob>"}})).is_err());
+}
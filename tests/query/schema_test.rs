@@ -0,0 +1,166 @@
+use gizmo_graph_db::query::gizmo;
+use gizmo_graph_db::query::schema;
+use gizmo_graph_db::graph::quad::Quad;
+use gizmo_graph_db::graph::value::Value;
+use serde_derive::{Serialize, Deserialize};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+enum Status {
+    Cool,
+    Smart
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Address {
+    #[serde(rename = "<city>")]
+    city: String,
+    zip: Option<i64>
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Person {
+    id: String,
+    #[serde(rename = "<name>")]
+    name: String,
+    age: i64,
+    status: Status,
+    follows: Vec<String>,
+    address: Option<Address>,
+    homes: Vec<Address>
+}
+
+fn alice() -> Person {
+    Person {
+        id: "<alice>".into(),
+        name: "Alice".into(),
+        age: 30,
+        status: Status::Cool,
+        follows: vec!["<bob>".into(), "<dani>".into()],
+        address: Some(Address { city: "Paris".into(), zip: Some(75001) }),
+        homes: vec![Address { city: "Lyon".into(), zip: None }]
+    }
+}
+
+#[test]
+fn to_quads_tests() {
+    let (node, mut quads) = schema::to_quads(&alice()).unwrap();
+    assert_eq!(node, Value::from("<alice>"));
+    quads.sort_by_key(|q| q.to_string());
+    let mut expected = vec![
+        Quad::new("<alice>", "<name>", "Alice", Value::None),
+        Quad::new("<alice>", "<age>", 30, Value::None),
+        Quad::new("<alice>", "<status>", "Cool", Value::None),
+        Quad::new("<alice>", "<follows>", "<bob>", Value::None),
+        Quad::new("<alice>", "<follows>", "<dani>", Value::None),
+        Quad::new("<alice>", "<address>", "<alice/address>", Value::None),
+        Quad::new("<alice/address>", "<city>", "Paris", Value::None),
+        Quad::new("<alice/address>", "<zip>", 75001, Value::None),
+        Quad::new("<alice>", "<homes>", "<alice/homes/0>", Value::None),
+        Quad::new("<alice/homes/0>", "<city>", "Lyon", Value::None),
+    ];
+    expected.sort_by_key(|q| q.to_string());
+    assert_eq!(quads, expected);
+
+    let mut person = alice();
+    person.id = "alice".into();
+    assert_eq!(schema::to_quads(&person).unwrap_err(), "the id \"alice\" is not an IRI like <alice>");
+}
+
+#[test]
+fn load_tests() {
+    let graph = gizmo::new_memory_graph();
+    let node = graph.write_object(&alice()).unwrap();
+    assert_eq!(graph.load_object::<Person, _>(node).unwrap(), alice());
+
+    // quads written by hand load the same, fields the node has no values for are empty
+    graph.write(vec![
+        Quad::new("<bob>", "<name>", "Bob", ()),
+        Quad::new("<bob>", "<age>", 25, ()),
+        Quad::new("<bob>", "<status>", "Smart", ()),
+        Quad::new("<bob>", "<follows>", "<fred>", ()),
+    ]);
+    assert_eq!(graph.load_object::<Person, _>("<bob>").unwrap(), Person {
+        id: "<bob>".into(),
+        name: "Bob".into(),
+        age: 25,
+        status: Status::Smart,
+        follows: vec!["<fred>".into()],
+        address: None,
+        homes: vec![]
+    });
+
+    let err = graph.load_object::<Person, _>("<fred>").unwrap_err();
+    assert_eq!(err, "<fred> \"<name>\": the node has no value for it");
+
+    graph.write(vec![Quad::new("<bob>", "<age>", 26, ())]);
+    let err = graph.load_object::<Person, _>("<bob>").unwrap_err();
+    assert_eq!(err, "<bob> \"age\": the node has 2 values for it, load them into a Vec");
+}
+
+#[test]
+fn rewrite_tests() {
+    let graph = gizmo::new_memory_graph();
+    graph.write_object(&alice()).unwrap();
+
+    // writing the object again replaces the values of its fields
+    let mut person = alice();
+    person.age = 31;
+    person.follows = vec!["<fred>".into()];
+    person.address = None;
+    person.homes[0].city = "Nice".into();
+    let node = graph.write_object(&person).unwrap();
+    assert_eq!(graph.load_object::<Person, _>(node).unwrap(), person);
+    assert!(graph.g().v("<alice>").out("<address>", None).try_iter_values().unwrap().is_empty());
+
+    // the nested structs no longer held are deleted with their fields
+    assert!(graph.g().v("<alice/address>").out("<city>", None).try_iter_values().unwrap().is_empty());
+    person.homes.push(Address { city: "Lille".into(), zip: Some(59000) });
+    graph.write_object(&person).unwrap();
+    person.homes.pop();
+    graph.write_object(&person).unwrap();
+    assert_eq!(graph.load_object::<Person, _>("<alice>").unwrap(), person);
+    assert!(graph.g().v("<alice/homes/1>").out_predicates().try_iter_values().unwrap().is_empty());
+
+    // values of the node in other graphs are left as they are
+    graph.write(vec![Quad::new("<alice>", "<age>", 40, "<old_graph>")]);
+    graph.write_object(&person).unwrap();
+    let ages = graph.g().v("<alice>").label_context("<old_graph>", None).out("<age>", None).try_iter_values().unwrap();
+    assert_eq!(ages, vec![Value::from(40)]);
+}
+
+#[test]
+fn vec_set_tests() {
+    // a Vec is a set, a value in it twice is one quad and it loads in store order
+    let mut person = alice();
+    person.follows = vec!["<dani>".into(), "<bob>".into(), "<dani>".into()];
+    let (_, quads) = schema::to_quads(&person).unwrap();
+    assert_eq!(quads.iter().filter(|q| q.predicate == Value::from("<follows>")).count(), 2);
+
+    let graph = gizmo::new_memory_graph();
+    let node = graph.write_object(&person).unwrap();
+    let mut loaded = graph.load_object::<Person, _>(node).unwrap();
+    loaded.follows.sort();
+    assert_eq!(loaded.follows, vec!["<bob>".to_string(), "<dani>".to_string()]);
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Link {
+    id: String,
+    next: Option<Box<Link>>
+}
+
+#[test]
+fn load_cycle_tests() {
+    let graph = gizmo::new_memory_graph();
+    graph.write(vec![
+        Quad::new("<a>", "<next>", "<b>", ()),
+        Quad::new("<b>", "<next>", "<c>", ()),
+    ]);
+    let link = graph.load_object::<Link, _>("<a>").unwrap();
+    assert_eq!(link.next.unwrap().next.unwrap().id, "<c>");
+
+    // nodes linking in a cycle fail to load instead of nesting forever
+    graph.write(vec![Quad::new("<c>", "<next>", "<a>", ())]);
+    let err = graph.load_object::<Link, _>("<a>").unwrap_err();
+    assert!(err.ends_with("<a> links back to itself, it can't load into a struct nested in its own"), "{}", err);
+}
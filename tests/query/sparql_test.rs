@@ -0,0 +1,116 @@
+use gizmo_graph_db::query::sparql;
+use gizmo_graph_db::graph::quad::Quad;
+use serde_json::json;
+use super::common;
+
+
+#[test]
+fn sparql_parse_test() {
+    let q = sparql::parse(r#"
+        PREFIX ex: <http://example.org/>
+        SELECT DISTINCT ?a ?b WHERE {
+            ?a ex:follows ?b , <bob> ; a ex:Person .
+            GRAPH ?g { ?b <status> "cool" }
+            OPTIONAL { ?a <name> ?n }
+            FILTER (?n != "x" && 3 < ?b)
+        } ORDER BY DESC(?a) ?b LIMIT 5 OFFSET 1
+    "#).unwrap();
+
+    assert_eq!(q.vars, Some(vec!["a".to_string(), "b".to_string()]));
+    assert!(q.distinct);
+    assert_eq!(q.pattern.triples.len(), 4);
+    assert_eq!(q.pattern.triples[0].predicate, sparql::Term::Value("<http://example.org/follows>".into()));
+    assert_eq!(q.pattern.triples[3].label, Some(sparql::Term::Var("g".to_string())));
+    assert_eq!(q.pattern.optionals.len(), 1);
+    assert_eq!(q.pattern.filters.len(), 2);
+    assert_eq!(q.pattern.vars(), vec!["a", "b", "g", "n"]);
+    assert_eq!((q.order.len(), q.limit, q.offset), (2, 5, 1));
+
+    assert!(sparql::parse("SELECT ?a WHERE { ?a <p> ?b ").is_err());
+    assert!(sparql::parse("SELECT ?a WHERE { ?a x:p ?b }").is_err());
+    assert!(sparql::parse("SELECT ?a WHERE { ?a <p> ?b FILTER (?a = 1 || ?a = 2) }").is_err());
+    assert!(sparql::parse("SELECT ?a WHERE { ?a <p> \"x\"@en }").is_err());
+    assert!(sparql::parse("SELECT ?a WHERE { { ?a <p> ?b } UNION { ?a <q> ?b } ?a <r> ?c }").is_err());
+}
+
+
+#[test]
+fn sparql_query_test() {
+    let g = common::simple_graph(vec![
+        Quad::new("<alice>", "<age>", 30, ()),
+        Quad::new("<bob>", "<age>", 25, ())
+    ]);
+
+    let r = g.sparql("SELECT ?a ?b WHERE { ?a <follows> ?b . ?b <status> \"cool_person\" }").unwrap();
+    assert_eq!(r["head"], json!({"vars": ["a", "b"]}));
+    assert_eq!(common::sorted(common::binding_rows(&r, &["a", "b"])), vec!["alice bob", "charlie bob", "charlie dani", "dani bob", "dani greg", "fred greg"]);
+
+    let r = g.sparql("SELECT * WHERE { <alice> <follows> ?b . ?b <follows> ?c . ?c <follows> ?d }").unwrap();
+    assert_eq!(r["head"], json!({"vars": ["b", "c", "d"]}));
+    assert_eq!(common::binding_rows(&r, &["b", "c", "d"]), vec!["bob fred greg"]);
+
+    // terms come in the SPARQL JSON results format
+    let r = g.sparql("SELECT ?p ?o WHERE { <alice> ?p ?o }").unwrap();
+    let mut bindings = r["results"]["bindings"].as_array().unwrap().clone();
+    bindings.sort_by_key(|b| b["p"]["value"].to_string());
+    assert_eq!(bindings, vec![
+        json!({"p": {"type": "uri", "value": "age"}, "o": {"type": "literal", "value": "30", "datatype": "http://www.w3.org/2001/XMLSchema#integer"}}),
+        json!({"p": {"type": "uri", "value": "follows"}, "o": {"type": "uri", "value": "bob"}})
+    ]);
+
+    let r = g.sparql("PREFIX : <> SELECT ?a ?g WHERE { GRAPH ?g { ?a :status \"smart_person\" } }").unwrap();
+    assert_eq!(common::sorted(common::binding_rows(&r, &["a", "g"])), vec!["emily smart_graph", "greg smart_graph"]);
+
+    let r = g.sparql("SELECT ?a WHERE { ?a <age> ?n FILTER (?n >= 26) }").unwrap();
+    assert_eq!(common::binding_rows(&r, &["a"]), vec!["alice"]);
+    let r = g.sparql("SELECT ?a WHERE { ?a <status> ?s FILTER (?s != \"smart_person\" && regex(str(?a), \"^[a-e]\")) }").unwrap();
+    assert_eq!(common::sorted(common::binding_rows(&r, &["a"])), vec!["bob", "dani"]);
+    let r = g.sparql("SELECT ?a WHERE { ?a <follows> <greg> FILTER regex(str(?a), \"RED\", \"i\") }").unwrap();
+    assert_eq!(common::binding_rows(&r, &["a"]), vec!["fred"]);
+
+    let r = g.sparql("SELECT ?a ?n WHERE { ?a <follows> <bob> OPTIONAL { ?a <age> ?n } }").unwrap();
+    assert_eq!(common::sorted(common::binding_rows(&r, &["a", "n"])), vec!["alice 30", "charlie -", "dani -"]);
+
+    let r = g.sparql("SELECT ?a WHERE { { ?a <follows> <fred> } UNION { ?a <age> 30 } }").unwrap();
+    assert_eq!(common::sorted(common::binding_rows(&r, &["a"])), vec!["alice", "bob", "emily"]);
+
+    // ?a and ?b follow each other both ways, reached twice in the tree of shapes
+    g.write(vec![Quad::new("<greg>", "<follows>", "<dani>", ())]);
+    let r = g.sparql("SELECT ?a ?b WHERE { ?a <follows> ?b . ?b <follows> ?a }").unwrap();
+    assert_eq!(common::sorted(common::binding_rows(&r, &["a", "b"])), vec!["dani greg", "greg dani"]);
+}
+
+
+#[test]
+fn sparql_order_page_test() {
+    let g = common::simple_graph(vec![
+        Quad::new("<alice>", "<age>", 30, ()),
+        Quad::new("<bob>", "<age>", 25, ())
+    ]);
+
+    let r = g.sparql("SELECT ?a ?b WHERE { ?a <follows> ?b } ORDER BY DESC(?a) ?b").unwrap();
+    assert_eq!(common::binding_rows(&r, &["a", "b"]), vec![
+        "fred greg", "emily fred", "dani bob", "dani greg", "charlie bob", "charlie dani", "bob fred", "alice bob"
+    ]);
+
+    // the page counts solutions, not the nodes they start from
+    let r = g.sparql("SELECT ?a ?b WHERE { ?a <follows> ?b } ORDER BY ?a ?b LIMIT 3 OFFSET 2").unwrap();
+    assert_eq!(common::binding_rows(&r, &["a", "b"]), vec!["charlie bob", "charlie dani", "dani bob"]);
+
+    let r = g.sparql("SELECT ?a ?b WHERE { ?a <follows> ?b } ORDER BY ?b LIMIT 2").unwrap();
+    assert_eq!(common::binding_rows(&r, &["b"]), vec!["bob", "bob"]);
+
+    let r = g.sparql("SELECT DISTINCT ?b WHERE { ?a <follows> ?b } ORDER BY ?b").unwrap();
+    assert_eq!(common::binding_rows(&r, &["b"]), vec!["bob", "dani", "fred", "greg"]);
+    let r = g.sparql("SELECT DISTINCT ?b WHERE { ?a <follows> ?b } ORDER BY ?b OFFSET 1 LIMIT 2").unwrap();
+    assert_eq!(common::binding_rows(&r, &["b"]), vec!["dani", "fred"]);
+
+    // a page past the end of the solutions, and counts out of range
+    let r = g.sparql("SELECT ?a WHERE { ?a <follows> ?b } OFFSET 18446744073709551615 LIMIT 18446744073709551615").unwrap();
+    assert_eq!(common::binding_rows(&r, &["a"]), Vec::<String>::new());
+    assert!(g.sparql("SELECT ?a WHERE { ?a <follows> ?b } LIMIT 18446744073709551616").is_err());
+    assert!(g.sparql("SELECT ?a WHERE { ?a <follows> ?b } OFFSET -1").is_err());
+
+    assert!(g.sparql("SELECT ?a WHERE { ?a <follows> ?b FILTER (?c > 1) }").is_err());
+    assert!(g.sparql("SELECT ?a WHERE { ?a <follows> ?b . ?c <status> ?d }").is_err());
+}